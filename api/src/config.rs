@@ -5,6 +5,7 @@
 
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::fmt::{self, Display, Formatter};
 use std::fs;
 use std::io::{Error, ErrorKind, Result};
 use std::path::Path;
@@ -15,6 +16,69 @@ use std::sync::Arc;
 use serde::Deserialize;
 use serde_json::Value;
 
+/// Error information about an invalid configuration field.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigFieldError {
+    /// Path of the offending field, such as `backend.registry.host`.
+    pub path: String,
+    /// Reason why the field is invalid.
+    pub reason: String,
+}
+
+/// Errors found when validating configuration information, one entry per offending field.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConfigValidationError {
+    errors: Vec<ConfigFieldError>,
+}
+
+impl ConfigValidationError {
+    /// Get all offending fields.
+    pub fn errors(&self) -> &[ConfigFieldError] {
+        &self.errors
+    }
+
+    fn add<R: Into<String>>(&mut self, path: &str, reason: R) {
+        self.errors.push(ConfigFieldError {
+            path: path.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    fn merge(&mut self, result: std::result::Result<(), ConfigValidationError>) {
+        if let Err(e) = result {
+            self.errors.extend(e.errors);
+        }
+    }
+
+    fn into_result(self) -> std::result::Result<(), ConfigValidationError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl Display for ConfigValidationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (idx, e) in self.errors.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}: {}", e.path, e.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigValidationError {}
+
+impl From<ConfigValidationError> for Error {
+    fn from(e: ConfigValidationError) -> Self {
+        einval!(format!("invalid configuration, {}", e))
+    }
+}
+
 /// Configuration file format version 2, based on Toml.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct ConfigV2 {
@@ -90,27 +154,22 @@ impl ConfigV2 {
     }
 
     /// Validate the configuration object.
-    pub fn validate(&self) -> bool {
+    pub fn validate(&self) -> std::result::Result<(), ConfigValidationError> {
+        let mut errors = ConfigValidationError::default();
         if self.version != 2 {
-            return false;
+            errors.add("version", format!("must be 2, got {}", self.version));
         }
         if let Some(backend_cfg) = self.backend.as_ref() {
-            if !backend_cfg.validate() {
-                return false;
-            }
+            errors.merge(backend_cfg.validate());
         }
         if let Some(cache_cfg) = self.cache.as_ref() {
-            if !cache_cfg.validate() {
-                return false;
-            }
+            errors.merge(cache_cfg.validate());
         }
         if let Some(rafs_cfg) = self.rafs.as_ref() {
-            if !rafs_cfg.validate() {
-                return false;
-            }
+            errors.merge(rafs_cfg.validate());
         }
 
-        true
+        errors.into_result()
     }
 
     /// Get configuration information for storage backend.
@@ -205,24 +264,17 @@ impl FromStr for ConfigV2 {
 
     fn from_str(s: &str) -> Result<ConfigV2> {
         if let Ok(v) = serde_json::from_str::<ConfigV2>(s) {
-            return if v.validate() {
-                Ok(v)
-            } else {
-                Err(einval!("invalid configuration"))
-            };
+            v.validate()?;
+            return Ok(v);
         }
         if let Ok(v) = toml::from_str::<ConfigV2>(s) {
-            return if v.validate() {
-                Ok(v)
-            } else {
-                Err(einval!("invalid configuration"))
-            };
+            v.validate()?;
+            return Ok(v);
         }
         if let Ok(v) = serde_json::from_str::<RafsConfig>(s) {
             if let Ok(v) = ConfigV2::try_from(v) {
-                if v.validate() {
-                    return Ok(v);
-                }
+                v.validate()?;
+                return Ok(v);
             }
         }
         Err(einval!("failed to parse configuration information"))
@@ -252,47 +304,81 @@ pub struct BackendConfigV2 {
 
 impl BackendConfigV2 {
     /// Validate storage backend configuration.
-    pub fn validate(&self) -> bool {
+    pub fn validate(&self) -> std::result::Result<(), ConfigValidationError> {
+        let mut errors = ConfigValidationError::default();
+        let missing = |errors: &mut ConfigValidationError, ty: &str| {
+            errors.add(
+                &format!("backend.{}", ty),
+                format!("must be present for backend type '{}'", ty),
+            )
+        };
+
         match self.backend_type.as_str() {
             "localdisk" => match self.localdisk.as_ref() {
                 Some(v) => {
                     if v.device_path.is_empty() {
-                        return false;
+                        errors.add("backend.localdisk.device_path", "must not be empty");
                     }
                 }
-                None => return false,
+                None => missing(&mut errors, "localdisk"),
             },
             "localfs" => match self.localfs.as_ref() {
                 Some(v) => {
                     if v.blob_file.is_empty() && v.dir.is_empty() {
-                        return false;
+                        errors.add(
+                            "backend.localfs.dir",
+                            "either `dir` or `blob_file` must be specified",
+                        );
                     }
                 }
-                None => return false,
+                None => missing(&mut errors, "localfs"),
             },
             "oss" => match self.oss.as_ref() {
                 Some(v) => {
-                    if v.endpoint.is_empty() || v.bucket_name.is_empty() {
-                        return false;
+                    if v.endpoint.is_empty() {
+                        errors.add("backend.oss.endpoint", "must not be empty");
+                    }
+                    if v.bucket_name.is_empty() {
+                        errors.add("backend.oss.bucket_name", "must not be empty");
                     }
+                    v.retry.validate("backend.oss.retry", &mut errors);
+                    v.hedge.validate("backend.oss.hedge", &mut errors);
+                    validate_mirrors(&v.mirror_strategy, &v.mirrors, "backend.oss", &mut errors);
                 }
-                None => return false,
+                None => missing(&mut errors, "oss"),
             },
             "s3" => match self.s3.as_ref() {
                 Some(v) => {
-                    if v.region.is_empty() || v.bucket_name.is_empty() {
-                        return false;
+                    if v.region.is_empty() {
+                        errors.add("backend.s3.region", "must not be empty");
                     }
+                    if v.bucket_name.is_empty() {
+                        errors.add("backend.s3.bucket_name", "must not be empty");
+                    }
+                    v.retry.validate("backend.s3.retry", &mut errors);
+                    v.hedge.validate("backend.s3.hedge", &mut errors);
+                    validate_mirrors(&v.mirror_strategy, &v.mirrors, "backend.s3", &mut errors);
                 }
-                None => return false,
+                None => missing(&mut errors, "s3"),
             },
             "registry" => match self.registry.as_ref() {
                 Some(v) => {
-                    if v.host.is_empty() || v.repo.is_empty() {
-                        return false;
+                    if v.host.is_empty() {
+                        errors.add("backend.registry.host", "must not be empty");
+                    }
+                    if v.repo.is_empty() {
+                        errors.add("backend.registry.repo", "must not be empty");
                     }
+                    v.retry.validate("backend.registry.retry", &mut errors);
+                    v.hedge.validate("backend.registry.hedge", &mut errors);
+                    validate_mirrors(
+                        &v.mirror_strategy,
+                        &v.mirrors,
+                        "backend.registry",
+                        &mut errors,
+                    );
                 }
-                None => return false,
+                None => missing(&mut errors, "registry"),
             },
 
             "http-proxy" => match self.http_proxy.as_ref() {
@@ -306,20 +392,31 @@ impl BackendConfigV2 {
                             || v.addr.starts_with("https://")
                             || is_valid_unix_socket_path(&v.addr))
                     {
-                        return false;
+                        errors.add(
+                            "backend.http-proxy.addr",
+                            "must be a http(s) URL or an absolute path to an existing unix socket",
+                        );
                     }
 
                     // check if v.path is valid url path format
                     if Path::new(&v.path).join("any_blob_id").to_str().is_none() {
-                        return false;
+                        errors.add("backend.http-proxy.path", "must be a valid URL path");
                     }
+                    v.retry.validate("backend.http-proxy.retry", &mut errors);
+                    v.hedge.validate("backend.http-proxy.hedge", &mut errors);
+                    validate_mirrors(
+                        &v.mirror_strategy,
+                        &v.mirrors,
+                        "backend.http-proxy",
+                        &mut errors,
+                    );
                 }
-                None => return false,
+                None => missing(&mut errors, "http-proxy"),
             },
-            _ => return false,
+            ty => errors.add("backend.type", format!("unknown backend type '{}'", ty)),
         }
 
-        true
+        errors.into_result()
     }
 
     /// Get configuration information for localdisk
@@ -445,12 +542,22 @@ pub struct OssConfig {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Retry policy and circuit breaker for failed read requests.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Issue duplicated requests for slow range requests to reduce tail latency.
+    #[serde(default)]
+    pub hedge: HedgeConfig,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
     /// Enable mirrors for the read request.
     #[serde(default)]
     pub mirrors: Vec<MirrorConfig>,
+    /// Strategy to select mirrors: "ordered", "round-robin", "least-latency", "weighted" or
+    /// "consistent-hash", default to "ordered".
+    #[serde(default)]
+    pub mirror_strategy: String,
 }
 
 /// S3 configuration information to access blobs.
@@ -489,12 +596,22 @@ pub struct S3Config {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Retry policy and circuit breaker for failed read requests.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Issue duplicated requests for slow range requests to reduce tail latency.
+    #[serde(default)]
+    pub hedge: HedgeConfig,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
     /// Enable mirrors for the read request.
     #[serde(default)]
     pub mirrors: Vec<MirrorConfig>,
+    /// Strategy to select mirrors: "ordered", "round-robin", "least-latency", "weighted" or
+    /// "consistent-hash", default to "ordered".
+    #[serde(default)]
+    pub mirror_strategy: String,
 }
 
 /// Http proxy configuration information to access blobs.
@@ -518,12 +635,22 @@ pub struct HttpProxyConfig {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Retry policy and circuit breaker for failed read requests.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Issue duplicated requests for slow range requests to reduce tail latency.
+    #[serde(default)]
+    pub hedge: HedgeConfig,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
     /// Enable mirrors for the read request.
     #[serde(default)]
     pub mirrors: Vec<MirrorConfig>,
+    /// Strategy to select mirrors: "ordered", "round-robin", "least-latency", "weighted" or
+    /// "consistent-hash", default to "ordered".
+    #[serde(default)]
+    pub mirror_strategy: String,
 }
 
 /// Container registry configuration information to access blobs.
@@ -551,6 +678,12 @@ pub struct RegistryConfig {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Retry policy and circuit breaker for failed read requests.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Issue duplicated requests for slow range requests to reduce tail latency.
+    #[serde(default)]
+    pub hedge: HedgeConfig,
     /// The field is a bearer token to be sent to registry to authorize registry requests.
     #[serde(default)]
     pub registry_token: Option<String>,
@@ -567,6 +700,10 @@ pub struct RegistryConfig {
     /// Enable mirrors for the read request.
     #[serde(default)]
     pub mirrors: Vec<MirrorConfig>,
+    /// Strategy to select mirrors: "ordered", "round-robin", "least-latency", "weighted" or
+    /// "consistent-hash", default to "ordered".
+    #[serde(default)]
+    pub mirror_strategy: String,
 }
 
 /// Configuration information for blob cache manager.
@@ -575,7 +712,8 @@ pub struct CacheConfigV2 {
     /// Type of blob cache: "blobcache", "fscache" or "dummy"
     #[serde(default, rename = "type")]
     pub cache_type: String,
-    /// Whether the data from the cache is compressed, not used anymore.
+    /// Whether to cache compressed data from the backend instead of decompressed data, which
+    /// saves disk space at the cost of decompressing chunks on every read. Not supported by fscache.
     #[serde(default, rename = "compressed")]
     pub cache_compressed: bool,
     /// Whether to validate data read from the cache.
@@ -584,6 +722,12 @@ pub struct CacheConfigV2 {
     /// Configuration for blob level prefetch.
     #[serde(default)]
     pub prefetch: PrefetchConfigV2,
+    /// Size in bytes of the in-memory cache for hot decompressed chunks, 0 to disable it.
+    #[serde(default)]
+    pub memory_cache_size: u64,
+    /// Configuration for rate limiting of on-demand data fetches from the storage backend.
+    #[serde(default)]
+    pub qos: QosConfig,
     /// Configuration information for file cache
     #[serde(rename = "filecache")]
     pub file_cache: Option<FileCacheConfig>,
@@ -594,40 +738,43 @@ pub struct CacheConfigV2 {
 
 impl CacheConfigV2 {
     /// Validate cache configuration information.
-    pub fn validate(&self) -> bool {
+    pub fn validate(&self) -> std::result::Result<(), ConfigValidationError> {
+        let mut errors = ConfigValidationError::default();
         match self.cache_type.as_str() {
-            "blobcache" | "filecache" => {
-                if let Some(c) = self.file_cache.as_ref() {
+            "blobcache" | "filecache" => match self.file_cache.as_ref() {
+                Some(c) => {
                     if c.work_dir.is_empty() {
-                        return false;
+                        errors.add("cache.filecache.work_dir", "must not be empty");
+                    }
+                    if c.shared_chunk_store && self.cache_compressed {
+                        errors.add(
+                            "cache.filecache.shared_chunk_store",
+                            "can't be used together with `cache.compressed`",
+                        );
                     }
-                } else {
-                    return false;
                 }
-            }
-            "fscache" => {
-                if let Some(c) = self.fs_cache.as_ref() {
+                None => errors.add(
+                    "cache.filecache",
+                    format!("must be present for cache type '{}'", self.cache_type),
+                ),
+            },
+            "fscache" => match self.fs_cache.as_ref() {
+                Some(c) => {
                     if c.work_dir.is_empty() {
-                        return false;
+                        errors.add("cache.fscache.work_dir", "must not be empty");
                     }
-                } else {
-                    return false;
                 }
-            }
+                None => errors.add("cache.fscache", "must be present for cache type 'fscache'"),
+            },
             "" | "dummycache" => {}
-            _ => return false,
+            ty => errors.add("cache.type", format!("unknown cache type '{}'", ty)),
         }
-
-        if self.prefetch.enable {
-            if self.prefetch.batch_size > 0x10000000 {
-                return false;
-            }
-            if self.prefetch.threads == 0 || self.prefetch.threads > 1024 {
-                return false;
-            }
+        self.prefetch.validate("cache.prefetch", &mut errors);
+        if self.qos.enable && self.qos.weight == 0 {
+            errors.add("cache.qos.weight", "must be greater than 0");
         }
 
-        true
+        errors.into_result()
     }
 
     /// Check whether the cache type is `filecache`
@@ -663,6 +810,55 @@ impl CacheConfigV2 {
     }
 }
 
+/// Configuration information for quality of service of on-demand data fetches.
+///
+/// On-demand fetches from the storage backend are rate limited at three scopes: the mount itself,
+/// all mounts of the daemon and all mounts fetching from the same storage backend host. Rate limits
+/// of the daemon and host scopes are shared by mounts, and mounts waiting for them are served in
+/// proportion to their `weight`. The first mount decides the shared rate limits, and mounts with
+/// conflicting daemon or host rate limits are rejected while the shared ones are in use.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct QosConfig {
+    /// Whether to enable rate limiting of on-demand data fetches.
+    #[serde(default)]
+    pub enable: bool,
+    /// Weight of the mount when sharing daemon and host rate limits with other mounts.
+    #[serde(default = "default_qos_weight")]
+    pub weight: u32,
+    /// Rate limits for the mount.
+    #[serde(default)]
+    pub mount: RateLimitConfig,
+    /// Rate limits for all mounts of the daemon.
+    #[serde(default)]
+    pub daemon: RateLimitConfig,
+    /// Rate limits for each storage backend host.
+    #[serde(default)]
+    pub host: RateLimitConfig,
+}
+
+impl Default for QosConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            weight: default_qos_weight(),
+            mount: RateLimitConfig::default(),
+            daemon: RateLimitConfig::default(),
+            host: RateLimitConfig::default(),
+        }
+    }
+}
+
+/// Rate limits for data fetches from storage backends.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct RateLimitConfig {
+    /// Network bandwidth rate limit in unit of Bytes per second and Zero means no limit.
+    #[serde(default)]
+    pub bandwidth_limit: u64,
+    /// Number of requests per second and Zero means no limit.
+    #[serde(default)]
+    pub request_limit: u32,
+}
+
 /// Configuration information for file cache.
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct FileCacheConfig {
@@ -672,6 +868,15 @@ pub struct FileCacheConfig {
     /// Deprecated: disable index mapping, keep it as false when possible.
     #[serde(default)]
     pub disable_indexed_map: bool,
+    /// Store chunk data in a content-addressed store keyed by chunk digest and shared by all blobs.
+    #[serde(default)]
+    pub shared_chunk_store: bool,
+    /// I/O engine to access cache files: "sync" (default) or "io_uring".
+    #[serde(default)]
+    pub io_engine: String,
+    /// Read cache files with O_DIRECT, only supported by the "io_uring" I/O engine.
+    #[serde(default)]
+    pub direct_io: bool,
 }
 
 impl FileCacheConfig {
@@ -763,27 +968,88 @@ pub struct RafsConfigV2 {
     /// Filesystem prefetching configuration.
     #[serde(default)]
     pub prefetch: PrefetchConfigV2,
+    /// Mapping from uids stored in the image to uids presented by the filesystem.
+    #[serde(default)]
+    pub uid_map: Vec<IdMapping>,
+    /// Mapping from gids stored in the image to gids presented by the filesystem.
+    #[serde(default)]
+    pub gid_map: Vec<IdMapping>,
 }
 
 impl RafsConfigV2 {
     /// Validate RAFS filesystem configuration information.
-    pub fn validate(&self) -> bool {
+    pub fn validate(&self) -> std::result::Result<(), ConfigValidationError> {
+        let mut errors = ConfigValidationError::default();
         if self.mode != "direct" && self.mode != "cached" {
-            return false;
+            errors.add(
+                "rafs.mode",
+                format!("must be 'direct' or 'cached', got '{}'", self.mode),
+            );
         }
         if self.batch_size > 0x10000000 {
-            return false;
+            errors.add("rafs.batch_size", "must be 0..=0x10000000");
+        }
+        self.prefetch.validate("rafs.prefetch", &mut errors);
+        validate_id_mappings(&self.uid_map, "rafs.uid_map", &mut errors);
+        validate_id_mappings(&self.gid_map, "rafs.gid_map", &mut errors);
+
+        errors.into_result()
+    }
+}
+
+/// A range of uid/gid mapping, with the same semantics as a line of `/proc/self/uid_map`.
+///
+/// Ids `[container_id, container_id + size)` stored in the image are presented as ids
+/// `[host_id, host_id + size)` by the filesystem.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct IdMapping {
+    /// First id of the range stored in the image.
+    pub container_id: u32,
+    /// First id of the range presented by the filesystem.
+    pub host_id: u32,
+    /// Number of ids in the range.
+    pub size: u32,
+}
+
+impl IdMapping {
+    /// Map id `id` stored in the image if it's covered by the range.
+    pub fn map(&self, id: u32) -> Option<u32> {
+        if id >= self.container_id && id - self.container_id < self.size {
+            self.host_id.checked_add(id - self.container_id)
+        } else {
+            None
+        }
+    }
+}
+
+fn validate_id_mappings(mappings: &[IdMapping], path: &str, errors: &mut ConfigValidationError) {
+    let end = |start: u32, size: u32| start as u64 + size as u64;
+    for (idx, m) in mappings.iter().enumerate() {
+        if m.size == 0 {
+            errors.add(&format!("{}[{}].size", path, idx), "must be greater than 0");
+        } else if end(m.container_id, m.size) > u32::MAX as u64 + 1
+            || end(m.host_id, m.size) > u32::MAX as u64 + 1
+        {
+            errors.add(&format!("{}[{}].size", path, idx), "id range overflows");
         }
-        if self.prefetch.enable {
-            if self.prefetch.batch_size > 0x10000000 {
-                return false;
+        for (prev_idx, prev) in mappings[..idx].iter().enumerate() {
+            if (m.container_id as u64) < end(prev.container_id, prev.size)
+                && (prev.container_id as u64) < end(m.container_id, m.size)
+            {
+                errors.add(
+                    &format!("{}[{}].container_id", path, idx),
+                    format!("overlaps with {}[{}]", path, prev_idx),
+                );
             }
-            if self.prefetch.threads == 0 || self.prefetch.threads > 1024 {
-                return false;
+            if (m.host_id as u64) < end(prev.host_id, prev.size)
+                && (prev.host_id as u64) < end(m.host_id, m.size)
+            {
+                errors.add(
+                    &format!("{}[{}].host_id", path, idx),
+                    format!("overlaps with {}[{}]", path, prev_idx),
+                );
             }
         }
-
-        true
     }
 }
 
@@ -806,6 +1072,19 @@ pub struct PrefetchConfigV2 {
     pub prefetch_all: bool,
 }
 
+impl PrefetchConfigV2 {
+    fn validate(&self, path: &str, errors: &mut ConfigValidationError) {
+        if self.enable {
+            if self.batch_size > 0x10000000 {
+                errors.add(&format!("{}.batch_size", path), "must be 0..=0x10000000");
+            }
+            if self.threads == 0 || self.threads > 1024 {
+                errors.add(&format!("{}.threads", path), "must be 1..=1024");
+            }
+        }
+    }
+}
+
 /// Configuration information for network proxy.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct ProxyConfig {
@@ -838,6 +1117,209 @@ impl Default for ProxyConfig {
     }
 }
 
+/// Configuration information for retrying failed requests to storage backends.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RetryConfig {
+    /// Delay before the first retry, in milliseconds. The delay doubles for each following retry.
+    #[serde(default = "default_retry_base_delay")]
+    pub base_delay: u64,
+    /// Upper limit of the delay between retries, in milliseconds.
+    #[serde(default = "default_retry_max_delay")]
+    pub max_delay: u64,
+    /// Randomize the delay between retries to avoid retry storms from concurrent requests.
+    #[serde(default = "default_true")]
+    pub jitter: bool,
+    /// HTTP status codes to retry, either classes like "5xx" or exact codes like "429".
+    /// Network errors without an HTTP response are always retried.
+    #[serde(default = "default_retryable_status")]
+    pub retryable_status: Vec<String>,
+    /// Circuit breaker to fail fast when the storage backend is unavailable.
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            base_delay: default_retry_base_delay(),
+            max_delay: default_retry_max_delay(),
+            jitter: true,
+            retryable_status: default_retryable_status(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+        }
+    }
+}
+
+impl RetryConfig {
+    fn validate(&self, path: &str, errors: &mut ConfigValidationError) {
+        if self.base_delay == 0 {
+            errors.add(&format!("{}.base_delay", path), "must be greater than 0");
+        }
+        if self.max_delay < self.base_delay {
+            errors.add(
+                &format!("{}.max_delay", path),
+                "must not be less than `base_delay`",
+            );
+        }
+        for status in self.retryable_status.iter() {
+            if HttpStatusPattern::parse(status).is_none() {
+                errors.add(
+                    &format!("{}.retryable_status", path),
+                    format!("invalid HTTP status '{}'", status),
+                );
+            }
+        }
+        let cb = &self.circuit_breaker;
+        if cb.enable {
+            if cb.failure_threshold == 0 {
+                errors.add(
+                    &format!("{}.circuit_breaker.failure_threshold", path),
+                    "must be greater than 0",
+                );
+            }
+            if cb.probe_interval == 0 {
+                errors.add(
+                    &format!("{}.circuit_breaker.probe_interval", path),
+                    "must be greater than 0",
+                );
+            }
+        }
+    }
+}
+
+/// Pattern to match HTTP status codes of failed requests, as in [RetryConfig::retryable_status].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HttpStatusPattern {
+    /// Status class, such as 5 for "5xx".
+    Class(u16),
+    /// Exact status code, such as 429.
+    Code(u16),
+}
+
+impl HttpStatusPattern {
+    /// Parse patterns "1xx" to "5xx" and three digit status codes from 100 to 599.
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.len() != 3 || !s.is_ascii() {
+            return None;
+        }
+        let class = s[..1].parse::<u16>().ok()?;
+        if !(1..=5).contains(&class) {
+            return None;
+        }
+        if s[1..].eq_ignore_ascii_case("xx") {
+            Some(HttpStatusPattern::Class(class))
+        } else if s[1..].bytes().all(|c| c.is_ascii_digit()) {
+            s.parse::<u16>().ok().map(HttpStatusPattern::Code)
+        } else {
+            None
+        }
+    }
+
+    /// Check whether HTTP status code `status` matches the pattern.
+    pub fn matches(&self, status: u16) -> bool {
+        match self {
+            HttpStatusPattern::Class(c) => status / 100 == *c,
+            HttpStatusPattern::Code(c) => status == *c,
+        }
+    }
+}
+
+/// Configuration information for the circuit breaker of storage backends.
+///
+/// After `failure_threshold` consecutive failed requests, the circuit breaker opens and requests to the
+/// storage backend fail immediately. Every `probe_interval` seconds, a single request is allowed
+/// to probe the storage backend, and the circuit breaker closes once the probe succeeds.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CircuitBreakerConfig {
+    /// Whether to enable the circuit breaker.
+    #[serde(default)]
+    pub enable: bool,
+    /// Number of consecutive failed requests to open the circuit breaker, retries of a request
+    /// are not counted separately.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Interval to probe the storage backend when the circuit breaker is open, in seconds.
+    #[serde(default = "default_check_interval")]
+    pub probe_interval: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            failure_threshold: default_failure_threshold(),
+            probe_interval: default_check_interval(),
+        }
+    }
+}
+
+/// Configuration information for hedging slow requests to storage backends.
+///
+/// If a range request hasn't completed within the `percentile` latency of recent requests, a
+/// duplicated request is issued to another healthy mirror or the same server, and the response
+/// arriving first is used.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HedgeConfig {
+    /// Whether to enable request hedging, default to false.
+    #[serde(default)]
+    pub enable: bool,
+    /// Percentile of recent request latencies to issue the hedged request, in range 1..=99.
+    #[serde(default = "default_hedge_percentile")]
+    pub percentile: u8,
+    /// Minimal delay to issue the hedged request, in milliseconds.
+    #[serde(default = "default_hedge_min_delay")]
+    pub min_delay: u64,
+}
+
+impl Default for HedgeConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            percentile: default_hedge_percentile(),
+            min_delay: default_hedge_min_delay(),
+        }
+    }
+}
+
+// Valid strategies to select mirrors for storage backends.
+const MIRROR_STRATEGIES: [&str; 5] = [
+    "ordered",
+    "round-robin",
+    "least-latency",
+    "weighted",
+    "consistent-hash",
+];
+
+fn validate_mirrors(
+    strategy: &str,
+    mirrors: &[MirrorConfig],
+    path: &str,
+    errors: &mut ConfigValidationError,
+) {
+    if !strategy.is_empty() && !MIRROR_STRATEGIES.contains(&strategy) {
+        errors.add(
+            &format!("{}.mirror_strategy", path),
+            format!("unknown mirror strategy '{}'", strategy),
+        );
+    }
+    for (idx, mirror) in mirrors.iter().enumerate() {
+        if mirror.weight == 0 {
+            errors.add(
+                &format!("{}.mirrors[{}].weight", path, idx),
+                "must be greater than 0",
+            );
+        }
+    }
+}
+
+impl HedgeConfig {
+    fn validate(&self, path: &str, errors: &mut ConfigValidationError) {
+        if self.enable && !(1..=99).contains(&self.percentile) {
+            errors.add(&format!("{}.percentile", path), "must be 1..=99");
+        }
+    }
+}
+
 /// Configuration for registry mirror.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct MirrorConfig {
@@ -861,6 +1343,9 @@ pub struct MirrorConfig {
     /// Maximum number of failures before marking a mirror as unusable.
     #[serde(default = "default_failure_limit")]
     pub failure_limit: u8,
+    /// Relative weight of the mirror for the "weighted" and "consistent-hash" strategies.
+    #[serde(default = "default_mirror_weight")]
+    pub weight: u32,
 }
 
 impl Default for MirrorConfig {
@@ -872,6 +1357,7 @@ impl Default for MirrorConfig {
             health_check_interval: 5,
             failure_limit: 5,
             ping_url: String::new(),
+            weight: default_mirror_weight(),
         }
     }
 }
@@ -907,10 +1393,7 @@ impl BlobCacheEntryConfigV2 {
     }
 
     /// Validate the configuration object.
-    pub fn validate(&self) -> bool {
-        if self.version != 2 {
-            return false;
-        }
+    pub fn validate(&self) -> std::result::Result<(), ConfigValidationError> {
         let config: ConfigV2 = self.into();
         config.validate()
     }
@@ -921,18 +1404,12 @@ impl FromStr for BlobCacheEntryConfigV2 {
 
     fn from_str(s: &str) -> Result<BlobCacheEntryConfigV2> {
         if let Ok(v) = serde_json::from_str::<BlobCacheEntryConfigV2>(s) {
-            return if v.validate() {
-                Ok(v)
-            } else {
-                Err(einval!("invalid configuration"))
-            };
+            v.validate()?;
+            return Ok(v);
         }
         if let Ok(v) = toml::from_str::<BlobCacheEntryConfigV2>(s) {
-            return if v.validate() {
-                Ok(v)
-            } else {
-                Err(einval!("invalid configuration"))
-            };
+            v.validate()?;
+            return Ok(v);
         }
         Err(einval!("failed to parse configuration information"))
     }
@@ -1012,20 +1489,29 @@ pub struct BlobCacheEntry {
 }
 
 impl BlobCacheEntry {
-    pub fn prepare_configuration_info(&mut self) -> bool {
+    /// Convert legacy configuration information if needed, and validate cache and storage backend
+    /// configuration information.
+    pub fn prepare_configuration_info(&mut self) -> std::result::Result<(), ConfigValidationError> {
+        let mut errors = ConfigValidationError::default();
         if self.blob_config.is_none() {
             if let Some(legacy) = self.blob_config_legacy.as_ref() {
                 match legacy.try_into() {
-                    Err(_) => return false,
+                    Err(e) => errors.add("config", format!("invalid configuration, {}", e)),
                     Ok(v) => self.blob_config = Some(v),
                 }
             }
         }
 
         match self.blob_config.as_ref() {
-            None => false,
-            Some(cfg) => cfg.cache.validate() && cfg.backend.validate(),
+            None if errors.errors.is_empty() => errors.add("config_v2", "must be present"),
+            None => {}
+            Some(cfg) => {
+                errors.merge(cfg.cache.validate());
+                errors.merge(cfg.backend.validate());
+            }
         }
+
+        errors.into_result()
     }
 }
 
@@ -1041,19 +1527,24 @@ impl BlobCacheEntry {
     }
 
     /// Validate the configuration object.
-    pub fn validate(&self) -> bool {
+    pub fn validate(&self) -> std::result::Result<(), ConfigValidationError> {
+        let mut errors = ConfigValidationError::default();
         if self.blob_type != BLOB_CACHE_TYPE_META_BLOB
             && self.blob_type != BLOB_CACHE_TYPE_DATA_BLOB
         {
-            log::warn!("invalid blob type {} for blob cache entry", self.blob_type);
-            return false;
+            errors.add(
+                "type",
+                format!(
+                    "must be '{}' or '{}', got '{}'",
+                    BLOB_CACHE_TYPE_META_BLOB, BLOB_CACHE_TYPE_DATA_BLOB, self.blob_type
+                ),
+            );
         }
         if let Some(config) = self.blob_config.as_ref() {
-            if !config.validate() {
-                return false;
-            }
+            errors.merge(config.validate());
         }
-        true
+
+        errors.into_result()
     }
 }
 
@@ -1062,18 +1553,12 @@ impl FromStr for BlobCacheEntry {
 
     fn from_str(s: &str) -> Result<BlobCacheEntry> {
         if let Ok(v) = serde_json::from_str::<BlobCacheEntry>(s) {
-            return if v.validate() {
-                Ok(v)
-            } else {
-                Err(einval!("invalid configuration"))
-            };
+            v.validate()?;
+            return Ok(v);
         }
         if let Ok(v) = toml::from_str::<BlobCacheEntry>(s) {
-            return if v.validate() {
-                Ok(v)
-            } else {
-                Err(einval!("invalid configuration"))
-            };
+            v.validate()?;
+            return Ok(v);
         }
         Err(einval!("failed to parse configuration information"))
     }
@@ -1106,6 +1591,38 @@ fn default_failure_limit() -> u8 {
     5
 }
 
+fn default_retry_base_delay() -> u64 {
+    500
+}
+
+fn default_retry_max_delay() -> u64 {
+    30_000
+}
+
+fn default_retryable_status() -> Vec<String> {
+    vec!["5xx".to_string(), "429".to_string()]
+}
+
+fn default_failure_threshold() -> u32 {
+    10
+}
+
+fn default_mirror_weight() -> u32 {
+    1
+}
+
+fn default_hedge_percentile() -> u8 {
+    95
+}
+
+fn default_hedge_min_delay() -> u64 {
+    100
+}
+
+fn default_qos_weight() -> u32 {
+    1
+}
+
 fn default_work_dir() -> String {
     ".".to_string()
 }
@@ -1189,7 +1706,7 @@ struct CacheConfig {
     /// Type of blob cache: "blobcache", "fscache" or ""
     #[serde(default, rename = "type")]
     pub cache_type: String,
-    /// Whether the data from the cache is compressed, not used anymore.
+    /// Whether to cache compressed data from the backend instead of decompressed data.
     #[serde(default, rename = "compressed")]
     pub cache_compressed: bool,
     /// Blob cache manager specific configuration: FileCacheConfig, FsCacheConfig.
@@ -1212,6 +1729,8 @@ impl TryFrom<&CacheConfig> for CacheConfigV2 {
             cache_compressed: v.cache_compressed,
             cache_validate: v.cache_validate,
             prefetch: (&v.prefetch_config).into(),
+            memory_cache_size: 0,
+            qos: QosConfig::default(),
             file_cache: None,
             fs_cache: None,
         };
@@ -1289,6 +1808,8 @@ impl TryFrom<RafsConfig> for ConfigV2 {
             access_pattern: v.access_pattern,
             latest_read_files: v.latest_read_files,
             prefetch: v.fs_prefetch.into(),
+            uid_map: Vec::new(),
+            gid_map: Vec::new(),
         };
         if !cache.prefetch.enable && rafs.prefetch.enable {
             cache.prefetch = rafs.prefetch.clone();
@@ -1475,6 +1996,20 @@ mod tests {
             serde_json::from_str("{\"work_dir\":\"/proc/mounts\",\"disable_indexed_map\":true}")
                 .unwrap();
         assert!(config.get_work_dir().is_err());
+        assert!(!config.shared_chunk_store);
+
+        let config: FileCacheConfig =
+            serde_json::from_str("{\"work_dir\":\"/tmp\",\"shared_chunk_store\":true}").unwrap();
+        assert!(config.shared_chunk_store);
+        assert!(config.io_engine.is_empty());
+        assert!(!config.direct_io);
+
+        let config: FileCacheConfig = serde_json::from_str(
+            "{\"work_dir\":\"/tmp\",\"io_engine\":\"io_uring\",\"direct_io\":true}",
+        )
+        .unwrap();
+        assert_eq!(&config.io_engine, "io_uring");
+        assert!(config.direct_io);
     }
 
     #[test]
@@ -1850,6 +2385,7 @@ mod tests {
         type = "filecache"
         compressed = true
         validate = true
+        memory_cache_size = 1048576
         [cache.filecache]
         work_dir = "/tmp"
         [cache.fscache]
@@ -1859,6 +2395,11 @@ mod tests {
         threads = 8
         batch_size = 1000000
         bandwidth_limit = 10000000
+        [cache.qos]
+        enable = true
+        weight = 4
+        mount = { bandwidth_limit = 1048576, request_limit = 100 }
+        host = { bandwidth_limit = 10485760 }
         "#;
         let config: ConfigV2 = toml::from_str(content).unwrap();
         assert_eq!(config.version, 2);
@@ -1870,6 +2411,7 @@ mod tests {
         assert_eq!(&cache.cache_type, "filecache");
         assert!(cache.cache_compressed);
         assert!(cache.cache_validate);
+        assert_eq!(cache.memory_cache_size, 1048576);
         let filecache = cache.file_cache.as_ref().unwrap();
         assert_eq!(&filecache.work_dir, "/tmp");
         let fscache = cache.fs_cache.as_ref().unwrap();
@@ -1880,6 +2422,21 @@ mod tests {
         assert_eq!(prefetch.threads, 8);
         assert_eq!(prefetch.batch_size, 1000000);
         assert_eq!(prefetch.bandwidth_limit, 10000000);
+
+        let qos = &cache.qos;
+        assert!(qos.enable);
+        assert_eq!(qos.weight, 4);
+        assert_eq!(qos.mount.bandwidth_limit, 1048576);
+        assert_eq!(qos.mount.request_limit, 100);
+        assert_eq!(qos.daemon, RateLimitConfig::default());
+        assert_eq!(qos.host.bandwidth_limit, 10485760);
+        assert_eq!(qos.host.request_limit, 0);
+        assert!(cache.validate().is_ok());
+
+        let mut cache = cache.clone();
+        cache.qos.weight = 0;
+        let err = cache.validate().unwrap_err();
+        assert!(err.to_string().contains("cache.qos.weight"));
     }
 
     #[test]
@@ -1918,7 +2475,44 @@ mod tests {
         assert_eq!(rafs.prefetch.threads, 4);
         assert_eq!(rafs.prefetch.batch_size, 1000000);
         assert_eq!(rafs.prefetch.bandwidth_limit, 10000000);
-        assert!(rafs.prefetch.prefetch_all)
+        assert!(rafs.prefetch.prefetch_all);
+        assert!(rafs.uid_map.is_empty());
+        assert!(rafs.gid_map.is_empty());
+    }
+
+    #[test]
+    fn test_v2_rafs_id_mapping() {
+        let content = r#"version=2
+        [rafs]
+        mode = "direct"
+        uid_map = [
+            { container_id = 0, host_id = 100000, size = 1000 },
+            { container_id = 1000, host_id = 1000, size = 1 },
+        ]
+        gid_map = [{ container_id = 0, host_id = 100000, size = 65536 }]
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let rafs = config.rafs.as_ref().unwrap();
+        assert!(rafs.validate().is_ok());
+        assert_eq!(rafs.uid_map.len(), 2);
+        assert_eq!(rafs.uid_map[0].map(0), Some(100000));
+        assert_eq!(rafs.uid_map[0].map(999), Some(100999));
+        assert_eq!(rafs.uid_map[0].map(1000), None);
+        assert_eq!(rafs.uid_map[1].map(1000), Some(1000));
+        assert_eq!(rafs.gid_map[0].map(65535), Some(165535));
+
+        let mut rafs = rafs.clone();
+        rafs.uid_map[1].container_id = 999;
+        rafs.gid_map[0].size = 0;
+        let err = rafs.validate().unwrap_err().to_string();
+        assert!(err.contains("rafs.uid_map[1].container_id"));
+        assert!(!err.contains("rafs.uid_map[1].host_id"));
+        assert!(err.contains("rafs.gid_map[0].size"));
+
+        rafs.uid_map[1].container_id = u32::MAX;
+        rafs.uid_map[1].size = 2;
+        let err = rafs.validate().unwrap_err().to_string();
+        assert!(err.contains("rafs.uid_map[1].size"));
     }
 
     #[test]
@@ -2044,4 +2638,142 @@ mod tests {
         assert_eq!(&config.id, "id1");
         assert_eq!(config.backend.as_ref().unwrap().backend_type, "localfs");
     }
+
+    #[test]
+    fn test_config_validation_errors() {
+        let content = r#"
+        version = 2
+        backend.type = "registry"
+        backend.registry.host = ""
+        backend.registry.repo = "test/repo"
+        cache.type = "filecache"
+        cache.compressed = true
+        cache.filecache.work_dir = "/tmp"
+        cache.filecache.shared_chunk_store = true
+        cache.prefetch.enable = true
+        cache.prefetch.threads = 0
+        rafs.mode = "lazy"
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let err = config.validate().unwrap_err();
+        let paths: Vec<&str> = err.errors().iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "backend.registry.host",
+                "cache.filecache.shared_chunk_store",
+                "cache.prefetch.threads",
+                "rafs.mode"
+            ]
+        );
+        assert_eq!(
+            err.to_string(),
+            "backend.registry.host: must not be empty; cache.filecache.shared_chunk_store: can't be used together with `cache.compressed`; cache.prefetch.threads: must be 1..=1024; rafs.mode: must be 'direct' or 'cached', got 'lazy'"
+        );
+
+        let err = ConfigV2::from_str(content).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(err
+            .to_string()
+            .contains("backend.registry.host: must not be empty"));
+
+        let mut config = ConfigV2::new("id1");
+        assert!(config.validate().is_ok());
+        config.backend = Some(BackendConfigV2 {
+            backend_type: "oss".to_string(),
+            ..Default::default()
+        });
+        let err = config.validate().unwrap_err();
+        assert_eq!(err.errors().len(), 1);
+        assert_eq!(err.errors()[0].path, "backend.oss");
+    }
+
+    #[test]
+    fn test_retry_config() {
+        let content = r#"
+        version = 2
+        backend.type = "registry"
+        backend.registry.host = "localhost"
+        backend.registry.repo = "test/repo"
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let retry = &config
+            .get_backend_config()
+            .unwrap()
+            .registry
+            .as_ref()
+            .unwrap()
+            .retry;
+        assert_eq!(retry, &RetryConfig::default());
+        assert_eq!(retry.base_delay, 500);
+        assert_eq!(retry.retryable_status, vec!["5xx", "429"]);
+        assert!(!retry.circuit_breaker.enable);
+        let hedge = &config
+            .get_backend_config()
+            .unwrap()
+            .registry
+            .as_ref()
+            .unwrap()
+            .hedge;
+        assert!(!hedge.enable);
+        assert_eq!(hedge.percentile, 95);
+        assert_eq!(hedge.min_delay, 100);
+
+        let content = r#"
+        version = 2
+        backend.type = "registry"
+        backend.registry.host = "localhost"
+        backend.registry.repo = "test/repo"
+        backend.registry.retry.base_delay = 1000
+        backend.registry.retry.max_delay = 100
+        backend.registry.retry.retryable_status = ["5XX", "404", "6xx", "50"]
+        backend.registry.retry.circuit_breaker.enable = true
+        backend.registry.retry.circuit_breaker.failure_threshold = 0
+        backend.registry.hedge.enable = true
+        backend.registry.hedge.percentile = 100
+        backend.registry.mirror_strategy = "random"
+        [[backend.registry.mirrors]]
+        host = "http://127.0.0.1:65001"
+        weight = 0
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let err = config.validate().unwrap_err();
+        let paths: Vec<&str> = err.errors().iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "backend.registry.retry.max_delay",
+                "backend.registry.retry.retryable_status",
+                "backend.registry.retry.retryable_status",
+                "backend.registry.retry.circuit_breaker.failure_threshold",
+                "backend.registry.hedge.percentile",
+                "backend.registry.mirror_strategy",
+                "backend.registry.mirrors[0].weight",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_http_status_pattern() {
+        assert_eq!(
+            HttpStatusPattern::parse("5xx"),
+            Some(HttpStatusPattern::Class(5))
+        );
+        assert_eq!(
+            HttpStatusPattern::parse("1XX"),
+            Some(HttpStatusPattern::Class(1))
+        );
+        assert_eq!(
+            HttpStatusPattern::parse("429"),
+            Some(HttpStatusPattern::Code(429))
+        );
+        for s in ["6xx", "0xx", "50", "5000", "5x0", "+12", "4é", ""] {
+            assert!(HttpStatusPattern::parse(s).is_none(), "{}", s);
+        }
+
+        let pattern = HttpStatusPattern::parse("5xx").unwrap();
+        assert!(pattern.matches(500) && pattern.matches(503));
+        assert!(!pattern.matches(429));
+        assert!(HttpStatusPattern::Code(429).matches(429));
+    }
 }
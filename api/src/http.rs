@@ -11,7 +11,7 @@ use nydus_error::error::MetricsError;
 use serde::Deserialize;
 use serde_json::Error as SerdeError;
 
-use crate::BlobCacheEntry;
+use crate::{BackendConfigV2, BlobCacheEntry};
 
 /// Mount a filesystem.
 #[derive(Clone, Deserialize, Debug)]
@@ -80,11 +80,15 @@ pub enum ApiRequest {
     Remount(String, ApiMountCmd),
     /// Unmount a filesystem.
     Umount(String),
+    /// Update credentials, proxy and mirrors of the storage backend for a filesystem.
+    UpdateBackendConfig(String, Box<BackendConfigV2>),
 
     /// Get storage backend metrics.
     ExportBackendMetrics(Option<String>),
     /// Get blob cache metrics.
     ExportBlobcacheMetrics(Option<String>),
+    /// Scrub blob cache files and repair corrupted chunks.
+    ScrubBlobcache(Option<String>),
 
     // Nydus API v1 requests
     /// Get filesystem global metrics.
@@ -109,6 +113,8 @@ pub enum ApiRequest {
     DeleteBlobObject(BlobCacheObjectId),
     /// Delete a blob cache file
     DeleteBlobFile(String),
+    /// Update credentials, proxy and mirrors of the storage backend for a blob cache entry
+    UpdateBlobBackendConfig(BlobCacheObjectId, Box<BackendConfigV2>),
 }
 
 /// Kinds for daemon related error messages.
@@ -167,6 +173,8 @@ pub enum ApiResponsePayload {
     BackendMetrics(String),
     /// Blobcache metrics.
     BlobcacheMetrics(String),
+    /// Blobcache scrub result.
+    BlobcacheScrub(String),
     /// Daemon version, configuration and status information in json.
     DaemonInfo(String),
     /// No data is sent on the channel.
@@ -203,6 +211,8 @@ pub enum HttpError {
     BadRequest,
     /// Failed to configure the daemon.
     Configure(ApiError),
+    /// Invalid configuration information, with all offending fields.
+    InvalidConfig(String),
     /// Failed to query information about daemon.
     DaemonInfo(ApiError),
     /// Failed to query global events.
@@ -218,12 +228,16 @@ pub enum HttpError {
     Mount(ApiError),
     /// Failed to remount filesystem.
     Upgrade(ApiError),
+    /// Failed to update storage backend configuration.
+    UpdateBackendConfig(ApiError),
 
     // Metrics related errors
     /// Failed to get backend metrics.
     BackendMetrics(ApiError),
     /// Failed to get blobcache metrics.
     BlobcacheMetrics(ApiError),
+    /// Failed to scrub blobcache.
+    BlobcacheScrub(ApiError),
 
     // Filesystem related errors (v1)
     /// Failed to get filesystem backend information
@@ -10,6 +10,7 @@ use crate::http_handler::{
     error_response, extract_query_part, parse_body, success_response, translate_status_code,
     EndpointHandler, HttpResult,
 };
+use crate::BackendConfigV2;
 
 // Convert an ApiResponse to a HTTP response.
 //
@@ -26,6 +27,7 @@ fn convert_to_response<O: FnOnce(ApiError) -> HttpError>(api_resp: ApiResponse,
                 Events(d) => success_response(Some(d)),
                 BackendMetrics(d) => success_response(Some(d)),
                 BlobcacheMetrics(d) => success_response(Some(d)),
+                BlobcacheScrub(d) => success_response(Some(d)),
                 _ => panic!("Unexpected response message from API service"),
             }
         }
@@ -129,6 +131,25 @@ impl EndpointHandler for MetricsBlobcacheHandler {
     }
 }
 
+/// Scrub blob cache files to detect and repair corrupted chunks.
+pub struct BlobcacheScrubHandler {}
+impl EndpointHandler for BlobcacheScrubHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Put, None) => {
+                let id = extract_query_part(req, "blob_id");
+                let r = kicker(ApiRequest::ScrubBlobcache(id));
+                Ok(convert_to_response(r, HttpError::BlobcacheScrub))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
 /// Mount a filesystem.
 pub struct MountHandler {}
 impl EndpointHandler for MountHandler {
@@ -160,6 +181,31 @@ impl EndpointHandler for MountHandler {
     }
 }
 
+/// Update credentials, proxy and mirrors of the storage backend for a mounted filesystem.
+pub struct MountBackendHandler {}
+impl EndpointHandler for MountBackendHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        let mountpoint = extract_query_part(req, "mountpoint").ok_or_else(|| {
+            HttpError::QueryString("'mountpoint' should be specified in query string".to_string())
+        })?;
+        match (req.method(), req.body.as_ref()) {
+            (Method::Put, Some(body)) => {
+                let config: Box<BackendConfigV2> = parse_body(body)?;
+                config
+                    .validate()
+                    .map_err(|e| HttpError::InvalidConfig(e.to_string()))?;
+                let r = kicker(ApiRequest::UpdateBackendConfig(mountpoint, config));
+                Ok(convert_to_response(r, HttpError::UpdateBackendConfig))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
 /// Send fuse fd to new daemon.
 pub struct SendFuseFdHandler {}
 impl EndpointHandler for SendFuseFdHandler {
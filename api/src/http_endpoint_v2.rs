@@ -6,7 +6,7 @@
 
 //! Nydus API v2.
 
-use crate::BlobCacheEntry;
+use crate::{BackendConfigV2, BlobCacheEntry};
 use dbs_uhttp::{Method, Request, Response};
 
 use crate::http::{
@@ -87,9 +87,10 @@ impl EndpointHandler for BlobObjectListHandlerV2 {
             }
             (Method::Put, Some(body)) => {
                 let mut conf: Box<BlobCacheEntry> = parse_body(body)?;
-                if !conf.prepare_configuration_info() {
-                    return Err(HttpError::BadRequest);
-                }
+                conf.prepare_configuration_info()
+                    .map_err(|e| HttpError::InvalidConfig(e.to_string()))?;
+                conf.validate()
+                    .map_err(|e| HttpError::InvalidConfig(e.to_string()))?;
                 let r = kicker(ApiRequest::CreateBlobObject(conf));
                 Ok(convert_to_response(r, HttpError::CreateBlobObject))
             }
@@ -110,3 +111,68 @@ impl EndpointHandler for BlobObjectListHandlerV2 {
         }
     }
 }
+
+/// Update credentials, proxy and mirrors of the storage backend for a blob cache entry.
+pub struct BlobBackendHandlerV2 {}
+impl EndpointHandler for BlobBackendHandlerV2 {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Put, Some(body)) => {
+                let domain_id = extract_query_part(req, "domain_id").unwrap_or_default();
+                let blob_id = extract_query_part(req, "blob_id").ok_or_else(|| {
+                    HttpError::QueryString(
+                        "'blob_id' should be specified in query string".to_string(),
+                    )
+                })?;
+                let config: Box<BackendConfigV2> = parse_body(body)?;
+                config
+                    .validate()
+                    .map_err(|e| HttpError::InvalidConfig(e.to_string()))?;
+                let param = BlobCacheObjectId { domain_id, blob_id };
+                let r = kicker(ApiRequest::UpdateBlobBackendConfig(param, config));
+                Ok(convert_to_response(r, HttpError::UpdateBackendConfig))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dbs_uhttp::StatusCode;
+
+    #[test]
+    fn test_create_blob_object_with_invalid_config() {
+        let body = r#"{
+            "type": "bootstrap",
+            "id": "blob1",
+            "config_v2": {
+                "version": 2,
+                "backend": {"type": "localfs", "localfs": {"dir": "/tmp"}},
+                "cache": {"type": "filecache", "filecache": {"work_dir": ""}}
+            }
+        }"#;
+        let raw = format!(
+            "PUT http://localhost/api/v2/blobs HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let req = Request::try_from(raw.as_bytes(), None).unwrap();
+        let kicker = |_: ApiRequest| -> ApiResponse { panic!("invalid request reached daemon") };
+        let err = BlobObjectListHandlerV2 {}
+            .handle_request(&req, &kicker)
+            .unwrap_err();
+        assert!(matches!(&err, HttpError::InvalidConfig(msg)
+            if msg == "cache.filecache.work_dir: must not be empty"));
+
+        let response = error_response(err, StatusCode::BadRequest);
+        let body = response.body().unwrap();
+        let body = String::from_utf8_lossy(body.raw());
+        assert!(body.contains("cache.filecache.work_dir: must not be empty"));
+    }
+}
@@ -20,14 +20,17 @@ use crate::http::{
     ApiError, ApiRequest, ApiResponse, DaemonErrorKind, ErrorMessage, HttpError, MetricsErrorKind,
 };
 use crate::http_endpoint_common::{
-    EventsHandler, ExitHandler, MetricsBackendHandler, MetricsBlobcacheHandler, MountHandler,
-    SendFuseFdHandler, StartHandler, TakeoverFuseFdHandler,
+    BlobcacheScrubHandler, EventsHandler, ExitHandler, MetricsBackendHandler,
+    MetricsBlobcacheHandler, MountBackendHandler, MountHandler, SendFuseFdHandler, StartHandler,
+    TakeoverFuseFdHandler,
 };
 use crate::http_endpoint_v1::{
     FsBackendInfo, InfoHandler, MetricsFsAccessPatternHandler, MetricsFsFilesHandler,
     MetricsFsGlobalHandler, MetricsFsInflightHandler, HTTP_ROOT_V1,
 };
-use crate::http_endpoint_v2::{BlobObjectListHandlerV2, InfoV2Handler, HTTP_ROOT_V2};
+use crate::http_endpoint_v2::{
+    BlobBackendHandlerV2, BlobObjectListHandlerV2, InfoV2Handler, HTTP_ROOT_V2,
+};
 
 const EXIT_TOKEN: Token = Token(usize::MAX);
 const REQUEST_TOKEN: Token = Token(1);
@@ -147,8 +150,10 @@ lazy_static! {
         r.routes.insert(endpoint_v1!("/daemon/fuse/sendfd"), Box::new(SendFuseFdHandler{}));
         r.routes.insert(endpoint_v1!("/daemon/fuse/takeover"), Box::new(TakeoverFuseFdHandler{}));
         r.routes.insert(endpoint_v1!("/mount"), Box::new(MountHandler{}));
+        r.routes.insert(endpoint_v1!("/mount/backend"), Box::new(MountBackendHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/backend"), Box::new(MetricsBackendHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/blobcache"), Box::new(MetricsBlobcacheHandler{}));
+        r.routes.insert(endpoint_v1!("/blobcache/scrub"), Box::new(BlobcacheScrubHandler{}));
 
         // Nydus API, v1
         r.routes.insert(endpoint_v1!("/daemon"), Box::new(InfoHandler{}));
@@ -161,6 +166,7 @@ lazy_static! {
         // Nydus API, v2
         r.routes.insert(endpoint_v2!("/daemon"), Box::new(InfoV2Handler{}));
         r.routes.insert(endpoint_v2!("/blobs"), Box::new(BlobObjectListHandlerV2{}));
+        r.routes.insert(endpoint_v2!("/blobs/backend"), Box::new(BlobBackendHandlerV2{}));
 
         r
     };
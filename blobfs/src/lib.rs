@@ -191,9 +191,7 @@ impl BlobFs {
 
     fn load_bootstrap(cfg: &Config) -> io::Result<BootstrapArgs> {
         let blob_ondemand_conf = BlobOndemandConfig::from_str(&cfg.blob_ondemand_cfg)?;
-        if !blob_ondemand_conf.rafs_conf.validate() {
-            return Err(einval!("invlidate configuration for blobfs"));
-        }
+        blob_ondemand_conf.rafs_conf.validate()?;
         let rafs_cfg = blob_ondemand_conf.rafs_conf.get_rafs_config()?;
         if rafs_cfg.mode != "direct" {
             return Err(einval!("blobfs only supports RAFS 'direct' mode"));
@@ -38,6 +38,10 @@ impl Blob {
                 let mut chunk_data_buf = vec![0u8; RAFS_MAX_CHUNK_SIZE as usize];
                 for (idx, inode) in inodes.iter().enumerate() {
                     let node = &mut nodes[*inode];
+                    // Chunks of unchanged files have been reused from the parent bootstrap.
+                    if ctx.delta_build && !node.chunks.is_empty() {
+                        continue;
+                    }
                     let size = node
                         .dump_node_data(ctx, blob_mgr, blob_writer, &mut chunk_data_buf)
                         .context("failed to dump blob chunks")?;
@@ -80,7 +84,9 @@ impl Blob {
             | ConversionType::DirectoryToTargz
             | ConversionType::DirectoryToStargz
             | ConversionType::EStargzIndexToRef
-            | ConversionType::TargzToStargz => {
+            | ConversionType::TargzToStargz
+            | ConversionType::OciImageToRafs
+            | ConversionType::DockerArchiveToRafs => {
                 unimplemented!()
             }
         }
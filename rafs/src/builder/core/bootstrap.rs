@@ -274,7 +274,7 @@ impl Bootstrap {
         Ok(())
     }
 
-    fn load_parent_bootstrap(
+    pub(crate) fn load_parent_bootstrap(
         &mut self,
         ctx: &mut BuildContext,
         bootstrap_mgr: &mut BootstrapManager,
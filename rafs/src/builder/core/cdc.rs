@@ -0,0 +1,246 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Content-defined chunking based on the FastCDC algorithm.
+//!
+//! Chunk boundaries are determined by a rolling gear hash of file content instead of fixed
+//! offsets, so inserting or removing data in a file only changes chunks around the modification
+//! and other chunks can still be deduplicated. Normalized chunking is used to keep chunk sizes
+//! close to the average size: a stricter mask is applied before reaching the average size and a
+//! looser mask is applied after it.
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::metadata::RAFS_MAX_CHUNK_SIZE;
+
+/// Minimum allowed chunk size for content-defined chunking.
+pub const CDC_MIN_CHUNK_SIZE: u32 = 0x400;
+
+/// Content-defined chunker to split file data into variable sized chunks.
+#[derive(Clone)]
+pub struct CdcChunker {
+    min_size: u32,
+    avg_size: u32,
+    max_size: u32,
+    mask_s: u64,
+    mask_l: u64,
+    gear: [u64; 256],
+}
+
+impl CdcChunker {
+    /// Create a new instance of `CdcChunker` with the minimum, average and maximum chunk sizes.
+    ///
+    /// The average and maximum chunk sizes must be power of two, and the maximum chunk size is
+    /// used as the chunk size of the RAFS filesystem.
+    pub fn new(min_size: u32, avg_size: u32, max_size: u32) -> Result<Self> {
+        if min_size < CDC_MIN_CHUNK_SIZE
+            || min_size >= avg_size
+            || avg_size >= max_size
+            || !avg_size.is_power_of_two()
+            || !max_size.is_power_of_two()
+            || max_size < 0x1000
+            || max_size as u64 > RAFS_MAX_CHUNK_SIZE
+        {
+            bail!(
+                "invalid content-defined chunking sizes: min 0x{:x}, avg 0x{:x}, max 0x{:x}",
+                min_size,
+                avg_size,
+                max_size
+            );
+        }
+
+        let bits = avg_size.trailing_zeros();
+        Ok(CdcChunker {
+            min_size,
+            avg_size,
+            max_size,
+            mask_s: Self::mask(bits + 1),
+            mask_l: Self::mask(bits - 1),
+            gear: Self::gear_table(),
+        })
+    }
+
+    /// Parse chunking sizes in format `<min>,<avg>,<max>`, each size may be decimal or
+    /// hexadecimal with a `0x` prefix.
+    pub fn parse(arg: &str) -> Result<Self> {
+        let sizes = arg
+            .split(',')
+            .map(|v| {
+                let v = v.trim();
+                let res = if v.starts_with("0x") || v.starts_with("0X") {
+                    u32::from_str_radix(&v[2..], 16)
+                } else {
+                    v.parse::<u32>()
+                };
+                res.map_err(|_| anyhow!("invalid chunk size {}", v))
+            })
+            .collect::<Result<Vec<u32>>>()?;
+        if sizes.len() != 3 {
+            bail!("content-defined chunking sizes should be in format '<min>,<avg>,<max>'");
+        }
+
+        Self::new(sizes[0], sizes[1], sizes[2])
+    }
+
+    /// Get the minimum chunk size.
+    pub fn min_size(&self) -> u32 {
+        self.min_size
+    }
+
+    /// Get the average chunk size.
+    pub fn avg_size(&self) -> u32 {
+        self.avg_size
+    }
+
+    /// Get the maximum chunk size.
+    pub fn max_size(&self) -> u32 {
+        self.max_size
+    }
+
+    /// Get size of the first chunk in `data`.
+    ///
+    /// The caller should provide at least `max_size` bytes of data unless reaching the end of
+    /// file, otherwise the chunk boundary may differ from the one found with more data.
+    pub fn cut_point(&self, data: &[u8]) -> usize {
+        let len = data.len();
+        if len <= self.min_size as usize {
+            return len;
+        }
+
+        let max = std::cmp::min(len, self.max_size as usize);
+        let normal = std::cmp::min(max, self.avg_size as usize);
+        let mut hash = 0u64;
+        let mut idx = self.min_size as usize;
+
+        while idx < normal {
+            hash = (hash << 1).wrapping_add(self.gear[data[idx] as usize]);
+            if hash & self.mask_s == 0 {
+                return idx + 1;
+            }
+            idx += 1;
+        }
+        while idx < max {
+            hash = (hash << 1).wrapping_add(self.gear[data[idx] as usize]);
+            if hash & self.mask_l == 0 {
+                return idx + 1;
+            }
+            idx += 1;
+        }
+
+        max
+    }
+
+    // The gear hash shifts left, so use the most significant bits which are affected by more
+    // bytes for checking.
+    fn mask(bits: u32) -> u64 {
+        !0u64 << (64 - bits)
+    }
+
+    // Generate the gear table with a fixed seed, the table must be stable to generate identical
+    // chunks across builds.
+    fn gear_table() -> [u64; 256] {
+        let mut table = [0u64; 256];
+        let mut state = 0x6e79_6475_735f_6364u64;
+        for entry in table.iter_mut() {
+            // splitmix64
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            *entry = z ^ (z >> 31);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_data(size: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..size)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn split(chunker: &CdcChunker, data: &[u8]) -> Vec<usize> {
+        let mut pos = 0;
+        let mut sizes = Vec::new();
+        while pos < data.len() {
+            let size = chunker.cut_point(&data[pos..]);
+            sizes.push(size);
+            pos += size;
+        }
+        sizes
+    }
+
+    #[test]
+    fn test_cdc_chunker_new() {
+        assert!(CdcChunker::new(0x1000, 0x4000, 0x10000).is_ok());
+        assert!(CdcChunker::new(0x100, 0x4000, 0x10000).is_err());
+        assert!(CdcChunker::new(0x4000, 0x4000, 0x10000).is_err());
+        assert!(CdcChunker::new(0x1000, 0x3000, 0x10000).is_err());
+        assert!(CdcChunker::new(0x1000, 0x4000, 0x4000).is_err());
+        assert!(CdcChunker::new(0x1000, 0x4000, 0x4000000).is_err());
+
+        let chunker = CdcChunker::parse("0x1000, 16384,0x10000").unwrap();
+        assert_eq!(chunker.min_size(), 0x1000);
+        assert_eq!(chunker.avg_size(), 0x4000);
+        assert_eq!(chunker.max_size(), 0x10000);
+        assert!(CdcChunker::parse("0x1000,0x4000").is_err());
+        assert!(CdcChunker::parse("0x1000,0x4000,abc").is_err());
+    }
+
+    #[test]
+    fn test_cdc_chunker_cut_point() {
+        let chunker = CdcChunker::new(0x1000, 0x4000, 0x10000).unwrap();
+        let data = gen_data(0x100000, 1);
+        let sizes = split(&chunker, &data);
+        assert_eq!(sizes.iter().sum::<usize>(), data.len());
+        for size in &sizes[..sizes.len() - 1] {
+            assert!(*size > 0x1000 && *size <= 0x10000);
+        }
+        assert!(sizes.len() > 8);
+
+        // Small data and zero-filled data.
+        assert_eq!(chunker.cut_point(&data[..0x800]), 0x800);
+        assert_eq!(chunker.cut_point(&[0u8; 0x20000]), 0x10000);
+    }
+
+    #[test]
+    fn test_cdc_chunker_shift_resistance() {
+        let chunker = CdcChunker::new(0x1000, 0x4000, 0x10000).unwrap();
+        let data = gen_data(0x100000, 2);
+        let mut shifted = vec![0x5au8; 7];
+        shifted.extend_from_slice(&data);
+
+        // Inserting data at the start of the file only affects the leading chunks.
+        let chunks = split(&chunker, &data);
+        let shifted_chunks = split(&chunker, &shifted);
+        let tail = |sizes: &[usize], len: usize| -> Vec<usize> {
+            let mut end = len;
+            sizes
+                .iter()
+                .rev()
+                .map(|s| {
+                    end -= s;
+                    end
+                })
+                .take(sizes.len() / 2)
+                .collect::<Vec<_>>()
+        };
+        let offsets = tail(&chunks, data.len());
+        let shifted_offsets = tail(&shifted_chunks, shifted.len())
+            .iter()
+            .map(|o| o - 7)
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, shifted_offsets);
+    }
+}
@@ -34,7 +34,7 @@ use nydus_utils::{compress, digest, div_round_up, round_down_4k, BufReaderInfo};
 
 use super::node::{ChunkSource, Node};
 use crate::builder::{
-    ChunkDict, Feature, Features, HashChunkDict, Prefetch, PrefetchPolicy, WhiteoutSpec,
+    CdcChunker, ChunkDict, Feature, Features, HashChunkDict, Prefetch, PrefetchPolicy, WhiteoutSpec,
 };
 use crate::metadata::chunk::ChunkWrapper;
 use crate::metadata::layout::v5::RafsV5BlobTable;
@@ -62,6 +62,8 @@ pub enum ConversionType {
     TarToStargz,
     TarToRafs,
     TarToRef,
+    OciImageToRafs,
+    DockerArchiveToRafs,
 }
 
 impl Default for ConversionType {
@@ -85,6 +87,8 @@ impl FromStr for ConversionType {
             "targz-ref" => Ok(Self::TargzToRef),
             "tar-rafs" => Ok(Self::TarToRafs),
             "tar-stargz" => Ok(Self::TarToStargz),
+            "oci-rafs" => Ok(Self::OciImageToRafs),
+            "docker-rafs" => Ok(Self::DockerArchiveToRafs),
             // kept for backward compatibility
             "directory" => Ok(Self::DirectoryToRafs),
             "stargz_index" => Ok(Self::EStargzIndexToRef),
@@ -108,6 +112,8 @@ impl fmt::Display for ConversionType {
             ConversionType::TarToRafs => write!(f, "tar-rafs"),
             ConversionType::TarToStargz => write!(f, "tar-stargz"),
             ConversionType::TarToRef => write!(f, "tar-ref"),
+            ConversionType::OciImageToRafs => write!(f, "oci-rafs"),
+            ConversionType::DockerArchiveToRafs => write!(f, "docker-rafs"),
         }
     }
 }
@@ -1061,6 +1067,8 @@ pub struct BuildContext {
     pub whiteout_spec: WhiteoutSpec,
     /// Chunk slice size.
     pub chunk_size: u32,
+    /// Content-defined chunker to split files into variable sized chunks, only for RAFS v5.
+    pub chunker: Option<CdcChunker>,
     /// Version number of output metadata and data blob.
     pub fs_version: RafsVersion,
     /// Whether any directory/file has extended attributes.
@@ -1079,9 +1087,14 @@ pub struct BuildContext {
     /// Storage writing blob to single file or a directory.
     pub blob_storage: Option<ArtifactStorage>,
     pub blob_zran_generator: Option<Mutex<ZranContextGenerator<File>>>,
+    /// Spacing of compressed data between random access checkpoints of zran blobs.
+    pub blob_zran_span: u64,
     pub blob_tar_reader: Option<BufReaderInfo<File>>,
     pub blob_features: BlobFeatures,
     pub blob_inline_meta: bool,
+    /// Build a delta layer against the parent bootstrap. The source directory is the full root
+    /// filesystem of the new version, and chunks of unchanged files are reused from the parent.
+    pub delta_build: bool,
 
     pub features: Features,
     pub configuration: Arc<ConfigV2>,
@@ -1125,6 +1138,7 @@ impl BuildContext {
             whiteout_spec,
 
             chunk_size: RAFS_DEFAULT_CHUNK_SIZE as u32,
+            chunker: None,
             fs_version: RafsVersion::default(),
 
             conversion_type,
@@ -1133,9 +1147,11 @@ impl BuildContext {
             prefetch,
             blob_storage,
             blob_zran_generator: None,
+            blob_zran_span: RAFS_DEFAULT_CHUNK_SIZE,
             blob_tar_reader: None,
             blob_features,
             blob_inline_meta,
+            delta_build: false,
             has_xattr: false,
 
             features,
@@ -1151,6 +1167,12 @@ impl BuildContext {
         self.chunk_size = chunk_size;
     }
 
+    /// Enable content-defined chunking, the maximum chunk size is used as chunk size.
+    pub fn set_chunker(&mut self, chunker: CdcChunker) {
+        self.chunk_size = chunker.max_size();
+        self.chunker = Some(chunker);
+    }
+
     pub fn set_configuration(&mut self, config: Arc<ConfigV2>) {
         self.configuration = config;
     }
@@ -1168,6 +1190,7 @@ impl Default for BuildContext {
             whiteout_spec: WhiteoutSpec::default(),
 
             chunk_size: RAFS_DEFAULT_CHUNK_SIZE as u32,
+            chunker: None,
             fs_version: RafsVersion::default(),
 
             conversion_type: ConversionType::default(),
@@ -1176,10 +1199,12 @@ impl Default for BuildContext {
             prefetch: Prefetch::default(),
             blob_storage: None,
             blob_zran_generator: None,
+            blob_zran_span: RAFS_DEFAULT_CHUNK_SIZE,
             blob_tar_reader: None,
             blob_features: BlobFeatures::empty(),
             has_xattr: true,
             blob_inline_meta: false,
+            delta_build: false,
             features: Features::new(),
             configuration: Arc::new(ConfigV2::default()),
         }
@@ -4,6 +4,7 @@
 
 pub(crate) mod blob;
 pub(crate) mod bootstrap;
+pub(crate) mod cdc;
 pub(crate) mod chunk_dict;
 pub(crate) mod context;
 pub(crate) mod feature;
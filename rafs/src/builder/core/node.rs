@@ -3,6 +3,7 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::cmp;
 use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Display, Formatter, Result as FmtResult};
 use std::fs::{self, File};
@@ -20,11 +21,13 @@ use anyhow::{anyhow, bail, Context, Error, Result};
 use nydus_storage::device::BlobFeatures;
 use nydus_storage::meta::{BlobChunkInfoV2Ondisk, BlobMetaChunkInfo};
 use nydus_utils::compress;
-use nydus_utils::digest::{DigestHasher, RafsDigest};
+use nydus_utils::digest::{DigestHasher, RafsDigest, RafsDigestHasher};
 use nydus_utils::{div_round_up, event_tracer, root_tracer, try_round_up_4k, ByteSize};
 use sha2::digest::Digest;
 
-use crate::builder::{ArtifactWriter, BlobContext, BlobManager, BuildContext, ChunkDict, Overlay};
+use crate::builder::{
+    ArtifactWriter, BlobContext, BlobManager, BuildContext, CdcChunker, ChunkDict, Overlay,
+};
 use crate::metadata::chunk::ChunkWrapper;
 use crate::metadata::inode::InodeWrapper;
 use crate::metadata::layout::v6::EROFS_INODE_FLAT_PLAIN;
@@ -259,56 +262,148 @@ impl Node {
             None
         };
 
-        // `child_count` of regular file is reused as `chunk_count`.
-        for i in 0..self.inode.child_count() {
-            let chunk_size = ctx.chunk_size;
-            let file_offset = i as u64 * chunk_size as u64;
-            let uncompressed_size = if i == self.inode.child_count() - 1 {
-                (self.inode.size() - chunk_size as u64 * i as u64) as u32
-            } else {
-                chunk_size
-            };
+        if let Some(chunker) = ctx.chunker.as_ref() {
+            blob_size = self.dump_variable_chunks(
+                ctx,
+                chunker,
+                blob_mgr,
+                blob_writer,
+                reader,
+                data_buf,
+                &mut inode_hasher,
+            )?;
+        } else {
+            // `child_count` of regular file is reused as `chunk_count`.
+            for i in 0..self.inode.child_count() {
+                let chunk_size = ctx.chunk_size;
+                let file_offset = i as u64 * chunk_size as u64;
+                let uncompressed_size = if i == self.inode.child_count() - 1 {
+                    (self.inode.size() - chunk_size as u64 * i as u64) as u32
+                } else {
+                    chunk_size
+                };
+
+                let chunk_data = &mut data_buf[0..uncompressed_size as usize];
+                let (chunk, chunk_info) = self.read_file_chunk(ctx, reader, chunk_data)?;
+                if let Some(h) = inode_hasher.as_mut() {
+                    h.digest_update(chunk.id().as_ref());
+                }
+                blob_size += self.add_file_chunk(
+                    ctx,
+                    blob_mgr,
+                    blob_writer,
+                    file_offset,
+                    chunk,
+                    chunk_info,
+                    chunk_data,
+                )?;
+            }
+        }
+
+        // Finish inode digest calculation
+        if let Some(h) = inode_hasher {
+            self.inode.set_digest(h.digest_finalize());
+        }
 
-            let chunk_data = &mut data_buf[0..uncompressed_size as usize];
-            let (chunk, chunk_info) = self.read_file_chunk(ctx, reader, chunk_data)?;
+        Ok(blob_size)
+    }
+
+    // Split file data into variable sized chunks by content-defined chunking.
+    #[allow(clippy::too_many_arguments)]
+    fn dump_variable_chunks<R: Read>(
+        &mut self,
+        ctx: &BuildContext,
+        chunker: &CdcChunker,
+        blob_mgr: &mut BlobManager,
+        blob_writer: &mut ArtifactWriter,
+        reader: &mut R,
+        data_buf: &mut [u8],
+        inode_hasher: &mut Option<RafsDigestHasher>,
+    ) -> Result<u64> {
+        let max_size = chunker.max_size() as usize;
+        if data_buf.len() < max_size {
+            bail!("data buffer is too small for chunk size 0x{:x}", max_size);
+        }
+
+        let file_size = self.inode.size();
+        let mut file_offset = 0u64;
+        let mut filled = 0usize;
+        let mut blob_size = 0u64;
+        while file_offset < file_size {
+            // Keep `max_size` bytes of data in the buffer unless reaching the end of file, so
+            // chunk boundaries only depend on file content.
+            let wanted = cmp::min(max_size as u64, file_size - file_offset) as usize;
+            if filled < wanted {
+                reader
+                    .read_exact(&mut data_buf[filled..wanted])
+                    .with_context(|| format!("failed to read node file {:?}", self.path()))?;
+                filled = wanted;
+            }
+
+            let size = chunker.cut_point(&data_buf[..filled]);
+            let chunk_data = &data_buf[..size];
+            let mut chunk = self.inode.create_chunk();
+            chunk.set_id(RafsDigest::from_buf(chunk_data, ctx.digester));
             if let Some(h) = inode_hasher.as_mut() {
                 h.digest_update(chunk.id().as_ref());
             }
-
-            let mut chunk = match self.deduplicate_chunk(
+            blob_size += self.add_file_chunk(
                 ctx,
                 blob_mgr,
+                blob_writer,
                 file_offset,
-                uncompressed_size,
                 chunk,
-            )? {
-                None => continue,
-                Some(c) => c,
-            };
+                None,
+                chunk_data,
+            )?;
 
-            let (blob_index, blob_ctx) = blob_mgr.get_or_create_current_blob(ctx)?;
-            let chunk_index = blob_ctx.alloc_chunk_index()?;
-            chunk.set_blob_index(blob_index);
-            chunk.set_index(chunk_index);
-            chunk.set_file_offset(file_offset);
-            self.dump_file_chunk(ctx, blob_ctx, blob_writer, chunk_data, &mut chunk)?;
-
-            let chunk = Arc::new(chunk);
-            blob_size += chunk.compressed_size() as u64;
-            blob_ctx.add_chunk_meta_info(&chunk, chunk_info)?;
-            blob_mgr
-                .layered_chunk_dict
-                .add_chunk(chunk.clone(), ctx.digester);
-            self.chunks.push(NodeChunk {
-                source: ChunkSource::Build,
-                inner: chunk,
-            });
+            data_buf.copy_within(size..filled, 0);
+            filled -= size;
+            file_offset += size as u64;
         }
 
-        // Finish inode digest calculation
-        if let Some(h) = inode_hasher {
-            self.inode.set_digest(h.digest_finalize());
-        }
+        // The chunk count is unknown until file data has been split.
+        self.inode.set_child_count(self.chunks.len() as u32);
+
+        Ok(blob_size)
+    }
+
+    // Deduplicate the chunk or dump it into the data blob, return size of data written.
+    #[allow(clippy::too_many_arguments)]
+    fn add_file_chunk(
+        &mut self,
+        ctx: &BuildContext,
+        blob_mgr: &mut BlobManager,
+        blob_writer: &mut ArtifactWriter,
+        file_offset: u64,
+        chunk: ChunkWrapper,
+        chunk_info: Option<BlobChunkInfoV2Ondisk>,
+        chunk_data: &[u8],
+    ) -> Result<u64> {
+        let uncompressed_size = chunk_data.len() as u32;
+        let mut chunk =
+            match self.deduplicate_chunk(ctx, blob_mgr, file_offset, uncompressed_size, chunk)? {
+                None => return Ok(0),
+                Some(c) => c,
+            };
+
+        let (blob_index, blob_ctx) = blob_mgr.get_or_create_current_blob(ctx)?;
+        let chunk_index = blob_ctx.alloc_chunk_index()?;
+        chunk.set_blob_index(blob_index);
+        chunk.set_index(chunk_index);
+        chunk.set_file_offset(file_offset);
+        self.dump_file_chunk(ctx, blob_ctx, blob_writer, chunk_data, &mut chunk)?;
+
+        let chunk = Arc::new(chunk);
+        let blob_size = chunk.compressed_size() as u64;
+        blob_ctx.add_chunk_meta_info(&chunk, chunk_info)?;
+        blob_mgr
+            .layered_chunk_dict
+            .add_chunk(chunk.clone(), ctx.digester);
+        self.chunks.push(NodeChunk {
+            source: ChunkSource::Build,
+            inner: chunk,
+        });
 
         Ok(blob_size)
     }
@@ -200,6 +200,13 @@ impl Bootstrap {
         super_block.set_compressor(ctx.compressor);
         super_block.set_digester(ctx.digester);
         super_block.set_chunk_size(ctx.chunk_size);
+        if let Some(chunker) = ctx.chunker.as_ref() {
+            super_block.set_variable_chunk_size(
+                chunker.min_size(),
+                chunker.avg_size(),
+                chunker.max_size(),
+            );
+        }
         if ctx.explicit_uidgid {
             super_block.set_explicit_uidgid();
         }
@@ -2,13 +2,18 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashMap;
 use std::fs;
-use std::fs::DirEntry;
+use std::fs::{DirEntry, File};
+use std::io::Read;
+use std::path::PathBuf;
 
 use anyhow::{anyhow, Context, Result};
+use nydus_utils::digest::{self, RafsDigest};
 use nydus_utils::{event_tracer, root_tracer, timing_tracer};
 
 use super::core::blob::Blob;
+use super::core::bootstrap::Bootstrap;
 use super::core::context::{
     ArtifactWriter, BlobManager, BootstrapContext, BootstrapManager, BuildContext, BuildOutput,
 };
@@ -111,6 +116,96 @@ impl DirectoryBuilder {
 
         Ok(tree)
     }
+
+    /// Reuse chunks from the parent bootstrap for files which are not changed.
+    ///
+    /// A regular file is treated as unchanged if it has the same size and modification time as
+    /// the file at the same path in the parent bootstrap, and its data matches digests of chunks
+    /// of the parent file. Changed files will be dumped as usual, and their chunks are still
+    /// deduplicated against chunks from the parent bootstrap.
+    fn reuse_parent_chunks(
+        &mut self,
+        ctx: &mut BuildContext,
+        bootstrap_mgr: &mut BootstrapManager,
+        blob_mgr: &mut BlobManager,
+        tree: &mut Tree,
+    ) -> Result<()> {
+        let parent = Bootstrap::new()?.load_parent_bootstrap(ctx, bootstrap_mgr, blob_mgr)?;
+        let mut files = HashMap::new();
+        parent.iterate(&mut |node: &Node| {
+            if node.is_reg() {
+                files.insert(node.target().clone(), node.clone());
+            }
+            true
+        })?;
+
+        let (mut reused, mut changed) = (0u64, 0u64);
+        Self::reuse_tree_chunks(tree, &files, ctx.digester, &mut reused, &mut changed)?;
+        info!(
+            "delta build: {} files reused from parent bootstrap, {} files changed",
+            reused, changed
+        );
+
+        Ok(())
+    }
+
+    fn reuse_tree_chunks(
+        tree: &mut Tree,
+        files: &HashMap<PathBuf, Node>,
+        digester: digest::Algorithm,
+        reused: &mut u64,
+        changed: &mut u64,
+    ) -> Result<()> {
+        let node = &mut tree.node;
+        if node.is_reg() {
+            match files.get(node.target()) {
+                Some(p)
+                    if p.inode.size() == node.inode.size()
+                        && p.inode.mtime() == node.inode.mtime()
+                        && p.inode.mtime_nsec() == node.inode.mtime_nsec()
+                        && Self::is_same_data(node, p, digester)? =>
+                {
+                    node.chunks = p.chunks.clone();
+                    node.inode.set_child_count(p.inode.child_count());
+                    if node.inode.is_v5() {
+                        node.inode.set_digest(*p.inode.digest());
+                    }
+                    *reused += 1;
+                }
+                _ => *changed += 1,
+            }
+        }
+        for child in tree.children.iter_mut() {
+            Self::reuse_tree_chunks(child, files, digester, reused, changed)?;
+        }
+
+        Ok(())
+    }
+
+    // Check whether data of the file matches digests of chunks of the parent file, so files
+    // with the same size and mtime but different content, such as files from reproducible builds,
+    // won't reuse stale chunks.
+    fn is_same_data(node: &Node, parent: &Node, digester: digest::Algorithm) -> Result<bool> {
+        let mut file =
+            File::open(node.path()).with_context(|| format!("failed to open {:?}", node.path()))?;
+        let mut buf = Vec::new();
+        let mut size = 0u64;
+        for chunk in parent.chunks.iter() {
+            let len = chunk.inner.uncompressed_size() as u64;
+            if size + len > node.inode.size() {
+                return Ok(false);
+            }
+            buf.resize(len as usize, 0);
+            file.read_exact(&mut buf)
+                .with_context(|| format!("failed to read {:?}", node.path()))?;
+            if &RafsDigest::from_buf(&buf, digester) != chunk.inner.id() {
+                return Ok(false);
+            }
+            size += len;
+        }
+
+        Ok(size == node.inode.size())
+    }
 }
 
 impl Builder for DirectoryBuilder {
@@ -122,6 +217,13 @@ impl Builder for DirectoryBuilder {
     ) -> Result<BuildOutput> {
         let mut bootstrap_ctx = bootstrap_mgr.create_ctx()?;
         let layer_idx = u16::from(bootstrap_ctx.layered);
+        if ctx.delta_build {
+            if !bootstrap_ctx.layered {
+                return Err(anyhow!("delta build requires a parent bootstrap"));
+            }
+            // The source directory contains the full filesystem instead of an overlay layer.
+            bootstrap_ctx.layered = false;
+        }
         let mut blob_writer = if let Some(blob_stor) = ctx.blob_storage.clone() {
             ArtifactWriter::new(blob_stor)?
         } else {
@@ -131,10 +233,16 @@ impl Builder for DirectoryBuilder {
         };
 
         // Scan source directory to build upper layer tree.
-        let tree = timing_tracer!(
+        let mut tree = timing_tracer!(
             { self.build_tree(ctx, &mut bootstrap_ctx, layer_idx) },
             "build_tree"
         )?;
+        if ctx.delta_build {
+            timing_tracer!(
+                { self.reuse_parent_chunks(ctx, bootstrap_mgr, blob_mgr, &mut tree) },
+                "reuse_parent_chunks"
+            )?;
+        }
 
         // Build bootstrap
         let mut bootstrap = timing_tracer!(
@@ -189,3 +297,123 @@ impl Builder for DirectoryBuilder {
         BuildOutput::new(blob_mgr, &bootstrap_mgr.bootstrap_storage)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{ChunkSource, NodeChunk, Overlay};
+    use crate::metadata::chunk::ChunkWrapper;
+    use crate::metadata::{RafsVersion, RAFS_DEFAULT_CHUNK_SIZE};
+    use std::path::Path;
+    use std::sync::Arc;
+    use vmm_sys_util::tempdir::TempDir;
+
+    fn new_node(root: &Path, name: &str, data: &[u8]) -> Node {
+        let path = root.join(name);
+        fs::write(&path, data).unwrap();
+        Node::from_fs_object(
+            RafsVersion::V6,
+            root.to_path_buf(),
+            path,
+            Overlay::UpperAddition,
+            RAFS_DEFAULT_CHUNK_SIZE as u32,
+            false,
+            false,
+        )
+        .unwrap()
+    }
+
+    // Create a node in the parent bootstrap for `node`, with chunks of `data`.
+    fn parent_node(node: &Node, data: &[u8]) -> Node {
+        let mut parent = node.clone();
+        parent.chunks = data
+            .chunks(0x1000)
+            .enumerate()
+            .map(|(idx, buf)| {
+                let mut chunk = ChunkWrapper::new(RafsVersion::V6);
+                chunk.set_index(idx as u32);
+                chunk.set_id(RafsDigest::from_buf(buf, digest::Algorithm::Blake3));
+                chunk.set_uncompressed_size(buf.len() as u32);
+                NodeChunk {
+                    source: ChunkSource::Parent,
+                    inner: Arc::new(chunk),
+                }
+            })
+            .collect();
+        parent
+    }
+
+    #[test]
+    fn test_reuse_tree_chunks() {
+        let tmpdir = TempDir::new().unwrap();
+        let root = tmpdir.as_path();
+        let data = vec![0x5au8; 0x2800];
+        let mut changed_data = data.clone();
+        changed_data[0x2000] = 0;
+
+        let mut tree = Tree::new(
+            Node::from_fs_object(
+                RafsVersion::V6,
+                root.to_path_buf(),
+                root.to_path_buf(),
+                Overlay::UpperAddition,
+                RAFS_DEFAULT_CHUNK_SIZE as u32,
+                false,
+                false,
+            )
+            .unwrap(),
+        );
+        let unchanged = new_node(root, "unchanged", &data);
+        // Same size and mtime as the parent file, but different content.
+        let changed = new_node(root, "changed", &changed_data);
+        let added = new_node(root, "added", &data);
+
+        let mut files = HashMap::new();
+        files.insert(unchanged.target().clone(), parent_node(&unchanged, &data));
+        files.insert(changed.target().clone(), parent_node(&changed, &data));
+        let removed = new_node(root, "removed", &data);
+        files.insert(removed.target().clone(), parent_node(&removed, &data));
+        fs::remove_file(root.join("removed")).unwrap();
+
+        tree.children.push(Tree::new(unchanged));
+        tree.children.push(Tree::new(changed));
+        tree.children.push(Tree::new(added));
+
+        let (mut reused, mut changed) = (0u64, 0u64);
+        DirectoryBuilder::reuse_tree_chunks(
+            &mut tree,
+            &files,
+            digest::Algorithm::Blake3,
+            &mut reused,
+            &mut changed,
+        )
+        .unwrap();
+        assert_eq!(reused, 1);
+        assert_eq!(changed, 2);
+        assert_eq!(tree.children[0].node.chunks.len(), 3);
+        assert!(tree.children[1].node.chunks.is_empty());
+        assert!(tree.children[2].node.chunks.is_empty());
+    }
+
+    #[test]
+    fn test_is_same_data() {
+        let tmpdir = TempDir::new().unwrap();
+        let root = tmpdir.as_path();
+        let data = vec![0x5au8; 0x2800];
+        let node = new_node(root, "file", &data);
+        let algo = digest::Algorithm::Blake3;
+
+        assert!(DirectoryBuilder::is_same_data(&node, &parent_node(&node, &data), algo).unwrap());
+        // Parent chunks don't cover the whole file.
+        let parent = parent_node(&node, &data[..0x2000]);
+        assert!(!DirectoryBuilder::is_same_data(&node, &parent, algo).unwrap());
+        // Parent chunks are beyond end of the file.
+        let parent = parent_node(&node, &[0x5au8; 0x3000]);
+        assert!(!DirectoryBuilder::is_same_data(&node, &parent, algo).unwrap());
+        // Chunks are generated with another digest algorithm.
+        let parent = parent_node(&node, &data);
+        assert!(
+            !DirectoryBuilder::is_same_data(&node, &parent, digest::Algorithm::Sha256).unwrap()
+        );
+    }
+}
@@ -11,6 +11,7 @@ use sha2::Digest;
 
 pub use self::compact::BlobCompactor;
 pub use self::core::bootstrap::Bootstrap;
+pub use self::core::cdc::CdcChunker;
 pub use self::core::chunk_dict::{parse_chunk_dict_arg, ChunkDict, HashChunkDict};
 pub use self::core::context::{
     ArtifactStorage, ArtifactWriter, BlobContext, BlobManager, BootstrapContext, BootstrapManager,
@@ -117,7 +117,8 @@ impl<'a> TarballTreeBuilder<'a> {
                     && buf[2] == 0x08
                 {
                     buf_reader.seek_relative(-3).unwrap();
-                    let generator = ZranContextGenerator::from_buf_reader(buf_reader)?;
+                    let mut generator = ZranContextGenerator::from_buf_reader(buf_reader)?;
+                    generator.set_checkpoint_span(self.ctx.blob_zran_span);
                     let reader = generator.reader();
                     self.ctx.blob_zran_generator = Some(Mutex::new(generator));
                     self.ctx.blob_features.insert(BlobFeatures::ZRAN);
@@ -17,6 +17,7 @@
 use std::any::Any;
 use std::cmp;
 use std::ffi::{CStr, OsStr, OsString};
+use std::fs::File;
 use std::io::Result;
 use std::ops::Deref;
 use std::os::unix::ffi::OsStrExt;
@@ -30,7 +31,7 @@ use fuse_backend_rs::api::filesystem::*;
 use fuse_backend_rs::api::BackendFileSystem;
 use nix::unistd::{getegid, geteuid};
 
-use nydus_api::ConfigV2;
+use nydus_api::{BackendConfigV2, ConfigV2, IdMapping};
 use nydus_storage::device::{BlobDevice, BlobIoVec, BlobPrefetchRequest};
 use nydus_storage::{RAFS_DEFAULT_CHUNK_SIZE, RAFS_MAX_CHUNK_SIZE};
 use nydus_utils::{
@@ -50,6 +51,8 @@ pub type Handle = u64;
 pub const RAFS_DEFAULT_ATTR_TIMEOUT: u64 = 1 << 32;
 /// Rafs default entry timeout value.
 pub const RAFS_DEFAULT_ENTRY_TIMEOUT: u64 = RAFS_DEFAULT_ATTR_TIMEOUT;
+/// Id presented for uid/gid not covered by the uid/gid mapping table.
+pub const RAFS_OVERFLOW_ID: u32 = 65534;
 
 /// Struct to glue fuse, storage backend and filesystem metadata together.
 ///
@@ -74,6 +77,8 @@ pub struct Rafs {
     i_uid: u32,
     i_gid: u32,
     i_time: u64,
+    uid_map: Vec<IdMapping>,
+    gid_map: Vec<IdMapping>,
 }
 
 impl Rafs {
@@ -112,6 +117,8 @@ impl Rafs {
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
+            uid_map: rafs_cfg.uid_map.clone(),
+            gid_map: rafs_cfg.gid_map.clone(),
         };
 
         // Rafs v6 does must store chunk info into local file cache. So blob cache is required
@@ -164,6 +171,18 @@ impl Rafs {
         Ok(())
     }
 
+    /// Update credentials, proxy and mirrors of storage backends for blobs.
+    ///
+    /// Return the updated configuration of the filesystem instance.
+    pub fn update_backend_config(
+        &self,
+        backend_cfg: &BackendConfigV2,
+    ) -> RafsResult<Arc<ConfigV2>> {
+        self.device
+            .update_backend_config(backend_cfg)
+            .map_err(RafsError::SwapBackend)
+    }
+
     /// Import an rafs bootstrap to initialize the filesystem instance.
     pub fn import(
         &mut self,
@@ -211,6 +230,25 @@ impl Rafs {
         &self.sb.meta
     }
 
+    /// Get the cache file to pass through reads of the regular file `ino` to.
+    ///
+    /// Kernel FUSE passthrough reads the backing file at the same offsets as the FUSE file, so a
+    /// cache file is returned only if all data of the file is ready and the cache file has the
+    /// same content as the file. Otherwise `None` is returned and the file should be accessed by
+    /// `read()`.
+    pub fn get_passthrough_file(&self, ino: u64) -> Result<Option<Arc<File>>> {
+        let inode = self.sb.get_inode(ino, false)?;
+        if !inode.is_reg() || inode.size() == 0 || self.digest_validate {
+            return Ok(None);
+        }
+
+        let descs = inode.alloc_bio_vecs(&self.device, 0, inode.size() as usize, false)?;
+        match self.device.get_backing_file(&descs) {
+            Some((file, 0)) if file.metadata()?.len() == inode.size() => Ok(Some(file)),
+            _ => Ok(None),
+        }
+    }
+
     fn xattr_supported(&self) -> bool {
         self.xattr_enabled || self.sb.meta.has_xattr()
     }
@@ -277,6 +315,9 @@ impl Rafs {
         if !self.sb.meta.explicit_uidgid() {
             attr.uid = self.i_uid;
             attr.gid = self.i_gid;
+        } else {
+            attr.uid = map_id(&self.uid_map, attr.uid);
+            attr.gid = map_id(&self.gid_map, attr.gid);
         }
 
         // Older rafs image or the root inode doesn't include mtime, in such cases
@@ -305,6 +346,9 @@ impl Rafs {
         if !self.sb.meta.explicit_uidgid() {
             entry.attr.st_uid = self.i_uid;
             entry.attr.st_gid = self.i_gid;
+        } else {
+            entry.attr.st_uid = map_id(&self.uid_map, entry.attr.st_uid);
+            entry.attr.st_gid = map_id(&self.gid_map, entry.attr.st_gid);
         }
 
         // Older rafs image doesn't include mtime, in such case we use runtime timestamp.
@@ -326,6 +370,18 @@ impl Rafs {
     }
 }
 
+// Map uid/gid stored in the image by the mapping table, ids not covered by the table are mapped
+// to the overflow id.
+fn map_id(mappings: &[IdMapping], id: u32) -> u32 {
+    if mappings.is_empty() {
+        return id;
+    }
+    mappings
+        .iter()
+        .find_map(|m| m.map(id))
+        .unwrap_or(RAFS_OVERFLOW_ID)
+}
+
 impl Rafs {
     fn prefetch(&self, reader: RafsIoReader, prefetch_files: Option<Vec<PathBuf>>) {
         let sb = self.sb.clone();
@@ -830,6 +886,7 @@ impl FileSystem for Rafs {
 
     fn access(&self, ctx: &Context, ino: u64, mask: u32) -> Result<()> {
         let mut rec = FopRecorder::settle(Access, ino, &self.ios);
+        // uid/gid of the inode have been mapped by the uid/gid mapping table.
         let st = self.get_inode_attr(ino)?;
         let mode = mask as i32 & (libc::R_OK | libc::W_OK | libc::X_OK);
 
@@ -1001,4 +1058,26 @@ pub(crate) mod tests {
             }
         }
     }
+
+    #[test]
+    fn it_should_map_id() {
+        assert_eq!(map_id(&[], 1000), 1000);
+
+        let mappings = [
+            IdMapping {
+                container_id: 0,
+                host_id: 100000,
+                size: 1000,
+            },
+            IdMapping {
+                container_id: 1000,
+                host_id: 1000,
+                size: 1,
+            },
+        ];
+        assert_eq!(map_id(&mappings, 0), 100000);
+        assert_eq!(map_id(&mappings, 999), 100999);
+        assert_eq!(map_id(&mappings, 1000), 1000);
+        assert_eq!(map_id(&mappings, 1001), RAFS_OVERFLOW_ID);
+    }
 }
@@ -379,7 +379,10 @@ impl RafsInode for CachedInodeV5 {
         }
         if self.is_reg() {
             let chunks = (self.i_size + chunk_size - 1) / chunk_size;
-            if !self.has_hole() && chunks != self.i_data.len() as u64 {
+            if !self.has_hole()
+                && !self.has_variable_chunk_size()
+                && chunks != self.i_data.len() as u64
+            {
                 return Err(einval!("invalid chunk count"));
             }
             let blocks = (self.i_size + 511) / 512;
@@ -666,6 +669,10 @@ impl RafsV5InodeOps for CachedInodeV5 {
     fn has_hole(&self) -> bool {
         self.i_flags.contains(RafsV5InodeFlags::HAS_HOLE)
     }
+
+    fn has_variable_chunk_size(&self) -> bool {
+        self.i_meta.has_variable_chunk_size()
+    }
 }
 
 /// Cached information about an Rafs Data Chunk.
@@ -453,7 +453,10 @@ impl RafsInode for OndiskInodeWrapper {
                 return Err(std::io::Error::from_raw_os_error(libc::EOPNOTSUPP));
             }
             let chunks = (inode.i_size + chunk_size - 1) / chunk_size;
-            if !inode.has_hole() && chunks != inode.i_child_count as u64 {
+            if !inode.has_hole()
+                && !state.meta.has_variable_chunk_size()
+                && chunks != inode.i_child_count as u64
+            {
                 return Err(einval!(format!(
                     "invalid chunk count, ino {}, expected {}, actual {}",
                     inode.i_ino, chunks, inode.i_child_count,
@@ -783,6 +786,10 @@ impl RafsV5InodeOps for OndiskInodeWrapper {
     }
 
     impl_inode_wrapper!(has_hole, bool);
+
+    fn has_variable_chunk_size(&self) -> bool {
+        self.mapping.state().meta.has_variable_chunk_size()
+    }
 }
 
 pub struct DirectChunkInfoV5 {
@@ -68,7 +68,7 @@ pub(crate) const RAFSV5_SUPERBLOCK_SIZE: usize = 8192;
 pub(crate) const RAFSV5_EXT_BLOB_ENTRY_SIZE: usize = 64;
 
 const RAFSV5_SUPER_MAGIC: u32 = 0x5241_4653;
-const RAFSV5_SUPERBLOCK_RESERVED_SIZE: usize = RAFSV5_SUPERBLOCK_SIZE - 80;
+const RAFSV5_SUPERBLOCK_RESERVED_SIZE: usize = RAFSV5_SUPERBLOCK_SIZE - 88;
 const RAFSV5_EXT_BLOB_RESERVED_SIZE: usize = RAFSV5_EXT_BLOB_ENTRY_SIZE - 24;
 
 /// Trait to get information about a Rafs v5 inode.
@@ -81,6 +81,9 @@ pub(crate) trait RafsV5InodeOps {
 
     /// Check whether the inode has hole chunk.
     fn has_hole(&self) -> bool;
+
+    /// Check whether chunks of the inode have variable sizes.
+    fn has_variable_chunk_size(&self) -> bool;
 }
 
 pub(crate) trait RafsV5InodeChunkOps {
@@ -120,7 +123,11 @@ pub struct RafsV5SuperBlock {
     s_blob_table_size: u32,
     s_extended_blob_table_entries: u32, // 72 bytes
     /// Extended Blob Table
-    s_extended_blob_table_offset: u64, // 80 bytes --- reduce me from `RAFS_SUPERBLOCK_RESERVED_SIZE`
+    s_extended_blob_table_offset: u64, // 80 bytes
+    /// Minimum chunk size for content-defined chunking, `s_block_size` is the maximum chunk size.
+    s_cdc_min_size: u32,
+    /// Average chunk size for content-defined chunking.
+    s_cdc_avg_size: u32, // 88 bytes --- reduce me from `RAFS_SUPERBLOCK_RESERVED_SIZE`
     /// Unused area
     s_reserved: [u8; RAFSV5_SUPERBLOCK_RESERVED_SIZE],
 }
@@ -157,6 +164,12 @@ impl RafsV5SuperBlock {
             return Err(einval!("invalid block size"));
         } else if RafsSuperFlags::from_bits(self.flags()).is_none() {
             return Err(einval!("invalid super block flags"));
+        } else if self.has_variable_chunk_size()
+            && (self.cdc_min_size() == 0
+                || self.cdc_min_size() > self.cdc_avg_size()
+                || self.cdc_avg_size() > self.block_size())
+        {
+            return Err(einval!("invalid content-defined chunking sizes"));
         }
 
         let meta_range = MetaRange::new(
@@ -249,6 +262,19 @@ impl RafsV5SuperBlock {
         self.s_flags |= RafsSuperFlags::HAS_XATTR.bits();
     }
 
+    /// Check whether chunks of regular files have variable sizes.
+    pub fn has_variable_chunk_size(&self) -> bool {
+        self.flags() & RafsSuperFlags::VARIABLE_CHUNK_SIZE.bits() != 0
+    }
+
+    /// Enable content-defined chunking with the minimum, average and maximum chunk sizes.
+    pub fn set_variable_chunk_size(&mut self, min_size: u32, avg_size: u32, max_size: u32) {
+        self.set_chunk_size(max_size);
+        self.set_cdc_min_size(min_size);
+        self.set_cdc_avg_size(avg_size);
+        self.s_flags |= RafsSuperFlags::VARIABLE_CHUNK_SIZE.bits();
+    }
+
     impl_pub_getter_setter!(magic, set_magic, s_magic, u32);
     impl_pub_getter_setter!(version, set_version, s_fs_version, u32);
     impl_pub_getter_setter!(sb_size, set_sb_size, s_sb_size, u32);
@@ -298,6 +324,8 @@ impl RafsV5SuperBlock {
         s_extended_blob_table_entries,
         u32
     );
+    impl_pub_getter_setter!(cdc_min_size, set_cdc_min_size, s_cdc_min_size, u32);
+    impl_pub_getter_setter!(cdc_avg_size, set_cdc_avg_size, s_cdc_avg_size, u32);
 
     /// Load a super block from a `RafsIoReader` object.
     pub fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
@@ -340,6 +368,8 @@ impl Default for RafsV5SuperBlock {
             s_blob_table_offset: u64::to_le(0),
             s_extended_blob_table_offset: u64::to_le(0),
             s_extended_blob_table_entries: u32::to_le(0),
+            s_cdc_min_size: u32::to_le(0),
+            s_cdc_avg_size: u32::to_le(0),
             s_reserved: [0u8; RAFSV5_SUPERBLOCK_RESERVED_SIZE],
         }
     }
@@ -1221,13 +1251,17 @@ pub(crate) fn rafsv5_alloc_bio_vecs<I: RafsInode + RafsV5InodeChunkOps + RafsV5I
     let end = offset
         .checked_add(size as u64)
         .ok_or_else(|| einval!("invalid read size"))?;
-    let (index_start, index_end) = calculate_bio_chunk_index(
-        offset,
-        end,
-        inode.get_chunk_size() as u64,
-        inode.get_child_count(),
-        inode.has_hole(),
-    );
+    let (index_start, index_end) = if inode.has_variable_chunk_size() {
+        calculate_variable_bio_chunk_index(inode, offset, end, inode.get_child_count())?
+    } else {
+        calculate_bio_chunk_index(
+            offset,
+            end,
+            inode.get_chunk_size() as u64,
+            inode.get_child_count(),
+            inode.has_hole(),
+        )
+    };
     trace!(
         "alloc bio desc offset {} size {} i_size {} index_start {} index_end {} i_child_count {}",
         offset,
@@ -1358,6 +1392,50 @@ fn calculate_bio_chunk_index(
     (index_start, index_end)
 }
 
+/// Calculate bio chunk indices that overlaps with the provided IO range for files with variable
+/// sized chunks.
+///
+/// # Parameters
+/// - inode: the inode owning the chunks, chunks are sorted by file offset.
+/// - offset: IO offset to the file start, inclusive.
+/// - end: IO end to the file start, exclusive.
+/// - chunk_cnt: maximum number of chunks
+fn calculate_variable_bio_chunk_index<I: RafsV5InodeChunkOps>(
+    inode: &I,
+    offset: u64,
+    end: u64,
+    chunk_cnt: u32,
+) -> Result<(u32, u32)> {
+    debug_assert!(offset < end);
+
+    // Find the first chunk ending after `offset`, then the first chunk starting at or after `end`.
+    let index_start = partition_chunks(inode, 0, chunk_cnt, |c| {
+        c.file_offset() + c.uncompressed_size() as u64 <= offset
+    })?;
+    let index_end = partition_chunks(inode, index_start, chunk_cnt, |c| c.file_offset() < end)?;
+
+    Ok((index_start, index_end))
+}
+
+// Binary search for the first chunk in range `low..high` which doesn't match `pred`.
+fn partition_chunks<I: RafsV5InodeChunkOps>(
+    inode: &I,
+    mut low: u32,
+    mut high: u32,
+    pred: impl Fn(&dyn BlobV5ChunkInfo) -> bool,
+) -> Result<u32> {
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if pred(inode.get_chunk_info_v5(mid)?.as_ref()) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    Ok(low)
+}
+
 pub(crate) fn rafsv5_align(size: usize) -> usize {
     if size & (RAFSV5_ALIGNMENT - 1) == 0 {
         size
@@ -1732,6 +1810,53 @@ pub mod tests {
         }
     }
 
+    struct MockVariableChunks(Vec<MockChunkInfo>);
+
+    impl RafsV5InodeChunkOps for MockVariableChunks {
+        fn get_chunk_info_v5(&self, idx: u32) -> Result<Arc<dyn BlobV5ChunkInfo>> {
+            Ok(Arc::new(self.0[idx as usize]))
+        }
+    }
+
+    #[test]
+    fn test_calculate_variable_bio_chunk_index() {
+        // Chunks: [0, 100), [100, 1100), [1100, 1200), [1200, 5200)
+        let mut chunks = Vec::new();
+        let mut file_offset = 0;
+        for size in [100u32, 1000, 100, 4000] {
+            let mut chunk = MockChunkInfo::new();
+            chunk.file_offset = file_offset;
+            chunk.uncompress_size = size;
+            file_offset += size as u64;
+            chunks.push(chunk);
+        }
+        let inode = MockVariableChunks(chunks);
+
+        let io_range: Vec<(u64, u64, u32, u32)> = vec![
+            (0, 1, 0, 1),
+            (0, 100, 0, 1),
+            (0, 101, 0, 2),
+            (99, 2, 0, 2),
+            (100, 1000, 1, 2),
+            (100, 1001, 1, 3),
+            (1099, 102, 1, 4),
+            (1100, 100, 2, 3),
+            (1200, 1, 3, 4),
+            (5199, 1, 3, 4),
+            (5199, 100, 3, 4),
+            (5200, 100, 4, 4),
+            (0, 0x10000, 0, 4),
+        ];
+
+        for (io_start, io_size, expected_start, expected_end) in io_range.iter() {
+            let (start, end) =
+                calculate_variable_bio_chunk_index(&inode, *io_start, *io_start + *io_size, 4)
+                    .unwrap();
+            assert_eq!(start, *expected_start);
+            assert_eq!(end, *expected_end);
+        }
+    }
+
     #[test]
     fn test_rafsv5_align() {
         assert_eq!(rafsv5_align(0), 0);
@@ -29,6 +29,10 @@ impl RafsSuper {
         self.meta.flags = RafsSuperFlags::from_bits(sb.flags())
             .ok_or_else(|| einval!(format!("invalid super flags {:x}", sb.flags())))?;
         info!("RAFS v5 super block features: {}", self.meta.flags);
+        if sb.has_variable_chunk_size() {
+            self.meta.cdc_min_size = sb.cdc_min_size();
+            self.meta.cdc_avg_size = sb.cdc_avg_size();
+        }
 
         self.meta.inodes_count = sb.inodes_count();
         self.meta.inode_table_entries = sb.inode_table_entries();
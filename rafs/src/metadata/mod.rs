@@ -286,6 +286,8 @@ bitflags! {
         const COMPRESSION_ZSTD = 0x0000_0080;
         /// Chunk digests are inlined in RAFS v6 data blob.
         const INLINED_CHUNK_DIGEST = 0x0000_0100;
+        /// Chunks of regular files have variable sizes, generated by content-defined chunking.
+        const VARIABLE_CHUNK_SIZE = 0x0000_0200;
 
         // Reserved for future compatible changes.
         const PRESERVED_COMPAT_7 = 0x0100_0000;
@@ -420,6 +422,10 @@ pub struct RafsSuperMeta {
     pub root_inode: Inode,
     /// Chunk size.
     pub chunk_size: u32,
+    /// V5: minimum chunk size for content-defined chunking.
+    pub cdc_min_size: u32,
+    /// V5: average chunk size for content-defined chunking.
+    pub cdc_avg_size: u32,
     /// Number of inodes in the filesystem.
     pub inodes_count: u64,
     /// V5: superblock flags for Rafs v5.
@@ -486,6 +492,13 @@ impl RafsSuperMeta {
         self.flags.contains(RafsSuperFlags::HAS_XATTR)
     }
 
+    /// Check whether chunks of regular files have variable sizes.
+    ///
+    /// If so, `chunk_size` is the maximum chunk size instead of the size of each chunk.
+    pub fn has_variable_chunk_size(&self) -> bool {
+        self.is_v5() && self.flags.contains(RafsSuperFlags::VARIABLE_CHUNK_SIZE)
+    }
+
     /// Check whether data blobs have inlined chunk digest array.
     pub fn has_inlined_chunk_digest(&self) -> bool {
         self.is_v6() && self.flags.contains(RafsSuperFlags::INLINED_CHUNK_DIGEST)
@@ -530,6 +543,8 @@ impl Default for RafsSuperMeta {
             inodes_count: 0,
             root_inode: 0,
             chunk_size: 0,
+            cdc_min_size: 0,
+            cdc_avg_size: 0,
             flags: RafsSuperFlags::empty(),
             inode_table_entries: 0,
             inode_table_offset: 0,
@@ -286,4 +286,8 @@ impl RafsV5InodeOps for MockInode {
     fn has_hole(&self) -> bool {
         false
     }
+
+    fn has_variable_chunk_size(&self) -> bool {
+        false
+    }
 }
@@ -13,15 +13,15 @@ use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
 
 use nydus_api::{
-    BlobCacheEntry, BlobCacheList, BlobCacheObjectId, ConfigV2, BLOB_CACHE_TYPE_DATA_BLOB,
-    BLOB_CACHE_TYPE_META_BLOB,
+    BackendConfigV2, BlobCacheEntry, BlobCacheList, BlobCacheObjectId, ConfigV2,
+    BLOB_CACHE_TYPE_DATA_BLOB, BLOB_CACHE_TYPE_META_BLOB,
 };
 use nydus_rafs::metadata::layout::v6::{EROFS_BLOCK_BITS_12, EROFS_BLOCK_SIZE_4096};
 use nydus_rafs::metadata::{RafsBlobExtraInfo, RafsSuper};
 use nydus_storage::cache::BlobCache;
 use nydus_storage::device::BlobInfo;
 use nydus_storage::factory::BLOB_FACTORY;
-use tokio_uring::buf::IoBufMut;
+use tokio_uring::buf::{IoBuf, IoBufMut};
 use tokio_uring::fs::File;
 
 const ID_SPLITTER: &str = "/";
@@ -40,7 +40,7 @@ pub struct MetaBlobConfig {
     blob_id: String,
     scoped_blob_id: String,
     path: PathBuf,
-    config: Arc<ConfigV2>,
+    config: Mutex<Arc<ConfigV2>>,
     blobs: Mutex<Vec<Arc<DataBlobConfig>>>,
     blob_extra_infos: HashMap<String, RafsBlobExtraInfo>,
 }
@@ -57,8 +57,8 @@ impl MetaBlobConfig {
     }
 
     /// Get the ['ConfigV2'] object associated with the cached data blob.
-    pub fn config_v2(&self) -> &Arc<ConfigV2> {
-        &self.config
+    pub fn config_v2(&self) -> Arc<ConfigV2> {
+        self.config.lock().unwrap().clone()
     }
 
     pub fn get_blobs(&self) -> Vec<Arc<DataBlobConfig>> {
@@ -79,7 +79,7 @@ impl MetaBlobConfig {
 pub struct DataBlobConfig {
     scoped_blob_id: String,
     blob_info: Arc<BlobInfo>,
-    config: Arc<ConfigV2>,
+    config: Mutex<Arc<ConfigV2>>,
     ref_count: AtomicU32,
 }
 
@@ -90,8 +90,8 @@ impl DataBlobConfig {
     }
 
     /// Get the ['ConfigV2'] object associated with the cached data blob.
-    pub fn config_v2(&self) -> &Arc<ConfigV2> {
-        &self.config
+    pub fn config_v2(&self) -> Arc<ConfigV2> {
+        self.config.lock().unwrap().clone()
     }
 }
 
@@ -106,7 +106,7 @@ pub enum BlobConfig {
 
 impl BlobConfig {
     /// Get the ['ConfigV2'] object associated with the cached data blob.
-    pub fn config_v2(&self) -> &Arc<ConfigV2> {
+    pub fn config_v2(&self) -> Arc<ConfigV2> {
         match self {
             BlobConfig::MetaBlob(v) => v.config_v2(),
             BlobConfig::DataBlob(v) => v.config_v2(),
@@ -119,7 +119,7 @@ impl BlobConfig {
         BlobConfig::DataBlob(Arc::new(DataBlobConfig {
             blob_info,
             scoped_blob_id,
-            config,
+            config: Mutex::new(config),
             ref_count: AtomicU32::new(1),
         }))
     }
@@ -137,12 +137,19 @@ impl BlobConfig {
             blob_id,
             scoped_blob_id,
             path,
-            config,
+            config: Mutex::new(config),
             blobs: Mutex::new(Vec::new()),
             blob_extra_infos,
         }))
     }
 
+    fn set_config_v2(&self, config: Arc<ConfigV2>) {
+        match self {
+            BlobConfig::MetaBlob(o) => *o.config.lock().unwrap() = config,
+            BlobConfig::DataBlob(o) => *o.config.lock().unwrap() = config,
+        }
+    }
+
     fn key(&self) -> &str {
         match self {
             BlobConfig::MetaBlob(o) => &o.scoped_blob_id,
@@ -235,6 +242,14 @@ impl BlobCacheState {
     fn get(&self, key: &str) -> Option<BlobConfig> {
         self.id_to_config_map.get(key).cloned()
     }
+
+    fn update_config(&self, old: &ConfigV2, new: &Arc<ConfigV2>) {
+        for entry in self.id_to_config_map.values() {
+            if entry.config_v2().as_ref() == old {
+                entry.set_config_v2(new.clone());
+            }
+        }
+    }
 }
 
 /// Structure to manage and cache RAFS meta/data blob objects.
@@ -300,6 +315,29 @@ impl BlobCacheMgr {
         self.get_state().remove(param)
     }
 
+    /// Update credentials, proxy and mirrors of the storage backend for a cached blob object.
+    ///
+    /// The storage backend is shared by all blob objects created with the same configuration, so
+    /// the configuration of all those blob objects will be updated too.
+    pub fn update_backend_config(
+        &self,
+        param: &BlobCacheObjectId,
+        backend_cfg: &BackendConfigV2,
+    ) -> Result<()> {
+        backend_cfg
+            .validate()
+            .map_err(|e| einval!(format!("blob_cache: {}", e)))?;
+        let key = generate_blob_key(&param.domain_id, &param.blob_id);
+        let config = self
+            .get_config(&key)
+            .ok_or_else(|| enoent!(format!("blob_cache: no cached blob object for {}", key)))?;
+        let old = config.config_v2();
+        let new = BLOB_FACTORY.update_backend_config(&old, backend_cfg)?;
+        self.get_state().update_config(&old, &new);
+
+        Ok(())
+    }
+
     /// Get configuration information of the cached blob with specified `key`.
     pub fn get_config(&self, key: &str) -> Option<BlobConfig> {
         self.get_state().get(key)
@@ -408,7 +446,7 @@ impl BlobCacheMgr {
                 domain_id
             );
             let data_blob =
-                BlobConfig::new_data_blob(domain_id.to_string(), bi, meta_obj.config.clone());
+                BlobConfig::new_data_blob(domain_id.to_string(), bi, meta_obj.config_v2());
             let data_blob_config = match &data_blob {
                 BlobConfig::DataBlob(entry) => entry.clone(),
                 _ => panic!("blob_cache: internal error"),
@@ -480,7 +518,7 @@ impl MetaBlob {
 
     /// Read data from the cached metadata blob in asynchronous mode.
     pub async fn async_read<T: IoBufMut>(&self, pos: u64, buf: T) -> (Result<usize>, T) {
-        self.file.read_at(buf, pos).await
+        read_exact_at(&self.file, pos, buf).await
     }
 }
 
@@ -496,7 +534,7 @@ impl DataBlob {
     pub fn new(config: &Arc<DataBlobConfig>) -> Result<Self> {
         let blob_id = config.blob_info().blob_id();
         let blob = BLOB_FACTORY
-            .new_blob_cache(config.config_v2(), &config.blob_info)
+            .new_blob_cache(&config.config_v2(), &config.blob_info)
             .map_err(|e| {
                 warn!(
                     "blob_cache: failed to create cache object for blob {}",
@@ -527,7 +565,7 @@ impl DataBlob {
     pub async fn async_read<T: IoBufMut>(&self, pos: u64, buf: T) -> (Result<usize>, T) {
         match self.blob.get_blob_object() {
             Some(obj) => match obj.fetch_range_uncompressed(pos, buf.bytes_total() as u64) {
-                Ok(_) => self.file.read_at(buf, pos).await,
+                Ok(_) => read_exact_at(&self.file, pos, buf).await,
                 Err(e) => (Err(e), buf),
             },
             None => (
@@ -541,6 +579,32 @@ impl DataBlob {
     }
 }
 
+// Fill `buf` with data from `file` at `pos`, retrying on short reads.
+//
+// A cache file may end in the middle of the last block, so data beyond end of file is zeroed.
+async fn read_exact_at<T: IoBufMut>(file: &File, pos: u64, mut buf: T) -> (Result<usize>, T) {
+    let size = buf.bytes_total();
+    let mut count = 0;
+    while count < size {
+        let (res, slice) = file.read_at(buf.slice(count..), pos + count as u64).await;
+        buf = slice.into_inner();
+        match res {
+            Ok(0) => {
+                // Safe because the range is within the buffer.
+                unsafe {
+                    std::ptr::write_bytes(buf.stable_mut_ptr().add(count), 0, size - count);
+                    buf.set_init(size);
+                }
+                count = size;
+            }
+            Ok(v) => count += v,
+            Err(e) => return (Err(e), buf),
+        }
+    }
+
+    (Ok(size), buf)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -583,7 +647,7 @@ mod tests {
         let cfg = create_factory_config();
         let content = cfg.replace("/tmp/nydus", tmpdir.as_path().to_str().unwrap());
         let mut entry: BlobCacheEntry = serde_json::from_str(&content).unwrap();
-        assert!(entry.prepare_configuration_info());
+        entry.prepare_configuration_info().unwrap();
         let blob_config = entry.blob_config.as_ref().unwrap();
 
         assert_eq!(&entry.blob_type, "bootstrap");
@@ -609,7 +673,7 @@ mod tests {
             blob_id: "123456789-123".to_string(),
             scoped_blob_id: "domain1".to_string(),
             path: path.clone(),
-            config,
+            config: Mutex::new(config),
             blobs: Mutex::new(Vec::new()),
             blob_extra_infos: HashMap::new(),
         };
@@ -672,7 +736,7 @@ mod tests {
             ]
          }"#;
         let mut list: BlobCacheList = serde_json::from_str(config).unwrap();
-        assert!(list.blobs[0].prepare_configuration_info());
+        list.blobs[0].prepare_configuration_info().unwrap();
 
         assert_eq!(list.blobs.len(), 2);
         assert_eq!(&list.blobs[0].blob_type, "bootstrap");
@@ -719,7 +783,7 @@ mod tests {
             .replace("/tmp/nydus", tmpdir.as_path().to_str().unwrap())
             .replace("RAFS_V5", &source_path.display().to_string());
         let mut entry: BlobCacheEntry = serde_json::from_str(&content).unwrap();
-        assert!(entry.prepare_configuration_info());
+        entry.prepare_configuration_info().unwrap();
 
         let mgr = BlobCacheMgr::new();
         mgr.add_blob_entry(&entry).unwrap();
@@ -12,15 +12,25 @@
 //! device, so it can be directly mounted by Linux EROFS fs driver.
 
 use std::cmp::min;
-use std::io::Result;
-use std::sync::Arc;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Result, Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use dbs_allocator::{Constraint, IntervalTree, NodeState, Range};
 use nydus_rafs::metadata::layout::v6::EROFS_BLOCK_BITS_12;
+use nydus_utils::digest::RafsDigest;
+use nydus_utils::verity::VerityGenerator;
 use tokio_uring::buf::IoBufMut;
 
 use crate::blob_cache::{BlobCacheMgr, BlobConfig, DataBlob, MetaBlob};
 
+// Number of blocks to read from the block device at once when exporting.
+const EXPORT_BATCH_BLOCKS: u32 = 256;
+
 enum BlockRange {
     Hole,
     MetaBlob(Arc<MetaBlob>),
@@ -225,6 +235,93 @@ impl BlockDevice {
 
         (Ok(total_size), buf)
     }
+
+    /// Export the block device as a raw disk image, which can be mounted by Linux EROFS fs driver
+    /// through loop device without a running daemon.
+    ///
+    /// Data is read from the block device with `threads` worker threads. If `verity` is true,
+    /// a dm-verity compatible hash tree is appended to the disk image, starting at offset
+    /// `blocks() * 4096`, and the root digest is returned.
+    pub fn export(&self, output: &Path, threads: u32, verity: bool) -> Result<Option<RafsDigest>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(output)
+            .map_err(|e| {
+                eother!(format!(
+                    "block_device: failed to create output file {}, {}",
+                    output.display(),
+                    e
+                ))
+            })?;
+        let data_size = (self.blocks as u64) << EROFS_BLOCK_BITS_12;
+        file.set_len(data_size)?;
+
+        let generator = if verity {
+            Some(Mutex::new(VerityGenerator::new(self.blocks)?))
+        } else {
+            None
+        };
+        let next = AtomicU32::new(0);
+        let failed = AtomicBool::new(false);
+        let result = Mutex::new(Ok(()));
+        thread::scope(|s| {
+            for _ in 0..threads.max(1) {
+                s.spawn(|| {
+                    let res = tokio_uring::start(async {
+                        let mut buf =
+                            vec![0u8; (EXPORT_BATCH_BLOCKS as usize) << EROFS_BLOCK_BITS_12];
+                        while !failed.load(Ordering::Relaxed) {
+                            let start = next.fetch_add(EXPORT_BATCH_BLOCKS, Ordering::Relaxed);
+                            if start >= self.blocks {
+                                break;
+                            }
+                            let count = min(EXPORT_BATCH_BLOCKS, self.blocks - start);
+                            let size = (count as usize) << EROFS_BLOCK_BITS_12;
+                            let (res, b) = self.async_read(start, count, buf).await;
+                            buf = b;
+                            res?;
+                            file.write_all_at(&buf[..size], (start as u64) << EROFS_BLOCK_BITS_12)?;
+                            if let Some(generator) = generator.as_ref() {
+                                let digests = buf[..size]
+                                    .chunks(1 << EROFS_BLOCK_BITS_12)
+                                    .map(VerityGenerator::digest_block)
+                                    .collect::<Vec<_>>();
+                                let mut generator = generator.lock().unwrap();
+                                for (idx, digest) in digests.into_iter().enumerate() {
+                                    generator.set_digest(start + idx as u32, digest)?;
+                                }
+                            }
+                        }
+                        Ok::<_, std::io::Error>(())
+                    });
+                    if let Err(e) = res {
+                        failed.store(true, Ordering::Relaxed);
+                        *result.lock().unwrap() = Err(e);
+                    }
+                });
+            }
+        });
+        result.into_inner().unwrap()?;
+
+        let root = match generator {
+            None => None,
+            Some(generator) => {
+                let mut generator = generator.into_inner().unwrap();
+                let root = generator.generate_all_digests();
+                let mut writer = BufWriter::new(&file);
+                writer.seek(SeekFrom::Start(data_size))?;
+                generator.write_tree(&mut writer)?;
+                writer.into_inner().map_err(|e| e.into_error())?;
+                Some(root)
+            }
+        };
+        file.sync_all()?;
+
+        Ok(root)
+    }
 }
 
 #[cfg(test)]
@@ -236,9 +333,7 @@ mod tests {
     use std::path::PathBuf;
     use vmm_sys_util::tempdir::TempDir;
 
-    #[test]
-    fn test_block_device() {
-        let tmpdir = TempDir::new().unwrap();
+    fn create_block_device(tmpdir: &TempDir) -> BlockDevice {
         let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
         let mut source_path = PathBuf::from(root_dir);
         source_path.push("../tests/texture/blobs/be7d77eeb719f70884758d1aa800ed0fb09d701aaec469964e9d54325f0d5fef");
@@ -275,7 +370,7 @@ mod tests {
             .replace("/tmp/nydus", tmpdir.as_path().to_str().unwrap())
             .replace("RAFS_V5", &source_path.display().to_string());
         let mut entry: BlobCacheEntry = serde_json::from_str(&content).unwrap();
-        assert!(entry.prepare_configuration_info());
+        entry.prepare_configuration_info().unwrap();
 
         let mgr = BlobCacheMgr::new();
         mgr.add_blob_entry(&entry).unwrap();
@@ -290,7 +385,13 @@ mod tests {
         assert!(mgr.get_config(&key).is_some());
 
         let mgr = Arc::new(mgr);
-        let device = BlockDevice::new(blob_id.clone(), mgr).unwrap();
+        BlockDevice::new(blob_id, mgr).unwrap()
+    }
+
+    #[test]
+    fn test_block_device() {
+        let tmpdir = TempDir::new().unwrap();
+        let device = create_block_device(&tmpdir);
         assert_eq!(device.blocks(), 0x209);
 
         tokio_uring::start(async move {
@@ -335,4 +436,28 @@ mod tests {
             assert!(res.is_err());
         });
     }
+
+    #[test]
+    fn test_block_device_export() {
+        let tmpdir = TempDir::new().unwrap();
+        let device = create_block_device(&tmpdir);
+        let blocks = device.blocks() as usize;
+
+        let output = tmpdir.as_path().join("image.erofs");
+        assert!(device.export(&output, 2, false).unwrap().is_none());
+        let image = fs::read(&output).unwrap();
+        assert_eq!(image.len(), blocks << EROFS_BLOCK_BITS_12);
+        assert_eq!(image[1024], 0xe2);
+        assert_eq!(image[1027], 0xe0);
+
+        let root = device.export(&output, 3, true).unwrap().unwrap();
+        let image2 = fs::read(&output).unwrap();
+        assert_eq!(&image2[..image.len()], &image[..]);
+        // 0x209 data blocks need 5 leaf hash blocks and one root level hash block.
+        assert_eq!(image2.len(), image.len() + 6 * 4096);
+        assert_eq!(
+            root,
+            VerityGenerator::digest_block(&image2[image.len()..image.len() + 4096])
+        );
+    }
 }
@@ -583,7 +583,7 @@ mod tests {
             .replace("/tmp/nydus", tmpdir.as_path().to_str().unwrap())
             .replace("RAFS_V5", &source_path.display().to_string());
         let mut entry: BlobCacheEntry = serde_json::from_str(&content).unwrap();
-        assert!(entry.prepare_configuration_info());
+        entry.prepare_configuration_info().unwrap();
 
         let mgr = BlobCacheMgr::new();
         mgr.add_blob_entry(&entry).unwrap();
@@ -510,7 +510,8 @@ impl FsCacheHandler {
 
     fn do_prefetch(cfg: &DataBlobConfig, blob: Arc<dyn BlobCache>) -> Result<()> {
         let blob_info = cfg.blob_info().deref();
-        let cache_cfg = cfg.config_v2().get_cache_config()?;
+        let config = cfg.config_v2();
+        let cache_cfg = config.get_cache_config()?;
         if !cache_cfg.prefetch.enable {
             return Ok(());
         }
@@ -559,7 +560,7 @@ impl FsCacheHandler {
         let mut blob_info = config.blob_info().deref().clone();
         blob_info.set_fscache_file(Some(file));
         let blob_ref = Arc::new(blob_info);
-        BLOB_FACTORY.new_blob_cache(config.config_v2(), &blob_ref)
+        BLOB_FACTORY.new_blob_cache(&config.config_v2(), &blob_ref)
     }
 
     fn fill_bootstrap_cache(bootstrap: Arc<FsCacheBootstrap>) -> Result<u64> {
@@ -661,7 +662,7 @@ impl FsCacheHandler {
                     }
                     let id = blob.blob_id().to_string();
                     drop(blob);
-                    BLOB_FACTORY.gc(Some((factory_config, &id)));
+                    BLOB_FACTORY.gc(Some((&factory_config, &id)));
                 }
                 _ => warn!("fscache: blob object not ready {}", hdr.object_id),
             }
@@ -16,7 +16,7 @@ use std::sync::{Arc, MutexGuard};
 use fuse_backend_rs::api::{BackFileSystem, Vfs};
 #[cfg(target_os = "linux")]
 use fuse_backend_rs::passthrough::{Config, PassthroughFs};
-use nydus_api::ConfigV2;
+use nydus_api::{BackendConfigV2, ConfigV2};
 use nydus_rafs::fs::Rafs;
 use nydus_rafs::{RafsError, RafsIoRead};
 use nydus_storage::factory::BLOB_FACTORY;
@@ -52,7 +52,7 @@ pub struct FsBackendUmountCmd {
 pub struct FsBackendCollection(HashMap<String, FsBackendDescriptor>);
 
 impl FsBackendCollection {
-    fn add(&mut self, id: &str, cmd: &FsBackendMountCmd) -> Result<()> {
+    pub(crate) fn add(&mut self, id: &str, cmd: &FsBackendMountCmd) -> Result<()> {
         // We only wash Rafs backend now.
         let fs_config = match cmd.fs_type {
             FsBackendType::Rafs => {
@@ -79,7 +79,13 @@ impl FsBackendCollection {
         Ok(())
     }
 
-    fn del(&mut self, id: &str) {
+    pub(crate) fn update_config(&mut self, id: &str, config: &ConfigV2) {
+        if let Some(desc) = self.0.get_mut(id) {
+            desc.config = Some(config.clone_without_secrets());
+        }
+    }
+
+    pub(crate) fn del(&mut self, id: &str) {
         self.0.remove(id);
     }
 }
@@ -169,6 +175,40 @@ pub trait FsService: Send + Sync {
         Ok(())
     }
 
+    /// Update credentials, proxy and mirrors of the storage backend for a filesystem instance.
+    fn update_backend_config(&self, mountpoint: &str, backend_cfg: &BackendConfigV2) -> Result<()> {
+        backend_cfg
+            .validate()
+            .map_err(|e| Error::InvalidConfig(e.to_string()))?;
+        let rootfs = self
+            .backend_from_mountpoint(mountpoint)?
+            .ok_or(Error::NotFound)?;
+        let any_fs = rootfs.deref().as_any();
+        let rafs = any_fs
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
+        let config = rafs
+            .update_backend_config(backend_cfg)
+            .map_err(Error::Rafs)?;
+        info!("storage backend configuration updated for {}", mountpoint);
+
+        // Save the new configuration, so it will be used after online upgrade or failover.
+        self.backend_collection().update_config(mountpoint, &config);
+        if let Some(mut mgr_guard) = self.upgrade_mgr() {
+            let config = serde_json::to_string(config.as_ref()).map_err(Error::Serde)?;
+            if let Err(e) = mgr_guard.update_mounts_config(mountpoint, config) {
+                warn!(
+                    "failed to update filesystem configuration to upgrade manager, {}",
+                    e
+                );
+                warn!("disable online upgrade due to inconsistent status!!!");
+                mgr_guard.disable_upgrade();
+            }
+        }
+
+        Ok(())
+    }
+
     /// Umount a filesystem instance.
     fn umount(&self, cmd: FsBackendUmountCmd) -> Result<()> {
         let _ = self
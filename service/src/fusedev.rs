@@ -28,7 +28,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use fuse_backend_rs::abi::fuse_abi::{InHeader, OutHeader};
 use fuse_backend_rs::api::server::{MetricsHook, Server};
-use fuse_backend_rs::api::Vfs;
+use fuse_backend_rs::api::{Vfs, VfsOptions};
 use fuse_backend_rs::transport::{FuseChannel, FuseSession};
 use mio::Waker;
 #[cfg(target_os = "linux")]
@@ -40,9 +40,9 @@ use crate::daemon::{
     DaemonState, DaemonStateMachineContext, DaemonStateMachineInput, DaemonStateMachineSubscriber,
     NydusDaemon,
 };
-use crate::fs_service::{FsBackendCollection, FsBackendMountCmd, FsService};
+use crate::fs_service::{FsBackendCollection, FsBackendMountCmd, FsBackendUmountCmd, FsService};
 use crate::upgrade::{self, FailoverPolicy, UpgradeManager};
-use crate::{Error as NydusError, Result as NydusResult};
+use crate::{Error as NydusError, FsBackendType, Result as NydusResult};
 
 #[derive(Serialize)]
 struct FuseOp {
@@ -199,6 +199,34 @@ impl FusedevFsService {
         inflight_op
     }
 
+    fn kick_one_server(&self, waker: Arc<Waker>) -> NydusResult<JoinHandle<Result<()>>> {
+        let mut s = self
+            .create_fuse_server()
+            .map_err(NydusError::CreateFuseServer)?;
+        let inflight_op = self.create_inflight_op();
+        thread::Builder::new()
+            .name("fuse_server".to_string())
+            .spawn(move || {
+                if let Err(_err) = s.svc_loop(&inflight_op) {
+                    // Notify the daemon controller that one working thread has exited.
+                    if let Err(err) = waker.wake() {
+                        error!("fail to exit daemon, error: {:?}", err);
+                    }
+                }
+                Ok(())
+            })
+            .map_err(NydusError::ThreadSpawn)
+    }
+
+    fn collect_inflight_ops(&self, ops: &mut Vec<serde_json::Value>) -> NydusResult<()> {
+        for w in self.inflight_ops.lock().unwrap().iter() {
+            if let Some(op) = w.op.lock().unwrap().as_ref() {
+                ops.push(serde_json::to_value(op).map_err(NydusError::Serde)?);
+            }
+        }
+        Ok(())
+    }
+
     fn umount(&self) -> NydusResult<()> {
         let mut session = self.session.lock().expect("Not expect poisoned lock.");
         session.umount().map_err(NydusError::SessionShutdown)?;
@@ -296,24 +324,7 @@ impl FusedevDaemon {
     }
 
     fn kick_one_server(&self, waker: Arc<Waker>) -> NydusResult<()> {
-        let mut s = self
-            .service
-            .create_fuse_server()
-            .map_err(NydusError::CreateFuseServer)?;
-        let inflight_op = self.service.create_inflight_op();
-        let thread = thread::Builder::new()
-            .name("fuse_server".to_string())
-            .spawn(move || {
-                if let Err(_err) = s.svc_loop(&inflight_op) {
-                    // Notify the daemon controller that one working thread has exited.
-                    if let Err(err) = waker.wake() {
-                        error!("fail to exit daemon, error: {:?}", err);
-                    }
-                }
-                Ok(())
-            })
-            .map_err(NydusError::ThreadSpawn)?;
-
+        let thread = self.service.kick_one_server(waker)?;
         self.fuse_service_threads.lock().unwrap().push(thread);
 
         Ok(())
@@ -445,6 +456,101 @@ impl NydusDaemon for FusedevDaemon {
     }
 }
 
+/// A FUSE mountpoint hosted by a shared Nydus daemon in singleton mode.
+///
+/// Every [FusedevMountpoint] object has its own FUSE session, worker threads and VFS, and the
+/// filesystem instance is mounted at the root of the VFS.
+pub(crate) struct FusedevMountpoint {
+    service: Arc<FusedevFsService>,
+    threads: Mutex<Vec<JoinHandle<Result<()>>>>,
+}
+
+impl FusedevMountpoint {
+    /// Create a FUSE mountpoint at `cmd.mountpoint`, and mount the filesystem described by `cmd`.
+    pub fn new(
+        mut cmd: FsBackendMountCmd,
+        threads_cnt: u32,
+        readonly: bool,
+        waker: Arc<Waker>,
+    ) -> NydusResult<Self> {
+        let mnt = Path::new(&cmd.mountpoint).canonicalize().map_err(|e| {
+            NydusError::InvalidArguments(format!("mountpoint {}, {}", cmd.mountpoint, e))
+        })?;
+        let mut opts = VfsOptions::default();
+        match cmd.fs_type {
+            FsBackendType::PassthroughFs => {
+                // passthroughfs requires !no_open
+                opts.no_open = false;
+                opts.no_opendir = false;
+                opts.killpriv_v2 = true;
+            }
+            // rafs can be readonly and skip open
+            FsBackendType::Rafs => opts.no_open = true,
+        }
+        let vfs = Arc::new(Vfs::new(opts));
+        let service = FusedevFsService::new(vfs, &mnt, None, FailoverPolicy::Flush, readonly)
+            .map_err(|e| NydusError::StartService(format!("{}", e)))?;
+
+        cmd.mountpoint = "/".to_string();
+        service.mount(cmd)?;
+        service
+            .session
+            .lock()
+            .unwrap()
+            .mount()
+            .map_err(|e| NydusError::StartService(format!("{}", e)))?;
+        if let Ok(conn) = calc_fuse_conn(&mnt) {
+            service.conn.store(conn, Ordering::Relaxed);
+        }
+
+        let mountpoint = FusedevMountpoint {
+            service: Arc::new(service),
+            threads: Mutex::new(Vec::new()),
+        };
+        for _ in 0..threads_cnt {
+            match mountpoint.service.kick_one_server(waker.clone()) {
+                Ok(thread) => mountpoint.threads.lock().unwrap().push(thread),
+                Err(e) => {
+                    if let Err(e) = mountpoint.umount() {
+                        warn!("failed to umount FUSE mountpoint {:?}, {}", mnt, e);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(mountpoint)
+    }
+
+    /// Get the filesystem instance mounted at the FUSE mountpoint.
+    pub fn backend(&self) -> NydusResult<Option<Arc<fuse_backend_rs::api::BackFileSystem>>> {
+        self.service.backend_from_mountpoint("/")
+    }
+
+    /// Collect in-flight FUSE requests of the mountpoint.
+    pub fn collect_inflight_ops(&self, ops: &mut Vec<serde_json::Value>) -> NydusResult<()> {
+        self.service.collect_inflight_ops(ops)
+    }
+
+    /// Umount the FUSE mountpoint, stop worker threads and release the filesystem instance.
+    pub fn umount(&self) -> NydusResult<()> {
+        self.service.umount()?;
+        for thread in self.threads.lock().unwrap().drain(..) {
+            match thread.join() {
+                Ok(Err(e)) => warn!("FUSE service thread exited with error, {}", e),
+                Err(_) => warn!("FUSE service thread panicked"),
+                Ok(Ok(())) => {}
+            }
+        }
+        FsService::umount(
+            self.service.as_ref(),
+            FsBackendUmountCmd {
+                mountpoint: "/".to_string(),
+            },
+        )
+    }
+}
+
 #[cfg(target_os = "macos")]
 fn is_mounted(mp: impl AsRef<Path>) -> Result<bool> {
     let mp = mp
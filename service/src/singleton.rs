@@ -5,10 +5,12 @@
 //! Nydus daemon to host multiple services, including fscache and fusedev.
 
 use std::any::Any;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
 
+use fuse_backend_rs::api::{BackFileSystem, Vfs, VfsOptions};
 use mio::Waker;
 use nydus_api::config::BlobCacheList;
 use nydus_api::BuildTimeInfo;
@@ -18,9 +20,155 @@ use crate::daemon::{
     DaemonState, DaemonStateMachineContext, DaemonStateMachineInput, DaemonStateMachineSubscriber,
     NydusDaemon,
 };
-use crate::fs_service::FsService;
+use crate::fs_service::{FsBackendCollection, FsBackendMountCmd, FsBackendUmountCmd, FsService};
+use crate::fusedev::FusedevMountpoint;
+use crate::upgrade::UpgradeManager;
 use crate::{Error, Result};
 
+/// Filesystem service to host multiple FUSE mountpoints in singleton mode.
+///
+/// Mountpoints in mount/umount requests are host paths of FUSE mountpoints. A FUSE session with
+/// dedicated worker threads and VFS is created for each mountpoint, and the filesystem instance is
+/// mounted at root of the VFS.
+struct FusedevSingletonService {
+    // Filesystem instances are mounted into VFS of each FUSE mountpoint instead of this one.
+    vfs: Vfs,
+    threads_cnt: u32,
+    waker: Arc<Waker>,
+    // A mountpoint is reserved with `None` while it's being mounted or umounted, so the lock isn't
+    // held across FUSE session setup and filesystem loading.
+    mountpoints: Mutex<HashMap<String, Option<Arc<FusedevMountpoint>>>>,
+    backend_collection: Mutex<FsBackendCollection>,
+}
+
+impl FusedevSingletonService {
+    fn new(threads_cnt: u32, waker: Arc<Waker>) -> Self {
+        FusedevSingletonService {
+            vfs: Vfs::new(VfsOptions::default()),
+            threads_cnt,
+            waker,
+            mountpoints: Mutex::new(HashMap::new()),
+            backend_collection: Default::default(),
+        }
+    }
+
+    /// Umount all FUSE mountpoints.
+    fn umount_all(&self) {
+        let mountpoints = self.mountpoints.lock().unwrap().drain().collect::<Vec<_>>();
+        for (path, mountpoint) in mountpoints {
+            let mountpoint = match mountpoint {
+                Some(v) => v,
+                None => continue,
+            };
+            if let Err(e) = mountpoint.umount() {
+                error!("failed to umount FUSE mountpoint {}, {}", path, e);
+            }
+            self.backend_collection.lock().unwrap().del(&path);
+        }
+    }
+}
+
+impl FsService for FusedevSingletonService {
+    fn get_vfs(&self) -> &Vfs {
+        &self.vfs
+    }
+
+    fn backend_from_mountpoint(&self, mp: &str) -> Result<Option<Arc<BackFileSystem>>> {
+        match self.mountpoints.lock().unwrap().get(mp) {
+            Some(Some(mountpoint)) => mountpoint.backend(),
+            _ => Ok(None),
+        }
+    }
+
+    fn upgrade_mgr(&self) -> Option<MutexGuard<UpgradeManager>> {
+        None
+    }
+
+    fn mount(&self, cmd: FsBackendMountCmd) -> Result<()> {
+        {
+            let mut mountpoints = self.mountpoints.lock().unwrap();
+            if mountpoints.contains_key(&cmd.mountpoint) {
+                return Err(Error::AlreadyExists);
+            }
+            mountpoints.insert(cmd.mountpoint.clone(), None);
+        }
+
+        // Filesystems are read-only in singleton mode.
+        let result =
+            FusedevMountpoint::new(cmd.clone(), self.threads_cnt, true, self.waker.clone());
+        let mut mountpoints = self.mountpoints.lock().unwrap();
+        match result {
+            Ok(mountpoint) => {
+                mountpoints.insert(cmd.mountpoint.clone(), Some(Arc::new(mountpoint)));
+            }
+            Err(e) => {
+                mountpoints.remove(&cmd.mountpoint);
+                return Err(e);
+            }
+        }
+        drop(mountpoints);
+        info!(
+            "{} filesystem mounted at FUSE mountpoint {}",
+            &cmd.fs_type, &cmd.mountpoint
+        );
+
+        if let Err(e) = self.backend_collection().add(&cmd.mountpoint, &cmd) {
+            warn!(
+                "failed to add filesystem instance to metrics manager, {}",
+                e
+            );
+        }
+
+        Ok(())
+    }
+
+    fn umount(&self, cmd: FsBackendUmountCmd) -> Result<()> {
+        // Mountpoints being mounted or umounted are not available yet.
+        let mountpoint = self
+            .mountpoints
+            .lock()
+            .unwrap()
+            .get_mut(&cmd.mountpoint)
+            .and_then(|v| v.take())
+            .ok_or(Error::NotFound)?;
+        if let Err(e) = mountpoint.umount() {
+            // Keep the mountpoint so the request may be retried.
+            self.mountpoints
+                .lock()
+                .unwrap()
+                .insert(cmd.mountpoint.clone(), Some(mountpoint));
+            return Err(e);
+        }
+        self.mountpoints.lock().unwrap().remove(&cmd.mountpoint);
+        self.backend_collection().del(&cmd.mountpoint);
+        info!("FUSE mountpoint {} umounted", &cmd.mountpoint);
+
+        Ok(())
+    }
+
+    fn backend_collection(&self) -> MutexGuard<FsBackendCollection> {
+        self.backend_collection.lock().unwrap()
+    }
+
+    fn export_inflight_ops(&self) -> Result<Option<String>> {
+        let mut ops = Vec::new();
+        for mountpoint in self.mountpoints.lock().unwrap().values().flatten() {
+            mountpoint.collect_inflight_ops(&mut ops)?;
+        }
+
+        if ops.is_empty() {
+            Ok(None)
+        } else {
+            let resp = serde_json::to_string(&ops).map_err(Error::Serde)?;
+            Ok(Some(resp))
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 #[allow(dead_code)]
 struct ServiceController {
     bti: BuildTimeInfo,
@@ -36,6 +184,8 @@ struct ServiceController {
     fscache_enabled: AtomicBool,
     #[cfg(target_os = "linux")]
     fscache: Mutex<Option<Arc<crate::fs_cache::FsCacheHandler>>>,
+
+    fuse_service: Option<Arc<FusedevSingletonService>>,
 }
 
 impl ServiceController {
@@ -75,6 +225,10 @@ impl ServiceController {
                 fscache.stop();
             }
         }
+
+        if let Some(fuse_service) = self.fuse_service.as_ref() {
+            fuse_service.umount_all();
+        }
     }
 
     fn initialize_blob_cache(&self, config: &Option<serde_json::Value>) -> std::io::Result<()> {
@@ -200,7 +354,9 @@ impl NydusDaemon for ServiceController {
     }
 
     fn get_default_fs_service(&self) -> Option<Arc<dyn FsService>> {
-        None
+        self.fuse_service
+            .as_ref()
+            .map(|s| s.clone() as Arc<dyn FsService>)
     }
 
     fn get_blob_cache_mgr(&self) -> Option<Arc<BlobCacheMgr>> {
@@ -237,6 +393,9 @@ impl DaemonStateMachineSubscriber for ServiceController {
 }
 
 /// Create and start a Nydus daemon to host fscache and fusedev services.
+///
+/// The fusedev service is enabled if `fuse_threads` is specified, which is the number of worker
+/// threads for each FUSE mountpoint.
 #[allow(clippy::too_many_arguments, unused)]
 pub fn create_daemon(
     id: Option<String>,
@@ -244,6 +403,7 @@ pub fn create_daemon(
     fscache: Option<&str>,
     tag: Option<&str>,
     threads: Option<&str>,
+    fuse_threads: Option<u32>,
     config: Option<serde_json::Value>,
     bti: BuildTimeInfo,
     waker: Arc<Waker>,
@@ -257,13 +417,16 @@ pub fn create_daemon(
         result_receiver: Mutex::new(from_sm),
         state: Default::default(),
         supervisor,
-        waker,
+        waker: waker.clone(),
 
         blob_cache_mgr: Arc::new(BlobCacheMgr::new()),
 
         fscache_enabled: AtomicBool::new(false),
         #[cfg(target_os = "linux")]
         fscache: Mutex::new(None),
+
+        fuse_service: fuse_threads
+            .map(|threads| Arc::new(FusedevSingletonService::new(threads, waker))),
     };
 
     service_controller.initialize_blob_cache(&config)?;
@@ -284,3 +447,78 @@ pub fn create_daemon(
 
     Ok(daemon)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FsBackendType;
+    use mio::{Poll, Token};
+
+    fn new_service() -> (Poll, FusedevSingletonService) {
+        let poll = Poll::new().unwrap();
+        let waker = Arc::new(Waker::new(poll.registry(), Token(0)).unwrap());
+        (poll, FusedevSingletonService::new(1, waker))
+    }
+
+    fn mount_cmd(mountpoint: &str) -> FsBackendMountCmd {
+        FsBackendMountCmd {
+            fs_type: FsBackendType::Rafs,
+            source: "/nonexistent/bootstrap".to_string(),
+            config: String::new(),
+            mountpoint: mountpoint.to_string(),
+            prefetch_files: None,
+        }
+    }
+
+    fn umount_cmd(mountpoint: &str) -> FsBackendUmountCmd {
+        FsBackendUmountCmd {
+            mountpoint: mountpoint.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_fusedev_singleton_mount_failure() {
+        let (_poll, service) = new_service();
+        let mnt = "/nonexistent/fuse/mountpoint";
+
+        // The reserved mountpoint is released if mount fails, so the request may be retried.
+        assert!(service.mount(mount_cmd(mnt)).is_err());
+        assert!(service.mountpoints.lock().unwrap().is_empty());
+        assert!(!matches!(
+            service.mount(mount_cmd(mnt)),
+            Err(Error::AlreadyExists)
+        ));
+        assert!(service.mountpoints.lock().unwrap().is_empty());
+        assert!(matches!(
+            service.umount(umount_cmd(mnt)),
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_fusedev_singleton_busy_mountpoint() {
+        let (_poll, service) = new_service();
+        let mnt = "/mnt/busy";
+
+        // Simulate a mountpoint being mounted or umounted by another request.
+        service
+            .mountpoints
+            .lock()
+            .unwrap()
+            .insert(mnt.to_string(), None);
+        assert!(matches!(
+            service.mount(mount_cmd(mnt)),
+            Err(Error::AlreadyExists)
+        ));
+        assert!(matches!(
+            service.umount(umount_cmd(mnt)),
+            Err(Error::NotFound)
+        ));
+        assert!(service.mountpoints.lock().unwrap().contains_key(mnt));
+        assert!(service.backend_from_mountpoint(mnt).unwrap().is_none());
+        assert!(service.export_inflight_ops().unwrap().is_none());
+
+        service.umount_all();
+        assert!(service.mountpoints.lock().unwrap().is_empty());
+    }
+}
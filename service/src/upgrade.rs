@@ -62,6 +62,11 @@ impl UpgradeManager {
         Ok(())
     }
 
+    /// Update configuration of a filesystem instance in the upgrade manager.
+    pub fn update_mounts_config(&mut self, _mountpoint: &str, _config: String) -> Result<()> {
+        Ok(())
+    }
+
     /// Remove a filesystem instance from the upgrade manager.
     pub fn remove_mounts_state(&mut self, _cmd: FsBackendUmountCmd) -> Result<()> {
         Ok(())
@@ -0,0 +1,225 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compare filesystem trees of two RAFS filesystems.
+//!
+//! Files are compared by metadata and chunk digests recorded in the bootstraps, so there's no
+//! need to download data blobs.
+
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::OpenOptions;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nydus_api::ConfigV2;
+use nydus_rafs::metadata::RafsSuper;
+use nydus_utils::digest::RafsDigest;
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct FileEntry {
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    rdev: u32,
+    size: u64,
+    symlink: Option<OsString>,
+    xattrs: BTreeMap<Vec<u8>, Vec<u8>>,
+    chunks: Vec<RafsDigest>,
+}
+
+impl FileEntry {
+    fn is_content_changed(&self, other: &FileEntry) -> bool {
+        self.size != other.size || self.symlink != other.symlink || self.chunks != other.chunks
+    }
+
+    fn is_metadata_changed(&self, other: &FileEntry) -> bool {
+        self.mode != other.mode
+            || self.uid != other.uid
+            || self.gid != other.gid
+            || self.mtime != other.mtime
+            || self.rdev != other.rdev
+            || self.xattrs != other.xattrs
+    }
+}
+
+#[derive(Serialize)]
+struct ModifiedFile {
+    path: PathBuf,
+    metadata: bool,
+    content: bool,
+}
+
+/// Differences between two RAFS filesystems, with paths sorted in lexical order.
+#[derive(Default, Serialize)]
+pub(crate) struct ImageDiff {
+    added: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    modified: Vec<ModifiedFile>,
+}
+
+impl ImageDiff {
+    /// Compare the RAFS filesystem `new` against the RAFS filesystem `old`.
+    pub fn diff(old: &Path, new: &Path, config: Arc<ConfigV2>) -> Result<Self> {
+        let old_files = Self::load_files(old, config.clone())?;
+        let new_files = Self::load_files(new, config)?;
+
+        Ok(Self::compare(&old_files, &new_files))
+    }
+
+    fn compare(
+        old_files: &BTreeMap<PathBuf, FileEntry>,
+        new_files: &BTreeMap<PathBuf, FileEntry>,
+    ) -> Self {
+        let mut diff = ImageDiff::default();
+
+        for (path, old) in old_files.iter() {
+            match new_files.get(path) {
+                None => diff.removed.push(path.clone()),
+                Some(new) => {
+                    let metadata = old.is_metadata_changed(new);
+                    let content = old.is_content_changed(new);
+                    if metadata || content {
+                        diff.modified.push(ModifiedFile {
+                            path: path.clone(),
+                            metadata,
+                            content,
+                        });
+                    }
+                }
+            }
+        }
+        for path in new_files.keys() {
+            if !old_files.contains_key(path) {
+                diff.added.push(path.clone());
+            }
+        }
+
+        diff
+    }
+
+    fn load_files(path: &Path, config: Arc<ConfigV2>) -> Result<BTreeMap<PathBuf, FileEntry>> {
+        let (rs, _) = RafsSuper::load_from_file(path, config, false, false)
+            .with_context(|| format!("failed to load bootstrap {}", path.display()))?;
+        let mut files = BTreeMap::new();
+
+        rs.walk_directory::<PathBuf>(rs.superblock.root_ino(), None, &mut |inode, path| {
+            let attr = inode.get_attr();
+            let mut entry = FileEntry {
+                mode: attr.mode,
+                uid: attr.uid,
+                gid: attr.gid,
+                mtime: attr.mtime,
+                rdev: attr.rdev,
+                ..Default::default()
+            };
+            if inode.is_reg() {
+                entry.size = inode.size();
+                for idx in 0..inode.get_chunk_count() {
+                    let chunk = inode.get_chunk_info(idx)?;
+                    entry.chunks.push(*chunk.chunk_id());
+                }
+            } else if inode.is_symlink() {
+                entry.symlink = Some(inode.get_symlink()?);
+            }
+            if inode.has_xattr() {
+                for name in inode.get_xattrs()? {
+                    let value = inode
+                        .get_xattr(OsStr::from_bytes(&name))?
+                        .unwrap_or_default();
+                    entry.xattrs.insert(name, value);
+                }
+            }
+            files.insert(path.to_path_buf(), entry);
+            Ok(())
+        })?;
+
+        Ok(files)
+    }
+
+    pub fn dump_json(&self, path: &Path) -> Result<()> {
+        let w = OpenOptions::new()
+            .truncate(true)
+            .create(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Output file {:?} can't be opened", path))?;
+
+        serde_json::to_writer(w, self).context("Write output file failed")?;
+
+        Ok(())
+    }
+
+    pub fn dump(&self) {
+        for path in self.added.iter() {
+            println!("A\t{}", path.display());
+        }
+        for path in self.removed.iter() {
+            println!("D\t{}", path.display());
+        }
+        for file in self.modified.iter() {
+            let kind = match (file.metadata, file.content) {
+                (true, true) => "metadata,content",
+                (true, false) => "metadata",
+                _ => "content",
+            };
+            println!("M\t{}\t({})", file.path.display(), kind);
+        }
+        println!(
+            "\nAdded: {}, Removed: {}, Modified: {}",
+            self.added.len(),
+            self.removed.len(),
+            self.modified.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_files() {
+        let mut old_files = BTreeMap::new();
+        let mut new_files = BTreeMap::new();
+        let file = FileEntry {
+            mode: 0o100644,
+            size: 0x1000,
+            chunks: vec![RafsDigest { data: [1u8; 32] }],
+            ..Default::default()
+        };
+
+        old_files.insert(PathBuf::from("/"), FileEntry::default());
+        new_files.insert(PathBuf::from("/"), FileEntry::default());
+        old_files.insert(PathBuf::from("/removed"), FileEntry::default());
+        new_files.insert(PathBuf::from("/added"), FileEntry::default());
+        old_files.insert(PathBuf::from("/same"), file.clone());
+        new_files.insert(PathBuf::from("/same"), file.clone());
+
+        let mut chmod = file.clone();
+        chmod.mode = 0o100755;
+        old_files.insert(PathBuf::from("/chmod"), file.clone());
+        new_files.insert(PathBuf::from("/chmod"), chmod);
+
+        let mut rewrite = file.clone();
+        rewrite.chunks = vec![RafsDigest { data: [2u8; 32] }];
+        old_files.insert(PathBuf::from("/rewrite"), file);
+        new_files.insert(PathBuf::from("/rewrite"), rewrite);
+
+        let diff = ImageDiff::compare(&old_files, &new_files);
+        assert_eq!(diff.added, vec![PathBuf::from("/added")]);
+        assert_eq!(diff.removed, vec![PathBuf::from("/removed")]);
+        assert_eq!(diff.modified.len(), 2);
+        assert_eq!(diff.modified[0].path, PathBuf::from("/chmod"));
+        assert!(diff.modified[0].metadata);
+        assert!(!diff.modified[0].content);
+        assert_eq!(diff.modified[1].path, PathBuf::from("/rewrite"));
+        assert!(!diff.modified[1].metadata);
+        assert!(diff.modified[1].content);
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Garbage collection of data blobs which are not referenced by any live RAFS filesystem.
+//!
+//! Reachability is computed from blob tables of the live bootstraps. Blobs referenced through
+//! chunk dictionaries are recorded in blob tables too, so they are kept as long as any live
+//! bootstrap references them.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nydus_api::ConfigV2;
+use nydus_rafs::metadata::RafsSuper;
+use nydus_storage::device::BlobFeatures;
+use serde::Serialize;
+
+const BLOB_ID_LENGTH: usize = 64;
+
+#[derive(Serialize)]
+struct GcEntry {
+    path: PathBuf,
+    size: u64,
+}
+
+/// Result of a garbage collection pass.
+#[derive(Default, Serialize)]
+pub(crate) struct GcResult {
+    dry_run: bool,
+    kept: Vec<PathBuf>,
+    removed: Vec<GcEntry>,
+    reclaimed_size: u64,
+}
+
+impl GcResult {
+    pub fn dump_json(&self, path: &Path) -> Result<()> {
+        let w = OpenOptions::new()
+            .truncate(true)
+            .create(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Output file {:?} can't be opened", path))?;
+
+        serde_json::to_writer(w, self).context("Write output file failed")?;
+
+        Ok(())
+    }
+
+    pub fn dump(&self) {
+        let action = if self.dry_run {
+            "Unreferenced"
+        } else {
+            "Removed"
+        };
+        for entry in self.removed.iter() {
+            println!("{}\t{}\t{}", action, entry.size, entry.path.display());
+        }
+        println!(
+            "\n{} files: {}, Reclaimed size: {}, Kept files: {}",
+            action,
+            self.removed.len(),
+            self.reclaimed_size,
+            self.kept.len()
+        );
+    }
+}
+
+/// Collector to find and remove unreferenced blob files.
+#[derive(Default)]
+pub(crate) struct BlobGc {
+    referenced: HashSet<String>,
+}
+
+impl BlobGc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark all blobs referenced by the RAFS filesystem at `path` as live.
+    pub fn add_bootstrap(&mut self, path: &Path, config: Arc<ConfigV2>) -> Result<()> {
+        let (rs, _) = RafsSuper::load_from_file(path, config, false, false)
+            .with_context(|| format!("failed to load bootstrap {}", path.display()))?;
+        for blob in rs.superblock.get_blob_infos() {
+            self.referenced.insert(blob.blob_id());
+            self.referenced.insert(blob.raw_blob_id().to_string());
+            if blob.has_feature(BlobFeatures::SEPARATE) {
+                if let Ok(id) = blob.get_blob_meta_id() {
+                    self.referenced.insert(id);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove or report unreferenced blob files in `dirs`.
+    ///
+    /// Only files named by a blob id, optionally with a suffix such as `.blob.meta`, are
+    /// considered, files in `skip` are always kept.
+    pub fn collect(&self, dirs: &[PathBuf], skip: &[PathBuf], dry_run: bool) -> Result<GcResult> {
+        let mut result = GcResult {
+            dry_run,
+            ..Default::default()
+        };
+        let mut paths = Vec::new();
+        for dir in dirs {
+            let entries = fs::read_dir(dir)
+                .with_context(|| format!("failed to read dir {}", dir.display()))?;
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    paths.push(entry.path());
+                }
+            }
+        }
+        paths.sort();
+        paths.dedup();
+
+        for path in paths {
+            let id = match path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(blob_id_of)
+            {
+                None => continue,
+                Some(id) => id,
+            };
+            if self.referenced.contains(id) || Self::is_skipped(&path, skip) {
+                result.kept.push(path);
+                continue;
+            }
+
+            let size = fs::metadata(&path)?.len();
+            if !dry_run {
+                fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove blob {}", path.display()))?;
+                info!("removed unreferenced blob {}", path.display());
+            }
+            result.reclaimed_size += size;
+            result.removed.push(GcEntry { path, size });
+        }
+
+        Ok(result)
+    }
+
+    fn is_skipped(path: &Path, skip: &[PathBuf]) -> bool {
+        match fs::canonicalize(path) {
+            Ok(p) => skip.iter().any(|s| s == &p),
+            Err(_) => false,
+        }
+    }
+}
+
+// Get blob id from file names like `<blob_id>` or `<blob_id>.blob.meta`.
+fn blob_id_of(name: &str) -> Option<&str> {
+    let id = name.split('.').next()?;
+    if id.len() == BLOB_ID_LENGTH && id.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vmm_sys_util::tempdir::TempDir;
+
+    #[test]
+    fn test_collect_unreferenced_blobs() {
+        let tmpdir = TempDir::new().unwrap();
+        let dir = tmpdir.as_path();
+        let dirs = vec![dir.to_path_buf()];
+        let live = "a".repeat(BLOB_ID_LENGTH);
+        let dead = "b".repeat(BLOB_ID_LENGTH);
+        let skipped = "c".repeat(BLOB_ID_LENGTH);
+        fs::write(dir.join(&live), [0u8; 16]).unwrap();
+        fs::write(dir.join(format!("{}.blob.meta", live)), [0u8; 8]).unwrap();
+        fs::write(dir.join(&dead), [0u8; 32]).unwrap();
+        fs::write(dir.join(format!("{}.blob.meta", dead)), [0u8; 8]).unwrap();
+        fs::write(dir.join(&skipped), [0u8; 4]).unwrap();
+        fs::write(dir.join("manifest.json"), [0u8; 4]).unwrap();
+
+        let mut gc = BlobGc::new();
+        gc.referenced.insert(live.clone());
+        let skip = vec![fs::canonicalize(dir.join(&skipped)).unwrap()];
+
+        let result = gc.collect(&dirs, &skip, true).unwrap();
+        assert_eq!(result.removed.len(), 2);
+        assert_eq!(result.reclaimed_size, 40);
+        assert_eq!(result.kept.len(), 3);
+        assert!(dir.join(&dead).exists());
+
+        let result = gc.collect(&dirs, &skip, false).unwrap();
+        assert_eq!(result.removed.len(), 2);
+        assert!(!dir.join(&dead).exists());
+        assert!(!dir.join(format!("{}.blob.meta", dead)).exists());
+        assert!(dir.join(&live).exists());
+        assert!(dir.join(&skipped).exists());
+        assert!(dir.join("manifest.json").exists());
+    }
+
+    #[test]
+    fn test_blob_id_of() {
+        let id = "0123456789abcdef".repeat(4);
+        assert_eq!(blob_id_of(&id), Some(id.as_str()));
+        assert_eq!(blob_id_of(&format!("{}.blob.data", id)), Some(id.as_str()));
+        assert_eq!(blob_id_of("manifest.json"), None);
+        assert_eq!(blob_id_of(&"g".repeat(BLOB_ID_LENGTH)), None);
+    }
+}
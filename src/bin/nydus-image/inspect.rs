@@ -3,22 +3,35 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    collections::BTreeMap,
-    ffi::OsString,
-    fs::Permissions,
-    io::{Error, ErrorKind, Write},
+    cmp,
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
+    fs::{self, OpenOptions, Permissions},
+    io::{self, BufRead, BufReader, Error, ErrorKind, Read, Write},
     ops::DerefMut,
+    os::unix::ffi::OsStrExt,
+    os::unix::fs::OpenOptionsExt,
     os::unix::prelude::PermissionsExt,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
+use anyhow::Context;
+use fuse_backend_rs::api::filesystem::ZeroCopyWriter;
+use fuse_backend_rs::file_buf::FileVolatileSlice;
+use fuse_backend_rs::file_traits::FileReadWriteVolatile;
+use nix::sys::stat::{mknod, Mode, SFlag};
+use nix::unistd::{fchownat, FchownatFlags, Gid, Uid};
 use nydus_api::ConfigV2;
 use nydus_rafs::metadata::{RafsInode, RafsInodeExt, RafsInodeWalkAction, RafsSuper};
 use nydus_rafs::RafsIoReader;
-use nydus_storage::device::BlobChunkInfo;
+use nydus_storage::device::{BlobChunkInfo, BlobDevice};
+use regex::Regex;
 use serde_json::Value;
 
+// Size of buffer to read file data from blobs.
+const FILE_READ_BUF_SIZE: usize = 0x100000;
+
 pub(crate) struct RafsInspector {
     request_mode: bool,
     // Rafs Meta Data
@@ -31,6 +44,10 @@ pub(crate) struct RafsInspector {
     parent_inodes: Vec<u64>,
     // Inode of parent directory for rafs v6 files
     file_parents: BTreeMap<u64, Vec<u64>>,
+    // Configuration to access data blobs
+    config: Arc<ConfigV2>,
+    // Blob device to read file data, created on demand
+    device: Option<BlobDevice>,
 }
 
 impl RafsInspector {
@@ -40,7 +57,8 @@ impl RafsInspector {
         request_mode: bool,
         config: Arc<ConfigV2>,
     ) -> Result<Self, anyhow::Error> {
-        let (rafs_meta, f) = RafsSuper::load_from_file(bootstrap_path, config, false, false)?;
+        let (rafs_meta, f) =
+            RafsSuper::load_from_file(bootstrap_path, config.clone(), false, false)?;
         let root_ino = rafs_meta.superblock.root_ino();
 
         Ok(RafsInspector {
@@ -50,6 +68,8 @@ impl RafsInspector {
             cur_dir_ino: root_ino,
             parent_inodes: Vec::new(),
             file_parents: BTreeMap::new(),
+            config,
+            device: None,
         })
     }
 
@@ -569,9 +589,240 @@ Blob ID: {}
 
         Ok(None)
     }
+
+    // Implement command "cat"
+    // Print content of a regular file, data is fetched through the configured backend and cache
+    fn cmd_cat_file(&mut self, file_path: &str) -> Result<Option<Value>, anyhow::Error> {
+        let path = self.resolve_path(file_path);
+        let inode = self
+            .rafs_meta
+            .get_extended_inode(self.ino_from_path(&path)?, false)?;
+        if !inode.is_reg() {
+            bail!("{} is not a regular file", path.display());
+        }
+
+        let device = self.get_blob_device()?;
+        let mut reader = BufReader::with_capacity(
+            FILE_READ_BUF_SIZE,
+            InodeReader::new(&device, inode.as_inode()),
+        );
+        if self.request_mode {
+            let mut data = Vec::with_capacity(inode.size() as usize);
+            reader.read_to_end(&mut data)?;
+            // Binary content can't be represented in JSON strings without being mangled.
+            let data = String::from_utf8(data).map_err(|_| {
+                anyhow!(
+                    "{} contains non UTF-8 data, use \"extract\" instead",
+                    path.display()
+                )
+            })?;
+            Ok(Some(json!({"path": path,
+                           "size": inode.size(),
+                           "data": data})))
+        } else {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            io::copy(&mut reader, &mut stdout)?;
+            stdout.flush()?;
+            Ok(None)
+        }
+    }
+
+    // Implement command "extract"
+    // Copy a file or a directory tree out of the RAFS filesystem, preserving mode, owner and xattrs
+    fn cmd_extract(&mut self, file_path: &str, dest: &str) -> Result<Option<Value>, anyhow::Error> {
+        let path = self.resolve_path(file_path);
+        let ino = self.ino_from_path(&path)?;
+        let inode = self.rafs_meta.get_extended_inode(ino, false)?;
+        let device = self.get_blob_device()?;
+        let mut dest = PathBuf::from(dest);
+        let mut extractor = Extractor::new(&device);
+
+        if inode.is_dir() {
+            fs::create_dir_all(&dest)?;
+            self.walk_dir(ino, None, None, &mut |_parent, inode, path| {
+                let target = extract_target(&dest, path)?;
+                extractor.extract(inode, &target)
+            })?;
+        } else {
+            if dest.is_dir() {
+                dest = extract_target(&dest, Path::new(&inode.name()))?;
+            }
+            extractor.extract(inode.as_inode(), &dest)?;
+        }
+        extractor.finish()?;
+
+        if self.request_mode {
+            Ok(Some(json!({"path": path,
+                           "dest": dest,
+                           "files": extractor.count})))
+        } else {
+            println!(
+                "Extracted {} files from {} to {}",
+                extractor.count,
+                path.display(),
+                dest.display()
+            );
+            Ok(None)
+        }
+    }
+
+    // Implement command "find"
+    // Search files under current directory whose name, or path if the pattern contains '/',
+    // matches the glob pattern
+    fn cmd_find(&self, pattern: &str) -> Result<Option<Value>, anyhow::Error> {
+        let glob = glob::Pattern::new(pattern)
+            .map_err(|e| anyhow!("invalid glob pattern {}, {}", pattern, e))?;
+        let match_path = pattern.contains('/');
+        let parent = self.parent_path_of(self.cur_dir_ino)?;
+        let mut value = json!([]);
+
+        self.walk_dir(
+            self.cur_dir_ino,
+            parent.as_ref(),
+            None,
+            &mut |_parent, _inode, path| {
+                let matched = if match_path {
+                    glob.matches_path(path)
+                } else {
+                    path.file_name()
+                        .map(|n| glob.matches(&n.to_string_lossy()))
+                        .unwrap_or(false)
+                };
+                if matched {
+                    if self.request_mode {
+                        value.as_array_mut().unwrap().push(json!(path));
+                    } else {
+                        println!("{}", path.display());
+                    }
+                }
+                Ok(())
+            },
+        )?;
+
+        if self.request_mode {
+            return Ok(Some(value));
+        }
+        Ok(None)
+    }
+
+    // Implement command "grep"
+    // Search lines matching the regular expression in regular files under the specified path,
+    // which defaults to current directory
+    fn cmd_grep(
+        &mut self,
+        pattern: &str,
+        file_path: Option<&str>,
+    ) -> Result<Option<Value>, anyhow::Error> {
+        let regex =
+            Regex::new(pattern).map_err(|e| anyhow!("invalid pattern {}, {}", pattern, e))?;
+        let path = self.resolve_path(file_path.unwrap_or("."));
+        let ino = self.ino_from_path(&path)?;
+        let inode = self.rafs_meta.get_extended_inode(ino, false)?;
+        let device = self.get_blob_device()?;
+        let mut value = json!([]);
+
+        let mut grep_file = |inode: &dyn RafsInode, path: &Path| -> anyhow::Result<()> {
+            if !inode.is_reg() {
+                return Ok(());
+            }
+            let mut reader =
+                BufReader::with_capacity(FILE_READ_BUF_SIZE, InodeReader::new(&device, inode));
+            let mut line = Vec::new();
+            let mut line_no = 0;
+            loop {
+                line.clear();
+                if reader.read_until(b'\n', &mut line)? == 0 {
+                    break;
+                }
+                line_no += 1;
+                let text = String::from_utf8_lossy(&line);
+                let text = text.trim_end_matches('\n');
+                if regex.is_match(text) {
+                    if self.request_mode {
+                        let v = json!({"path": path, "line": line_no, "text": text});
+                        value.as_array_mut().unwrap().push(v);
+                    } else {
+                        println!("{}:{}:{}", path.display(), line_no, text);
+                    }
+                }
+            }
+            Ok(())
+        };
+
+        if inode.is_dir() {
+            let parent = self.parent_path_of(ino)?;
+            self.walk_dir(ino, parent.as_ref(), None, &mut |_parent, inode, path| {
+                grep_file(inode, path)
+            })?;
+        } else {
+            grep_file(inode.as_inode(), &path)?;
+        }
+
+        if self.request_mode {
+            return Ok(Some(value));
+        }
+        Ok(None)
+    }
 }
 
 impl RafsInspector {
+    // Create the blob device on first use, so commands not accessing file data work without
+    // a storage backend.
+    fn get_blob_device(&mut self) -> Result<BlobDevice, anyhow::Error> {
+        if let Some(device) = self.device.as_ref() {
+            return Ok(device.clone());
+        }
+
+        let blob_infos = self.rafs_meta.superblock.get_blob_infos();
+        let device = BlobDevice::new(&self.config, &blob_infos)
+            .map_err(|e| anyhow!("failed to create blob device, {}", e))?;
+        if self.config.is_chunk_validation_enabled()
+            && self.rafs_meta.meta.has_inlined_chunk_digest()
+        {
+            self.rafs_meta.superblock.set_blob_device(device.clone());
+        }
+        self.device = Some(device.clone());
+
+        Ok(device)
+    }
+
+    // Convert a path relative to current directory into a normalized absolute path.
+    fn resolve_path(&self, file_path: &str) -> PathBuf {
+        let mut path = PathBuf::from("/");
+        if !file_path.starts_with('/') {
+            if let Ok(cur_dir) = self.rafs_meta.path_from_ino(self.cur_dir_ino) {
+                path = path.join(cur_dir);
+            }
+        }
+        for comp in Path::new(file_path).components() {
+            match comp {
+                Component::Normal(name) => path.push(name),
+                Component::ParentDir => {
+                    path.pop();
+                }
+                _ => {}
+            }
+        }
+        path
+    }
+
+    fn ino_from_path(&self, path: &Path) -> Result<u64, anyhow::Error> {
+        self.rafs_meta
+            .ino_from_path(path)
+            .map_err(|e| anyhow!("failed to find {}, {}", path.display(), e))
+    }
+
+    // Get path of the parent directory of a directory inode, used as base path for `walk_dir()`.
+    fn parent_path_of(&self, ino: u64) -> Result<Option<PathBuf>, anyhow::Error> {
+        if ino == self.rafs_meta.superblock.root_ino() {
+            return Ok(None);
+        }
+        let inode = self.rafs_meta.get_extended_inode(ino, false)?;
+        let parent = self.rafs_meta.path_from_ino(inode.parent())?;
+        Ok(Some(PathBuf::from("/").join(parent)))
+    }
+
     /// Get file name of the inode, the rafs v6 file is handled separately.
     fn get_file_name(&self, parent_inode: &dyn RafsInodeExt, inode: &dyn RafsInode) -> OsString {
         let mut filename = OsString::from("");
@@ -652,6 +903,225 @@ Blocks:             {blocks}"#,
     }
 }
 
+// A `ZeroCopyWriter` to receive file data read from blobs into a memory buffer.
+struct BufferWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Write for BufferWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let size = cmp::min(data.len(), self.available_bytes());
+        self.buf[self.pos..self.pos + size].copy_from_slice(&data[..size]);
+        self.pos += size;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ZeroCopyWriter for BufferWriter<'_> {
+    fn write_from(
+        &mut self,
+        f: &mut dyn FileReadWriteVolatile,
+        count: usize,
+        off: u64,
+    ) -> io::Result<usize> {
+        let size = cmp::min(count, self.available_bytes());
+        let buf = &mut self.buf[self.pos..self.pos + size];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+        let size = f.read_vectored_at_volatile(&[slice], off)?;
+        self.pos += size;
+        Ok(size)
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+}
+
+// Read data of a regular file through the blob device.
+struct InodeReader<'a> {
+    device: &'a BlobDevice,
+    inode: &'a dyn RafsInode,
+    offset: u64,
+}
+
+impl<'a> InodeReader<'a> {
+    fn new(device: &'a BlobDevice, inode: &'a dyn RafsInode) -> Self {
+        InodeReader {
+            device,
+            inode,
+            offset: 0,
+        }
+    }
+}
+
+impl Read for InodeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let file_size = self.inode.size();
+        if buf.is_empty() || self.offset >= file_size {
+            return Ok(0);
+        }
+
+        let size = cmp::min(buf.len() as u64, file_size - self.offset) as usize;
+        let mut descs = self
+            .inode
+            .alloc_bio_vecs(self.device, self.offset, size, true)?;
+        let mut writer = BufferWriter {
+            buf: &mut buf[..size],
+            pos: 0,
+        };
+        for desc in descs.iter_mut() {
+            let size = self.device.read_to(&mut writer, desc)?;
+            if size as u32 != desc.size() {
+                break;
+            }
+        }
+        if writer.pos == 0 {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "failed to read data of inode {} at offset 0x{:x}",
+                    self.inode.ino(),
+                    self.offset
+                ),
+            ));
+        }
+
+        self.offset += writer.pos as u64;
+        Ok(writer.pos)
+    }
+}
+
+// Get the path under `dest` to extract the RAFS file at `path` to.
+//
+// Paths are built from file names in RAFS metadata, which can't be trusted. So paths escaping
+// from `dest`, either by special components such as `..` or through existing symlinks, are
+// rejected.
+fn extract_target(dest: &Path, path: &Path) -> anyhow::Result<PathBuf> {
+    let mut target = dest.to_path_buf();
+    for (idx, component) in path.components().enumerate() {
+        match component {
+            Component::RootDir if idx == 0 => continue,
+            Component::Normal(name) => target.push(name),
+            _ => bail!("invalid path {} to extract", path.display()),
+        }
+        if fs::symlink_metadata(&target)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false)
+        {
+            bail!("refuse to extract {} through symlink", target.display());
+        }
+    }
+    Ok(target)
+}
+
+// Create files extracted from RAFS filesystem on the local filesystem.
+struct Extractor<'a> {
+    device: &'a BlobDevice,
+    // Hard links are recreated against the first extracted path of the inode
+    links: HashMap<u64, PathBuf>,
+    // Directory permissions are applied after all their children have been created
+    dirs: Vec<(PathBuf, u32)>,
+    count: u64,
+}
+
+impl<'a> Extractor<'a> {
+    fn new(device: &'a BlobDevice) -> Self {
+        Extractor {
+            device,
+            links: HashMap::new(),
+            dirs: Vec::new(),
+            count: 0,
+        }
+    }
+
+    fn extract(&mut self, inode: &dyn RafsInode, target: &Path) -> anyhow::Result<()> {
+        let attr = inode.get_attr();
+        let mode = attr.mode & 0o7777;
+
+        if inode.is_dir() {
+            fs::create_dir_all(target)?;
+            self.dirs.push((target.to_path_buf(), mode));
+        } else if let Some(source) = self.links.get(&inode.ino()) {
+            fs::hard_link(source, target)
+                .with_context(|| format!("failed to create hard link {}", target.display()))?;
+            self.count += 1;
+            return Ok(());
+        } else if inode.is_symlink() {
+            std::os::unix::fs::symlink(inode.get_symlink()?, target)
+                .with_context(|| format!("failed to create symlink {}", target.display()))?;
+        } else if inode.is_reg() {
+            // Never overwrite existing files or write through symlinks.
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(target)
+                .with_context(|| format!("failed to create file {}", target.display()))?;
+            let mut reader =
+                BufReader::with_capacity(FILE_READ_BUF_SIZE, InodeReader::new(self.device, inode));
+            io::copy(&mut reader, &mut file)
+                .with_context(|| format!("failed to extract file {}", target.display()))?;
+        } else {
+            let kind = SFlag::from_bits_truncate(attr.mode & libc::S_IFMT);
+            mknod(
+                target,
+                kind,
+                Mode::from_bits_truncate(mode),
+                inode.rdev() as u64,
+            )
+            .with_context(|| format!("failed to create special file {}", target.display()))?;
+        }
+
+        if inode.get_attr().nlink > 1 && !inode.is_dir() {
+            self.links.insert(inode.ino(), target.to_path_buf());
+        }
+        // Restoring owner requires privilege, keep going without it.
+        if let Err(e) = fchownat(
+            None,
+            target,
+            Some(Uid::from_raw(attr.uid)),
+            Some(Gid::from_raw(attr.gid)),
+            FchownatFlags::NoFollowSymlink,
+        ) {
+            warn!("failed to set owner of {}, {}", target.display(), e);
+        }
+        if inode.has_xattr() {
+            for name in inode.get_xattrs()? {
+                let name = OsStr::from_bytes(&name);
+                if let Some(value) = inode.get_xattr(name)? {
+                    if let Err(e) = xattr::set(target, name, &value) {
+                        warn!(
+                            "failed to set xattr {:?} of {}, {}",
+                            name,
+                            target.display(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+        // Changing owner clears setuid/setgid bits, so set permissions after chown.
+        if inode.is_reg() {
+            fs::set_permissions(target, Permissions::from_mode(mode))?;
+        }
+        self.count += 1;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        for (dir, mode) in self.dirs.drain(..).rev() {
+            fs::set_permissions(&dir, Permissions::from_mode(mode))?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum ExecuteError {
     HelpCommand,
@@ -677,6 +1147,7 @@ impl Executor {
             None => return Ok(None),
         };
         let args = raw.next().map(|a| a.trim());
+        let args2 = raw.next().map(|a| a.trim());
         debug!("execute {:?} {:?}", cmd, args);
 
         let output = match (cmd, args) {
@@ -702,6 +1173,16 @@ impl Executor {
                 })?;
                 inspector.cmd_check_inode(ino)
             }
+            ("cat", Some(file_path)) => inspector.cmd_cat_file(file_path),
+            ("extract", Some(file_path)) => {
+                let dest = args2.ok_or_else(|| {
+                    println!("Destination path is not specified.");
+                    ExecuteError::ArgumentParse
+                })?;
+                inspector.cmd_extract(file_path, dest)
+            }
+            ("find", Some(pattern)) => inspector.cmd_find(pattern),
+            ("grep", Some(pattern)) => inspector.cmd_grep(pattern, args2),
             (cmd, _) => {
                 println!("Unsupported command: {}", cmd);
                 {
@@ -726,6 +1207,10 @@ impl Executor {
     prefetch:           Show prefetch table
     chunk OFFSET:       List basic info of a single chunk together with a list of files that share it
     icheck INODE:       Show path of the inode and basic information
+    cat FILE:           Print content of a regular file
+    extract PATH DEST:  Extract a file or a directory recursively to local path DEST
+    find PATTERN:       Find files under current directory matching the glob pattern
+    grep PATTERN [PATH]: Search regular files under PATH for lines matching the regex pattern
     exit:               Exit
         "#
         );
@@ -760,3 +1245,147 @@ impl Prompt {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{build_image, localfs_config};
+    use vmm_sys_util::tempdir::TempDir;
+
+    fn create_inspector(tmpdir: &TempDir) -> RafsInspector {
+        let source = tmpdir.as_path().join("source");
+        fs::create_dir_all(source.join("dir")).unwrap();
+        fs::write(source.join("text"), "hello\nworld\nnydus\n").unwrap();
+        fs::write(source.join("bin"), [0xffu8, 0xfe, 0x00, 0x80]).unwrap();
+        fs::write(source.join("dir/sub.txt"), "hello nydus\n").unwrap();
+        fs::write(source.join("dir/suid"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(source.join("dir/suid"), Permissions::from_mode(0o4755)).unwrap();
+        std::os::unix::fs::symlink("sub.txt", source.join("dir/link")).unwrap();
+
+        let blob_dir = tmpdir.as_path().join("blobs");
+        fs::create_dir_all(&blob_dir).unwrap();
+        let bootstrap = build_image(&source, &blob_dir);
+        RafsInspector::new(&bootstrap, true, localfs_config(&blob_dir)).unwrap()
+    }
+
+    #[test]
+    fn test_cmd_cat_file() {
+        let tmpdir = TempDir::new().unwrap();
+        let mut inspector = create_inspector(&tmpdir);
+
+        let value = inspector.cmd_cat_file("/text").unwrap().unwrap();
+        assert_eq!(value["size"], 18);
+        assert_eq!(value["data"], "hello\nworld\nnydus\n");
+        assert!(inspector.cmd_cat_file("/bin").is_err());
+        assert!(inspector.cmd_cat_file("/dir").is_err());
+        assert!(inspector.cmd_cat_file("/nonexist").is_err());
+    }
+
+    #[test]
+    fn test_cmd_find() {
+        let tmpdir = TempDir::new().unwrap();
+        let inspector = create_inspector(&tmpdir);
+
+        let value = inspector.cmd_find("*.txt").unwrap().unwrap();
+        assert_eq!(value, json!(["/dir/sub.txt"]));
+        let value = inspector.cmd_find("/dir/*").unwrap().unwrap();
+        let mut paths: Vec<&str> = value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_str().unwrap())
+            .collect();
+        paths.sort_unstable();
+        assert_eq!(paths, vec!["/dir/link", "/dir/sub.txt", "/dir/suid"]);
+        assert!(inspector.cmd_find("[").is_err());
+    }
+
+    #[test]
+    fn test_cmd_grep() {
+        let tmpdir = TempDir::new().unwrap();
+        let mut inspector = create_inspector(&tmpdir);
+
+        let value = inspector.cmd_grep("nydus", None).unwrap().unwrap();
+        let mut matches: Vec<(String, u64)> = value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| {
+                (
+                    v["path"].as_str().unwrap().to_string(),
+                    v["line"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        matches.sort_unstable();
+        assert_eq!(
+            matches,
+            vec![("/dir/sub.txt".to_string(), 1), ("/text".to_string(), 3)]
+        );
+
+        let value = inspector.cmd_grep("^wor", Some("/text")).unwrap().unwrap();
+        assert_eq!(
+            value,
+            json!([{"path": "/text", "line": 2, "text": "world"}])
+        );
+        assert!(inspector.cmd_grep("(", None).is_err());
+    }
+
+    #[test]
+    fn test_cmd_extract() {
+        let tmpdir = TempDir::new().unwrap();
+        let mut inspector = create_inspector(&tmpdir);
+        let dest = tmpdir.as_path().join("dest");
+
+        let value = inspector
+            .cmd_extract("/dir", dest.to_str().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(value["files"], 4);
+        assert_eq!(
+            fs::read_to_string(dest.join("sub.txt")).unwrap(),
+            "hello nydus\n"
+        );
+        let meta = fs::metadata(dest.join("suid")).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o4755);
+        assert_eq!(
+            fs::read_link(dest.join("link")).unwrap(),
+            PathBuf::from("sub.txt")
+        );
+
+        let value = inspector
+            .cmd_extract("/bin", dest.to_str().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(value["files"], 1);
+        assert_eq!(
+            fs::read(dest.join("bin")).unwrap(),
+            [0xffu8, 0xfe, 0x00, 0x80]
+        );
+        // Existing files are not overwritten.
+        assert!(inspector
+            .cmd_extract("/bin", dest.to_str().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_extract_target() {
+        let tmpdir = TempDir::new().unwrap();
+        let dest = tmpdir.as_path();
+        assert_eq!(
+            extract_target(dest, Path::new("/a/b")).unwrap(),
+            dest.join("a/b")
+        );
+        assert_eq!(
+            extract_target(dest, Path::new("a")).unwrap(),
+            dest.join("a")
+        );
+        assert_eq!(extract_target(dest, Path::new("/")).unwrap(), dest);
+        assert!(extract_target(dest, Path::new("..")).is_err());
+        assert!(extract_target(dest, Path::new("/a/../../b")).is_err());
+
+        std::os::unix::fs::symlink("/tmp", dest.join("link")).unwrap();
+        assert!(extract_target(dest, Path::new("/link")).is_err());
+        assert!(extract_target(dest, Path::new("/link/file")).is_err());
+    }
+}
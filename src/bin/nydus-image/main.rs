@@ -25,14 +25,20 @@ use clap::parser::ValueSource;
 use clap::{Arg, ArgAction, ArgMatches, Command as App};
 use nix::unistd::{getegid, geteuid};
 use nydus::get_build_time_info;
+#[cfg(feature = "block-export")]
+use nydus_api::BlobCacheEntry;
 use nydus_api::{BuildTimeInfo, ConfigV2, LocalFsConfig};
 use nydus_app::setup_logging;
 use nydus_rafs::builder::{
     parse_chunk_dict_arg, ArtifactStorage, BlobCompactor, BlobManager, BootstrapManager,
-    BuildContext, BuildOutput, Builder, ConversionType, DirectoryBuilder, Feature, Features,
-    HashChunkDict, Prefetch, PrefetchPolicy, StargzBuilder, TarballBuilder, WhiteoutSpec,
+    BuildContext, BuildOutput, Builder, CdcChunker, ConversionType, DirectoryBuilder, Feature,
+    Features, HashChunkDict, Prefetch, PrefetchPolicy, StargzBuilder, TarballBuilder, WhiteoutSpec,
 };
 use nydus_rafs::metadata::{RafsSuper, RafsSuperConfig, RafsVersion};
+#[cfg(feature = "block-export")]
+use nydus_service::blob_cache::{generate_blob_key, BlobCacheMgr};
+#[cfg(feature = "block-export")]
+use nydus_service::block_device::BlockDevice;
 use nydus_storage::backend::localfs::LocalFs;
 use nydus_storage::backend::BlobBackend;
 use nydus_storage::device::BlobFeatures;
@@ -44,12 +50,18 @@ use nydus_utils::{compress, digest, event_tracer, register_tracer, root_tracer,
 use serde::{Deserialize, Serialize};
 
 use crate::merge::Merger;
-use crate::unpack::{OCIUnpacker, Unpacker};
-use crate::validator::Validator;
+use crate::unpack::{DirUnpacker, OCIUnpacker, Unpacker};
+use crate::validator::{DataCheckResult, Validator};
 
+mod diff;
+mod gc;
 mod inspect;
 mod merge;
+mod oci;
+mod scrub;
 mod stat;
+#[cfg(test)]
+mod test;
 mod unpack;
 mod validator;
 
@@ -67,6 +79,9 @@ pub struct OutputSerializer {
     blobs: Vec<String>,
     /// Performance trace info for current build.
     trace: serde_json::Map<String, serde_json::Value>,
+    /// Result of checking data blobs, only for `check --data`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
 }
 
 impl OutputSerializer {
@@ -93,6 +108,7 @@ impl OutputSerializer {
                 bootstrap: build_output.bootstrap_path.unwrap_or_default(),
                 blobs: build_output.blobs,
                 trace,
+                data: None,
             };
 
             serde_json::to_writer_pretty(w, &output)
@@ -107,6 +123,7 @@ impl OutputSerializer {
         build_info: &BuildTimeInfo,
         blob_ids: Vec<String>,
         bootstrap: &Path,
+        data: Option<&DataCheckResult>,
     ) -> Result<()> {
         let output_json: Option<PathBuf> = matches
             .get_one::<String>("output-json")
@@ -126,6 +143,7 @@ impl OutputSerializer {
                 bootstrap: bootstrap.display().to_string(),
                 blobs: blob_ids,
                 trace,
+                data: data.map(serde_json::to_value).transpose()?,
             };
 
             serde_json::to_writer(w, &output).context("failed to write result to output file")?;
@@ -185,6 +203,8 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                             "targz-rafs",
                             "targz-ref",
                             "stargz_index",
+                            "oci-rafs",
+                            "docker-rafs",
                         ])
                 )
                 .arg(
@@ -223,6 +243,11 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .required_if_eq_any([("type", "estargztoc-ref"), ("type", "stargz_index")])
                         .help("OSS object id for the generated RAFS data blob")
                 )
+                .arg(
+                    Arg::new("image-manifest")
+                        .long("image-manifest")
+                        .help("File path to save the generated image manifest for 'oci-rafs' and 'docker-rafs' conversions, default to 'manifest.json' in '--blob-dir'"),
+                )
                 .arg(
                     Arg::new("blob-data-size")
                         .long("blob-data-size")
@@ -241,6 +266,19 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .help("Set the size of data chunks, must be power of two and between 0x1000-0x1000000:")
                         .required(false),
                 )
+                .arg(
+                    Arg::new("cdc-chunk-size")
+                        .long("cdc-chunk-size")
+                        .help("Split files into variable sized chunks by content-defined chunking, in format '<min>,<avg>,<max>' [RAFS v5 only]:")
+                        .conflicts_with("chunk-size")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("zran-span")
+                        .long("zran-span")
+                        .help("Set spacing of compressed data between random access checkpoints for 'targz-ref' conversion, between 0x10000-0x4000000:")
+                        .required(false),
+                )
                 .arg(
                     Arg::new("compressor")
                         .long("compressor")
@@ -281,6 +319,13 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .help("File path of the parent/referenced RAFS metadata blob (optional)")
                         .required(false),
                 )
+                .arg(
+                    Arg::new("delta")
+                        .long("delta")
+                        .help("Build a delta layer against '--parent-bootstrap' from the full root filesystem of the new version, reusing chunks of unchanged files")
+                        .action(ArgAction::SetTrue)
+                        .requires("parent-bootstrap"),
+                )
                 .arg(
                     Arg::new("aligned-chunk")
                         .long("aligned-chunk")
@@ -410,6 +455,27 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .action(ArgAction::SetTrue)
                         .required(false),
                 )
+                .arg(
+                    Arg::new("data")
+                        .long("data")
+                        .help("Verify chunk data and blob metadata digests through the storage backend")
+                        .action(ArgAction::SetTrue)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("threads")
+                        .long("threads")
+                        .help("Number of worker threads to verify chunk data")
+                        .default_value("4")
+                        .requires("data"),
+                )
+                .arg(
+                    Arg::new("allow-unverified")
+                        .long("allow-unverified")
+                        .help("Do not fail the check if chunk data of some blobs, such as ZRAN blobs, can't be verified")
+                        .action(ArgAction::SetTrue)
+                        .requires("data"),
+                )
                 .arg(
                     arg_output_json.clone(),
                 )
@@ -446,6 +512,24 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .required(false),
                 )
         )
+        .subcommand(
+            App::new("diff")
+                .about("Compare two RAFS filesystems and report added, removed and modified files")
+                .arg(
+                    Arg::new("BOOTSTRAP_A")
+                        .help("File path of the old RAFS metadata")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("BOOTSTRAP_B")
+                        .help("File path of the new RAFS metadata")
+                        .required(true),
+                )
+                .arg(arg_config.clone())
+                .arg(
+                    arg_output_json.clone(),
+                )
+        )
         .subcommand(
             App::new("stat")
                 .about("Generate statistics information for RAFS filesystems")
@@ -513,13 +597,63 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .short('O')
                         .help("bootstrap to output, default is source bootstrap add suffix .compact"),
                 )
+                .arg(
+                    arg_output_json.clone(),
+                )
+        )
+        .subcommand(
+            App::new("scrub")
+                .about("Verify cached data of a RAFS filesystem and drop corrupted chunks from blob caches")
+                .arg(
+                    Arg::new("BOOTSTRAP")
+                        .help("File path of RAFS metadata")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("blob-dir")
+                        .long("blob-dir")
+                        .short('D')
+                        .conflicts_with("config")
+                        .help("Directory for localfs storage backend, hosting data blobs and cache files"),
+                )
+                .arg(arg_config.clone())
+                .arg(
+                    arg_output_json.clone(),
+                )
+        )
+        .subcommand(
+            App::new("gc")
+                .about("Remove data blobs which are not referenced by any live RAFS filesystem")
+                .arg(
+                    Arg::new("BOOTSTRAP")
+                        .help("File paths of live RAFS metadata (allow one or more)")
+                        .required(true)
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("blob-dir")
+                        .long("blob-dir")
+                        .short('D')
+                        .conflicts_with("config")
+                        .help("Directory for localfs storage backend, hosting data blobs to collect"),
+                )
+                .arg(arg_config.clone())
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .short('f')
+                        .help("Remove unreferenced data blobs instead of only reporting them")
+                        .action(ArgAction::SetTrue)
+                        .required(false),
+                )
                 .arg(
                     arg_output_json,
                 )
         )
+        .subcommands(export_subcommands(&arg_config))
         .subcommand(
             App::new("unpack")
-            .about("Unpack a RAFS filesystem to a tar file")
+            .about("Unpack a RAFS filesystem to a tar file or a directory")
                 .arg(
                     Arg::new("BOOTSTRAP")
                         .help("File path of RAFS metadata")
@@ -558,7 +692,20 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                 Arg::new("output")
                 .long("output")
                 .help("path for output tar file")
-                .required(true),
+                .required_unless_present("output-dir"),
+                )
+            .arg(
+                Arg::new("output-dir")
+                .long("output-dir")
+                .help("path for output directory to restore the filesystem tree into")
+                .conflicts_with("output"),
+                )
+            .arg(
+                Arg::new("threads")
+                .long("threads")
+                .help("Number of worker threads to fetch and decompress data chunks")
+                .default_value("4")
+                .requires("output-dir"),
                 )
         )
         .arg(
@@ -581,6 +728,60 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
         )
 }
 
+// Exporting disk images depends on block device support of nydus-service, which is optional.
+#[cfg(feature = "block-export")]
+fn export_subcommands(arg_config: &Arg) -> Vec<App> {
+    vec![
+        App::new("export")
+            .about("Export a RAFS filesystem as a raw disk image, which can be mounted by EROFS driver without a daemon")
+            .arg(
+                Arg::new("BOOTSTRAP")
+                    .help("File path of RAFS metadata")
+                    .required_unless_present("bootstrap"),
+            )
+            .arg(
+                Arg::new("bootstrap")
+                    .short('B')
+                    .long("bootstrap")
+                    .help("[Deprecated] File path of RAFS meta blob/bootstrap")
+                    .conflicts_with("BOOTSTRAP")
+                    .required(false),
+            )
+            .arg(
+                Arg::new("block")
+                    .long("block")
+                    .help("Export the RAFS v6 filesystem as a raw block disk image to the file")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("blob-dir")
+                    .long("blob-dir")
+                    .short('D')
+                    .conflicts_with("config")
+                    .help("Directory for localfs storage backend, hosting data blobs and cache files"),
+            )
+            .arg(arg_config.clone())
+            .arg(
+                Arg::new("verity")
+                    .long("verity")
+                    .help("Append a dm-verity hash tree to the disk image")
+                    .action(ArgAction::SetTrue)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("threads")
+                    .long("threads")
+                    .help("Number of worker threads to read data blobs")
+                    .default_value("4"),
+            ),
+    ]
+}
+
+#[cfg(not(feature = "block-export"))]
+fn export_subcommands(_arg_config: &Arg) -> Vec<App> {
+    Vec::new()
+}
+
 fn init_log(matches: &ArgMatches) -> Result<()> {
     let mut log_file = None;
     if let Some(file) = matches.get_one::<String>("log-file") {
@@ -622,13 +823,23 @@ fn main() -> Result<()> {
         Command::check(matches, &build_info)
     } else if let Some(matches) = cmd.subcommand_matches("inspect") {
         Command::inspect(matches)
+    } else if let Some(matches) = cmd.subcommand_matches("diff") {
+        Command::diff(matches)
     } else if let Some(matches) = cmd.subcommand_matches("stat") {
         Command::stat(matches)
     } else if let Some(matches) = cmd.subcommand_matches("compact") {
         Command::compact(matches, &build_info)
+    } else if let Some(matches) = cmd.subcommand_matches("scrub") {
+        Command::scrub(matches)
+    } else if let Some(matches) = cmd.subcommand_matches("gc") {
+        Command::gc(matches)
     } else if let Some(matches) = cmd.subcommand_matches("unpack") {
         Command::unpack(matches)
     } else {
+        #[cfg(feature = "block-export")]
+        if let Some(matches) = cmd.subcommand_matches("export") {
+            return Command::export(matches);
+        }
         println!("{}", usage);
         Ok(())
     }
@@ -646,6 +857,7 @@ impl Command {
         let conversion_type: ConversionType = matches.get_one::<String>("type").unwrap().parse()?;
         let blob_storage = Self::get_blob_storage(matches, conversion_type)?;
         let blob_inline_meta = matches.get_flag("blob-inline-meta");
+        let delta_build = matches.get_flag("delta");
         let repeatable = matches.get_flag("repeatable");
         let version = Self::get_fs_version(matches)?;
         let chunk_size = Self::get_chunk_size(matches, conversion_type)?;
@@ -772,6 +984,25 @@ impl Command {
                     bail!("'--blob-id' is missing for '--type stargz_index'");
                 }
             }
+            ConversionType::OciImageToRafs | ConversionType::DockerArchiveToRafs => {
+                if conversion_type == ConversionType::OciImageToRafs {
+                    Self::ensure_directory(&source_path)?;
+                } else {
+                    Self::ensure_file(&source_path)?;
+                }
+                if !matches!(blob_storage, Some(ArtifactStorage::FileDir(_))) {
+                    bail!(
+                        "conversion type '{}' requires '--blob-dir' to store generated blobs",
+                        conversion_type
+                    );
+                }
+                if blob_inline_meta || parent_path.is_some() || blob_id.trim() != "" {
+                    bail!(
+                        "conversion type '{}' conflicts with '--blob-inline-meta', '--parent-bootstrap' and '--blob-id'",
+                        conversion_type
+                    );
+                }
+            }
             ConversionType::DirectoryToStargz
             | ConversionType::TargzToStargz
             | ConversionType::TarToStargz => {
@@ -785,6 +1016,16 @@ impl Command {
         if features.is_enabled(Feature::BlobToc) && version == RafsVersion::V5 {
             bail!("`--features blob-toc` can't be used with `--version 5` ");
         }
+        if delta_build && conversion_type != ConversionType::DirectoryToRafs {
+            bail!(
+                "conversion type '{}' conflicts with '--delta', only 'dir-rafs' is supported",
+                conversion_type
+            );
+        }
+
+        let chunker = Self::get_cdc_chunker(matches, conversion_type, version)?;
+        // The maximum chunk size is used as chunk size for content-defined chunking.
+        let chunk_size = chunker.as_ref().map(|c| c.max_size()).unwrap_or(chunk_size);
 
         let mut build_ctx = BuildContext::new(
             blob_id,
@@ -803,6 +1044,13 @@ impl Command {
         );
         build_ctx.set_fs_version(version);
         build_ctx.set_chunk_size(chunk_size);
+        if let Some(chunker) = chunker {
+            build_ctx.set_chunker(chunker);
+        }
+        build_ctx.delta_build = delta_build;
+        if let Some(span) = Self::get_zran_span(matches, conversion_type)? {
+            build_ctx.blob_zran_span = span;
+        }
 
         let mut config = Self::get_configuration(matches)?;
         if let Some(cache) = Arc::get_mut(&mut config).unwrap().cache.as_mut() {
@@ -829,6 +1077,13 @@ impl Command {
             )?);
         }
 
+        if matches!(
+            conversion_type,
+            ConversionType::OciImageToRafs | ConversionType::DockerArchiveToRafs
+        ) {
+            return Self::create_from_image(matches, &build_ctx, &blob_mgr, build_info);
+        }
+
         let mut bootstrap_mgr = if blob_inline_meta {
             BootstrapManager::new(None, parent_path)
         } else {
@@ -855,7 +1110,9 @@ impl Command {
             ConversionType::DirectoryToStargz
             | ConversionType::DirectoryToTargz
             | ConversionType::TarToStargz
-            | ConversionType::TargzToStargz => unimplemented!(),
+            | ConversionType::TargzToStargz
+            | ConversionType::OciImageToRafs
+            | ConversionType::DockerArchiveToRafs => unimplemented!(),
         };
         let build_output = timing_tracer!(
             {
@@ -874,6 +1131,44 @@ impl Command {
         OutputSerializer::dump(matches, build_output, build_info)
     }
 
+    // Convert all layers of a container image and merge them into a nydus image.
+    fn create_from_image(
+        matches: &ArgMatches,
+        build_ctx: &BuildContext,
+        blob_mgr: &BlobManager,
+        build_info: &BuildTimeInfo,
+    ) -> Result<()> {
+        let blob_dir = match build_ctx.blob_storage.as_ref() {
+            Some(ArtifactStorage::FileDir(d)) => d.clone(),
+            _ => bail!("'--blob-dir' is missing"),
+        };
+        let bootstrap = matches
+            .get_one::<String>("bootstrap")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| blob_dir.join("image.boot"));
+        let manifest = matches
+            .get_one::<String>("image-manifest")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| blob_dir.join("manifest.json"));
+
+        let builder = oci::OciImageBuilder::new(&blob_dir)?;
+        let build_output = timing_tracer!(
+            {
+                builder
+                    .build(build_ctx, blob_mgr.get_chunk_dict(), &bootstrap, &manifest)
+                    .context("build failed")
+            },
+            "total_build"
+        )?;
+
+        info!(
+            "successfully built nydus image, manifest {}: \n{}",
+            manifest.display(),
+            build_output
+        );
+        OutputSerializer::dump(matches, build_output, build_info)
+    }
+
     fn merge(matches: &ArgMatches, build_info: &BuildTimeInfo) -> Result<()> {
         let source_bootstrap_paths: Vec<PathBuf> = matches
             .get_many::<String>("SOURCE")
@@ -989,11 +1284,6 @@ impl Command {
         config
             .internal
             .set_blob_accessible(matches.get_one::<String>("config").is_some());
-        let output = matches.get_one::<String>("output").expect("pass in output");
-        if output.is_empty() {
-            return Err(anyhow!("invalid empty --output option"));
-        }
-
         let blob = matches.get_one::<String>("blob").map(|s| s.as_str());
         let backend: Option<Arc<dyn BlobBackend + Send + Sync>> = match blob {
             Some(blob_path) => {
@@ -1011,6 +1301,26 @@ impl Command {
             None => Self::get_backend(matches, "unpacker").ok(),
         };
 
+        if let Some(output) = matches.get_one::<String>("output-dir") {
+            // Data blobs may also be accessed through `--blob-dir` or `--config`.
+            let backend = backend.or_else(|| {
+                let backend_cfg = config.get_backend_config().ok()?;
+                BlobFactory::new_backend(backend_cfg, "unpacker").ok()
+            });
+            let threads = Self::get_threads(matches)? as usize;
+            fs::create_dir_all(output)
+                .with_context(|| format!("failed to create output directory {}", output))?;
+            return DirUnpacker::new(bootstrap, backend, Path::new(output), threads)
+                .with_context(|| "fail to create unpacker")?
+                .unpack(config)
+                .with_context(|| "fail to unpack");
+        }
+
+        let output = matches.get_one::<String>("output").expect("pass in output");
+        if output.is_empty() {
+            return Err(anyhow!("invalid empty --output option"));
+        }
+
         OCIUnpacker::new(bootstrap, backend, output)
             .with_context(|| "fail to create unpacker")?
             .unpack(config)
@@ -1026,7 +1336,7 @@ impl Command {
             .internal
             .set_blob_accessible(matches.get_one::<String>("bootstrap").is_none());
 
-        let mut validator = Validator::new(bootstrap_path, config)?;
+        let mut validator = Validator::new(bootstrap_path, config.clone())?;
         let blobs = validator
             .check(verbose)
             .with_context(|| format!("failed to check bootstrap {:?}", bootstrap_path))?;
@@ -1047,7 +1357,64 @@ impl Command {
             blob_ids.push(blob.blob_id().to_string());
         }
 
-        OutputSerializer::dump_for_check(matches, build_info, blob_ids, bootstrap_path)?;
+        let data = if matches.get_flag("data") {
+            let threads = Self::get_threads(matches)? as usize;
+            let result = validator
+                .check_data(&config, threads)
+                .with_context(|| format!("failed to check data of {:?}", bootstrap_path))?;
+            for blob in result.blobs.iter() {
+                let state = if !blob.errors.is_empty() {
+                    "FAILED"
+                } else if blob.skipped {
+                    "SKIPPED"
+                } else {
+                    "OK"
+                };
+                println!(
+                    "\t blob {}: {}, chunks: {}, failed chunks: {}",
+                    blob.blob_id, state, blob.chunks, blob.failed_chunks
+                );
+                for err in blob.errors.iter() {
+                    println!("\t\t {}", err);
+                }
+            }
+            for file in result.files.iter() {
+                println!("\t file {}: FAILED", file.path.display());
+                for err in file.errors.iter() {
+                    println!("\t\t {}", err);
+                }
+            }
+            Some(result)
+        } else {
+            None
+        };
+
+        OutputSerializer::dump_for_check(
+            matches,
+            build_info,
+            blob_ids,
+            bootstrap_path,
+            data.as_ref(),
+        )?;
+
+        if let Some(result) = data {
+            let allow_unverified = matches.get_flag("allow-unverified");
+            if !result.is_valid(allow_unverified) {
+                let failed = result.blobs.iter().filter(|b| !b.errors.is_empty()).count();
+                if result.files.is_empty() && failed == 0 {
+                    bail!(
+                        "data of {} blobs can't be verified, use `--allow-unverified` to ignore them",
+                        result.blobs.iter().filter(|b| b.skipped).count()
+                    );
+                }
+                bail!(
+                    "data of RAFS filesystem is invalid, {} files and {} blobs failed the check",
+                    result.files.len(),
+                    failed
+                );
+            }
+            println!("RAFS filesystem data is valid");
+        }
 
         Ok(())
     }
@@ -1081,6 +1448,31 @@ impl Command {
         Ok(())
     }
 
+    fn diff(matches: &ArgMatches) -> Result<()> {
+        // Safe to unwrap because they are required arguments.
+        let old = matches
+            .get_one::<String>("BOOTSTRAP_A")
+            .map(Path::new)
+            .unwrap();
+        let new = matches
+            .get_one::<String>("BOOTSTRAP_B")
+            .map(Path::new)
+            .unwrap();
+        let config = Self::get_configuration(matches)?;
+        config
+            .internal
+            .set_blob_accessible(matches.get_one::<String>("config").is_some());
+
+        let diff = diff::ImageDiff::diff(old, new, config)?;
+        if let Some(path) = matches.get_one::<String>("output-json").map(PathBuf::from) {
+            diff.dump_json(&path)?;
+        } else {
+            diff.dump();
+        }
+
+        Ok(())
+    }
+
     fn stat(matches: &ArgMatches) -> Result<()> {
         let digester = matches
             .get_one::<String>("digester")
@@ -1142,6 +1534,142 @@ impl Command {
         Ok(())
     }
 
+    fn scrub(matches: &ArgMatches) -> Result<()> {
+        let bootstrap_path = Self::get_bootstrap(matches)?;
+        let config = Self::get_configuration(matches)?;
+        match config.cache.as_ref() {
+            Some(cache) if cache.is_filecache() => {}
+            _ => bail!("one of `--blob-dir` and `--config` with filecache must be specified"),
+        }
+
+        let result = scrub::scrub(bootstrap_path, &config)?;
+        if let Some(path) = matches.get_one::<String>("output-json").map(PathBuf::from) {
+            result.dump_json(&path)?;
+        } else {
+            result.dump();
+        }
+        if result.failed_blobs() > 0 {
+            bail!("{} blobs can't be scrubbed", result.failed_blobs());
+        }
+        println!(
+            "Scrubbed blob caches of {:?}, corrupted chunks: {}",
+            bootstrap_path,
+            result.corrupted_chunks()
+        );
+
+        Ok(())
+    }
+
+    fn gc(matches: &ArgMatches) -> Result<()> {
+        let config = Self::get_configuration(matches)?;
+        let dirs = if let Some(dir) = matches.get_one::<String>("blob-dir") {
+            vec![PathBuf::from(dir)]
+        } else if matches.get_one::<String>("config").is_some() {
+            let localfs = config.get_backend_config()?.get_localfs_config()?;
+            if !localfs.blob_file.is_empty() {
+                bail!("garbage collection of a single blob file is not supported");
+            }
+            let mut dirs = Vec::new();
+            if !localfs.dir.is_empty() {
+                dirs.push(PathBuf::from(&localfs.dir));
+            }
+            dirs.extend(localfs.alt_dirs.iter().map(PathBuf::from));
+            dirs
+        } else {
+            Vec::new()
+        };
+        if dirs.is_empty() {
+            bail!("one of `--blob-dir` and `--config` with localfs backend must be specified");
+        }
+
+        let mut gc = gc::BlobGc::new();
+        let mut bootstraps = Vec::new();
+        for bootstrap in matches.get_many::<String>("BOOTSTRAP").unwrap() {
+            let path = PathBuf::from(bootstrap);
+            Self::ensure_file(&path)?;
+            gc.add_bootstrap(&path, config.clone())?;
+            bootstraps.push(fs::canonicalize(&path)?);
+        }
+
+        for dir in dirs.iter() {
+            Self::ensure_directory(dir)?;
+        }
+        let result = gc.collect(&dirs, &bootstraps, !matches.get_flag("force"))?;
+        if let Some(path) = matches.get_one::<String>("output-json").map(PathBuf::from) {
+            result.dump_json(&path)?;
+        } else {
+            result.dump();
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "block-export")]
+    fn export(matches: &ArgMatches) -> Result<()> {
+        let bootstrap_path = Self::get_bootstrap(matches)?;
+        let output = PathBuf::from(matches.get_one::<String>("block").unwrap());
+        let threads = Self::get_threads(matches)?;
+        let verity = matches.get_flag("verity");
+        let config = Self::get_configuration(matches)?;
+
+        // Data blobs are accessed through the blob cache, so a file cache is needed.
+        let cache = match config.cache.as_ref() {
+            Some(cache) if cache.is_filecache() => cache,
+            _ => bail!("one of `--blob-dir` and `--config` with filecache must be specified"),
+        };
+        let blob_id = bootstrap_path
+            .file_name()
+            .and_then(|v| v.to_str())
+            .ok_or_else(|| anyhow!("invalid bootstrap path {}", bootstrap_path.display()))?;
+        let mut entry: BlobCacheEntry = serde_json::from_value(json!({
+            "type": "bootstrap",
+            "id": blob_id,
+            "config_v2": {
+                "version": 2,
+                "id": "export",
+                "backend": config.get_backend_config()?,
+                "cache": cache,
+                "metadata_path": bootstrap_path,
+            }
+        }))?;
+        entry
+            .prepare_configuration_info()
+            .context("invalid storage backend or cache configuration")?;
+
+        let mgr = BlobCacheMgr::new();
+        mgr.add_blob_entry(&entry)
+            .with_context(|| format!("failed to load bootstrap {:?}", bootstrap_path))?;
+        let device = BlockDevice::new(generate_blob_key(&entry.domain_id, blob_id), Arc::new(mgr))
+            .context("failed to create block device")?;
+        let root = device
+            .export(&output, threads, verity)
+            .with_context(|| format!("failed to export disk image {:?}", output))?;
+
+        println!(
+            "Exported RAFS filesystem to {}, data blocks: {}",
+            output.display(),
+            device.blocks()
+        );
+        if let Some(root) = root {
+            println!(
+                "Verity hash offset: {}, root digest: {}",
+                (device.blocks() as u64) << 12,
+                root
+            );
+        }
+
+        Ok(())
+    }
+
+    fn get_threads(matches: &ArgMatches) -> Result<u32> {
+        let threads = matches.get_one::<String>("threads").unwrap();
+        threads
+            .parse::<u32>()
+            .ok()
+            .filter(|v| *v > 0 && *v <= 1024)
+            .ok_or_else(|| anyhow!("invalid number of threads {}", threads))
+    }
+
     fn get_bootstrap(matches: &ArgMatches) -> Result<&Path> {
         match matches.get_one::<String>("bootstrap") {
             Some(s) => Ok(Path::new(s)),
@@ -1228,9 +1756,9 @@ impl Command {
         } else {
             ConfigV2::default()
         };
-        if !config.validate() {
-            return Err(anyhow!("invalid configuration: {:?}", config));
-        }
+        config
+            .validate()
+            .map_err(|e| anyhow!("invalid configuration, {}", e))?;
 
         Ok(Arc::new(config))
     }
@@ -1278,6 +1806,52 @@ impl Command {
         }
     }
 
+    fn get_cdc_chunker(
+        matches: &ArgMatches,
+        ty: ConversionType,
+        version: RafsVersion,
+    ) -> Result<Option<CdcChunker>> {
+        match matches.get_one::<String>("cdc-chunk-size") {
+            None => Ok(None),
+            Some(v) => {
+                if version != RafsVersion::V5 {
+                    bail!("'--cdc-chunk-size' is only supported by RAFS v5, please use '--fs-version 5'");
+                }
+                match ty {
+                    ConversionType::DirectoryToRafs
+                    | ConversionType::TarToRafs
+                    | ConversionType::TargzToRafs
+                    | ConversionType::EStargzToRafs
+                    | ConversionType::OciImageToRafs
+                    | ConversionType::DockerArchiveToRafs => {}
+                    _ => bail!("conversion type '{}' conflicts with '--cdc-chunk-size'", ty),
+                }
+                Ok(Some(CdcChunker::parse(v)?))
+            }
+        }
+    }
+
+    fn get_zran_span(matches: &ArgMatches, ty: ConversionType) -> Result<Option<u64>> {
+        match matches.get_one::<String>("zran-span") {
+            None => Ok(None),
+            Some(v) => {
+                if ty != ConversionType::TargzToRef && ty != ConversionType::EStargzToRef {
+                    bail!("conversion type '{}' conflicts with '--zran-span'", ty);
+                }
+                let span = if v.starts_with("0x") || v.starts_with("0X") {
+                    u64::from_str_radix(&v[2..], 16).context(format!("invalid zran span {}", v))?
+                } else {
+                    v.parse::<u64>()
+                        .context(format!("invalid zran span {}", v))?
+                };
+                if !(0x10000..=0x4000000).contains(&span) {
+                    bail!("invalid zran span: {}", span);
+                }
+                Ok(Some(span))
+            }
+        }
+    }
+
     fn get_chunk_size(matches: &ArgMatches, ty: ConversionType) -> Result<u32> {
         match matches.get_one::<String>("chunk-size") {
             None => {
@@ -13,7 +13,8 @@ use hex::FromHex;
 use nydus_api::ConfigV2;
 use nydus_rafs::builder::{
     ArtifactStorage, BlobContext, BlobManager, Bootstrap, BootstrapContext, BuildContext,
-    BuildOutput, ChunkSource, HashChunkDict, MetadataTreeBuilder, Overlay, Tree, WhiteoutSpec,
+    BuildOutput, CdcChunker, ChunkSource, HashChunkDict, MetadataTreeBuilder, Overlay, Tree,
+    WhiteoutSpec,
 };
 use nydus_rafs::metadata::{RafsInodeExt, RafsSuper, RafsVersion};
 use nydus_storage::device::{BlobFeatures, BlobInfo};
@@ -150,6 +151,15 @@ impl Merger {
             ctx.compressor = rs.meta.get_compressor();
             ctx.digester = rs.meta.get_digester();
             ctx.explicit_uidgid = rs.meta.explicit_uidgid();
+            // Chunks of fixed size can also be located by file offset, so the merged filesystem
+            // has variable sized chunks if any layer has.
+            if rs.meta.has_variable_chunk_size() && ctx.chunker.is_none() {
+                ctx.chunker = Some(CdcChunker::new(
+                    rs.meta.cdc_min_size,
+                    rs.meta.cdc_avg_size,
+                    rs.meta.chunk_size,
+                )?);
+            }
 
             let mut parent_blob_added = false;
             let blobs = &rs.superblock.get_blob_infos();
@@ -0,0 +1,541 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Build RAFS filesystems from OCI image layouts or docker-archive tarballs.
+//!
+//! Each image layer is converted into a RAFS data blob and a per layer bootstrap with OCI
+//! whiteout rules, then per layer bootstraps are merged into the image bootstrap. An image
+//! manifest and image config referring to the generated data blobs and bootstrap are also
+//! generated, so the result could be pushed to registries as a nydus image.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nydus_rafs::builder::{
+    ArtifactStorage, BlobManager, BootstrapManager, BuildContext, BuildOutput, Builder, ChunkDict,
+    ConversionType, Prefetch, TarballBuilder, WhiteoutSpec,
+};
+use nydus_utils::digest::{self, RafsDigest};
+use serde_json::Value;
+use vmm_sys_util::tempdir::TempDir;
+
+use crate::merge::Merger;
+
+const MEDIA_TYPE_OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+const MEDIA_TYPE_DOCKER_MANIFEST_LIST: &str =
+    "application/vnd.docker.distribution.manifest.list.v2+json";
+const MEDIA_TYPE_OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const MEDIA_TYPE_OCI_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+const MEDIA_TYPE_OCI_LAYER_TAR: &str = "application/vnd.oci.image.layer.v1.tar";
+const MEDIA_TYPE_NYDUS_BLOB: &str = "application/vnd.oci.image.layer.nydus.blob.v1";
+const ANNOTATION_NYDUS_BLOB: &str = "containerd.io/snapshot/nydus-blob";
+const ANNOTATION_NYDUS_BOOTSTRAP: &str = "containerd.io/snapshot/nydus-bootstrap";
+
+/// Raw image config and layer files of a source image.
+struct ImageSource {
+    config: Vec<u8>,
+    layers: Vec<PathBuf>,
+}
+
+impl ImageSource {
+    /// Load image from an OCI image layout directory.
+    fn from_oci_layout(dir: &Path) -> Result<Self> {
+        let index = Self::read_json(&dir.join("index.json"))?;
+        let mut desc = Self::select_manifest(&index)?;
+        // Multi-platform images refer to image manifests through a nested image index.
+        let manifest = loop {
+            let content =
+                fs::read(Self::blob_path(dir, &desc)?).context("failed to read image manifest")?;
+            let value: Value =
+                serde_json::from_slice(&content).context("invalid image manifest")?;
+            let media_type = desc["mediaType"]
+                .as_str()
+                .or_else(|| value["mediaType"].as_str())
+                .unwrap_or_default();
+            if media_type == MEDIA_TYPE_OCI_INDEX || media_type == MEDIA_TYPE_DOCKER_MANIFEST_LIST {
+                desc = Self::select_manifest(&value)?;
+            } else {
+                break value;
+            }
+        };
+
+        let config = fs::read(Self::blob_path(dir, &manifest["config"])?)
+            .context("failed to read image config")?;
+        let mut layers = Vec::new();
+        for layer in manifest["layers"]
+            .as_array()
+            .ok_or_else(|| anyhow!("invalid image manifest, no layers"))?
+        {
+            layers.push(Self::blob_path(dir, layer)?);
+        }
+
+        Ok(ImageSource { config, layers })
+    }
+
+    /// Load image from a tarball generated by `docker save`, which is unpacked into `work_dir`.
+    fn from_docker_archive(file: &Path, work_dir: &Path) -> Result<Self> {
+        let reader = File::open(file)
+            .with_context(|| format!("failed to open docker archive {}", file.display()))?;
+        tar::Archive::new(reader)
+            .unpack(work_dir)
+            .with_context(|| format!("failed to unpack docker archive {}", file.display()))?;
+
+        let manifests = Self::read_json(&work_dir.join("manifest.json"))?;
+        let manifests = manifests
+            .as_array()
+            .ok_or_else(|| anyhow!("invalid manifest.json in docker archive"))?;
+        if manifests.len() != 1 {
+            bail!(
+                "docker archive should contain exactly one image, found {}",
+                manifests.len()
+            );
+        }
+        let manifest = &manifests[0];
+
+        let config_path = Self::archive_path(work_dir, &manifest["Config"])?;
+        let config = fs::read(config_path).context("failed to read image config")?;
+        let mut layers = Vec::new();
+        for layer in manifest["Layers"]
+            .as_array()
+            .ok_or_else(|| anyhow!("invalid manifest.json in docker archive, no layers"))?
+        {
+            layers.push(Self::archive_path(work_dir, layer)?);
+        }
+
+        Ok(ImageSource { config, layers })
+    }
+
+    fn read_json(path: &Path) -> Result<Value> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("invalid json file {}", path.display()))
+    }
+
+    // Select the image manifest for current platform from an image index, or the first one.
+    fn select_manifest(index: &Value) -> Result<Value> {
+        let manifests = index["manifests"]
+            .as_array()
+            .ok_or_else(|| anyhow!("invalid image index, no manifests"))?;
+        let arch = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            v => v,
+        };
+        manifests
+            .iter()
+            .find(|m| m["platform"]["os"] == "linux" && m["platform"]["architecture"] == arch)
+            .or_else(|| manifests.first())
+            .cloned()
+            .ok_or_else(|| anyhow!("image index is empty"))
+    }
+
+    fn blob_path(dir: &Path, desc: &Value) -> Result<PathBuf> {
+        let digest = desc["digest"]
+            .as_str()
+            .ok_or_else(|| anyhow!("invalid descriptor, no digest"))?;
+        match digest.split_once(':') {
+            Some((algo, hex)) if !algo.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                Ok(dir.join("blobs").join(algo).join(hex))
+            }
+            _ => bail!("invalid digest {} in descriptor", digest),
+        }
+    }
+
+    fn archive_path(work_dir: &Path, path: &Value) -> Result<PathBuf> {
+        let path = Path::new(
+            path.as_str()
+                .ok_or_else(|| anyhow!("invalid file path in docker archive manifest"))?,
+        );
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            bail!("invalid file path {} in docker archive", path.display());
+        }
+        Ok(work_dir.join(path))
+    }
+}
+
+/// Builder to convert a whole container image into a nydus image.
+pub(crate) struct OciImageBuilder {
+    blob_dir: PathBuf,
+    work_dir: TempDir,
+}
+
+impl OciImageBuilder {
+    /// Create a new instance of `OciImageBuilder` to save generated files into `blob_dir`.
+    pub fn new(blob_dir: &Path) -> Result<Self> {
+        // Keep temporary files on the same filesystem as generated blobs.
+        let work_dir =
+            TempDir::new_with_prefix(blob_dir.join(".nydus-image-")).with_context(|| {
+                format!("failed to create work directory in {}", blob_dir.display())
+            })?;
+
+        Ok(OciImageBuilder {
+            blob_dir: blob_dir.to_path_buf(),
+            work_dir,
+        })
+    }
+
+    /// Build the image specified by `ctx.source_path`, the merged bootstrap is saved into
+    /// `bootstrap` and the nydus image manifest is saved into `manifest`.
+    ///
+    /// Options to build per layer blobs are taken from `ctx`, except that the OCI whiteout
+    /// spec is always used.
+    pub fn build(
+        &self,
+        ctx: &BuildContext,
+        chunk_dict: Arc<dyn ChunkDict>,
+        bootstrap: &Path,
+        manifest: &Path,
+    ) -> Result<BuildOutput> {
+        let image = match ctx.conversion_type {
+            ConversionType::OciImageToRafs => ImageSource::from_oci_layout(&ctx.source_path)?,
+            ConversionType::DockerArchiveToRafs => {
+                ImageSource::from_docker_archive(&ctx.source_path, self.work_dir.as_path())?
+            }
+            _ => bail!("unsupported conversion type {}", ctx.conversion_type),
+        };
+
+        let mut bootstraps = Vec::with_capacity(image.layers.len());
+        for (idx, layer) in image.layers.iter().enumerate() {
+            let layer_bootstrap = self.work_dir.as_path().join(format!("layer-{}.boot", idx));
+            self.build_layer(ctx, chunk_dict.clone(), layer, &layer_bootstrap)
+                .with_context(|| format!("failed to build image layer {}", layer.display()))?;
+            bootstraps.push(layer_bootstrap);
+        }
+        if bootstraps.is_empty() {
+            bail!("image has no layers");
+        }
+
+        let mut merge_ctx = BuildContext {
+            prefetch: ctx.prefetch.clone(),
+            ..Default::default()
+        };
+        merge_ctx.configuration = ctx.configuration.clone();
+        let output = Merger::merge(
+            &mut merge_ctx,
+            None,
+            bootstraps,
+            None,
+            None,
+            None,
+            None,
+            ArtifactStorage::SingleFile(bootstrap.to_path_buf()),
+            None,
+            ctx.configuration.clone(),
+        )?;
+        self.dump_manifest(&image, &output, bootstrap, manifest)?;
+
+        Ok(output)
+    }
+
+    fn build_layer(
+        &self,
+        template: &BuildContext,
+        chunk_dict: Arc<dyn ChunkDict>,
+        source: &Path,
+        bootstrap: &Path,
+    ) -> Result<BuildOutput> {
+        let conversion_type = Self::detect_layer_type(source)?;
+        let mut ctx = BuildContext::new(
+            String::new(),
+            template.aligned_chunk,
+            0,
+            template.compressor,
+            template.digester,
+            template.explicit_uidgid,
+            WhiteoutSpec::Oci,
+            conversion_type,
+            source.to_path_buf(),
+            // Prefetch table is generated when merging layers.
+            Prefetch::default(),
+            Some(ArtifactStorage::FileDir(self.blob_dir.clone())),
+            false,
+            template.features.clone(),
+        );
+        ctx.set_fs_version(template.fs_version);
+        ctx.set_chunk_size(template.chunk_size);
+        if let Some(chunker) = template.chunker.as_ref() {
+            ctx.set_chunker(chunker.clone());
+        }
+        ctx.set_configuration(template.configuration.clone());
+
+        let mut blob_mgr = BlobManager::new(ctx.digester);
+        blob_mgr.set_chunk_dict(chunk_dict);
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::SingleFile(bootstrap.to_path_buf())),
+            None,
+        );
+
+        TarballBuilder::new(conversion_type).build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+    }
+
+    // Layers may be plain tar or gzip compressed tar, detect it by magic number.
+    fn detect_layer_type(path: &Path) -> Result<ConversionType> {
+        let mut file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let mut buf = [0u8; 512];
+        let mut size = 0;
+        while size < buf.len() {
+            match file.read(&mut buf[size..])? {
+                0 => break,
+                n => size += n,
+            }
+        }
+
+        if size >= 2 && buf[0] == 0x1f && buf[1] == 0x8b {
+            Ok(ConversionType::TargzToRafs)
+        } else if size >= 4 && buf[..4] == [0x28, 0xb5, 0x2f, 0xfd] {
+            bail!("zstd compressed image layer is not supported")
+        } else {
+            Ok(ConversionType::TarToRafs)
+        }
+    }
+
+    // Generate the nydus image manifest and image config. The bootstrap is packed into a tar
+    // file as `image/image.boot`, as expected by nydus snapshotter.
+    fn dump_manifest(
+        &self,
+        image: &ImageSource,
+        output: &BuildOutput,
+        bootstrap: &Path,
+        manifest_path: &Path,
+    ) -> Result<()> {
+        let mut layers = Vec::new();
+        let mut diff_ids = Vec::new();
+        for blob_id in output.blobs.iter() {
+            let path = self.blob_dir.join(blob_id);
+            let size = match fs::metadata(&path) {
+                Ok(m) => m.len(),
+                Err(e) => {
+                    warn!("skip data blob {} in image manifest, {}", blob_id, e);
+                    continue;
+                }
+            };
+            let digest = format!("sha256:{}", blob_id);
+            layers.push(json!({
+                "mediaType": MEDIA_TYPE_NYDUS_BLOB,
+                "digest": digest,
+                "size": size,
+                "annotations": {ANNOTATION_NYDUS_BLOB: "true"},
+            }));
+            diff_ids.push(digest);
+        }
+
+        let bootstrap_tar = self.blob_dir.join("bootstrap.tar");
+        let mut builder = tar::Builder::new(
+            File::create(&bootstrap_tar)
+                .with_context(|| format!("failed to create {}", bootstrap_tar.display()))?,
+        );
+        builder.append_path_with_name(bootstrap, "image/image.boot")?;
+        builder.into_inner()?.sync_all()?;
+        let (digest, size) = Self::write_blob(&self.blob_dir, &fs::read(&bootstrap_tar)?)?;
+        fs::remove_file(&bootstrap_tar)?;
+        layers.push(json!({
+            "mediaType": MEDIA_TYPE_OCI_LAYER_TAR,
+            "digest": digest,
+            "size": size,
+            "annotations": {ANNOTATION_NYDUS_BOOTSTRAP: "true"},
+        }));
+        diff_ids.push(digest);
+
+        let mut config: Value =
+            serde_json::from_slice(&image.config).context("invalid image config")?;
+        config["rootfs"] = json!({"type": "layers", "diff_ids": diff_ids});
+        let (config_digest, config_size) =
+            Self::write_blob(&self.blob_dir, &serde_json::to_vec(&config)?)?;
+
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": MEDIA_TYPE_OCI_MANIFEST,
+            "config": {
+                "mediaType": MEDIA_TYPE_OCI_CONFIG,
+                "digest": config_digest,
+                "size": config_size,
+            },
+            "layers": layers,
+        });
+        let w = OpenOptions::new()
+            .truncate(true)
+            .create(true)
+            .write(true)
+            .open(manifest_path)
+            .with_context(|| format!("can not open output file {}", manifest_path.display()))?;
+        serde_json::to_writer_pretty(w, &manifest).context("failed to write image manifest")?;
+
+        Ok(())
+    }
+
+    // Save content into `dir` with its sha256 digest as file name.
+    fn write_blob(dir: &Path, data: &[u8]) -> Result<(String, u64)> {
+        let digest = RafsDigest::from_buf(data, digest::Algorithm::Sha256).to_string();
+        let path = dir.join(&digest);
+        fs::write(&path, data).with_context(|| format!("failed to write {}", path.display()))?;
+        Ok((format!("sha256:{}", digest), data.len() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_blob(dir: &Path, value: &Value) -> Value {
+        let data = serde_json::to_vec(value).unwrap();
+        let hex = RafsDigest::from_buf(&data, digest::Algorithm::Sha256).to_string();
+        fs::create_dir_all(dir.join("blobs/sha256")).unwrap();
+        fs::write(dir.join("blobs/sha256").join(&hex), &data).unwrap();
+        json!({"digest": format!("sha256:{}", hex), "size": data.len()})
+    }
+
+    #[test]
+    fn test_select_manifest() {
+        let arch = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            v => v,
+        };
+        let index = json!({"manifests": [
+            {"digest": "sha256:01", "platform": {"os": "windows", "architecture": arch}},
+            {"digest": "sha256:02", "platform": {"os": "linux", "architecture": "unknown"}},
+            {"digest": "sha256:03", "platform": {"os": "linux", "architecture": arch}},
+        ]});
+        let desc = ImageSource::select_manifest(&index).unwrap();
+        assert_eq!(desc["digest"], "sha256:03");
+
+        // Fall back to the first manifest if no one matches current platform.
+        let index = json!({"manifests": [
+            {"digest": "sha256:01", "platform": {"os": "windows", "architecture": arch}},
+            {"digest": "sha256:02"},
+        ]});
+        let desc = ImageSource::select_manifest(&index).unwrap();
+        assert_eq!(desc["digest"], "sha256:01");
+
+        assert!(ImageSource::select_manifest(&json!({"manifests": []})).is_err());
+        assert!(ImageSource::select_manifest(&json!({})).is_err());
+    }
+
+    #[test]
+    fn test_from_oci_layout() {
+        let tmpdir = TempDir::new().unwrap();
+        let dir = tmpdir.as_path();
+        let config = write_blob(dir, &json!({"architecture": "amd64"}));
+        let layer = write_blob(dir, &json!("layer"));
+        let mut manifest_desc = write_blob(
+            dir,
+            &json!({
+                "schemaVersion": 2,
+                "mediaType": MEDIA_TYPE_OCI_MANIFEST,
+                "config": config,
+                "layers": [layer],
+            }),
+        );
+        manifest_desc["mediaType"] = json!(MEDIA_TYPE_OCI_MANIFEST);
+        // Multi-platform image index referred by the top level index.
+        let mut nested_desc = write_blob(dir, &json!({ "manifests": [manifest_desc] }));
+        nested_desc["mediaType"] = json!(MEDIA_TYPE_OCI_INDEX);
+        fs::write(
+            dir.join("index.json"),
+            serde_json::to_vec(&json!({ "manifests": [nested_desc] })).unwrap(),
+        )
+        .unwrap();
+
+        let image = ImageSource::from_oci_layout(dir).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&image.config).unwrap(),
+            json!({"architecture": "amd64"})
+        );
+        assert_eq!(image.layers.len(), 1);
+        let hex = layer["digest"].as_str().unwrap().strip_prefix("sha256:");
+        assert_eq!(image.layers[0], dir.join("blobs/sha256").join(hex.unwrap()));
+
+        let desc = json!({"digest": "sha256:../../etc/passwd"});
+        assert!(ImageSource::blob_path(dir, &desc).is_err());
+        assert!(ImageSource::blob_path(dir, &json!({"digest": "0123"})).is_err());
+        assert!(ImageSource::blob_path(dir, &json!({})).is_err());
+    }
+
+    #[test]
+    fn test_archive_path() {
+        let dir = Path::new("/work");
+        assert_eq!(
+            ImageSource::archive_path(dir, &json!("abc/layer.tar")).unwrap(),
+            dir.join("abc/layer.tar")
+        );
+        assert_eq!(
+            ImageSource::archive_path(dir, &json!("./config.json")).unwrap(),
+            dir.join("./config.json")
+        );
+        assert!(ImageSource::archive_path(dir, &json!("../layer.tar")).is_err());
+        assert!(ImageSource::archive_path(dir, &json!("abc/../../layer.tar")).is_err());
+        assert!(ImageSource::archive_path(dir, &json!("/etc/passwd")).is_err());
+        assert!(ImageSource::archive_path(dir, &json!(1)).is_err());
+    }
+
+    #[test]
+    fn test_from_docker_archive() {
+        let tmpdir = TempDir::new().unwrap();
+        let archive = tmpdir.as_path().join("image.tar");
+        let create_archive = |manifest: Value| {
+            let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+            let mut append = |name: &str, data: &[u8]| {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append_data(&mut header, name, data).unwrap();
+            };
+            append("manifest.json", &serde_json::to_vec(&manifest).unwrap());
+            append("config.json", b"{}");
+            append("abc/layer.tar", b"layer");
+            builder.finish().unwrap();
+        };
+
+        create_archive(json!([{"Config": "config.json", "Layers": ["abc/layer.tar"]}]));
+        let work_dir = TempDir::new().unwrap();
+        let image = ImageSource::from_docker_archive(&archive, work_dir.as_path()).unwrap();
+        assert_eq!(image.config, b"{}");
+        assert_eq!(image.layers, vec![work_dir.as_path().join("abc/layer.tar")]);
+
+        create_archive(json!([{"Config": "config.json", "Layers": ["../layer.tar"]}]));
+        let work_dir = TempDir::new().unwrap();
+        assert!(ImageSource::from_docker_archive(&archive, work_dir.as_path()).is_err());
+
+        create_archive(json!([
+            {"Config": "config.json", "Layers": []},
+            {"Config": "config.json", "Layers": []},
+        ]));
+        let work_dir = TempDir::new().unwrap();
+        assert!(ImageSource::from_docker_archive(&archive, work_dir.as_path()).is_err());
+    }
+
+    #[test]
+    fn test_detect_layer_type() {
+        let tmpdir = TempDir::new().unwrap();
+        let path = tmpdir.as_path().join("layer");
+
+        fs::write(&path, [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+        assert_eq!(
+            OciImageBuilder::detect_layer_type(&path).unwrap(),
+            ConversionType::TargzToRafs
+        );
+        fs::write(&path, [0x28, 0xb5, 0x2f, 0xfd, 0x00]).unwrap();
+        assert!(OciImageBuilder::detect_layer_type(&path).is_err());
+        fs::write(&path, [0u8; 1024]).unwrap();
+        assert_eq!(
+            OciImageBuilder::detect_layer_type(&path).unwrap(),
+            ConversionType::TarToRafs
+        );
+        fs::write(&path, [0u8; 0]).unwrap();
+        assert_eq!(
+            OciImageBuilder::detect_layer_type(&path).unwrap(),
+            ConversionType::TarToRafs
+        );
+        assert!(OciImageBuilder::detect_layer_type(&tmpdir.as_path().join("none")).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Offline scrub of blob cache files referenced by a RAFS filesystem.
+//!
+//! It shares the implementation of the `/api/v1/blobcache/scrub` API of nydusd, so blob caches
+//! can be verified and repaired without a running nydusd, for example after a power loss.
+
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nydus_api::ConfigV2;
+use nydus_rafs::metadata::RafsSuper;
+use nydus_storage::cache::BlobScrubResult;
+use nydus_storage::factory::BLOB_FACTORY;
+use serde::Serialize;
+
+/// Result of scrubbing blob caches of a RAFS filesystem.
+#[derive(Default, Serialize)]
+pub(crate) struct ScrubResult {
+    blobs: Vec<BlobScrubResult>,
+}
+
+impl ScrubResult {
+    pub fn dump_json(&self, path: &Path) -> Result<()> {
+        let w = OpenOptions::new()
+            .truncate(true)
+            .create(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Output file {:?} can't be opened", path))?;
+
+        serde_json::to_writer(w, self).context("Write output file failed")?;
+
+        Ok(())
+    }
+
+    pub fn dump(&self) {
+        for blob in self.blobs.iter() {
+            match blob.error.as_ref() {
+                Some(e) => println!("\t blob {}: FAILED, {}", blob.blob_id, e),
+                None => println!(
+                    "\t blob {}: checked chunks: {}, skipped chunks: {}, corrupted chunks: {:?}",
+                    blob.blob_id, blob.checked_chunks, blob.skipped_chunks, blob.corrupted_chunks
+                ),
+            }
+        }
+    }
+
+    /// Get number of blobs which can't be scrubbed.
+    pub fn failed_blobs(&self) -> usize {
+        self.blobs.iter().filter(|b| b.error.is_some()).count()
+    }
+
+    /// Get number of corrupted chunks found in all blob caches.
+    pub fn corrupted_chunks(&self) -> usize {
+        self.blobs.iter().map(|b| b.corrupted_chunks.len()).sum()
+    }
+}
+
+/// Scrub blob caches of all data blobs referenced by the RAFS filesystem at `bootstrap`.
+///
+/// Cache files are opened with the cache configuration from `config`, so it must match the
+/// configuration used by nydusd. The blob caches shouldn't be in use by a running nydusd, and
+/// empty cache files are created for blobs which haven't been cached yet.
+pub(crate) fn scrub(bootstrap: &Path, config: &Arc<ConfigV2>) -> Result<ScrubResult> {
+    let (rs, _) = RafsSuper::load_from_file(bootstrap, config.clone(), false, false)
+        .with_context(|| format!("failed to load bootstrap {}", bootstrap.display()))?;
+    let mut result = ScrubResult::default();
+    for blob_info in rs.superblock.get_blob_infos() {
+        let blob_id = blob_info.blob_id();
+        let blob = BLOB_FACTORY
+            .new_blob_cache(config, &blob_info)
+            .with_context(|| format!("failed to open cache for blob {}", blob_id))?;
+        let results = BLOB_FACTORY.scrub(Some(&blob_id));
+        drop(blob);
+        BLOB_FACTORY.gc(Some((config, &blob_id)));
+        result.blobs.append(&mut results?);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{build_image_with_features, localfs_config};
+    use nydus_rafs::builder::Features;
+    use nydus_storage::cache::BlobCache;
+    use nydus_storage::device::BlobObject;
+    use std::convert::TryFrom;
+    use std::fs;
+    use std::os::unix::fs::FileExt;
+    use vmm_sys_util::tempdir::TempDir;
+
+    #[test]
+    fn test_scrub_blob_cache() {
+        let source = TempDir::new().unwrap();
+        let dir = TempDir::new().unwrap();
+        fs::write(source.as_path().join("a"), vec![0x5au8; 0x2800]).unwrap();
+        fs::write(source.as_path().join("b"), vec![0xa5u8; 0x1800]).unwrap();
+        // Chunk digests are only available for blobs with TOC.
+        let bootstrap = build_image_with_features(
+            source.as_path(),
+            dir.as_path(),
+            Features::try_from("blob-toc").unwrap(),
+        );
+        let config = localfs_config(dir.as_path());
+
+        // Nothing has been cached yet.
+        let result = scrub(&bootstrap, &config).unwrap();
+        assert_eq!(result.blobs.len(), 1);
+        assert_eq!(result.blobs[0].checked_chunks, 0);
+        assert_eq!(result.blobs[0].skipped_chunks, 2);
+        assert_eq!(result.failed_blobs(), 0);
+
+        let (rs, _) = RafsSuper::load_from_file(&bootstrap, config.clone(), false, false).unwrap();
+        let blob_info = rs.superblock.get_blob_infos()[0].clone();
+        let blob_id = blob_info.blob_id();
+        let blob = BLOB_FACTORY.new_blob_cache(&config, &blob_info).unwrap();
+        blob.get_blob_object()
+            .unwrap()
+            .fetch_range_uncompressed(0, blob_info.uncompressed_size())
+            .unwrap();
+        drop(blob);
+        BLOB_FACTORY.gc(Some((&config, &blob_id)));
+
+        let result = scrub(&bootstrap, &config).unwrap();
+        assert_eq!(result.blobs[0].checked_chunks, 2);
+        assert_eq!(result.corrupted_chunks(), 0);
+
+        // Corrupt the first chunk in the cache file.
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .open(dir.as_path().join(format!("{}.blob.data", blob_id)))
+            .unwrap();
+        file.write_all_at(&[0u8; 16], 0).unwrap();
+        let result = scrub(&bootstrap, &config).unwrap();
+        assert_eq!(result.blobs[0].checked_chunks, 2);
+        assert_eq!(result.corrupted_chunks(), 1);
+
+        // The corrupted chunk has been dropped from the cache.
+        let result = scrub(&bootstrap, &config).unwrap();
+        assert_eq!(result.blobs[0].checked_chunks, 1);
+        assert_eq!(result.blobs[0].skipped_chunks, 1);
+        assert_eq!(result.corrupted_chunks(), 0);
+    }
+}
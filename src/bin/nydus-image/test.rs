@@ -0,0 +1,55 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use nydus_api::ConfigV2;
+use nydus_rafs::builder::{
+    ArtifactStorage, BlobManager, BootstrapManager, BuildContext, Builder, ConversionType,
+    DirectoryBuilder, Features, Prefetch, WhiteoutSpec,
+};
+use nydus_rafs::metadata::RafsVersion;
+use nydus_utils::{compress, digest};
+
+/// Build a RAFS v6 filesystem from directory `source`.
+///
+/// The bootstrap is stored at `<dir>/bootstrap` and data blobs are stored in `dir`.
+pub(crate) fn build_image(source: &Path, dir: &Path) -> PathBuf {
+    build_image_with_features(source, dir, Features::new())
+}
+
+/// Build a RAFS v6 filesystem from directory `source`, with extra builder `features`.
+pub(crate) fn build_image_with_features(source: &Path, dir: &Path, features: Features) -> PathBuf {
+    let bootstrap = dir.join("bootstrap");
+    let mut ctx = BuildContext::new(
+        String::new(),
+        true,
+        0,
+        compress::Algorithm::Lz4Block,
+        digest::Algorithm::Blake3,
+        true,
+        WhiteoutSpec::Oci,
+        ConversionType::DirectoryToRafs,
+        source.to_path_buf(),
+        Prefetch::default(),
+        Some(ArtifactStorage::FileDir(dir.to_path_buf())),
+        false,
+        features,
+    );
+    ctx.set_fs_version(RafsVersion::V6);
+    let mut blob_mgr = BlobManager::new(ctx.digester);
+    let mut bootstrap_mgr =
+        BootstrapManager::new(Some(ArtifactStorage::SingleFile(bootstrap.clone())), None);
+    DirectoryBuilder::new()
+        .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+        .unwrap();
+
+    bootstrap
+}
+
+/// Create configuration to access data blobs stored in `dir`.
+pub(crate) fn localfs_config(dir: &Path) -> Arc<ConfigV2> {
+    Arc::new(ConfigV2::new_localfs("", dir.to_str().unwrap()).unwrap())
+}
@@ -0,0 +1,554 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Unpack a RAFS filesystem into a directory.
+//!
+//! The directory tree is restored in two passes. The first pass creates all directories, files,
+//! hardlinks, symlinks and special files, and collects data chunks of regular files. Then chunks
+//! are sorted by blob offset, fetched and decompressed by multiple worker threads, and written to
+//! files with `pwrite()`. Chunks containing only zeros are not written, so holes in sparse files are
+//! kept. At last, xattrs, ownership, permissions and timestamps are restored in reverse order, so
+//! modification time of directories won't be changed by creating their children.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, FileExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{Context, Result};
+use nix::sys::stat::{mknod, utimensat, Mode, SFlag, UtimensatFlags};
+use nix::sys::time::TimeSpec;
+use nix::unistd::{fchownat, geteuid, FchownatFlags, Gid, Uid};
+use nydus_api::ConfigV2;
+use nydus_rafs::metadata::chunk::ChunkWrapper;
+use nydus_rafs::metadata::inode::InodeWrapper;
+use nydus_rafs::metadata::{RafsInodeExt, RafsSuper};
+use nydus_rafs::RafsIterator;
+use nydus_storage::backend::{BlobBackend, BlobReader};
+use nydus_storage::device::{BlobFeatures, BlobInfo};
+use nydus_storage::utils::alloc_buf;
+use nydus_utils::compress;
+
+use super::Unpacker;
+
+/// An unpacker to restore a RAFS filesystem into a directory.
+pub struct DirUnpacker {
+    bootstrap: PathBuf,
+    blob_backend: Option<Arc<dyn BlobBackend + Send + Sync>>,
+    output: PathBuf,
+    threads: usize,
+}
+
+impl DirUnpacker {
+    pub fn new(
+        bootstrap: &Path,
+        blob_backend: Option<Arc<dyn BlobBackend + Send + Sync>>,
+        output: &Path,
+        threads: usize,
+    ) -> Result<Self> {
+        Ok(DirUnpacker {
+            bootstrap: bootstrap.to_path_buf(),
+            blob_backend,
+            output: output.to_path_buf(),
+            threads: threads.max(1),
+        })
+    }
+
+    fn load_rafs(&self, config: Arc<ConfigV2>) -> Result<RafsSuper> {
+        let (rs, _) = RafsSuper::load_from_file(
+            self.bootstrap.as_path(),
+            config.clone(),
+            config.is_chunk_validation_enabled(),
+            false,
+        )?;
+        Ok(rs)
+    }
+
+    // Create directory entries and collect data chunks of regular files.
+    fn create_entries(&self, rafs: &RafsSuper, state: &mut UnpackState) -> Result<()> {
+        let mut links: HashMap<u64, PathBuf> = HashMap::new();
+
+        for (inode, path) in RafsIterator::new(rafs) {
+            let target = self.entry_target(&path)?;
+            let wrapper = InodeWrapper::from_inode_info(inode.clone());
+
+            if inode.is_hardlink() && !inode.is_dir() {
+                if let Some(source) = links.get(&inode.ino()) {
+                    fs::hard_link(source, &target).with_context(|| {
+                        format!("failed to create hardlink {}", target.display())
+                    })?;
+                    continue;
+                }
+                links.insert(inode.ino(), target.clone());
+            }
+
+            if inode.is_dir() {
+                if target != self.output {
+                    fs::create_dir(&target).with_context(|| {
+                        format!("failed to create directory {}", target.display())
+                    })?;
+                }
+            } else if inode.is_symlink() {
+                let link = inode.get_symlink()?;
+                symlink(&link, &target)
+                    .with_context(|| format!("failed to create symlink {}", target.display()))?;
+            } else if inode.is_reg() {
+                let file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .custom_flags(libc::O_NOFOLLOW)
+                    .open(&target)
+                    .with_context(|| format!("failed to create file {}", target.display()))?;
+                // Leave holes in the file, they will be filled by worker threads.
+                file.set_len(wrapper.size())?;
+                self.collect_chunks(rafs, inode.as_ref(), state.files.len(), state)?;
+                state.files.push(target.clone());
+            } else {
+                let kind = if wrapper.is_chrdev() {
+                    SFlag::S_IFCHR
+                } else if wrapper.is_blkdev() {
+                    SFlag::S_IFBLK
+                } else if wrapper.is_fifo() {
+                    SFlag::S_IFIFO
+                } else if wrapper.is_sock() {
+                    SFlag::S_IFSOCK
+                } else {
+                    bail!("unsupported file type of {}", path.display());
+                };
+                let mode = Mode::from_bits_truncate(wrapper.mode() & 0o7777);
+                mknod(&target, kind, mode, wrapper.rdev() as u64).with_context(|| {
+                    format!("failed to create special file {}", target.display())
+                })?;
+            }
+
+            state.entries.push((inode, target));
+        }
+
+        Ok(())
+    }
+
+    // Get the path to restore an entry, the path comes from the image so it must be checked to
+    // avoid writing outside of the output directory.
+    fn entry_target(&self, path: &Path) -> Result<PathBuf> {
+        let mut target = self.output.clone();
+        for (idx, component) in path.components().enumerate() {
+            match component {
+                Component::RootDir if idx == 0 => continue,
+                Component::Normal(name) => target.push(name),
+                _ => bail!("invalid path {} to unpack", path.display()),
+            }
+            if fs::symlink_metadata(&target)
+                .map(|m| m.file_type().is_symlink())
+                .unwrap_or(false)
+            {
+                bail!("refuse to unpack {} through symlink", target.display());
+            }
+        }
+        Ok(target)
+    }
+
+    fn collect_chunks(
+        &self,
+        rafs: &RafsSuper,
+        inode: &dyn RafsInodeExt,
+        file_idx: usize,
+        state: &mut UnpackState,
+    ) -> Result<()> {
+        for idx in 0..inode.get_chunk_count() {
+            let chunk = inode.get_chunk_info(idx)?;
+            // RAFS v5 records file offset in chunks, and RAFS v6 has a chunk for each chunk-sized
+            // range of the file.
+            let offset = if rafs.meta.is_v5() {
+                ChunkWrapper::from_chunk_info(chunk.clone()).file_offset()
+            } else {
+                idx as u64 * rafs.meta.chunk_size as u64
+            };
+            state.chunks.push(ChunkTask {
+                blob_index: chunk.blob_index(),
+                compressed: chunk.is_compressed(),
+                compressed_offset: chunk.compressed_offset(),
+                compressed_size: chunk.compressed_size(),
+                uncompressed_size: chunk.uncompressed_size(),
+                file_idx,
+                file_offset: offset,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Fetch, decompress and write data chunks with worker threads.
+    fn write_chunks(&self, blobs: &[Arc<BlobInfo>], state: &mut UnpackState) -> Result<()> {
+        if state.chunks.is_empty() {
+            return Ok(());
+        }
+        let backend = self
+            .blob_backend
+            .as_ref()
+            .context("both blob path or blob backend config are not specified")?;
+        let mut readers = Vec::with_capacity(blobs.len());
+        for blob in blobs {
+            let reader = backend.get_reader(&blob.blob_id()).map_err(|e| {
+                anyhow!("failed to get reader for blob {}, {:?}", blob.blob_id(), e)
+            })?;
+            readers.push(reader);
+        }
+
+        // Access data blobs sequentially as possible.
+        state
+            .chunks
+            .sort_by_key(|c| (c.blob_index, c.compressed_offset, c.file_idx, c.file_offset));
+
+        let chunks = &state.chunks;
+        let files = &state.files;
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let result = Mutex::new(Ok(()));
+        thread::scope(|s| {
+            for _ in 0..self.threads {
+                s.spawn(|| {
+                    let mut data = Vec::new();
+                    let mut cached = None;
+                    let mut opened = None;
+                    while !failed.load(Ordering::Relaxed) {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        if idx >= chunks.len() {
+                            break;
+                        }
+                        let chunk = &chunks[idx];
+                        let res = chunk
+                            .load(blobs, &readers, &mut data, &mut cached)
+                            .and_then(|_| {
+                                let path = &files[chunk.file_idx];
+                                chunk.write(&data, path, &mut opened).with_context(|| {
+                                    format!("failed to write file {}", path.display())
+                                })
+                            });
+                        if let Err(e) = res {
+                            failed.store(true, Ordering::Relaxed);
+                            *result.lock().unwrap() = Err(e);
+                        }
+                    }
+                });
+            }
+        });
+
+        result.into_inner().unwrap()
+    }
+
+    // Restore xattrs, ownership, permission and timestamps of entries.
+    fn set_attributes(&self, state: &UnpackState) -> Result<()> {
+        let is_root = geteuid().is_root();
+
+        for (inode, target) in state.entries.iter().rev() {
+            let wrapper = InodeWrapper::from_inode_info(inode.clone());
+
+            if inode.has_xattr() {
+                for name in inode.get_xattrs()? {
+                    let name = OsStr::from_bytes(&name);
+                    if let Some(value) = inode.get_xattr(name)? {
+                        xattr::set(target, name, &value).with_context(|| {
+                            format!("failed to set xattr {:?} for {}", name, target.display())
+                        })?;
+                    }
+                }
+            }
+            if is_root {
+                fchownat(
+                    None,
+                    target,
+                    Some(Uid::from_raw(wrapper.uid())),
+                    Some(Gid::from_raw(wrapper.gid())),
+                    FchownatFlags::NoFollowSymlink,
+                )
+                .with_context(|| format!("failed to change owner of {}", target.display()))?;
+            }
+            if !inode.is_symlink() {
+                fs::set_permissions(target, Permissions::from_mode(wrapper.mode() & 0o7777))
+                    .with_context(|| {
+                        format!("failed to change permission of {}", target.display())
+                    })?;
+            }
+            let mtime = TimeSpec::new(wrapper.mtime() as i64, wrapper.mtime_nsec() as i64);
+            utimensat(
+                None,
+                target,
+                &mtime,
+                &mtime,
+                UtimensatFlags::NoFollowSymlink,
+            )
+            .with_context(|| format!("failed to set timestamps of {}", target.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Unpacker for DirUnpacker {
+    fn unpack(&self, config: Arc<ConfigV2>) -> Result<()> {
+        debug!(
+            "directory unpacker, bootstrap file: {:?}, output directory: {:?}",
+            self.bootstrap, self.output
+        );
+
+        let rafs = self.load_rafs(config)?;
+        let blobs = rafs.superblock.get_blob_infos();
+        // Chunks are fetched and decompressed one by one, check blobs before touching the output
+        // directory.
+        for blob in blobs.iter() {
+            if blob.has_feature(BlobFeatures::ZRAN) || blob.is_legacy_stargz() {
+                bail!(
+                    "unpacking blob {} into a directory is not supported, its chunks can't be decompressed independently",
+                    blob.blob_id()
+                );
+            }
+        }
+        let mut state = UnpackState::default();
+
+        self.create_entries(&rafs, &mut state)?;
+        self.write_chunks(&blobs, &mut state)?;
+        self.set_attributes(&state)
+    }
+}
+
+#[derive(Default)]
+struct UnpackState {
+    // Entries to restore attributes, in the order of creation.
+    entries: Vec<(Arc<dyn RafsInodeExt>, PathBuf)>,
+    // Paths of regular files having data.
+    files: Vec<PathBuf>,
+    chunks: Vec<ChunkTask>,
+}
+
+struct ChunkTask {
+    blob_index: u32,
+    compressed: bool,
+    compressed_offset: u64,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    file_idx: usize,
+    file_offset: u64,
+}
+
+impl ChunkTask {
+    // Load chunk data into `data`, chunks shared by multiple files are adjacent after sorting,
+    // so `cached` is used to avoid fetching the same chunk again.
+    fn load(
+        &self,
+        blobs: &[Arc<BlobInfo>],
+        readers: &[Arc<dyn BlobReader>],
+        data: &mut Vec<u8>,
+        cached: &mut Option<(u32, u64)>,
+    ) -> Result<()> {
+        let key = (self.blob_index, self.compressed_offset);
+        if *cached == Some(key) {
+            return Ok(());
+        }
+        *cached = None;
+
+        let idx = self.blob_index as usize;
+        let (blob, reader) = match (blobs.get(idx), readers.get(idx)) {
+            (Some(blob), Some(reader)) => (blob, reader),
+            _ => bail!("invalid blob index {}", self.blob_index),
+        };
+        let mut buf = alloc_buf(self.compressed_size as usize);
+        let cnt = reader
+            .read_all(&mut buf, self.compressed_offset)
+            .map_err(|e| anyhow!("failed to read chunk from blob {}, {:?}", blob.blob_id(), e))?;
+        if cnt != buf.len() {
+            bail!(
+                "chunk at offset 0x{:x} of blob {} is truncated",
+                self.compressed_offset,
+                blob.blob_id()
+            );
+        }
+
+        if self.compressed {
+            data.resize(self.uncompressed_size as usize, 0);
+            compress::decompress(&buf, data, blob.compressor())
+                .context("failed to decompress chunk")?;
+        } else {
+            *data = buf;
+        }
+        *cached = Some(key);
+
+        Ok(())
+    }
+
+    // Write chunk data into the file at `path`, chunks of the same file are mostly adjacent after
+    // sorting, so the file opened for the last chunk is kept in `opened` for reuse.
+    fn write(&self, data: &[u8], path: &Path, opened: &mut Option<(usize, File)>) -> Result<()> {
+        // Keep holes of sparse files.
+        if data.iter().all(|v| *v == 0) {
+            return Ok(());
+        }
+        if !matches!(opened, Some((idx, _)) if *idx == self.file_idx) {
+            let file = OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(path)?;
+            *opened = Some((self.file_idx, file));
+        }
+        if let Some((_, file)) = opened.as_ref() {
+            file.write_all_at(data, self.file_offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nydus_api::LocalFsConfig;
+    use nydus_rafs::builder::{
+        ArtifactStorage, BlobManager, BootstrapManager, BuildContext, Builder, ConversionType,
+        DirectoryBuilder, Features, Prefetch, WhiteoutSpec,
+    };
+    use nydus_rafs::metadata::RafsVersion;
+    use nydus_storage::backend::localfs::LocalFs;
+    use nydus_utils::digest;
+    use std::os::unix::fs::MetadataExt;
+    use vmm_sys_util::tempdir::TempDir;
+
+    const MTIME: i64 = 1_600_000_000;
+
+    fn set_mtime(path: &Path) {
+        let mtime = TimeSpec::new(MTIME, 0);
+        utimensat(None, path, &mtime, &mtime, UtimensatFlags::NoFollowSymlink).unwrap();
+    }
+
+    fn unpack_into_directory(version: RafsVersion) {
+        let tmpdir = TempDir::new().unwrap();
+        let source = tmpdir.as_path().join("source");
+        let blobs = tmpdir.as_path().join("blobs");
+        let output = tmpdir.as_path().join("output");
+        for dir in [&source, &blobs, &output] {
+            fs::create_dir(dir).unwrap();
+        }
+
+        let data: Vec<u8> = (0..0x1_8000u32).map(|v| v as u8).collect();
+        let file = source.join("file");
+        fs::write(&file, &data).unwrap();
+        fs::set_permissions(&file, Permissions::from_mode(0o640)).unwrap();
+        // Not all filesystems support user xattrs.
+        let has_xattr = xattr::set(&file, "user.nydus", b"value").is_ok();
+        fs::hard_link(&file, source.join("hardlink")).unwrap();
+        symlink("file", source.join("symlink")).unwrap();
+        // Only the last 1MB chunk of the sparse file has data.
+        let sparse = File::create(source.join("sparse")).unwrap();
+        sparse.set_len(0x30_0000).unwrap();
+        sparse.write_all_at(&data, 0x20_0000).unwrap();
+        drop(sparse);
+        fs::create_dir(source.join("dir")).unwrap();
+        fs::set_permissions(source.join("dir"), Permissions::from_mode(0o750)).unwrap();
+        for name in ["file", "symlink", "sparse", "dir"] {
+            set_mtime(&source.join(name));
+        }
+        set_mtime(&source);
+
+        let bootstrap = tmpdir.as_path().join("bootstrap");
+        let mut ctx = BuildContext::new(
+            String::new(),
+            true,
+            0,
+            compress::Algorithm::Lz4Block,
+            digest::Algorithm::Blake3,
+            true,
+            WhiteoutSpec::Oci,
+            ConversionType::DirectoryToRafs,
+            source,
+            Prefetch::default(),
+            Some(ArtifactStorage::FileDir(blobs.clone())),
+            false,
+            Features::new(),
+        );
+        ctx.set_fs_version(version);
+        let mut blob_mgr = BlobManager::new(ctx.digester);
+        let mut bootstrap_mgr =
+            BootstrapManager::new(Some(ArtifactStorage::SingleFile(bootstrap.clone())), None);
+        DirectoryBuilder::new()
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+
+        let backend_config = LocalFsConfig {
+            blob_file: String::new(),
+            dir: blobs.to_str().unwrap().to_owned(),
+            alt_dirs: Vec::new(),
+        };
+        let backend = LocalFs::new(&backend_config, Some("test_unpack")).unwrap();
+        DirUnpacker::new(&bootstrap, Some(Arc::new(backend)), &output, 2)
+            .unwrap()
+            .unpack(Arc::new(ConfigV2::default()))
+            .unwrap();
+
+        let file = output.join("file");
+        assert_eq!(fs::read(&file).unwrap(), data);
+        let md = fs::metadata(&file).unwrap();
+        assert_eq!(md.mode() & 0o7777, 0o640);
+        assert_eq!(md.mtime(), MTIME);
+        assert_eq!(md.nlink(), 2);
+        assert_eq!(
+            fs::metadata(output.join("hardlink")).unwrap().ino(),
+            md.ino()
+        );
+        if has_xattr {
+            let value = xattr::get(&file, "user.nydus").unwrap();
+            assert_eq!(value, Some(b"value".to_vec()));
+        }
+
+        let link = output.join("symlink");
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("file"));
+        assert_eq!(fs::symlink_metadata(&link).unwrap().mtime(), MTIME);
+
+        let sparse = output.join("sparse");
+        let content = fs::read(&sparse).unwrap();
+        assert_eq!(content.len(), 0x30_0000);
+        assert!(content[..0x20_0000].iter().all(|v| *v == 0));
+        assert_eq!(&content[0x20_0000..0x21_8000], data.as_slice());
+        assert!(content[0x21_8000..].iter().all(|v| *v == 0));
+        // Chunks of zeros are not written, so they stay as holes.
+        assert!(fs::metadata(&sparse).unwrap().blocks() * 512 < 0x30_0000);
+
+        let md = fs::metadata(output.join("dir")).unwrap();
+        assert_eq!(md.mode() & 0o7777, 0o750);
+        assert_eq!(md.mtime(), MTIME);
+        assert_eq!(fs::metadata(&output).unwrap().mtime(), MTIME);
+    }
+
+    #[test]
+    fn test_unpack_into_directory() {
+        unpack_into_directory(RafsVersion::V6);
+    }
+
+    #[test]
+    fn test_unpack_v5_into_directory() {
+        unpack_into_directory(RafsVersion::V5);
+    }
+
+    #[test]
+    fn test_entry_target() {
+        let tmpdir = TempDir::new().unwrap();
+        let output = tmpdir.as_path().join("output");
+        fs::create_dir(&output).unwrap();
+        let unpacker = DirUnpacker::new(Path::new("bootstrap"), None, &output, 1).unwrap();
+
+        assert_eq!(unpacker.entry_target(Path::new("/")).unwrap(), output);
+        assert_eq!(
+            unpacker.entry_target(Path::new("/a/b")).unwrap(),
+            output.join("a/b")
+        );
+        assert!(unpacker.entry_target(Path::new("/a/../../b")).is_err());
+        assert!(unpacker.entry_target(Path::new("a/./b")).is_ok());
+        assert!(unpacker.entry_target(Path::new("a//b/..")).is_err());
+
+        symlink(tmpdir.as_path(), output.join("link")).unwrap();
+        assert!(unpacker.entry_target(Path::new("/link")).is_err());
+        assert!(unpacker.entry_target(Path::new("/link/file")).is_err());
+    }
+}
@@ -25,6 +25,9 @@ use self::pax::{
     PAXSpecialSectionBuilder,
 };
 
+pub use self::dir::DirUnpacker;
+
+mod dir;
 mod pax;
 
 pub trait Unpacker {
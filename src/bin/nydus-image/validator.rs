@@ -4,14 +4,27 @@
 
 //! Validator for RAFS format
 
-use std::path::Path;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use anyhow::{Context, Result};
-use nydus_api::ConfigV2;
+use nydus_api::{BackendConfigV2, ConfigV2};
 use nydus_rafs::builder::Tree;
 use nydus_rafs::metadata::RafsSuper;
-use nydus_storage::device::BlobInfo;
+use nydus_storage::backend::BlobReader;
+use nydus_storage::device::{BlobFeatures, BlobInfo};
+use nydus_storage::factory::BlobFactory;
+use nydus_storage::meta::toc::{TocEntryList, TocLocation};
+use nydus_storage::utils::alloc_buf;
+use nydus_utils::compress;
+use nydus_utils::digest::{self, DigestHasher, RafsDigest};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+
+// Number of chunk reads in flight for each worker thread when verifying chunk data.
+const CHECK_READS_PER_THREAD: usize = 8;
 
 pub struct Validator {
     sb: RafsSuper,
@@ -40,4 +53,595 @@ impl Validator {
 
         Ok(self.sb.superblock.get_blob_infos())
     }
+
+    /// Verify data of all chunks and blob metadata through the storage backend.
+    ///
+    /// Each chunk referenced by the filesystem is read from its data blob, decompressed and
+    /// checked against the chunk digest. Chunks are read by the asynchronous interfaces of the
+    /// storage backend, so many reads are in flight with only `threads` worker threads to
+    /// decompress and digest chunk data. Digests of the blob ToC and the separate RAFS blob are
+    /// verified too if available.
+    pub fn check_data(&self, config: &ConfigV2, threads: usize) -> Result<DataCheckResult> {
+        let backend_config = config.get_backend_config()?;
+        let blob_infos = self.sb.superblock.get_blob_infos();
+        let mut blobs = blob_infos
+            .iter()
+            .map(|blob| BlobDataCheck::new(blob.blob_id()))
+            .collect::<Vec<_>>();
+
+        let mut readers = Vec::with_capacity(blob_infos.len());
+        for (idx, blob) in blob_infos.iter().enumerate() {
+            match Self::get_reader(backend_config, &blob.blob_id()) {
+                Ok(reader) => {
+                    if let Err(e) = Self::check_blob_meta(blob, reader.as_ref(), backend_config) {
+                        blobs[idx].errors.push(format!("{}", e));
+                    }
+                    if blob.has_feature(BlobFeatures::ZRAN) {
+                        blobs[idx].skipped = true;
+                        readers.push(None);
+                    } else {
+                        readers.push(Some(reader));
+                    }
+                }
+                Err(e) => {
+                    blobs[idx].errors.push(format!("{}", e));
+                    readers.push(None);
+                }
+            }
+        }
+
+        // Chunks may be shared by multiple files, so verify each chunk only once.
+        let tree = Tree::from_bootstrap(&self.sb, &mut ())
+            .context("failed to load bootstrap for validator")?;
+        let mut tasks = Vec::new();
+        let mut chunk_map = HashMap::new();
+        tree.iterate(&mut |node| {
+            for chunk in node.chunks.iter() {
+                let c = &chunk.inner;
+                let key = (c.blob_index(), c.index());
+                let idx = *chunk_map.entry(key).or_insert_with(|| {
+                    tasks.push(ChunkTask {
+                        blob_index: c.blob_index() as usize,
+                        digest: *c.id(),
+                        compressed: c.is_compressed(),
+                        compressed_offset: c.compressed_offset(),
+                        compressed_size: c.compressed_size(),
+                        uncompressed_size: c.uncompressed_size(),
+                        files: Vec::new(),
+                    });
+                    tasks.len() - 1
+                });
+                let files = &mut tasks[idx].files;
+                if files.last() != Some(node.target()) {
+                    files.push(node.target().clone());
+                }
+            }
+            true
+        })?;
+
+        let threads = threads.max(1);
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(threads)
+            .thread_name("nydus_check")
+            .enable_all()
+            .build()
+            .context("failed to create runtime to verify chunk data")?;
+        let tasks = Arc::new(tasks);
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        runtime.block_on(async {
+            let permits = threads * CHECK_READS_PER_THREAD;
+            let sema = Arc::new(Semaphore::new(permits));
+            for idx in 0..tasks.len() {
+                let task = &tasks[idx];
+                let blob = match blob_infos.get(task.blob_index) {
+                    None => {
+                        let msg = format!("invalid blob index {}", task.blob_index);
+                        failures.lock().unwrap().push((idx, msg));
+                        continue;
+                    }
+                    Some(blob) => blob.clone(),
+                };
+                let reader = match readers[task.blob_index].as_ref() {
+                    None => continue,
+                    Some(reader) => reader.clone(),
+                };
+                let permit = sema.clone().acquire_owned().await.unwrap();
+                let tasks = tasks.clone();
+                let failures = failures.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = tasks[idx].verify(&blob, reader).await {
+                        failures.lock().unwrap().push((idx, format!("{}", e)));
+                    }
+                    drop(permit);
+                });
+            }
+            // All chunks have been verified once all permits are returned.
+            let _ = sema.acquire_many(permits as u32).await;
+        });
+
+        let failures = std::mem::take(&mut *failures.lock().unwrap());
+        Ok(DataCheckResult::new(blobs, &tasks, failures))
+    }
+
+    fn get_reader(config: &BackendConfigV2, blob_id: &str) -> Result<Arc<dyn BlobReader>> {
+        let backend = BlobFactory::new_backend(config, blob_id)
+            .with_context(|| format!("failed to create backend for blob {}", blob_id))?;
+        backend
+            .get_reader(blob_id)
+            .map_err(|e| anyhow!("failed to open blob {}, {:?}", blob_id, e))
+    }
+
+    // Verify digests of the blob ToC and the separate RAFS blob.
+    fn check_blob_meta(
+        blob: &BlobInfo,
+        reader: &dyn BlobReader,
+        config: &BackendConfigV2,
+    ) -> Result<()> {
+        if !blob.has_feature(BlobFeatures::SEPARATE) {
+            return Self::check_blob_toc(blob, reader);
+        }
+
+        // The RAFS blob id is unknown for blobs with inlined meta, so nothing to verify.
+        let meta_id = match blob.get_blob_meta_id() {
+            Ok(id) => id,
+            Err(_) => return Ok(()),
+        };
+        let meta_reader = Self::get_reader(config, &meta_id)?;
+        if !blob.has_feature(BlobFeatures::INLINED_FS_META) && blob.blob_meta_digest() != &[0u8; 32]
+        {
+            let digest = Self::digest_blob(meta_reader.as_ref())?;
+            if &digest.data != blob.blob_meta_digest() {
+                bail!(
+                    "digest of RAFS blob {} mismatch, expect {}, got {}",
+                    meta_id,
+                    hex::encode(blob.blob_meta_digest()),
+                    digest
+                );
+            }
+        }
+
+        Self::check_blob_toc(blob, meta_reader.as_ref())
+    }
+
+    fn check_blob_toc(blob: &BlobInfo, reader: &dyn BlobReader) -> Result<()> {
+        if blob.blob_toc_size() == 0 || blob.blob_toc_digest() == &[0u8; 32] {
+            return Ok(());
+        }
+
+        let blob_size = reader
+            .blob_size()
+            .map_err(|e| anyhow!("failed to get blob size, {:?}", e))?;
+        let toc_size = blob.blob_toc_size() as u64;
+        if blob_size < toc_size {
+            bail!(
+                "blob size 0x{:x} is smaller than ToC size 0x{:x}",
+                blob_size,
+                toc_size
+            );
+        }
+        let mut location = TocLocation::new(blob_size - toc_size, toc_size);
+        location.validate_digest = true;
+        location.digest.data = *blob.blob_toc_digest();
+        TocEntryList::read_from_blob::<File>(reader, None, &location)
+            .context("failed to verify blob ToC")?;
+
+        Ok(())
+    }
+
+    fn digest_blob(reader: &dyn BlobReader) -> Result<RafsDigest> {
+        let size = reader
+            .blob_size()
+            .map_err(|e| anyhow!("failed to get blob size, {:?}", e))?;
+        let mut hasher = RafsDigest::hasher(digest::Algorithm::Sha256);
+        let mut buf = alloc_buf(0x100000);
+        let mut offset = 0;
+        while offset < size {
+            let len = std::cmp::min(buf.len() as u64, size - offset) as usize;
+            let cnt = reader
+                .read_all(&mut buf[..len], offset)
+                .map_err(|e| anyhow!("failed to read blob, {:?}", e))?;
+            if cnt != len {
+                bail!(
+                    "unexpected end of blob at offset 0x{:x}",
+                    offset + cnt as u64
+                );
+            }
+            hasher.digest_update(&buf[..len]);
+            offset += len as u64;
+        }
+
+        Ok(hasher.digest_finalize())
+    }
+}
+
+struct ChunkTask {
+    blob_index: usize,
+    digest: RafsDigest,
+    compressed: bool,
+    compressed_offset: u64,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    files: Vec<PathBuf>,
+}
+
+impl ChunkTask {
+    async fn verify(&self, blob: &BlobInfo, reader: Arc<dyn BlobReader>) -> Result<()> {
+        let size = self.compressed_size as usize;
+        let mut buf = Vec::new();
+        while buf.len() < size {
+            let offset = self.compressed_offset + buf.len() as u64;
+            let data = reader
+                .clone()
+                .async_read(offset, size - buf.len())
+                .await
+                .map_err(|e| {
+                    anyhow!(
+                        "failed to read chunk at offset 0x{:x}, {:?}",
+                        self.compressed_offset,
+                        e
+                    )
+                })?;
+            if data.is_empty() {
+                break;
+            } else if buf.is_empty() {
+                buf = data;
+            } else {
+                buf.extend_from_slice(&data);
+            }
+        }
+        if buf.len() != size {
+            bail!(
+                "chunk at offset 0x{:x} is truncated, expect 0x{:x} bytes, got 0x{:x}",
+                self.compressed_offset,
+                size,
+                buf.len()
+            );
+        }
+
+        let data = if self.compressed {
+            let mut data = alloc_buf(self.uncompressed_size as usize);
+            compress::decompress(&buf, &mut data, blob.compressor()).with_context(|| {
+                format!(
+                    "failed to decompress chunk at offset 0x{:x}",
+                    self.compressed_offset
+                )
+            })?;
+            data
+        } else {
+            buf
+        };
+
+        let digest = RafsDigest::from_buf(&data, blob.digester());
+        if digest != self.digest {
+            bail!(
+                "digest of chunk at offset 0x{:x} mismatch, expect {}, got {}",
+                self.compressed_offset,
+                self.digest,
+                digest
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Result of checking a data blob.
+#[derive(Serialize)]
+pub struct BlobDataCheck {
+    pub blob_id: String,
+    /// Number of chunks referenced by the filesystem.
+    pub chunks: usize,
+    /// Number of chunks failed to pass the check.
+    pub failed_chunks: usize,
+    /// Chunk data is not verified, for blob formats such as ZRAN.
+    pub skipped: bool,
+    pub errors: Vec<String>,
+}
+
+impl BlobDataCheck {
+    fn new(blob_id: String) -> Self {
+        BlobDataCheck {
+            blob_id,
+            chunks: 0,
+            failed_chunks: 0,
+            skipped: false,
+            errors: Vec::new(),
+        }
+    }
+}
+
+/// Result of checking data of a file.
+#[derive(Serialize)]
+pub struct FileDataCheck {
+    pub path: PathBuf,
+    pub errors: Vec<String>,
+}
+
+/// Result of checking data of a RAFS filesystem.
+#[derive(Serialize)]
+pub struct DataCheckResult {
+    pub blobs: Vec<BlobDataCheck>,
+    /// Files with invalid data.
+    pub files: Vec<FileDataCheck>,
+}
+
+impl DataCheckResult {
+    // Merge chunk check failures, identified by index into `tasks`, into per blob and per file
+    // results.
+    fn new(
+        mut blobs: Vec<BlobDataCheck>,
+        tasks: &[ChunkTask],
+        mut failures: Vec<(usize, String)>,
+    ) -> Self {
+        for task in tasks.iter() {
+            if let Some(blob) = blobs.get_mut(task.blob_index) {
+                blob.chunks += 1;
+            }
+        }
+
+        let mut files = BTreeMap::new();
+        failures.sort_by_key(|(idx, _)| *idx);
+        for (idx, msg) in failures {
+            let task = &tasks[idx];
+            if let Some(blob) = blobs.get_mut(task.blob_index) {
+                blob.failed_chunks += 1;
+                blob.errors.push(msg.clone());
+            }
+            for path in task.files.iter() {
+                files
+                    .entry(path.clone())
+                    .or_insert_with(Vec::new)
+                    .push(msg.clone());
+            }
+        }
+
+        DataCheckResult {
+            blobs,
+            files: files
+                .into_iter()
+                .map(|(path, errors)| FileDataCheck { path, errors })
+                .collect(),
+        }
+    }
+
+    /// Check whether all data has been verified successfully.
+    ///
+    /// Blobs whose chunk data can't be verified, such as ZRAN blobs, fail the check unless
+    /// `allow_skipped` is true.
+    pub fn is_valid(&self, allow_skipped: bool) -> bool {
+        self.files.is_empty()
+            && self
+                .blobs
+                .iter()
+                .all(|b| b.errors.is_empty() && (allow_skipped || !b.skipped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nydus_api::LocalFsConfig;
+    use nydus_storage::backend::BackendResult;
+    use nydus_utils::metrics::BackendMetrics;
+    use vmm_sys_util::tempdir::TempDir;
+
+    struct MockBlobReader {
+        data: Vec<u8>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl MockBlobReader {
+        fn new(data: Vec<u8>) -> Self {
+            Self {
+                data,
+                metrics: Default::default(),
+            }
+        }
+    }
+
+    impl BlobReader for MockBlobReader {
+        fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            let offset = offset as usize;
+            if offset >= self.data.len() {
+                return Ok(0_usize);
+            }
+            let end = self.data.len().min(offset + buf.len());
+            buf[..end - offset].copy_from_slice(&self.data[offset..end]);
+            Ok(end - offset)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            self.metrics.as_ref()
+        }
+
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(self.data.len() as u64)
+        }
+    }
+
+    fn localfs_config(dir: &str) -> BackendConfigV2 {
+        BackendConfigV2 {
+            backend_type: "localfs".to_string(),
+            localfs: Some(LocalFsConfig {
+                blob_file: "".to_string(),
+                dir: dir.to_string(),
+                alt_dirs: vec![],
+            }),
+            localdisk: None,
+            oss: None,
+            registry: None,
+            s3: None,
+            http_proxy: None,
+        }
+    }
+
+    fn new_task(blob_index: usize, data: &[u8], offset: u64, size: usize) -> ChunkTask {
+        ChunkTask {
+            blob_index,
+            digest: RafsDigest::from_buf(data, digest::Algorithm::Sha256),
+            compressed: false,
+            compressed_offset: offset,
+            compressed_size: size as u32,
+            uncompressed_size: data.len() as u32,
+            files: vec![PathBuf::from("/file")],
+        }
+    }
+
+    fn verify(task: &ChunkTask, blob: &BlobInfo, reader: &Arc<dyn BlobReader>) -> Result<()> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(task.verify(blob, reader.clone()))
+    }
+
+    #[test]
+    fn test_chunk_task_verify() {
+        let chunk = vec![0x5au8; 0x1000];
+        let (compressed, is_compressed) =
+            compress::compress(&chunk, compress::Algorithm::Lz4Block).unwrap();
+        assert!(is_compressed);
+        let compressed = compressed.to_vec();
+        let mut data = chunk.clone();
+        data.extend_from_slice(&compressed);
+        let reader: Arc<dyn BlobReader> = Arc::new(MockBlobReader::new(data));
+
+        let mut blob = BlobInfo::new(
+            0,
+            "blob".to_string(),
+            0,
+            0,
+            0x1000,
+            2,
+            BlobFeatures::empty(),
+        );
+        blob.set_compressor(compress::Algorithm::Lz4Block);
+        blob.set_digester(digest::Algorithm::Sha256);
+
+        let task = new_task(0, &chunk, 0, chunk.len());
+        verify(&task, &blob, &reader).unwrap();
+
+        let mut task = new_task(0, &chunk, 0x1000, compressed.len());
+        task.compressed = true;
+        verify(&task, &blob, &reader).unwrap();
+
+        // Digest mismatch.
+        let task = new_task(0, &[0u8; 0x1000], 0, 0x1000);
+        let err = format!("{}", verify(&task, &blob, &reader).unwrap_err());
+        assert!(err.contains("mismatch"), "{}", err);
+
+        // Truncated chunk.
+        let task = new_task(0, &chunk, 0x1000, 0x1000 + compressed.len());
+        let err = format!("{}", verify(&task, &blob, &reader).unwrap_err());
+        assert!(err.contains("truncated"), "{}", err);
+
+        // Corrupted compressed data.
+        let mut task = new_task(0, &chunk, 0, 0x100);
+        task.compressed = true;
+        assert!(verify(&task, &blob, &reader).is_err());
+    }
+
+    #[test]
+    fn test_check_blob_toc() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = Path::new(root_dir).join("tests/texture/toc");
+        let id = "2fa78cad554b75ac91a4a125ed148d0ddeb25efa4aaa8bd80e5dc292690a4dca";
+        let config = localfs_config(path.to_str().unwrap());
+        let reader = Validator::get_reader(&config, id).unwrap();
+        let digest = [
+            79u8, 223, 187, 54, 239, 116, 163, 198, 58, 40, 226, 171, 175, 165, 64, 68, 199, 89,
+            65, 85, 190, 182, 221, 173, 159, 54, 130, 92, 254, 88, 40, 108,
+        ];
+
+        let mut blob = BlobInfo::new(0, id.to_string(), 0, 0, 0x1000, 0, BlobFeatures::empty());
+        // No ToC to verify.
+        Validator::check_blob_meta(&blob, reader.as_ref(), &config).unwrap();
+
+        blob.set_blob_toc_size(1024);
+        blob.set_blob_toc_digest(digest);
+        Validator::check_blob_meta(&blob, reader.as_ref(), &config).unwrap();
+
+        let mut digest = digest;
+        digest[0] ^= 0xff;
+        blob.set_blob_toc_digest(digest);
+        assert!(Validator::check_blob_meta(&blob, reader.as_ref(), &config).is_err());
+
+        blob.set_blob_toc_size(20000);
+        assert!(Validator::check_blob_toc(&blob, reader.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_check_separate_blob_meta() {
+        let tmpdir = TempDir::new().unwrap();
+        let meta = vec![0x33u8; 0x3000];
+        let digest = RafsDigest::from_buf(&meta, digest::Algorithm::Sha256);
+        let meta_id = digest.to_string();
+        std::fs::write(tmpdir.as_path().join(&meta_id), &meta).unwrap();
+        let config = localfs_config(tmpdir.as_path().to_str().unwrap());
+        let reader = MockBlobReader::new(Vec::new());
+
+        let mut blob = BlobInfo::new(
+            0,
+            "blob".to_string(),
+            0,
+            0,
+            0x1000,
+            0,
+            BlobFeatures::SEPARATE,
+        );
+        blob.set_blob_meta_digest(digest.data);
+        Validator::check_blob_meta(&blob, &reader, &config).unwrap();
+
+        // Corrupted RAFS blob.
+        std::fs::write(tmpdir.as_path().join(&meta_id), &meta[..0x2000]).unwrap();
+        let err = format!(
+            "{}",
+            Validator::check_blob_meta(&blob, &reader, &config).unwrap_err()
+        );
+        assert!(err.contains("mismatch"), "{}", err);
+
+        // Missing RAFS blob.
+        std::fs::remove_file(tmpdir.as_path().join(&meta_id)).unwrap();
+        assert!(Validator::check_blob_meta(&blob, &reader, &config).is_err());
+    }
+
+    #[test]
+    fn test_data_check_result() {
+        let blobs = vec![
+            BlobDataCheck::new("blob1".to_string()),
+            BlobDataCheck::new("blob2".to_string()),
+        ];
+        let mut tasks = vec![
+            new_task(0, &[1u8], 0, 1),
+            new_task(0, &[2u8], 1, 1),
+            new_task(1, &[3u8], 0, 1),
+        ];
+        tasks[1].files.push(PathBuf::from("/another"));
+        let failures = vec![(2, "error2".to_string()), (1, "error1".to_string())];
+
+        let result = DataCheckResult::new(blobs, &tasks, failures);
+        assert!(!result.is_valid(true));
+        assert_eq!(result.blobs[0].chunks, 2);
+        assert_eq!(result.blobs[0].failed_chunks, 1);
+        assert_eq!(result.blobs[0].errors, vec!["error1".to_string()]);
+        assert_eq!(result.blobs[1].chunks, 1);
+        assert_eq!(result.blobs[1].failed_chunks, 1);
+        assert_eq!(result.files.len(), 2);
+        assert_eq!(result.files[0].path, PathBuf::from("/another"));
+        assert_eq!(result.files[1].path, PathBuf::from("/file"));
+        assert_eq!(
+            result.files[1].errors,
+            vec!["error1".to_string(), "error2".to_string()]
+        );
+
+        let mut blobs = vec![
+            BlobDataCheck::new("blob1".to_string()),
+            BlobDataCheck::new("blob2".to_string()),
+        ];
+        blobs[1].skipped = true;
+        let result = DataCheckResult::new(blobs, &tasks, Vec::new());
+        assert!(result.files.is_empty());
+        assert!(!result.is_valid(false));
+        assert!(result.is_valid(true));
+    }
 }
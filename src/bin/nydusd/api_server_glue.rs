@@ -17,8 +17,10 @@ use nydus::daemon::NydusDaemon;
 use nydus::{FsBackendMountCmd, FsBackendType, FsBackendUmountCmd, FsService};
 use nydus_api::{
     start_http_thread, ApiError, ApiMountCmd, ApiRequest, ApiResponse, ApiResponsePayload,
-    ApiResult, BlobCacheEntry, BlobCacheObjectId, DaemonConf, DaemonErrorKind, MetricsErrorKind,
+    ApiResult, BackendConfigV2, BlobCacheEntry, BlobCacheObjectId, DaemonConf, DaemonErrorKind,
+    MetricsErrorKind,
 };
+use nydus_storage::factory::BLOB_FACTORY;
 use nydus_utils::metrics;
 
 use crate::DAEMON_CONTROLLER;
@@ -45,8 +47,12 @@ impl ApiServer {
             ApiRequest::Mount(mountpoint, info) => self.do_mount(mountpoint, info),
             ApiRequest::Remount(mountpoint, info) => self.do_remount(mountpoint, info),
             ApiRequest::Umount(mountpoint) => self.do_umount(mountpoint),
+            ApiRequest::UpdateBackendConfig(mountpoint, config) => {
+                self.update_backend_config(&mountpoint, &config)
+            }
             ApiRequest::ExportBackendMetrics(id) => Self::export_backend_metrics(id),
             ApiRequest::ExportBlobcacheMetrics(id) => Self::export_blobcache_metrics(id),
+            ApiRequest::ScrubBlobcache(id) => Self::scrub_blobcache(id),
 
             // Nydus API v1
             ApiRequest::ExportFsGlobalMetrics(id) => Self::export_global_metrics(id),
@@ -63,6 +69,9 @@ impl ApiServer {
             ApiRequest::CreateBlobObject(entry) => self.create_blob_cache_entry(&entry),
             ApiRequest::DeleteBlobObject(param) => self.remove_blob_cache_entry(&param),
             ApiRequest::DeleteBlobFile(blob_id) => self.blob_cache_gc(blob_id),
+            ApiRequest::UpdateBlobBackendConfig(param, config) => {
+                self.update_blob_backend_config(&param, &config)
+            }
         };
 
         self.respond(resp);
@@ -166,6 +175,15 @@ impl ApiServer {
             .map_err(|e| ApiError::Metrics(MetricsErrorKind::Stats(e)))
     }
 
+    fn scrub_blobcache(id: Option<String>) -> ApiResponse {
+        let results = BLOB_FACTORY
+            .scrub(id.as_deref())
+            .map_err(|e| ApiError::DaemonAbnormal(DaemonErrorKind::Other(e.to_string())))?;
+        serde_json::to_string(&results)
+            .map(ApiResponsePayload::BlobcacheScrub)
+            .map_err(|e| ApiError::DaemonAbnormal(DaemonErrorKind::Serde(e)))
+    }
+
     #[inline]
     fn get_daemon_object(&self) -> std::result::Result<Arc<dyn NydusDaemon>, ApiError> {
         Ok(DAEMON_CONTROLLER.get_daemon())
@@ -255,6 +273,13 @@ impl ApiServer {
             .map_err(|e| ApiError::MountFilesystem(e.into()))
     }
 
+    fn update_backend_config(&self, mountpoint: &str, config: &BackendConfigV2) -> ApiResponse {
+        self.get_default_fs_service()?
+            .update_backend_config(mountpoint, config)
+            .map(|_| ApiResponsePayload::Empty)
+            .map_err(|e| ApiError::MountFilesystem(e.into()))
+    }
+
     fn send_fuse_fd(&self) -> ApiResponse {
         let d = self.get_daemon_object()?;
 
@@ -302,6 +327,20 @@ impl ApiServer {
         }
     }
 
+    fn update_blob_backend_config(
+        &self,
+        param: &BlobCacheObjectId,
+        config: &BackendConfigV2,
+    ) -> ApiResponse {
+        match DAEMON_CONTROLLER.get_blob_cache_mgr() {
+            None => Err(ApiError::DaemonAbnormal(DaemonErrorKind::Unsupported)),
+            Some(mgr) => mgr
+                .update_backend_config(param, config)
+                .map(|_| ApiResponsePayload::Empty)
+                .map_err(|e| ApiError::DaemonAbnormal(DaemonErrorKind::Other(e.to_string()))),
+        }
+    }
+
     fn blob_cache_gc(&self, blob_id: String) -> ApiResponse {
         self.get_daemon_object()?
             .delete_blob(blob_id)
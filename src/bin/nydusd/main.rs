@@ -186,17 +186,22 @@ fn append_singleton_subcmd_options(cmd: Command) -> Command {
         .about("Run the Nydus daemon to host multiple blobcache/fscache/fuse/virtio-fs services");
     let subcmd = append_fscache_options(subcmd);
 
-    // TODO: enable support of fuse service
-    /*
-    let subcmd = subcmd.arg(
-        Arg::new("fuse")
-            .long("fuse")
-            .short("f")
-            .help("Run as a shared FUSE server"),
-    );
-    let subcmd = append_fuse_options(subcmd);
-    let subcmd = append_fs_options(subcmd);
-    */
+    let subcmd = subcmd
+        .arg(
+            Arg::new("fuse")
+                .long("fuse")
+                .short('f')
+                .action(ArgAction::SetTrue)
+                .help("Enable FUSE service, FUSE mountpoints are managed by the mount/umount API"),
+        )
+        .arg(
+            Arg::new("fuse-threads")
+                .long("fuse-threads")
+                .default_value("4")
+                .help("Number of worker threads to serve FUSE I/O requests for each mountpoint")
+                .value_parser(thread_validator)
+                .required(false),
+        );
 
     cmd.subcommand(subcmd)
 }
@@ -569,6 +574,16 @@ fn process_singleton_arguments(
     let fscache = subargs.value_of("fscache").map(|s| s.as_str());
     let tag = subargs.value_of("fscache-tag").map(|s| s.as_str());
     let threads = subargs.value_of("fscache-threads").map(|s| s.as_str());
+    let fuse_threads = if subargs.is_present("fuse") {
+        // The argument always has a value because of its default value.
+        let n = subargs.value_of("fuse-threads").unwrap();
+        let threads = n
+            .parse()
+            .map_err(|_| einval!(format!("invalid number of FUSE threads {}", n)))?;
+        Some(threads)
+    } else {
+        None
+    };
     info!("Start Nydus daemon in singleton mode!");
     let daemon = create_daemon(
         id,
@@ -576,6 +591,7 @@ fn process_singleton_arguments(
         fscache,
         tag,
         threads,
+        fuse_threads,
         config,
         bti,
         DAEMON_CONTROLLER.alloc_waker(),
@@ -681,16 +697,8 @@ mod nbd {
                 "both option `-C/--config` and `-B/--bootstrap` are missing"
             ));
         };
-        if !entry.prepare_configuration_info() {
-            return Err(einval!(
-                "invalid blob cache entry configuration information"
-            ));
-        }
-        if entry.validate() == false {
-            return Err(einval!(
-                "invalid blob cache entry configuration information"
-            ));
-        }
+        entry.prepare_configuration_info()?;
+        entry.validate()?;
 
         // Safe to unwrap because `DEVICE` is mandatory option.
         let device = args.value_of("DEVICE").unwrap().to_string();
@@ -7,38 +7,72 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::{Read, Result};
 use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU8, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{fmt, thread};
 
+use arc_swap::{ArcSwap, ArcSwapOption};
+use bytes::Bytes;
+use lazy_static::lazy_static;
 use log::{max_level, Level};
 
-use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::header::{HeaderName, HeaderValue, RANGE};
 use reqwest::{
     self,
     blocking::{Body, Client, Response},
     header::HeaderMap,
     redirect::Policy,
-    Method, StatusCode, Url,
+    Method, ResponseBuilderExt, StatusCode, Url,
 };
 
-use nydus_api::{HttpProxyConfig, MirrorConfig, OssConfig, ProxyConfig, RegistryConfig, S3Config};
+use nydus_api::{
+    HedgeConfig, HttpProxyConfig, MirrorConfig, OssConfig, ProxyConfig, RegistryConfig,
+    RetryConfig, S3Config,
+};
+use nydus_utils::metrics::{BackendMetrics, MirrorMetrics};
 use url::ParseError;
 
+use crate::backend::retry::{random_u64, CircuitBreaker, RetryPolicy};
+
 const HEADER_AUTHORIZATION: &str = "Authorization";
 
 const RATE_LIMITED_LOG_TIME: u8 = 2;
 
+// Number of recent request latencies to compute the hedging delay.
+const HEDGE_LATENCY_SAMPLES: usize = 256;
+// Recompute the hedging delay once every so many requests.
+const HEDGE_UPDATE_INTERVAL: usize = 32;
+// Weight of history in the moving average of mirror latency, the new sample weighs 1/8.
+const MIRROR_LATENCY_EWMA_WEIGHT: u64 = 8;
+// Penalty added to latency of failed mirror requests, in microseconds.
+const MIRROR_ERROR_PENALTY_MICROS: u64 = 1_000_000;
+// Try another mirror first for one in so many requests with the least-latency strategy.
+const MIRROR_LATENCY_EXPLORE_INTERVAL: usize = 16;
+
 thread_local! {
     pub static LAST_FALLBACK_AT: RefCell<SystemTime> = RefCell::new(UNIX_EPOCH);
 }
 
+lazy_static! {
+    // Runtime to drive network IO of hedgeable requests, whose futures are polled by the threads
+    // sending the requests.
+    static ref HEDGE_RUNTIME: Option<tokio::runtime::Runtime> =
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("nydus_hedge")
+            .enable_all()
+            .build()
+            .map_err(|e| warn!("failed to create runtime for hedged requests, {}", e))
+            .ok();
+}
+
 /// Error codes related to network communication.
 #[derive(Debug)]
 pub enum ConnectionError {
     Disconnected,
     ErrorWithMsg(String),
+    Status(StatusCode, String),
     Common(reqwest::Error),
     Format(reqwest::Error),
     Url(String, ParseError),
@@ -52,6 +86,7 @@ impl fmt::Display for ConnectionError {
         match self {
             ConnectionError::Disconnected => write!(f, "network connection disconnected"),
             ConnectionError::ErrorWithMsg(s) => write!(f, "network error, {}", s),
+            ConnectionError::Status(c, s) => write!(f, "network error, status {}, {}", c, s),
             ConnectionError::Common(e) => write!(f, "network error, {}", e),
             ConnectionError::Format(e) => write!(f, "{}", e),
             ConnectionError::Url(s, e) => write!(f, "failed to parse URL {}, {}", s, e),
@@ -62,6 +97,34 @@ impl fmt::Display for ConnectionError {
     }
 }
 
+impl ConnectionError {
+    /// Get HTTP status code of the failed request, if any.
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            ConnectionError::Status(c, _) => Some(c.as_u16()),
+            ConnectionError::Common(e) | ConnectionError::Format(e) => {
+                e.status().map(|c| c.as_u16())
+            }
+            _ => None,
+        }
+    }
+}
+
+// FNV-1a hash of `key` and `salt` with a final mixing step, which is stable across processes
+// and versions, so all nodes map the same blob to the same mirror.
+fn stable_hash(key: &[u8], salt: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in key.iter().chain(&[0u8]).chain(salt.iter()) {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
 /// Specialized `Result` for network communication.
 type ConnectionResult<T> = std::result::Result<T, ConnectionError>;
 
@@ -74,6 +137,9 @@ pub(crate) struct ConnectionConfig {
     pub timeout: u32,
     pub connect_timeout: u32,
     pub retry_limit: u8,
+    pub retry: RetryConfig,
+    pub hedge: HedgeConfig,
+    pub mirror_strategy: String,
 }
 
 impl Default for ConnectionConfig {
@@ -85,6 +151,9 @@ impl Default for ConnectionConfig {
             timeout: 5,
             connect_timeout: 5,
             retry_limit: 0,
+            retry: RetryConfig::default(),
+            hedge: HedgeConfig::default(),
+            mirror_strategy: String::new(),
         }
     }
 }
@@ -98,6 +167,9 @@ impl From<OssConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            retry: c.retry,
+            hedge: c.hedge,
+            mirror_strategy: c.mirror_strategy,
         }
     }
 }
@@ -111,6 +183,9 @@ impl From<S3Config> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            retry: c.retry,
+            hedge: c.hedge,
+            mirror_strategy: c.mirror_strategy,
         }
     }
 }
@@ -124,6 +199,9 @@ impl From<RegistryConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            retry: c.retry,
+            hedge: c.hedge,
+            mirror_strategy: c.mirror_strategy,
         }
     }
 }
@@ -137,6 +215,9 @@ impl From<HttpProxyConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            retry: c.retry,
+            hedge: c.hedge,
+            mirror_strategy: c.mirror_strategy,
         }
     }
 }
@@ -212,11 +293,14 @@ const SCHEME_REVERSION_CACHE_RETAIN: i16 = 2;
 #[derive(Debug)]
 struct Proxy {
     client: Client,
+    async_client: reqwest::Client,
     health: ProxyHealth,
     fallback: bool,
     use_http: bool,
     // Cache whether should try to replace scheme for proxy url.
     replace_scheme: AtomicI16,
+    // Set when the proxy has been replaced by a configuration update.
+    retired: AtomicBool,
 }
 
 impl Proxy {
@@ -253,18 +337,134 @@ pub(crate) fn respond(resp: Response, catch_status: bool) -> ConnectionResult<Re
     if !catch_status || is_success_status(resp.status()) {
         Ok(resp)
     } else {
+        let status = resp.status();
         let msg = resp.text().map_err(ConnectionError::Format)?;
-        Err(ConnectionError::ErrorWithMsg(msg))
+        Err(ConnectionError::Status(status, msg))
+    }
+}
+
+/// Convert an asynchronous HTTP `Response` into an `Result<Response>`.
+pub(crate) async fn respond_async(
+    resp: reqwest::Response,
+    catch_status: bool,
+) -> ConnectionResult<reqwest::Response> {
+    if !catch_status || is_success_status(resp.status()) {
+        Ok(resp)
+    } else {
+        let status = resp.status();
+        let msg = resp.text().await.map_err(ConnectionError::Format)?;
+        Err(ConnectionError::Status(status, msg))
+    }
+}
+
+/// Track latencies of recent range requests to decide when to issue hedged requests.
+#[derive(Debug)]
+struct Hedger {
+    percentile: usize,
+    min_delay: Duration,
+    // Ring buffer of recent request latencies in microseconds, and total number of requests.
+    samples: Mutex<(Vec<u64>, usize)>,
+    // Current hedging delay in microseconds.
+    delay: AtomicU64,
+}
+
+impl Hedger {
+    fn new(config: &HedgeConfig) -> Option<Self> {
+        if !config.enable {
+            return None;
+        }
+
+        let min_delay = Duration::from_millis(config.min_delay);
+        Some(Hedger {
+            percentile: config.percentile.clamp(1, 99) as usize,
+            min_delay,
+            samples: Mutex::new((Vec::with_capacity(HEDGE_LATENCY_SAMPLES), 0)),
+            delay: AtomicU64::new(min_delay.as_micros() as u64),
+        })
+    }
+
+    fn delay(&self) -> Duration {
+        Duration::from_micros(self.delay.load(Ordering::Relaxed))
+    }
+
+    fn record(&self, latency: Duration) {
+        let mut guard = self.samples.lock().unwrap();
+        let (samples, count) = &mut *guard;
+        let latency = latency.as_micros() as u64;
+        if samples.len() < HEDGE_LATENCY_SAMPLES {
+            samples.push(latency);
+        } else {
+            samples[*count % HEDGE_LATENCY_SAMPLES] = latency;
+        }
+        *count += 1;
+
+        if *count % HEDGE_UPDATE_INTERVAL == 0 {
+            let mut sorted = samples.clone();
+            sorted.sort_unstable();
+            let idx = (sorted.len() - 1) * self.percentile / 100;
+            let delay = sorted[idx].max(self.min_delay.as_micros() as u64);
+            self.delay.store(delay, Ordering::Relaxed);
+        }
     }
 }
 
 /// A network connection to communicate with remote server.
+///
+/// The proxy and mirrors may be replaced by [Connection::update()] at runtime, requests already
+/// in flight keep using the proxy and mirrors they have started with.
 #[derive(Debug)]
 pub(crate) struct Connection {
     client: Client,
-    proxy: Option<Arc<Proxy>>,
-    pub mirrors: Vec<Arc<Mirror>>,
+    async_client: reqwest::Client,
+    proxy: ArcSwapOption<Proxy>,
+    pub mirrors: ArcSwap<Vec<Arc<Mirror>>>,
+    mirror_strategy: ArcSwap<MirrorStrategy>,
+    mirror_round_robin: AtomicUsize,
     pub shutdown: AtomicBool,
+    retry_policy: RetryPolicy,
+    circuit_breaker: Option<CircuitBreaker>,
+    hedger: Option<Hedger>,
+    metrics: Option<Arc<BackendMetrics>>,
+}
+
+/// Proxy and mirrors created for a new configuration, pending to be switched to.
+pub(crate) struct ConnectionUpdate {
+    proxy: Option<Arc<Proxy>>,
+    mirror_strategy: MirrorStrategy,
+    mirrors: Vec<Arc<Mirror>>,
+    connect_timeout: u64,
+    timeout: u64,
+}
+
+/// Strategy to select mirrors for requests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MirrorStrategy {
+    /// Try mirrors in configuration order.
+    Ordered,
+    /// Rotate the first mirror to try for each request.
+    RoundRobin,
+    /// Try mirrors in order of average request latency, other mirrors are tried first once in a
+    /// while to refresh their latency.
+    LeastLatency,
+    /// Shuffle mirrors randomly according to their weights.
+    Weighted,
+    /// Order mirrors by rendezvous hashing of blob id, so the same blob goes to the same mirror.
+    ConsistentHash,
+}
+
+impl FromStr for MirrorStrategy {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "" | "ordered" => Ok(MirrorStrategy::Ordered),
+            "round-robin" => Ok(MirrorStrategy::RoundRobin),
+            "least-latency" => Ok(MirrorStrategy::LeastLatency),
+            "weighted" => Ok(MirrorStrategy::Weighted),
+            "consistent-hash" => Ok(MirrorStrategy::ConsistentHash),
+            _ => Err(einval!(format!("unknown mirror strategy '{}'", s))),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -277,9 +477,82 @@ pub(crate) struct Mirror {
     failed_times: AtomicU8,
     /// Failure count for which mirror is considered unavailable.
     failure_limit: u8,
+    /// Set when the mirror has been replaced by a configuration update.
+    retired: AtomicBool,
+    /// Moving average of request latency in microseconds, 0 if no request has been sent.
+    latency: AtomicU64,
+    /// Metrics for the mirror.
+    metrics: Option<Arc<MirrorMetrics>>,
 }
 
 impl Mirror {
+    /// Record latency and result of a request sent to the mirror.
+    fn record(&self, latency: Duration, error: bool) {
+        let mut sample = latency.as_micros() as u64;
+        if error {
+            sample = sample.saturating_add(MIRROR_ERROR_PENALTY_MICROS);
+        }
+        let old = self.latency.load(Ordering::Relaxed);
+        let ewma = if old == 0 {
+            sample
+        } else {
+            (old.saturating_mul(MIRROR_LATENCY_EWMA_WEIGHT - 1) + sample)
+                / MIRROR_LATENCY_EWMA_WEIGHT
+        };
+        self.latency.store(ewma.max(1), Ordering::Relaxed);
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.end(ewma, error);
+        }
+    }
+
+    /// Count a failed request, and disable the mirror once reaching the failure limit.
+    fn on_failure(&self) {
+        let failed_times = self.failed_times.fetch_add(1, Ordering::Relaxed) + 1;
+        if failed_times >= self.failure_limit {
+            warn!(
+                "reach to failure limit {}, disable mirror: {:?}",
+                self.failure_limit, self
+            );
+            self.set_status(false);
+        }
+    }
+
+    fn set_status(&self, healthy: bool) {
+        self.status.store(healthy, Ordering::Relaxed);
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.set_healthy(healthy);
+        }
+    }
+
+    // Weighted rendezvous hashing score of the mirror for `key`.
+    fn rendezvous_score(&self, key: &str) -> f64 {
+        let hash = stable_hash(key.as_bytes(), self.config.host.as_bytes());
+        // Map the hash into (0, 1) and compute `-weight / ln(u)`.
+        let u = (hash as f64 + 1.0) / (u64::MAX as f64 + 2.0);
+        -(self.config.weight.max(1) as f64) / u.ln()
+    }
+
+    /// Get URL and headers to send hedged requests, for a request originally sent to `current`.
+    fn hedge_target(
+        &self,
+        current: &Mirror,
+        url: &str,
+        headers: &HeaderMap,
+    ) -> Option<(String, HeaderMap)> {
+        let mirror_url = self.mirror_url(url).ok()?;
+        let mut headers = headers.clone();
+        for (key, _) in current.config.headers.iter() {
+            headers.remove(HeaderName::from_str(key).ok()?);
+        }
+        for (key, value) in self.config.headers.iter() {
+            headers.insert(
+                HeaderName::from_str(key).ok()?,
+                HeaderValue::from_str(value).ok()?,
+            );
+        }
+        Some((mirror_url.to_string(), headers))
+    }
+
     /// Convert original URL to mirror URL.
     fn mirror_url(&self, url: &str) -> ConnectionResult<Url> {
         let mirror_host = Url::parse(&self.config.host)
@@ -302,65 +575,224 @@ impl Mirror {
 
 impl Connection {
     /// Create a new connection according to the configuration.
-    pub fn new(config: &ConnectionConfig) -> Result<Arc<Connection>> {
+    pub fn new(
+        config: &ConnectionConfig,
+        metrics: Option<Arc<BackendMetrics>>,
+    ) -> Result<Arc<Connection>> {
         info!("backend config: {:?}", config);
         let client = Self::build_connection("", config)?;
+        let async_client = Self::build_async_connection("", config)?;
+        let proxy = Self::new_proxy(config)?;
+        let mirror_strategy = MirrorStrategy::from_str(&config.mirror_strategy)?;
+        let mirrors = Self::new_mirrors(config, metrics.as_ref());
 
-        let proxy = if !config.proxy.url.is_empty() {
-            let ping_url = if !config.proxy.ping_url.is_empty() {
-                Some(Url::from_str(&config.proxy.ping_url).map_err(|e| einval!(e))?)
-            } else {
-                None
-            };
-            Some(Arc::new(Proxy {
-                client: Self::build_connection(&config.proxy.url, config)?,
-                health: ProxyHealth::new(config.proxy.check_interval, ping_url),
-                fallback: config.proxy.fallback,
-                use_http: config.proxy.use_http,
-                replace_scheme: AtomicI16::new(SCHEME_REVERSION_CACHE_UNSET),
-            }))
+        let connection = Arc::new(Connection {
+            client,
+            async_client,
+            proxy: ArcSwapOption::new(proxy),
+            mirrors: ArcSwap::new(Arc::new(mirrors)),
+            mirror_strategy: ArcSwap::new(Arc::new(mirror_strategy)),
+            mirror_round_robin: AtomicUsize::new(0),
+            shutdown: AtomicBool::new(false),
+            retry_policy: RetryPolicy::new(&config.retry),
+            circuit_breaker: CircuitBreaker::new(&config.retry.circuit_breaker),
+            hedger: Hedger::new(&config.hedge),
+            metrics,
+        });
+
+        // Start  proxy's health checking thread.
+        connection.start_proxy_health_thread(config.connect_timeout as u64);
+
+        // Start mirrors' health checking thread.
+        connection.start_mirrors_health_thread(config.timeout as u64);
+
+        Ok(connection)
+    }
+
+    /// Get the policy to retry failed requests.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Get the circuit breaker for the remote server, if enabled.
+    pub fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.circuit_breaker.as_ref()
+    }
+
+    /// Update proxy and mirrors of the connection.
+    ///
+    /// The new proxy and mirrors are switched in atomically, and health checking threads for the
+    /// old ones will exit.
+    pub fn update(&self, config: &ConnectionConfig) -> Result<()> {
+        let update = self.prepare_update(config)?;
+        self.apply_update(update);
+
+        Ok(())
+    }
+
+    /// Prepare proxy and mirrors for a new configuration, without switching to them.
+    ///
+    /// It allows backends to validate the new configuration and switch credentials before
+    /// requests are sent through the new proxy and mirrors by [Connection::apply_update()].
+    pub(crate) fn prepare_update(&self, config: &ConnectionConfig) -> Result<ConnectionUpdate> {
+        info!("update backend config: {:?}", config);
+        Ok(ConnectionUpdate {
+            proxy: Self::new_proxy(config)?,
+            mirror_strategy: MirrorStrategy::from_str(&config.mirror_strategy)?,
+            mirrors: Self::new_mirrors(config, self.metrics.as_ref()),
+            connect_timeout: config.connect_timeout as u64,
+            timeout: config.timeout as u64,
+        })
+    }
+
+    /// Switch to the proxy and mirrors prepared by [Connection::prepare_update()].
+    ///
+    /// The old proxy and mirrors are marked as retired, so their health checking threads exit,
+    /// and metrics of mirrors removed from the configuration are dropped.
+    pub(crate) fn apply_update(&self, update: ConnectionUpdate) {
+        self.mirror_strategy.store(Arc::new(update.mirror_strategy));
+        if let Some(proxy) = self.proxy.swap(update.proxy) {
+            proxy.retired.store(true, Ordering::Release);
+        }
+        let mirrors = Arc::new(update.mirrors);
+        for mirror in self.mirrors.swap(mirrors.clone()).iter() {
+            mirror.retired.store(true, Ordering::Release);
+            if let Some(metrics) = self.metrics.as_ref() {
+                if !mirrors.iter().any(|m| m.config.host == mirror.config.host) {
+                    metrics.remove_mirror_metrics(&mirror.config.host);
+                }
+            }
+        }
+
+        self.start_proxy_health_thread(update.connect_timeout);
+        self.start_mirrors_health_thread(update.timeout);
+    }
+
+    fn new_proxy(config: &ConnectionConfig) -> Result<Option<Arc<Proxy>>> {
+        if config.proxy.url.is_empty() {
+            return Ok(None);
+        }
+
+        let ping_url = if !config.proxy.ping_url.is_empty() {
+            Some(Url::from_str(&config.proxy.ping_url).map_err(|e| einval!(e))?)
         } else {
             None
         };
+        Ok(Some(Arc::new(Proxy {
+            client: Self::build_connection(&config.proxy.url, config)?,
+            async_client: Self::build_async_connection(&config.proxy.url, config)?,
+            health: ProxyHealth::new(config.proxy.check_interval, ping_url),
+            fallback: config.proxy.fallback,
+            use_http: config.proxy.use_http,
+            replace_scheme: AtomicI16::new(SCHEME_REVERSION_CACHE_UNSET),
+            retired: AtomicBool::new(false),
+        })))
+    }
 
+    fn new_mirrors(
+        config: &ConnectionConfig,
+        metrics: Option<&Arc<BackendMetrics>>,
+    ) -> Vec<Arc<Mirror>> {
         let mut mirrors = Vec::new();
         for mirror_config in config.mirrors.iter() {
             if !mirror_config.host.is_empty() {
+                let metrics = metrics.map(|m| m.mirror_metrics(&mirror_config.host));
+                if let Some(m) = metrics.as_ref() {
+                    m.set_healthy(true);
+                }
                 mirrors.push(Arc::new(Mirror {
                     config: mirror_config.clone(),
                     status: AtomicBool::from(true),
                     failed_times: AtomicU8::from(0),
                     failure_limit: mirror_config.failure_limit,
+                    retired: AtomicBool::new(false),
+                    latency: AtomicU64::new(0),
+                    metrics,
                 }));
             }
         }
+        mirrors
+    }
 
-        let connection = Arc::new(Connection {
-            client,
-            proxy,
-            mirrors,
-            shutdown: AtomicBool::new(false),
-        });
-
-        // Start  proxy's health checking thread.
-        connection.start_proxy_health_thread(config.connect_timeout as u64);
+    /// Order mirrors to try for a request to `url` according to the mirror strategy.
+    fn select_mirrors(&self, mirrors: &[Arc<Mirror>], url: &str) -> Vec<Arc<Mirror>> {
+        let mut selected = mirrors.to_vec();
+        if selected.len() <= 1 {
+            return selected;
+        }
 
-        // Start mirrors' health checking thread.
-        connection.start_mirrors_health_thread(config.timeout as u64);
+        match **self.mirror_strategy.load() {
+            MirrorStrategy::Ordered => {}
+            MirrorStrategy::RoundRobin => {
+                let start = self.mirror_round_robin.fetch_add(1, Ordering::Relaxed);
+                selected.rotate_left(start % mirrors.len());
+            }
+            MirrorStrategy::LeastLatency => {
+                // Mirrors without any latency sample go first, so they get a chance to be measured.
+                selected.sort_by_key(|m| m.latency.load(Ordering::Relaxed));
+                // Otherwise a mirror which has been slow or failed once would never be measured
+                // again, so move other mirrors to the front in turn once in a while.
+                let seq = self.mirror_round_robin.fetch_add(1, Ordering::Relaxed);
+                if seq % MIRROR_LATENCY_EXPLORE_INTERVAL == MIRROR_LATENCY_EXPLORE_INTERVAL - 1 {
+                    let idx = 1 + (seq / MIRROR_LATENCY_EXPLORE_INTERVAL) % (selected.len() - 1);
+                    let mirror = selected.remove(idx);
+                    selected.insert(0, mirror);
+                }
+            }
+            MirrorStrategy::Weighted => {
+                let mut remaining = selected;
+                selected = Vec::with_capacity(remaining.len());
+                while !remaining.is_empty() {
+                    let total: u64 = remaining
+                        .iter()
+                        .map(|m| m.config.weight.max(1) as u64)
+                        .sum();
+                    let mut point = random_u64() % total;
+                    let mut idx = 0;
+                    for (i, m) in remaining.iter().enumerate() {
+                        let weight = m.config.weight.max(1) as u64;
+                        if point < weight {
+                            idx = i;
+                            break;
+                        }
+                        point -= weight;
+                    }
+                    selected.push(remaining.remove(idx));
+                }
+            }
+            MirrorStrategy::ConsistentHash => {
+                // Use the last path segment, which is the blob id, as the key.
+                let key = Url::parse(url)
+                    .ok()
+                    .and_then(|u| {
+                        u.path_segments()
+                            .and_then(|s| s.last().map(|s| s.to_string()))
+                    })
+                    .unwrap_or_else(|| url.to_string());
+                let mut scored: Vec<(f64, Arc<Mirror>)> = selected
+                    .into_iter()
+                    .map(|m| (m.rendezvous_score(&key), m))
+                    .collect();
+                scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+                selected = scored.into_iter().map(|(_, m)| m).collect();
+            }
+        }
 
-        Ok(connection)
+        selected
     }
 
     fn start_proxy_health_thread(&self, connect_timeout: u64) {
-        if let Some(proxy) = self.proxy.as_ref() {
+        if let Some(proxy) = self.proxy.load_full() {
             if proxy.health.ping_url.is_some() {
-                let proxy = proxy.clone();
                 // Spawn thread to update the health status of proxy server
                 thread::spawn(move || {
                     let ping_url = proxy.health.ping_url.as_ref().unwrap();
                     let mut last_success = true;
 
                     loop {
+                        if proxy.retired.load(Ordering::Acquire) {
+                            break;
+                        }
                         let client = Client::new();
                         let _ = client
                             .get(ping_url.clone())
@@ -395,7 +827,7 @@ impl Connection {
     }
 
     fn start_mirrors_health_thread(&self, timeout: u64) {
-        for mirror in self.mirrors.iter() {
+        for mirror in self.mirrors.load().iter() {
             let mirror_cloned = mirror.clone();
             thread::spawn(move || {
                 let mirror_health_url = if mirror_cloned.config.ping_url.is_empty() {
@@ -407,6 +839,9 @@ impl Connection {
 
                 let client = Client::new();
                 loop {
+                    if mirror_cloned.retired.load(Ordering::Acquire) {
+                        break;
+                    }
                     // Try to recover the mirror server when it is unavailable.
                     if !mirror_cloned.status.load(Ordering::Relaxed) {
                         info!(
@@ -424,7 +859,7 @@ impl Connection {
                                 if resp.status() < StatusCode::INTERNAL_SERVER_ERROR {
                                     info!("Mirror server {} recovered", mirror_cloned.config.host);
                                     mirror_cloned.failed_times.store(0, Ordering::Relaxed);
-                                    mirror_cloned.status.store(true, Ordering::Relaxed);
+                                    mirror_cloned.set_status(true);
                                 }
                             })
                             .map_err(|e| {
@@ -471,7 +906,7 @@ impl Connection {
             return Err(ConnectionError::Disconnected);
         }
 
-        if let Some(proxy) = &self.proxy {
+        if let Some(proxy) = self.proxy.load_full() {
             if proxy.health.ok() {
                 let data_cloned = data.as_ref().cloned();
 
@@ -485,8 +920,9 @@ impl Connection {
                     }
                 }
 
-                let result = self.call_inner(
+                let result = self.call_hedged(
                     &proxy.client,
+                    &proxy.async_client,
                     method.clone(),
                     replaced_url,
                     &query,
@@ -494,6 +930,7 @@ impl Connection {
                     headers,
                     catch_status,
                     true,
+                    None,
                 );
 
                 match result {
@@ -524,9 +961,10 @@ impl Connection {
             }
         }
 
-        if !self.mirrors.is_empty() {
+        let mirrors = self.select_mirrors(&self.mirrors.load(), url);
+        if !mirrors.is_empty() {
             let mut fallback_due_auth = false;
-            for mirror in self.mirrors.iter() {
+            for (idx, mirror) in mirrors.iter().enumerate() {
                 // With configuration `auth_through` disabled, we should not intend to send authentication
                 // request to mirror. Mainly because mirrors like P2P/Dragonfly has a poor performance when
                 // relaying non-data requests. But it's still possible that ever returned token is expired.
@@ -558,8 +996,20 @@ impl Connection {
                     let current_url = mirror.mirror_url(url)?;
                     debug!("mirror server url {}", current_url);
 
-                    let result = self.call_inner(
+                    // Prefer to send hedged requests to the next healthy mirror.
+                    let hedge_target = if self.hedger.is_some() {
+                        mirrors[idx + 1..]
+                            .iter()
+                            .find(|m| m.status.load(Ordering::Relaxed))
+                            .and_then(|m| m.hedge_target(mirror, url, headers))
+                    } else {
+                        None
+                    };
+
+                    let start = Instant::now();
+                    let result = self.call_hedged(
                         &self.client,
+                        &self.async_client,
                         method.clone(),
                         current_url.as_str(),
                         &query,
@@ -567,29 +1017,25 @@ impl Connection {
                         headers,
                         catch_status,
                         false,
+                        hedge_target,
                     );
 
                     match result {
                         Ok(resp) => {
                             // If the response status >= INTERNAL_SERVER_ERROR, move to the next mirror server.
-                            if resp.status() < StatusCode::INTERNAL_SERVER_ERROR {
+                            let success = resp.status() < StatusCode::INTERNAL_SERVER_ERROR;
+                            mirror.record(start.elapsed(), !success);
+                            if success {
                                 return Ok(resp);
                             }
                         }
                         Err(err) => {
+                            mirror.record(start.elapsed(), true);
                             warn!(
                                 "request mirror server failed, mirror: {:?},  error: {:?}",
                                 mirror, err
                             );
-                            mirror.failed_times.fetch_add(1, Ordering::Relaxed);
-
-                            if mirror.failed_times.load(Ordering::Relaxed) >= mirror.failure_limit {
-                                warn!(
-                                    "reach to failure limit {}, disable mirror: {:?}",
-                                    mirror.failure_limit, mirror
-                                );
-                                mirror.status.store(false, Ordering::Relaxed);
-                            }
+                            mirror.on_failure();
                         }
                     }
                 }
@@ -603,8 +1049,9 @@ impl Connection {
             }
         }
 
-        self.call_inner(
+        self.call_hedged(
             &self.client,
+            &self.async_client,
             method,
             url,
             &query,
@@ -612,9 +1059,137 @@ impl Connection {
             headers,
             catch_status,
             false,
+            None,
         )
     }
 
+    /// Send a request without body asynchronously.
+    ///
+    /// It follows the same proxy and mirror policy as [Connection::call()] for data requests, so
+    /// the request is only sent to mirrors with `auth_through` disabled if `headers` contains an
+    /// authorization token. Range requests are hedged in the same way too, without blocking the
+    /// calling task.
+    pub async fn call_async(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &HeaderMap,
+        catch_status: bool,
+    ) -> ConnectionResult<reqwest::Response> {
+        if self.shutdown.load(Ordering::Acquire) {
+            return Err(ConnectionError::Disconnected);
+        }
+
+        if let Some(proxy) = self.proxy.load_full() {
+            if proxy.health.ok() {
+                let http_url = if proxy.use_http {
+                    proxy.try_use_http(url)
+                } else {
+                    None
+                };
+                let replaced_url = http_url.as_deref().unwrap_or(url);
+
+                let result = self
+                    .call_async_hedged(
+                        &proxy.async_client,
+                        method.clone(),
+                        replaced_url,
+                        headers,
+                        catch_status,
+                        true,
+                        None,
+                    )
+                    .await;
+
+                match result {
+                    Ok(resp) => {
+                        if !proxy.fallback || resp.status() < StatusCode::INTERNAL_SERVER_ERROR {
+                            return Ok(resp);
+                        }
+                    }
+                    Err(err) => {
+                        if !proxy.fallback {
+                            return Err(err);
+                        }
+                    }
+                }
+                warn!("Request proxy server failed, fallback to original server");
+            }
+        }
+
+        let mirrors = self.select_mirrors(&self.mirrors.load_full(), url);
+        for (idx, mirror) in mirrors.iter().enumerate() {
+            if !mirror.config.auth_through && !headers.contains_key(HEADER_AUTHORIZATION) {
+                break;
+            }
+            if !mirror.status.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            let mut mirror_headers = headers.clone();
+            for (key, value) in mirror.config.headers.iter() {
+                mirror_headers.insert(
+                    HeaderName::from_str(key).unwrap(),
+                    HeaderValue::from_str(value).unwrap(),
+                );
+            }
+            let current_url = mirror.mirror_url(url)?;
+            debug!("mirror server url {}", current_url);
+
+            // Prefer to send hedged requests to the next healthy mirror.
+            let hedge_target = if self.hedger.is_some() {
+                mirrors[idx + 1..]
+                    .iter()
+                    .find(|m| m.status.load(Ordering::Relaxed))
+                    .and_then(|m| m.hedge_target(mirror, url, &mirror_headers))
+            } else {
+                None
+            };
+
+            let start = Instant::now();
+            let result = self
+                .call_async_hedged(
+                    &self.async_client,
+                    method.clone(),
+                    current_url.as_str(),
+                    &mirror_headers,
+                    catch_status,
+                    false,
+                    hedge_target,
+                )
+                .await;
+
+            match result {
+                Ok(resp) => {
+                    let success = resp.status() < StatusCode::INTERNAL_SERVER_ERROR;
+                    mirror.record(start.elapsed(), !success);
+                    if success {
+                        return Ok(resp);
+                    }
+                }
+                Err(err) => {
+                    mirror.record(start.elapsed(), true);
+                    warn!(
+                        "request mirror server failed, mirror: {:?},  error: {:?}",
+                        mirror, err
+                    );
+                    mirror.on_failure();
+                }
+            }
+        }
+
+        self.call_async_hedged(
+            &self.async_client,
+            method,
+            url,
+            headers,
+            catch_status,
+            false,
+            None,
+        )
+        .await
+    }
+
     fn build_connection(proxy: &str, config: &ConnectionConfig) -> Result<Client> {
         let connect_timeout = if config.connect_timeout != 0 {
             Some(Duration::from_secs(config.connect_timeout as u64))
@@ -643,9 +1218,192 @@ impl Connection {
         cb.build().map_err(|e| einval!(e))
     }
 
+    fn build_async_connection(proxy: &str, config: &ConnectionConfig) -> Result<reqwest::Client> {
+        let mut cb = reqwest::Client::builder().redirect(Policy::none());
+        if config.connect_timeout != 0 {
+            cb = cb.connect_timeout(Duration::from_secs(config.connect_timeout as u64));
+        }
+        if config.timeout != 0 {
+            cb = cb.timeout(Duration::from_secs(config.timeout as u64));
+        }
+
+        if config.skip_verify {
+            cb = cb.danger_accept_invalid_certs(true);
+        }
+
+        if !proxy.is_empty() {
+            cb = cb.proxy(reqwest::Proxy::all(proxy).map_err(|e| einval!(e))?)
+        }
+
+        cb.build().map_err(|e| einval!(e))
+    }
+
+    /// Send a request, and issue a hedged request if it hasn't completed in time.
+    ///
+    /// Only range requests without request body are hedged. The hedged request is sent to
+    /// `hedge_target`, which is a pair of URL and headers, or the same URL if it's `None`.
+    /// The first successful response is used and the other one is dropped.
+    ///
+    /// Hedgeable requests are sent by the asynchronous client, and polled by the calling thread
+    /// until the whole response body has been received. So the hedging delay is measured against
+    /// the full data transfer and a slow body transfer gets hedged too, without creating threads
+    /// for each request.
+    ///
+    /// The calling thread can't block on the hedging runtime if it's already running inside a
+    /// tokio runtime, such as readers of the io_uring based cache, so requests from those threads
+    /// are sent without hedging.
     #[allow(clippy::too_many_arguments)]
-    fn call_inner<R: Read + Clone + Send + 'static>(
+    fn call_hedged<R: Read + Clone + Send + 'static>(
+        &self,
+        client: &Client,
+        async_client: &reqwest::Client,
+        method: Method,
+        url: &str,
+        query: &Option<&[(&str, &str)]>,
+        data: Option<ReqBody<R>>,
+        headers: &HeaderMap,
+        catch_status: bool,
+        proxy: bool,
+        hedge_target: Option<(String, HeaderMap)>,
+    ) -> ConnectionResult<Response> {
+        let (hedger, runtime) = match (self.hedger.as_ref(), HEDGE_RUNTIME.as_ref()) {
+            (Some(h), Some(rt))
+                if data.is_none()
+                    && method == Method::GET
+                    && headers.contains_key(RANGE)
+                    && tokio::runtime::Handle::try_current().is_err() =>
+            {
+                (h, rt)
+            }
+            _ => {
+                return Self::call_inner(
+                    client,
+                    method,
+                    url,
+                    query,
+                    data,
+                    headers,
+                    catch_status,
+                    proxy,
+                )
+            }
+        };
+
+        runtime
+            .block_on(self.fetch_hedged(
+                hedger,
+                async_client,
+                url,
+                query,
+                headers,
+                catch_status,
+                proxy,
+                hedge_target,
+            ))
+            .map(Response::from)
+    }
+
+    // Send a range request with the asynchronous client, and issue a hedged request to
+    // `hedge_target` if no response has been received in time.
+    #[allow(clippy::too_many_arguments)]
+    async fn fetch_hedged(
         &self,
+        hedger: &Hedger,
+        async_client: &reqwest::Client,
+        url: &str,
+        query: &Option<&[(&str, &str)]>,
+        headers: &HeaderMap,
+        catch_status: bool,
+        proxy: bool,
+        hedge_target: Option<(String, HeaderMap)>,
+    ) -> ConnectionResult<http::Response<Bytes>> {
+        let start = Instant::now();
+        let (hedge_url, hedge_headers) =
+            hedge_target.unwrap_or_else(|| (url.to_string(), headers.clone()));
+        let primary = Self::fetch_async(async_client, url, query, headers, catch_status, proxy);
+        tokio::pin!(primary);
+        let (hedged, ret) = tokio::select! {
+            ret = &mut primary => (false, ret),
+            _ = tokio::time::sleep(hedger.delay()) => {
+                debug!("hedge slow request {} to {}", url, hedge_url);
+                if let Some(metrics) = self.metrics.as_ref() {
+                    metrics.hedge();
+                }
+                let hedged = Self::fetch_async(
+                    async_client,
+                    &hedge_url,
+                    query,
+                    &hedge_headers,
+                    catch_status,
+                    proxy,
+                );
+                tokio::pin!(hedged);
+                // Use the first successful response, or wait for the other one if it fails.
+                tokio::select! {
+                    ret = &mut primary => match ret {
+                        Ok(resp) => (false, Ok(resp)),
+                        Err(_) => (true, hedged.await),
+                    },
+                    ret = &mut hedged => match ret {
+                        Ok(resp) => (true, Ok(resp)),
+                        Err(_) => (false, primary.await),
+                    },
+                }
+            }
+        };
+
+        if ret.is_ok() {
+            hedger.record(start.elapsed());
+            if hedged {
+                if let Some(metrics) = self.metrics.as_ref() {
+                    metrics.hedge_win();
+                }
+            }
+        }
+
+        ret
+    }
+
+    // Send a GET request with the asynchronous client and receive the whole response body.
+    async fn fetch_async(
+        client: &reqwest::Client,
+        url: &str,
+        query: &Option<&[(&str, &str)]>,
+        headers: &HeaderMap,
+        catch_status: bool,
+        proxy: bool,
+    ) -> ConnectionResult<http::Response<Bytes>> {
+        let start = Instant::now();
+        let mut rb = client.get(url).headers(headers.clone());
+        if let Some(q) = query.as_ref() {
+            rb = rb.query(q);
+        }
+        let resp = rb.send().await.map_err(ConnectionError::Common)?;
+        let resp = respond_async(resp, catch_status).await?;
+
+        let mut builder = http::Response::builder()
+            .status(resp.status())
+            .version(resp.version())
+            .url(resp.url().clone());
+        if let Some(h) = builder.headers_mut() {
+            *h = resp.headers().clone();
+        }
+        let body = resp.bytes().await.map_err(ConnectionError::Common)?;
+        debug!(
+            "Hedgeable Request: GET {}, proxy: {}, size: {}, duration: {}ms",
+            url,
+            proxy,
+            body.len(),
+            start.elapsed().as_millis(),
+        );
+
+        builder
+            .body(body)
+            .map_err(|e| ConnectionError::ErrorWithMsg(format!("invalid response, {}", e)))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn call_inner<R: Read + Clone + Send + 'static>(
         client: &Client,
         method: Method,
         url: &str,
@@ -705,6 +1463,65 @@ impl Connection {
             Ok(resp) => respond(resp, catch_status),
         }
     }
+
+    // Send a request asynchronously, hedging it if it's a range request.
+    #[allow(clippy::too_many_arguments)]
+    async fn call_async_hedged(
+        &self,
+        client: &reqwest::Client,
+        method: Method,
+        url: &str,
+        headers: &HeaderMap,
+        catch_status: bool,
+        proxy: bool,
+        hedge_target: Option<(String, HeaderMap)>,
+    ) -> ConnectionResult<reqwest::Response> {
+        match self.hedger.as_ref() {
+            Some(hedger) if method == Method::GET && headers.contains_key(RANGE) => self
+                .fetch_hedged(
+                    hedger,
+                    client,
+                    url,
+                    &None,
+                    headers,
+                    catch_status,
+                    proxy,
+                    hedge_target,
+                )
+                .await
+                .map(reqwest::Response::from),
+            _ => Self::call_async_inner(client, method, url, headers, catch_status, proxy).await,
+        }
+    }
+
+    async fn call_async_inner(
+        client: &reqwest::Client,
+        method: Method,
+        url: &str,
+        headers: &HeaderMap,
+        catch_status: bool,
+        proxy: bool,
+    ) -> ConnectionResult<reqwest::Response> {
+        let start = Instant::now();
+        let ret = client
+            .request(method.clone(), url)
+            .headers(headers.clone())
+            .send()
+            .await;
+
+        debug!(
+            "Async Request: {} {}, proxy: {}, duration: {}ms",
+            method,
+            url,
+            proxy,
+            Instant::now().duration_since(start).as_millis(),
+        );
+
+        match ret {
+            Err(err) => Err(ConnectionError::Common(err)),
+            Ok(resp) => respond_async(resp, catch_status).await,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -744,6 +1561,208 @@ mod tests {
         assert!(checker.ok());
     }
 
+    #[test]
+    fn test_hedger() {
+        assert!(Hedger::new(&HedgeConfig::default()).is_none());
+
+        let config = HedgeConfig {
+            enable: true,
+            percentile: 90,
+            min_delay: 5,
+        };
+        let hedger = Hedger::new(&config).unwrap();
+        assert_eq!(hedger.delay(), Duration::from_millis(5));
+
+        for i in 1..=HEDGE_UPDATE_INTERVAL as u64 * 4 {
+            hedger.record(Duration::from_millis(i));
+        }
+        assert_eq!(hedger.delay(), Duration::from_millis(115));
+
+        for _ in 0..HEDGE_LATENCY_SAMPLES {
+            hedger.record(Duration::from_millis(1));
+        }
+        assert_eq!(hedger.delay(), Duration::from_millis(5));
+    }
+
+    // Start a HTTP server responding "data" to each request, with delays before sending the
+    // response headers and body for the n-th request. The connection is closed without response
+    // if the delay is `u64::MAX`.
+    fn start_server(delays: Vec<(u64, u64)>) -> (String, Arc<AtomicUsize>) {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/blob", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let requests = count.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let idx = requests.fetch_add(1, Ordering::SeqCst);
+                let (header_delay, body_delay) = delays.get(idx).copied().unwrap_or_default();
+                thread::spawn(move || {
+                    let mut req = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    if header_delay == u64::MAX {
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(header_delay));
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 206 Partial Content\r\nContent-Length: 4\r\nConnection: close\r\n\r\n",
+                    );
+                    let _ = stream.flush();
+                    thread::sleep(Duration::from_millis(body_delay));
+                    let _ = stream.write_all(b"data");
+                });
+            }
+        });
+
+        (url, count)
+    }
+
+    fn hedged_get(delays: Vec<(u64, u64)>) -> (Duration, usize) {
+        let config = ConnectionConfig {
+            hedge: HedgeConfig {
+                enable: true,
+                percentile: 50,
+                min_delay: 100,
+            },
+            ..Default::default()
+        };
+        let connection = Connection::new(&config, None).unwrap();
+        let (url, count) = start_server(delays);
+        let mut headers = HeaderMap::new();
+        headers.insert(RANGE, "bytes=0-3".parse().unwrap());
+
+        let start = Instant::now();
+        let mut resp = connection
+            .call::<&[u8]>(Method::GET, &url, None, None, &mut headers, true, false)
+            .unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        let mut data = Vec::new();
+        resp.copy_to(&mut data).unwrap();
+        assert_eq!(data, b"data");
+
+        (elapsed, count.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn test_hedged_request() {
+        // Fast requests are not hedged.
+        let (_, requests) = hedged_get(vec![(0, 0)]);
+        assert_eq!(requests, 1);
+
+        // Slow response.
+        let (elapsed, requests) = hedged_get(vec![(3000, 0), (0, 0)]);
+        assert!(elapsed < Duration::from_millis(2000), "{:?}", elapsed);
+        assert_eq!(requests, 2);
+
+        // Slow body transfer after response headers have been received.
+        let (elapsed, requests) = hedged_get(vec![(0, 3000), (0, 0)]);
+        assert!(elapsed < Duration::from_millis(2000), "{:?}", elapsed);
+        assert_eq!(requests, 2);
+
+        // Fallback to the original request if the hedged one fails.
+        let (elapsed, requests) = hedged_get(vec![(300, 0), (u64::MAX, 0)]);
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert_eq!(requests, 2);
+    }
+
+    #[test]
+    fn test_hedged_request_in_runtime() {
+        // Requests issued from inside a runtime are not hedged, instead of panicking.
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (elapsed, requests) = rt.block_on(async { hedged_get(vec![(300, 0), (0, 0)]) });
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert_eq!(requests, 1);
+    }
+
+    #[test]
+    fn test_call_async() {
+        use crate::test::{MockHttpResponse, MockHttpServer};
+
+        // Mirrors and proxies fail requests to "/fail".
+        let server = |body: &'static str, can_fail: bool| {
+            MockHttpServer::new(move |req| {
+                if can_fail && req.contains("/fail ") {
+                    MockHttpResponse::new(502, b"")
+                } else {
+                    MockHttpResponse::new(206, body.as_bytes())
+                }
+            })
+        };
+        let get = |connection: &Connection, url: &str, auth: bool| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RANGE, "bytes=0-5".parse().unwrap());
+            if auth {
+                headers.insert(HEADER_AUTHORIZATION, "Bearer test".parse().unwrap());
+            }
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                let resp = connection
+                    .call_async(Method::GET, url, &headers, true)
+                    .await
+                    .unwrap();
+                resp.bytes().await.unwrap().to_vec()
+            })
+        };
+        let origin = server("origin", false);
+        let data_url = format!("{}/data", origin.url);
+        let fail_url = format!("{}/fail", origin.url);
+
+        // Requests with authorization token are sent to mirrors with `auth_through` disabled.
+        let mirror = server("mirror", true);
+        let config = ConnectionConfig {
+            mirrors: vec![MirrorConfig {
+                host: mirror.url.clone(),
+                health_check_interval: 3600,
+                failure_limit: 5,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let connection = Connection::new(&config, None).unwrap();
+        assert_eq!(get(&connection, &data_url, true), b"mirror");
+        assert_eq!(get(&connection, &data_url, false), b"origin");
+        assert_eq!(mirror.requests().len(), 1);
+        assert_eq!(origin.requests().len(), 1);
+        // Fallback to the original server if the mirror fails.
+        assert_eq!(get(&connection, &fail_url, true), b"origin");
+        assert_eq!(mirror.requests().len(), 2);
+        assert_eq!(origin.requests().len(), 2);
+        connection.shutdown();
+
+        // All requests are sent through the proxy.
+        let proxy = server("proxy", true);
+        let config = ConnectionConfig {
+            proxy: ProxyConfig {
+                url: proxy.url.clone(),
+                fallback: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let connection = Connection::new(&config, None).unwrap();
+        assert_eq!(get(&connection, &data_url, false), b"proxy");
+        assert!(proxy.requests()[0].starts_with(&format!("GET {} ", data_url)));
+        assert_eq!(origin.requests().len(), 2);
+        // Fallback to the original server if the proxy fails.
+        assert_eq!(get(&connection, &fail_url, false), b"origin");
+        assert_eq!(proxy.requests().len(), 2);
+        assert_eq!(origin.requests().len(), 3);
+        connection.shutdown();
+    }
+
     #[test]
     fn test_is_success_status() {
         assert!(!is_success_status(StatusCode::CONTINUE));
@@ -765,4 +1784,165 @@ mod tests {
         assert_eq!(config.proxy.url, "");
         assert!(config.mirrors.is_empty());
     }
+
+    #[test]
+    fn test_mirror_strategy() {
+        assert!(MirrorStrategy::from_str("random").is_err());
+
+        let mirrors = [
+            "http://mirror1:65001",
+            "http://mirror2:65001",
+            "http://mirror3:65001",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(idx, host)| MirrorConfig {
+            host: host.to_string(),
+            health_check_interval: 3600,
+            weight: idx as u32 + 1,
+            ..Default::default()
+        })
+        .collect();
+        let config = ConnectionConfig {
+            mirrors,
+            mirror_strategy: "round-robin".to_string(),
+            ..Default::default()
+        };
+        let metrics = BackendMetrics::new("test_mirror_strategy", "registry");
+        let connection = Connection::new(&config, Some(metrics.clone())).unwrap();
+        let url = "https://registry/v2/test/repo/blobs/sha256:abcd";
+        let hosts = |mirrors: Vec<Arc<Mirror>>| -> Vec<String> {
+            mirrors.iter().map(|m| m.config.host.clone()).collect()
+        };
+
+        let first = connection.select_mirrors(&connection.mirrors.load(), url);
+        let second = connection.select_mirrors(&connection.mirrors.load(), url);
+        assert_eq!(first.len(), 3);
+        assert_eq!(first[1].config.host, second[0].config.host);
+
+        connection
+            .mirror_strategy
+            .store(Arc::new(MirrorStrategy::LeastLatency));
+        let mirrors = connection.mirrors.load_full();
+        mirrors[0].record(Duration::from_millis(30), false);
+        mirrors[1].record(Duration::from_millis(10), false);
+        mirrors[2].record(Duration::from_millis(5), true);
+        assert_eq!(
+            hosts(connection.select_mirrors(&mirrors, url)),
+            vec![
+                "http://mirror2:65001",
+                "http://mirror1:65001",
+                "http://mirror3:65001"
+            ]
+        );
+        // Other mirrors are tried first in turn once in a while.
+        let explored: Vec<String> = (0..MIRROR_LATENCY_EXPLORE_INTERVAL * 2)
+            .map(|_| hosts(connection.select_mirrors(&mirrors, url)).remove(0))
+            .filter(|host| host != "http://mirror2:65001")
+            .collect();
+        assert_eq!(explored.len(), 2);
+        assert_ne!(explored[0], explored[1]);
+
+        connection
+            .mirror_strategy
+            .store(Arc::new(MirrorStrategy::Weighted));
+        let mut selected = hosts(connection.select_mirrors(&mirrors, url));
+        selected.sort();
+        assert_eq!(selected, hosts(mirrors.to_vec()));
+
+        connection
+            .mirror_strategy
+            .store(Arc::new(MirrorStrategy::ConsistentHash));
+        let first = hosts(connection.select_mirrors(&mirrors, url));
+        let other = "https://registry/v2/other/repo/blobs/sha256:abcd";
+        assert_eq!(first, hosts(connection.select_mirrors(&mirrors, other)));
+
+        connection.shutdown();
+        metrics.release().unwrap();
+    }
+
+    #[test]
+    fn test_connection_update() {
+        let mirror_config = |host: &str| MirrorConfig {
+            host: host.to_string(),
+            health_check_interval: 3600,
+            ..Default::default()
+        };
+        let mut config = ConnectionConfig {
+            mirrors: vec![
+                mirror_config("http://mirror1:65001"),
+                mirror_config("http://mirror2:65001"),
+            ],
+            ..Default::default()
+        };
+        let metrics = BackendMetrics::new("test_connection_update", "registry");
+        let connection = Connection::new(&config, Some(metrics.clone())).unwrap();
+        let old_mirrors = connection.mirrors.load_full();
+        assert_eq!(old_mirrors.len(), 2);
+        assert!(connection.proxy.load().is_none());
+        let mirror_hosts = || -> Vec<String> {
+            let value = serde_json::to_value(metrics.as_ref()).unwrap();
+            let mut hosts: Vec<String> = value["mirrors"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect();
+            hosts.sort();
+            hosts
+        };
+        assert_eq!(
+            mirror_hosts(),
+            vec!["http://mirror1:65001", "http://mirror2:65001"]
+        );
+
+        // Old mirrors are retired and replaced by new ones.
+        config.mirrors = vec![
+            mirror_config("http://mirror2:65001"),
+            mirror_config("http://mirror3:65001"),
+        ];
+        config.mirror_strategy = "least-latency".to_string();
+        config.proxy.url = "http://proxy:65001".to_string();
+        config.proxy.check_interval = 3600;
+        connection.update(&config).unwrap();
+        assert!(old_mirrors
+            .iter()
+            .all(|m| m.retired.load(Ordering::Acquire)));
+        let mirrors = connection.mirrors.load_full();
+        assert_eq!(mirrors.len(), 2);
+        assert_eq!(mirrors[1].config.host, "http://mirror3:65001");
+        assert!(!mirrors[1].retired.load(Ordering::Acquire));
+        // Metrics of removed mirrors are dropped.
+        assert_eq!(
+            mirror_hosts(),
+            vec!["http://mirror2:65001", "http://mirror3:65001"]
+        );
+        assert_eq!(
+            **connection.mirror_strategy.load(),
+            MirrorStrategy::LeastLatency
+        );
+        let proxy = connection.proxy.load_full().unwrap();
+        assert!(!proxy.retired.load(Ordering::Acquire));
+
+        // Invalid configuration doesn't change anything.
+        config.mirrors = vec![mirror_config("http://mirror4:65001")];
+        config.mirror_strategy = "invalid".to_string();
+        assert!(connection.update(&config).is_err());
+        assert!(!mirrors[1].retired.load(Ordering::Acquire));
+        assert_eq!(
+            connection.mirrors.load()[1].config.host,
+            "http://mirror3:65001"
+        );
+
+        // The proxy is retired when removed from configuration.
+        config.mirror_strategy = "ordered".to_string();
+        config.proxy.url = String::new();
+        connection.update(&config).unwrap();
+        assert!(proxy.retired.load(Ordering::Acquire));
+        assert!(connection.proxy.load().is_none());
+        assert!(mirrors[1].retired.load(Ordering::Acquire));
+
+        connection.shutdown();
+        metrics.release().unwrap();
+    }
 }
@@ -9,13 +9,14 @@ use hyper::Client as HyperClient;
 use hyper::{body, Body, Response};
 use hyperlocal::Uri as HyperLocalUri;
 use hyperlocal::{UnixClientExt, UnixConnector};
-use nydus_api::HttpProxyConfig;
+use nydus_api::{BackendConfigV2, HttpProxyConfig};
 use nydus_utils::metrics::BackendMetrics;
 use reqwest;
 use tokio::runtime::Runtime;
 
 use super::connection::{Connection, ConnectionConfig, ConnectionError};
-use super::{BackendError, BackendResult, BlobBackend, BlobReader};
+use super::retry::CircuitBreaker;
+use super::{BackendError, BackendFuture, BackendResult, BlobBackend, BlobReader};
 use std::path::Path;
 use std::{
     fmt,
@@ -177,18 +178,53 @@ impl LocalClient {
         Ok(headers)
     }
 
+    async fn read_range(
+        &self,
+        uri: Arc<hyper::Uri>,
+        offset: u64,
+        len: usize,
+    ) -> BackendResult<Vec<u8>> {
+        let resp = self.do_req(uri, false, offset, Some(len)).await;
+        match resp {
+            Ok(resp) => body::to_bytes(resp)
+                .await
+                .map_err(|e| HttpProxyError::ReadResponseBody(e).into())
+                .map(|bytes| bytes.to_vec()),
+            Err(e) => Err(e),
+        }
+    }
+
     fn try_read(&self, uri: Arc<hyper::Uri>, offset: u64, len: usize) -> BackendResult<Vec<u8>> {
-        self.runtime.block_on(async {
-            let resp = self.do_req(uri, false, offset, Some(len)).await;
-            match resp {
-                Ok(resp) => body::to_bytes(resp)
-                    .await
-                    .map_err(|e| HttpProxyError::ReadResponseBody(e).into())
-                    .map(|bytes| bytes.to_vec()),
-                Err(e) => Err(e),
-            }
-        })
+        self.runtime.block_on(self.read_range(uri, offset, len))
     }
+
+    // Connections of the hyper client are bound to its own runtime, so spawn the request there
+    // instead of polling it on the runtime of the caller.
+    async fn async_try_read(
+        &self,
+        uri: Arc<hyper::Uri>,
+        offset: u64,
+        len: usize,
+    ) -> BackendResult<Vec<u8>> {
+        let client = self.clone();
+        self.runtime
+            .spawn(async move { client.read_range(uri, offset, len).await })
+            .await
+            .map_err(|e| BackendError::AsyncTask(e.to_string()))?
+    }
+}
+
+fn range_headers(offset: u64, len: usize) -> BackendResult<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    let range = range_str_for_header(offset, Some(len));
+    headers.insert(
+        http::header::RANGE,
+        range
+            .as_str()
+            .parse()
+            .map_err(|e| HttpProxyError::ConstructHeader(format!("{}", e)))?,
+    );
+    Ok(headers)
 }
 
 impl BlobReader for HttpProxyReader {
@@ -245,15 +281,7 @@ impl BlobReader for HttpProxyReader {
                     Uri::Local(_) => unreachable!(),
                     Uri::Remote(ref uri) => uri.clone(),
                 };
-                let mut headers = HeaderMap::new();
-                let range = range_str_for_header(offset, Some(buf.len()));
-                headers.insert(
-                    http::header::RANGE,
-                    range
-                        .as_str()
-                        .parse()
-                        .map_err(|e| HttpProxyError::ConstructHeader(format!("{}", e)))?,
-                );
+                let mut headers = range_headers(offset, buf.len())?;
                 let mut resp = connection
                     .call::<&[u8]>(
                         Method::GET,
@@ -274,16 +302,47 @@ impl BlobReader for HttpProxyReader {
         }
     }
 
+    fn async_try_read(self: Arc<Self>, offset: u64, size: usize) -> BackendFuture<Vec<u8>>
+    where
+        Self: 'static,
+    {
+        Box::pin(async move {
+            match (&self.client, &self.uri) {
+                (Client::Local(client), Uri::Local(uri)) => {
+                    client.async_try_read(uri.clone(), offset, size).await
+                }
+                (Client::Remote(connection), Uri::Remote(uri)) => {
+                    let headers = range_headers(offset, size)?;
+                    let resp = connection
+                        .call_async(Method::GET, uri.as_str(), &headers, true)
+                        .await
+                        .map_err(HttpProxyError::RemoteRequest)?;
+                    let data = resp.bytes().await.map_err(HttpProxyError::Transport)?;
+                    Ok(data[..data.len().min(size)].to_vec())
+                }
+                _ => unreachable!(),
+            }
+        })
+    }
+
     fn metrics(&self) -> &nydus_utils::metrics::BackendMetrics {
         &self.metrics
     }
+
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        match &self.client {
+            Client::Local(_) => None,
+            Client::Remote(connection) => connection.circuit_breaker(),
+        }
+    }
 }
 
 impl HttpProxy {
     pub fn new(config: &HttpProxyConfig, id: Option<&str>) -> Result<HttpProxy> {
+        let metrics = id.map(|i| BackendMetrics::new(i, "http-proxy"));
         let client = if config.addr.starts_with("http://") || config.addr.starts_with("https://") {
             let conn_cfg: ConnectionConfig = config.clone().into();
-            let conn = Connection::new(&conn_cfg)?;
+            let conn = Connection::new(&conn_cfg, metrics.clone())?;
             Client::Remote(conn)
         } else {
             let client = HyperClient::unix();
@@ -298,7 +357,7 @@ impl HttpProxy {
             addr: config.addr.to_string(),
             path: config.path.to_string(),
             client,
-            metrics: id.map(|i| BackendMetrics::new(i, "http-proxy")),
+            metrics,
         })
     }
 }
@@ -345,6 +404,18 @@ impl BlobBackend for HttpProxy {
         });
         Ok(reader)
     }
+
+    fn update_config(&self, config: &BackendConfigV2) -> Result<()> {
+        match &self.client {
+            Client::Local(_) => Err(enosys!(
+                "http proxy backend over unix domain socket doesn't support updating configuration"
+            )),
+            Client::Remote(connection) => {
+                let con_config: ConnectionConfig = config.get_http_proxy_config()?.clone().into();
+                connection.update(&con_config)
+            }
+        }
+    }
 }
 
 impl Drop for HttpProxy {
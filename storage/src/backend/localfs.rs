@@ -336,4 +336,43 @@ mod tests {
         let blob4 = fs.get_blob(filename).unwrap();
         assert_eq!(blob4.blob_size().unwrap(), 4);
     }
+
+    #[test]
+    fn test_localfs_async_read() {
+        let tempfile = TempFile::new().unwrap();
+        let path = tempfile.as_path();
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        tempfile
+            .as_file()
+            .write_all(&[0x1u8, 0x2, 0x3, 0x4])
+            .unwrap();
+
+        let config = LocalFsConfig {
+            blob_file: "".to_string(),
+            dir: path.parent().unwrap().to_str().unwrap().to_owned(),
+            alt_dirs: Vec::new(),
+        };
+        let fs = LocalFs::new(&config, Some(filename)).unwrap();
+        let reader = fs.get_reader(filename).unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let handles: Vec<_> = (0..4u64)
+                .map(|offset| tokio::spawn(reader.clone().async_read(offset, 2)))
+                .collect();
+            let mut results = Vec::new();
+            for handle in handles {
+                results.push(handle.await.unwrap().unwrap());
+            }
+            assert_eq!(results[0], vec![0x1, 0x2]);
+            assert_eq!(results[2], vec![0x3, 0x4]);
+            assert_eq!(results[3], vec![0x4]);
+
+            let data = reader.clone().async_try_read(0, 8).await.unwrap();
+            assert_eq!(data, vec![0x1, 0x2, 0x3, 0x4]);
+        });
+    }
 }
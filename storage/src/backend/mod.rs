@@ -15,15 +15,17 @@
 //! - [LocalDisk](localdisk/struct.LocalDisk.html): backend driver to access blobs on local disk.
 
 use std::fmt;
+use std::future::Future;
 use std::io::Read;
-use std::{sync::Arc, time::Duration};
+use std::pin::Pin;
+use std::sync::Arc;
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
-use nydus_utils::{
-    metrics::{BackendMetrics, ERROR_HOLDER},
-    DelayType, Delayer,
-};
+use lazy_static::lazy_static;
+use nydus_api::BackendConfigV2;
+use nydus_utils::metrics::{BackendMetrics, ERROR_HOLDER};
 
+use self::retry::{CircuitBreaker, RetryPolicy};
 use crate::utils::{alloc_buf, copyv};
 use crate::StorageError;
 
@@ -46,6 +48,7 @@ pub mod object_storage;
 pub mod oss;
 #[cfg(feature = "backend-registry")]
 pub mod registry;
+pub mod retry;
 #[cfg(feature = "backend-s3")]
 pub mod s3;
 
@@ -54,8 +57,12 @@ pub mod s3;
 pub enum BackendError {
     /// Unsupported operation.
     Unsupported(String),
+    /// Storage backend is unavailable and the circuit breaker is open.
+    Unavailable,
     /// Failed to copy data from/into blob.
     CopyData(StorageError),
+    /// Failed to run asynchronous task.
+    AsyncTask(String),
     #[cfg(feature = "backend-localdisk")]
     /// Error from LocalDisk storage backend.
     LocalDisk(self::localdisk::LocalDiskError),
@@ -77,7 +84,11 @@ impl fmt::Display for BackendError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             BackendError::Unsupported(s) => write!(f, "{}", s),
+            BackendError::Unavailable => {
+                write!(f, "storage backend is unavailable, circuit breaker is open")
+            }
             BackendError::CopyData(e) => write!(f, "failed to copy data, {}", e),
+            BackendError::AsyncTask(s) => write!(f, "failed to run asynchronous task, {}", s),
             #[cfg(feature = "backend-registry")]
             BackendError::Registry(e) => write!(f, "{:?}", e),
             #[cfg(feature = "backend-localfs")]
@@ -92,9 +103,67 @@ impl fmt::Display for BackendError {
     }
 }
 
+impl BackendError {
+    /// Get HTTP status code of the failed request, if any.
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            #[cfg(feature = "backend-registry")]
+            BackendError::Registry(self::registry::RegistryError::Request(e)) => e.status_code(),
+            #[cfg(any(feature = "backend-oss", feature = "backend-s3"))]
+            BackendError::ObjectStorage(self::object_storage::ObjectStorageError::Request(e)) => {
+                e.status_code()
+            }
+            #[cfg(feature = "backend-http-proxy")]
+            BackendError::HttpProxy(self::http_proxy::HttpProxyError::RemoteRequest(e)) => {
+                e.status_code()
+            }
+            _ => None,
+        }
+    }
+}
+
 /// Specialized `Result` for storage backends.
 pub type BackendResult<T> = std::result::Result<T, BackendError>;
 
+/// Boxed future returned by asynchronous methods of storage backends.
+pub type BackendFuture<T> = Pin<Box<dyn Future<Output = BackendResult<T>> + Send>>;
+
+/// Run a blocking closure on the blocking thread pool of the current tokio runtime.
+///
+/// It's used to implement the asynchronous interfaces for storage backends without native
+/// asynchronous IO support, so it must be called within a tokio runtime.
+pub(crate) async fn spawn_blocking<F, T>(f: F) -> BackendResult<T>
+where
+    F: FnOnce() -> BackendResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| BackendError::AsyncTask(e.to_string()))?
+}
+
+/// Run `BlobReader::try_read()` on the blocking thread pool of the current tokio runtime.
+pub(crate) async fn blocking_try_read<R>(
+    reader: Arc<R>,
+    offset: u64,
+    size: usize,
+) -> BackendResult<Vec<u8>>
+where
+    R: BlobReader + ?Sized + 'static,
+{
+    spawn_blocking(move || {
+        let mut buf = alloc_buf(size);
+        let cnt = reader.try_read(&mut buf, offset)?;
+        buf.truncate(cnt);
+        Ok(buf)
+    })
+    .await
+}
+
+lazy_static! {
+    static ref DEFAULT_RETRY_POLICY: RetryPolicy = RetryPolicy::default();
+}
+
 /// Trait to read data from a on storage backend.
 pub trait BlobReader: Send + Sync {
     /// Get size of the blob file.
@@ -114,28 +183,53 @@ pub trait BlobReader: Send + Sync {
     /// - error code if error happens
     ///
     /// It will try `BlobBackend::retry_limit()` times at most and return the first successfully
-    /// read data.
+    /// read data. Retries are delayed and filtered according to `BlobReader::retry_policy()`,
+    /// and the request fails fast if `BlobReader::circuit_breaker()` is open. The circuit breaker
+    /// counts a failure only when the request finally fails, not for each failed attempt.
     fn read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
         let mut retry_count = self.retry_limit();
+        let mut attempt = 0;
         let begin_time = self.metrics().begin();
-
-        let mut delayer = Delayer::new(DelayType::BackOff, Duration::from_millis(500));
+        let policy = self.retry_policy();
+        let breaker = self.circuit_breaker();
 
         loop {
+            if let Some(cb) = breaker {
+                if !cb.allow(self.metrics()) {
+                    self.metrics().end(&begin_time, buf.len(), true);
+                    return Err(BackendError::Unavailable);
+                }
+            }
+
             match self.try_read(buf, offset) {
                 Ok(size) => {
+                    if let Some(cb) = breaker {
+                        cb.on_success(self.metrics());
+                    }
                     self.metrics().end(&begin_time, buf.len(), false);
                     return Ok(size);
                 }
                 Err(err) => {
-                    if retry_count > 0 {
+                    let retryable = policy.is_retryable(&err);
+                    if retryable && retry_count > 0 && !breaker.map_or(false, |cb| cb.is_open()) {
                         warn!(
                             "Read from backend failed: {:?}, retry count {}",
                             err, retry_count
                         );
                         retry_count -= 1;
-                        delayer.delay();
+                        self.metrics().retry();
+                        std::thread::sleep(policy.delay(attempt));
+                        attempt += 1;
                     } else {
+                        if let Some(cb) = breaker {
+                            // The storage backend is reachable if it rejects the request
+                            // explicitly.
+                            if retryable {
+                                cb.on_failure(self.metrics());
+                            } else {
+                                cb.on_success(self.metrics());
+                            }
+                        }
                         self.metrics().end(&begin_time, buf.len(), true);
                         ERROR_HOLDER
                             .lock()
@@ -196,6 +290,89 @@ pub trait BlobReader: Send + Sync {
         }
     }
 
+    /// Try to read a range of data from the blob file asynchronously.
+    ///
+    /// Try to read data of range [offset, offset + size) from the blob file, and returns the data
+    /// read, which may be smaller than `size`. The returned future must be polled within a tokio
+    /// runtime, so many ranges can be fetched concurrently without a thread per request.
+    ///
+    /// The default implementation runs `BlobReader::try_read()` on the blocking thread pool of
+    /// the tokio runtime, storage backends may override it with native asynchronous IO. Range
+    /// requests issued by network backends are hedged in the same way as `try_read()`.
+    fn async_try_read(self: Arc<Self>, offset: u64, size: usize) -> BackendFuture<Vec<u8>>
+    where
+        Self: 'static,
+    {
+        Box::pin(blocking_try_read(self, offset, size))
+    }
+
+    /// Read a range of data from the blob file asynchronously.
+    ///
+    /// It's the asynchronous version of `BlobReader::read()`, which follows the same retry policy
+    /// and circuit breaker, but waits between retries without blocking the worker thread. Same as
+    /// `read()`, it doesn't apply the QoS limits of blob caches, so callers fetching data for a
+    /// blob cache should throttle requests by themselves.
+    fn async_read(self: Arc<Self>, offset: u64, size: usize) -> BackendFuture<Vec<u8>>
+    where
+        Self: 'static,
+    {
+        Box::pin(async move {
+            let mut retry_count = self.retry_limit();
+            let mut attempt = 0;
+            let begin_time = self.metrics().begin();
+
+            loop {
+                if let Some(cb) = self.circuit_breaker() {
+                    if !cb.allow(self.metrics()) {
+                        self.metrics().end(&begin_time, size, true);
+                        return Err(BackendError::Unavailable);
+                    }
+                }
+
+                match self.clone().async_try_read(offset, size).await {
+                    Ok(data) => {
+                        if let Some(cb) = self.circuit_breaker() {
+                            cb.on_success(self.metrics());
+                        }
+                        self.metrics().end(&begin_time, size, false);
+                        return Ok(data);
+                    }
+                    Err(err) => {
+                        let breaker = self.circuit_breaker();
+                        let retryable = self.retry_policy().is_retryable(&err);
+                        if retryable && retry_count > 0 && !breaker.map_or(false, |cb| cb.is_open())
+                        {
+                            warn!(
+                                "Async read from backend failed: {:?}, retry count {}",
+                                err, retry_count
+                            );
+                            retry_count -= 1;
+                            self.metrics().retry();
+                            let delay = self.retry_policy().delay(attempt);
+                            tokio::time::sleep(delay).await;
+                            attempt += 1;
+                        } else {
+                            if let Some(cb) = breaker {
+                                if retryable {
+                                    cb.on_failure(self.metrics());
+                                } else {
+                                    cb.on_success(self.metrics());
+                                }
+                            }
+                            self.metrics().end(&begin_time, size, true);
+                            ERROR_HOLDER
+                                .lock()
+                                .unwrap()
+                                .push(&format!("{:?}", err))
+                                .unwrap_or_else(|_| error!("Failed when try to hold error"));
+                            return Err(err);
+                        }
+                    }
+                }
+            }
+        })
+    }
+
     /// Get metrics object.
     fn metrics(&self) -> &BackendMetrics;
 
@@ -203,6 +380,16 @@ pub trait BlobReader: Send + Sync {
     fn retry_limit(&self) -> u8 {
         0
     }
+
+    /// Get the policy to delay and filter retries.
+    fn retry_policy(&self) -> &RetryPolicy {
+        &DEFAULT_RETRY_POLICY
+    }
+
+    /// Get the circuit breaker shared by all readers of the storage backend, if enabled.
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        None
+    }
 }
 
 /// Trait to access blob files on backend storages, such as OSS, registry, local fs etc.
@@ -215,6 +402,16 @@ pub trait BlobBackend: Send + Sync {
 
     /// Get a blob reader object to access blod `blob_id`.
     fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>>;
+
+    /// Update credentials, proxy and mirrors of the storage backend at runtime.
+    ///
+    /// Other fields of `config`, such as endpoint or repository, are ignored. Blob readers
+    /// created from the backend object pick up the new configuration for subsequent requests.
+    fn update_config(&self, _config: &BackendConfigV2) -> std::io::Result<()> {
+        Err(enosys!(
+            "storage backend doesn't support updating configuration"
+        ))
+    }
 }
 
 /// A buffered reader for `BlobReader` object.
@@ -274,3 +471,81 @@ impl Read for BlobBufReader {
         Ok(sz)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nydus_api::{CircuitBreakerConfig, RetryConfig};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    struct FailingReader {
+        attempts: AtomicU32,
+        metrics: Arc<BackendMetrics>,
+        policy: RetryPolicy,
+        breaker: CircuitBreaker,
+    }
+
+    impl BlobReader for FailingReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(0x1000)
+        }
+
+        fn try_read(&self, _buf: &mut [u8], _offset: u64) -> BackendResult<usize> {
+            self.attempts.fetch_add(1, Ordering::Relaxed);
+            Err(BackendError::Unsupported("test".to_string()))
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn retry_limit(&self) -> u8 {
+            2
+        }
+
+        fn retry_policy(&self) -> &RetryPolicy {
+            &self.policy
+        }
+
+        fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+            Some(&self.breaker)
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_counts_requests() {
+        let reader = FailingReader {
+            attempts: AtomicU32::new(0),
+            metrics: BackendMetrics::new("test_circuit_breaker_counts_requests", "test"),
+            policy: RetryPolicy::new(&RetryConfig {
+                base_delay: 1,
+                max_delay: 1,
+                jitter: false,
+                ..Default::default()
+            }),
+            breaker: CircuitBreaker::new(&CircuitBreakerConfig {
+                enable: true,
+                failure_threshold: 2,
+                probe_interval: 3600,
+            })
+            .unwrap(),
+        };
+        let mut buf = [0u8; 16];
+
+        // Retries of a request count as a single failure.
+        assert!(reader.read(&mut buf, 0).is_err());
+        assert_eq!(reader.attempts.load(Ordering::Relaxed), 3);
+        assert!(!reader.breaker.is_open());
+
+        assert!(reader.read(&mut buf, 0).is_err());
+        assert_eq!(reader.attempts.load(Ordering::Relaxed), 6);
+        assert!(reader.breaker.is_open());
+        assert!(matches!(
+            reader.read(&mut buf, 0),
+            Err(BackendError::Unavailable)
+        ));
+        assert_eq!(reader.attempts.load(Ordering::Relaxed), 6);
+
+        reader.metrics.release().unwrap();
+    }
+}
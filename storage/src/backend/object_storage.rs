@@ -11,13 +11,16 @@ use std::io::{Error, Result};
 use std::marker::Send;
 use std::sync::Arc;
 
+use arc_swap::ArcSwap;
 use reqwest::header::{HeaderMap, CONTENT_LENGTH};
 use reqwest::Method;
 
+use nydus_api::BackendConfigV2;
 use nydus_utils::metrics::BackendMetrics;
 
-use super::connection::{Connection, ConnectionError};
-use super::{BackendError, BackendResult, BlobBackend, BlobReader};
+use super::connection::{Connection, ConnectionConfig, ConnectionError};
+use super::retry::{CircuitBreaker, RetryPolicy};
+use super::{BackendError, BackendFuture, BackendResult, BlobBackend, BlobReader};
 
 /// Error codes related to object storage backend.
 #[derive(Debug)]
@@ -63,6 +66,11 @@ pub trait ObjectStorageState: Send + Sync + Debug {
     ) -> Result<()>;
 
     fn retry_limit(&self) -> u8;
+
+    // `update_config` creates a copy of the state with credentials from the backend configuration.
+    fn update_config(&self, config: &BackendConfigV2) -> Result<Self>
+    where
+        Self: Sized;
 }
 
 struct ObjectStorageReader<T>
@@ -71,19 +79,52 @@ where
 {
     blob_id: String,
     connection: Arc<Connection>,
-    state: Arc<T>,
+    state: Arc<ArcSwap<T>>,
     metrics: Arc<BackendMetrics>,
 }
 
+impl<T> ObjectStorageReader<T>
+where
+    T: ObjectStorageState,
+{
+    // Build the url and signed headers to read data of range [offset, offset + size).
+    fn range_request(
+        &self,
+        offset: u64,
+        size: usize,
+    ) -> std::result::Result<(String, HeaderMap), ObjectStorageError> {
+        let query = &[];
+        let state = self.state.load();
+        let (resource, url) = state.url(&self.blob_id, query);
+        let mut headers = HeaderMap::new();
+        let end_at = offset + size as u64 - 1;
+        let range = format!("bytes={}-{}", offset, end_at);
+
+        headers.insert(
+            "Range",
+            range
+                .as_str()
+                .parse()
+                .map_err(|e| ObjectStorageError::ConstructHeader(format!("{}", e)))?,
+        );
+        state
+            .sign(Method::GET, &mut headers, resource.as_str(), url.as_str())
+            .map_err(ObjectStorageError::Auth)?;
+
+        Ok((url, headers))
+    }
+}
+
 impl<T> BlobReader for ObjectStorageReader<T>
 where
     T: ObjectStorageState,
 {
     fn blob_size(&self) -> BackendResult<u64> {
-        let (resource, url) = self.state.url(&self.blob_id, &[]);
+        let state = self.state.load();
+        let (resource, url) = state.url(&self.blob_id, &[]);
         let mut headers = HeaderMap::new();
 
-        self.state
+        state
             .sign(Method::HEAD, &mut headers, resource.as_str(), url.as_str())
             .map_err(ObjectStorageError::Auth)?;
 
@@ -116,22 +157,7 @@ where
     }
 
     fn try_read(&self, mut buf: &mut [u8], offset: u64) -> BackendResult<usize> {
-        let query = &[];
-        let (resource, url) = self.state.url(&self.blob_id, query);
-        let mut headers = HeaderMap::new();
-        let end_at = offset + buf.len() as u64 - 1;
-        let range = format!("bytes={}-{}", offset, end_at);
-
-        headers.insert(
-            "Range",
-            range
-                .as_str()
-                .parse()
-                .map_err(|e| ObjectStorageError::ConstructHeader(format!("{}", e)))?,
-        );
-        self.state
-            .sign(Method::GET, &mut headers, resource.as_str(), url.as_str())
-            .map_err(ObjectStorageError::Auth)?;
+        let (url, mut headers) = self.range_request(offset, buf.len())?;
 
         // Safe because the the call() is a synchronous operation.
         let mut resp = self
@@ -152,12 +178,36 @@ where
             .map(|size| size as usize)?)
     }
 
+    fn async_try_read(self: Arc<Self>, offset: u64, size: usize) -> BackendFuture<Vec<u8>>
+    where
+        Self: 'static,
+    {
+        Box::pin(async move {
+            let (url, headers) = self.range_request(offset, size)?;
+            let resp = self
+                .connection
+                .call_async(Method::GET, url.as_str(), &headers, true)
+                .await
+                .map_err(ObjectStorageError::Request)?;
+            let data = resp.bytes().await.map_err(ObjectStorageError::Transport)?;
+            Ok(data[..data.len().min(size)].to_vec())
+        })
+    }
+
     fn metrics(&self) -> &BackendMetrics {
         &self.metrics
     }
 
     fn retry_limit(&self) -> u8 {
-        self.state.retry_limit()
+        self.state.load().retry_limit()
+    }
+
+    fn retry_policy(&self) -> &RetryPolicy {
+        self.connection.retry_policy()
+    }
+
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.connection.circuit_breaker()
     }
 }
 
@@ -167,7 +217,7 @@ where
     T: ObjectStorageState,
 {
     connection: Arc<Connection>,
-    state: Arc<T>,
+    state: Arc<ArcSwap<T>>,
     metrics: Option<Arc<BackendMetrics>>,
     #[allow(unused)]
     id: Option<String>,
@@ -185,7 +235,7 @@ where
     ) -> Self {
         ObjectStorage {
             connection,
-            state,
+            state: Arc::new(ArcSwap::new(state)),
             metrics,
             id,
         }
@@ -220,6 +270,29 @@ where
             ))
         }
     }
+
+    fn update_config(&self, config: &BackendConfigV2) -> Result<()> {
+        let con_config: ConnectionConfig = match config.backend_type.as_str() {
+            #[cfg(feature = "backend-oss")]
+            "oss" => config.get_oss_config()?.clone().into(),
+            #[cfg(feature = "backend-s3")]
+            "s3" => config.get_s3_config()?.clone().into(),
+            ty => {
+                return Err(einval!(format!(
+                    "unsupported object storage backend type '{}'",
+                    ty
+                )))
+            }
+        };
+        let state = self.state.load().update_config(config)?;
+        let update = self.connection.prepare_update(&con_config)?;
+        // Switch credentials before switching proxy and mirrors.
+        self.state.store(Arc::new(state));
+        self.connection.apply_update(update);
+        info!("object storage backend configuration updated");
+
+        Ok(())
+    }
 }
 
 impl<T> Drop for ObjectStorage<T>
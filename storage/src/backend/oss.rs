@@ -13,7 +13,7 @@ use reqwest::header::HeaderMap;
 use reqwest::Method;
 use sha1::Sha1;
 
-use nydus_api::OssConfig;
+use nydus_api::{BackendConfigV2, OssConfig};
 use nydus_utils::metrics::BackendMetrics;
 
 use crate::backend::connection::{Connection, ConnectionConfig};
@@ -25,7 +25,7 @@ const HEADER_AUTHORIZATION: &str = "Authorization";
 type HmacSha1 = Hmac<Sha1>;
 
 // `OssState` is almost identical to `OssConfig`, but let's keep them separated.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct OssState {
     access_key_id: String,
     access_key_secret: String,
@@ -115,6 +115,14 @@ impl ObjectStorageState for OssState {
     fn retry_limit(&self) -> u8 {
         self.retry_limit
     }
+
+    fn update_config(&self, config: &BackendConfigV2) -> Result<Self> {
+        let oss_config = config.get_oss_config()?;
+        let mut state = self.clone();
+        state.access_key_id = oss_config.access_key_id.clone();
+        state.access_key_secret = oss_config.access_key_secret.clone();
+        Ok(state)
+    }
 }
 
 /// Storage backend to access data stored in OSS.
@@ -125,7 +133,8 @@ impl Oss {
     pub fn new(oss_config: &OssConfig, id: Option<&str>) -> Result<Oss> {
         let con_config: ConnectionConfig = oss_config.clone().into();
         let retry_limit = con_config.retry_limit;
-        let connection = Connection::new(&con_config)?;
+        let metrics = id.map(|i| BackendMetrics::new(i, "oss"));
+        let connection = Connection::new(&con_config, metrics.clone())?;
         let state = Arc::new(OssState {
             scheme: oss_config.scheme.clone(),
             object_prefix: oss_config.object_prefix.clone(),
@@ -135,7 +144,6 @@ impl Oss {
             bucket_name: oss_config.bucket_name.clone(),
             retry_limit,
         });
-        let metrics = id.map(|i| BackendMetrics::new(i, "oss"));
 
         Ok(ObjectStorage::new_object_storage(
             connection,
@@ -194,4 +202,21 @@ mod tests {
 
         oss.shutdown();
     }
+
+    #[test]
+    fn test_oss_update_config() {
+        let json_str = "{\"access_key_id\":\"key\",\"access_key_secret\":\"secret\",\"bucket_name\":\"images\",\"endpoint\":\"/oss\",\"object_prefix\":\"nydus\",\"scheme\":\"\",\"proxy\":{\"url\":\"\",\"ping_url\":\"\",\"fallback\":true,\"check_interval\":5},\"timeout\":5,\"connect_timeout\":5,\"retry_limit\":5}";
+        let config: OssConfig = serde_json::from_str(json_str).unwrap();
+        let oss = Oss::new(&config, Some("test-image")).unwrap();
+
+        let json_str = "{\"type\":\"oss\",\"oss\":{\"access_key_id\":\"key2\",\"access_key_secret\":\"secret2\",\"bucket_name\":\"images\",\"endpoint\":\"/oss\"}}";
+        let backend_cfg: BackendConfigV2 = serde_json::from_str(json_str).unwrap();
+        oss.update_config(&backend_cfg).unwrap();
+
+        let json_str = "{\"type\":\"localfs\",\"localfs\":{\"dir\":\"/tmp\"}}";
+        let backend_cfg: BackendConfigV2 = serde_json::from_str(json_str).unwrap();
+        assert!(oss.update_config(&backend_cfg).is_err());
+
+        oss.shutdown();
+    }
 }
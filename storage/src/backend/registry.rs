@@ -11,20 +11,24 @@ use std::sync::{Arc, RwLock};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{fmt, thread};
 
-use arc_swap::ArcSwapOption;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use reqwest::blocking::Response;
 pub use reqwest::header::HeaderMap;
 use reqwest::header::{HeaderValue, CONTENT_LENGTH};
 use reqwest::{Method, StatusCode};
 use url::{ParseError, Url};
 
-use nydus_api::RegistryConfig;
+use nydus_api::{BackendConfigV2, RegistryConfig};
 use nydus_utils::metrics::BackendMetrics;
 
 use crate::backend::connection::{
-    is_success_status, respond, Connection, ConnectionConfig, ConnectionError, ReqBody,
+    is_success_status, respond, respond_async, Connection, ConnectionConfig, ConnectionError,
+    ReqBody,
+};
+use crate::backend::retry::{CircuitBreaker, RetryPolicy};
+use crate::backend::{
+    blocking_try_read, BackendError, BackendFuture, BackendResult, BlobBackend, BlobReader,
 };
-use crate::backend::{BackendError, BackendResult, BlobBackend, BlobReader};
 
 const REGISTRY_CLIENT_ID: &str = "nydus-registry-client";
 const HEADER_AUTHORIZATION: &str = "Authorization";
@@ -162,16 +166,33 @@ impl fmt::Display for Scheme {
     }
 }
 
+#[derive(Debug, Default)]
+struct RegistryCredential {
+    // Base64 encoded registry auth
+    auth: Option<String>,
+    username: String,
+    password: String,
+}
+
+impl RegistryCredential {
+    fn new(auth: Option<String>) -> Result<Self> {
+        let (username, password) = Registry::get_authorization_info(&auth)?;
+        Ok(RegistryCredential {
+            auth,
+            username,
+            password,
+        })
+    }
+}
+
 struct RegistryState {
     // HTTP scheme like: https, http
     scheme: Scheme,
     host: String,
     // Image repo name like: library/ubuntu
     repo: String,
-    // Base64 encoded registry auth
-    auth: Option<String>,
-    username: String,
-    password: String,
+    // Registry auth, may be updated at runtime to support short-lived credentials.
+    credential: ArcSwap<RegistryCredential>,
     // Retry limit for read operation
     retry_limit: u8,
     // Scheme specified for blob server
@@ -237,12 +258,13 @@ impl RegistryState {
         // the query and in the body to be compatible with different registry
         // implementations, which have been tested on these platforms:
         // docker hub, harbor, github ghcr, aliyun acr.
+        let credential = self.credential.load();
         let query = [
             ("service", auth.service.as_str()),
             ("scope", auth.scope.as_str()),
             ("grant_type", "password"),
-            ("username", self.username.as_str()),
-            ("password", self.password.as_str()),
+            ("username", credential.username.as_str()),
+            ("password", credential.password.as_str()),
             ("client_id", REGISTRY_CLIENT_ID),
         ];
 
@@ -291,6 +313,8 @@ impl RegistryState {
     fn get_auth_header(&self, auth: Auth, connection: &Arc<Connection>) -> Result<String> {
         match auth {
             Auth::Basic(_) => self
+                .credential
+                .load()
                 .auth
                 .as_ref()
                 .map(|auth| format!("Basic {}", auth))
@@ -455,7 +479,9 @@ impl RegistryReader {
 
             if let Some(resp_auth_header) = resp.headers().get(HEADER_WWW_AUTHENTICATE) {
                 // Get token from registry authorization server
-                if let Some(auth) = RegistryState::parse_auth(resp_auth_header, &self.state.auth) {
+                if let Some(auth) =
+                    RegistryState::parse_auth(resp_auth_header, &self.state.credential.load().auth)
+                {
                     let auth_header = self
                         .state
                         .get_auth_header(auth, &self.connection)
@@ -634,6 +660,74 @@ impl RegistryReader {
             .map_err(RegistryError::Transport)
             .map(|size| size as usize)
     }
+
+    /// Read a range of data from the blob asynchronously, with the cached redirected URL or
+    /// authorization header.
+    ///
+    /// Returns `None` if the request needs to be authenticated, redirected or fallback to http,
+    /// which should be handled by `_try_read()` instead.
+    async fn async_try_read_cached(
+        &self,
+        offset: u64,
+        size: usize,
+    ) -> RegistryResult<Option<Vec<u8>>> {
+        let mut headers = HeaderMap::new();
+        let end_at = offset + size as u64 - 1;
+        let range = format!("bytes={}-{}", offset, end_at);
+        headers.insert("Range", range.parse().unwrap());
+
+        let cached_redirect = self.state.cached_redirect.get(&self.blob_id);
+        let url = match cached_redirect.as_ref() {
+            Some(location) => location.clone(),
+            None => {
+                let url = format!("/blobs/sha256:{}", self.blob_id);
+                let url = self
+                    .state
+                    .url(url.as_str(), &[])
+                    .map_err(|e| RegistryError::Url(url, e))?;
+                let cached_auth = self.state.cached_auth.get();
+                if !cached_auth.is_empty() {
+                    headers.insert(
+                        HEADER_AUTHORIZATION,
+                        HeaderValue::from_str(cached_auth.as_str()).unwrap(),
+                    );
+                }
+                url
+            }
+        };
+
+        let resp = match self
+            .connection
+            .call_async(Method::GET, url.as_str(), &headers, false)
+            .await
+        {
+            Ok(resp) => resp,
+            Err(ConnectionError::Common(e)) if self.state.needs_fallback_http(&e) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(RegistryError::Request(e)),
+        };
+
+        let status = resp.status();
+        if cached_redirect.is_some()
+            && [StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN].contains(&status)
+        {
+            warn!(
+                "The redirected link has expired: {}, will retry read",
+                url.as_str()
+            );
+            self.state.cached_redirect.remove(&self.blob_id);
+            return Ok(None);
+        } else if status == StatusCode::UNAUTHORIZED || REDIRECTED_STATUS_CODE.contains(&status) {
+            return Ok(None);
+        }
+
+        let resp = respond_async(resp, true)
+            .await
+            .map_err(RegistryError::Request)?;
+        let data = resp.bytes().await.map_err(RegistryError::Transport)?;
+        Ok(Some(data[..data.len().min(size)].to_vec()))
+    }
 }
 
 impl BlobReader for RegistryReader {
@@ -679,6 +773,20 @@ impl BlobReader for RegistryReader {
             .map_err(BackendError::Registry)
     }
 
+    fn async_try_read(self: Arc<Self>, offset: u64, size: usize) -> BackendFuture<Vec<u8>>
+    where
+        Self: 'static,
+    {
+        Box::pin(async move {
+            if let Some(data) = self.async_try_read_cached(offset, size).await? {
+                return Ok(data);
+            }
+            // Authentication, redirection and fallback to http involve several requests and
+            // shared states, so reuse the blocking implementation for them.
+            blocking_try_read(self, offset, size).await
+        })
+    }
+
     fn metrics(&self) -> &BackendMetrics {
         &self.metrics
     }
@@ -686,6 +794,14 @@ impl BlobReader for RegistryReader {
     fn retry_limit(&self) -> u8 {
         self.state.retry_limit
     }
+
+    fn retry_policy(&self) -> &RetryPolicy {
+        self.connection.retry_policy()
+    }
+
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.connection.circuit_breaker()
+    }
 }
 
 /// Storage backend based on image registry.
@@ -693,6 +809,7 @@ pub struct Registry {
     connection: Arc<Connection>,
     state: Arc<RegistryState>,
     metrics: Arc<BackendMetrics>,
+    refresh_token_thread: AtomicBool,
 }
 
 impl Registry {
@@ -708,10 +825,10 @@ impl Registry {
         }
 
         let retry_limit = con_config.retry_limit;
-        let connection = Connection::new(&con_config)?;
-        let auth = trim(config.auth.clone());
+        let metrics = BackendMetrics::new(id, "registry");
+        let connection = Connection::new(&con_config, Some(metrics.clone()))?;
+        let credential = RegistryCredential::new(trim(config.auth.clone()))?;
         let registry_token = trim(config.registry_token.clone());
-        let (username, password) = Self::get_authorization_info(&auth)?;
         let cached_auth = if let Some(registry_token) = registry_token {
             // Store the registry bearer token to cached_auth, prefer to
             // use the token stored in cached_auth to request registry.
@@ -730,10 +847,8 @@ impl Registry {
             scheme,
             host: config.host.clone(),
             repo: config.repo.clone(),
-            auth,
+            credential: ArcSwap::new(Arc::new(credential)),
             cached_auth,
-            username,
-            password,
             retry_limit,
             blob_url_scheme: config.blob_url_scheme.clone(),
             blob_redirected_host: config.blob_redirected_host.clone(),
@@ -742,23 +857,28 @@ impl Registry {
             cached_bearer_auth: ArcSwapOption::new(None),
         });
 
-        let mirrors = connection.mirrors.clone();
-
         let registry = Registry {
             connection,
             state,
-            metrics: BackendMetrics::new(id, "registry"),
+            metrics,
+            refresh_token_thread: AtomicBool::new(false),
         };
+        registry.check_refresh_token_thread();
+
+        Ok(registry)
+    }
 
-        for mirror in mirrors.iter() {
+    // Start the token refreshing thread if any mirror doesn't pass through authentication requests.
+    fn check_refresh_token_thread(&self) {
+        for mirror in self.connection.mirrors.load().iter() {
             if !mirror.config.auth_through {
-                registry.start_refresh_token_thread();
-                info!("Refresh token thread started.");
+                if !self.refresh_token_thread.swap(true, Ordering::AcqRel) {
+                    self.start_refresh_token_thread();
+                    info!("Refresh token thread started.");
+                }
                 break;
             }
         }
-
-        Ok(registry)
     }
 
     fn get_authorization_info(auth: &Option<String>) -> Result<(String, String)> {
@@ -848,6 +968,36 @@ impl BlobBackend for Registry {
             metrics: self.metrics.clone(),
         }))
     }
+
+    fn update_config(&self, config: &BackendConfigV2) -> Result<()> {
+        let config = config.get_registry_config()?;
+        if !config.proxy.url.is_empty() && !config.mirrors.is_empty() {
+            return Err(einval!(
+                "connection: proxy and mirrors cannot be configured at the same time."
+            ));
+        }
+        let credential = RegistryCredential::new(trim(config.auth.clone()))?;
+        let con_config: ConnectionConfig = config.clone().into();
+        let update = self.connection.prepare_update(&con_config)?;
+
+        // Switch credential and drop cached tokens obtained with the old credential before
+        // switching proxy and mirrors, so no request is sent through them with old tokens.
+        let cached_auth = match trim(config.registry_token.clone()) {
+            Some(registry_token) => format!("Bearer {}", registry_token),
+            None => String::new(),
+        };
+        self.state.credential.store(Arc::new(credential));
+        self.state.refresh_token_time.store(None);
+        self.state.cached_bearer_auth.store(None);
+        self.state
+            .cached_auth
+            .set(&self.state.cached_auth.get(), cached_auth);
+        self.connection.apply_update(update);
+        self.check_refresh_token_thread();
+        info!("registry backend configuration updated");
+
+        Ok(())
+    }
 }
 
 impl Drop for Registry {
@@ -874,6 +1024,7 @@ fn trim(value: Option<String>) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use nydus_api::MirrorConfig;
 
     #[test]
     fn test_string_cache() {
@@ -906,9 +1057,11 @@ mod tests {
             scheme: Scheme::new(false),
             host: "alibaba-inc.com".to_string(),
             repo: "nydus".to_string(),
-            auth: None,
-            username: "test".to_string(),
-            password: "password".to_string(),
+            credential: ArcSwap::new(Arc::new(RegistryCredential {
+                auth: None,
+                username: "test".to_string(),
+                password: "password".to_string(),
+            })),
             retry_limit: 5,
             blob_url_scheme: "https".to_string(),
             blob_redirected_host: "oss.alibaba-inc.com".to_string(),
@@ -955,6 +1108,148 @@ mod tests {
         assert!(RegistryState::parse_auth(&header, &None).is_none());
     }
 
+    #[test]
+    fn test_async_try_read() {
+        use crate::test::{http_header, MockHttpResponse, MockHttpServer};
+
+        let data: Vec<u8> = (0..=255u8).collect();
+        let blob = data.clone();
+        // Accept any authorization header, and require basic authentication otherwise.
+        let server = MockHttpServer::new(move |req| {
+            if http_header(req, "authorization").is_none() {
+                return MockHttpResponse::new(401, b"")
+                    .header("WWW-Authenticate", "Basic realm=\"test\"");
+            }
+            let range = http_header(req, "range").unwrap();
+            let (start, end) = range.trim_start_matches("bytes=").split_once('-').unwrap();
+            let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+            MockHttpResponse::new(206, &blob[start..=end])
+        });
+        let auth = base64::encode("user:pass");
+        let new_registry = |token: Option<&str>| {
+            let config = RegistryConfig {
+                scheme: "http".to_string(),
+                host: server.url.trim_start_matches("http://").to_string(),
+                repo: "test".to_string(),
+                auth: Some(auth.clone()),
+                registry_token: token.map(|t| t.to_string()),
+                ..Default::default()
+            };
+            Registry::new(&config, Some("test_async_try_read")).unwrap()
+        };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        // The cached token is used by the asynchronous request directly.
+        let registry = new_registry(Some("token"));
+        let reader = registry.get_reader("blob").unwrap();
+        let buf = rt.block_on(reader.async_try_read(0x10, 4)).unwrap();
+        assert_eq!(buf, &data[0x10..0x14]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("GET /v2/test/blobs/sha256:blob "));
+        assert_eq!(
+            http_header(&requests[0], "authorization"),
+            Some("Bearer token")
+        );
+        registry.shutdown();
+        registry.metrics.release().unwrap();
+
+        // Fallback to the blocking implementation for authentication.
+        let registry = new_registry(None);
+        let reader = registry.get_reader("blob").unwrap();
+        let buf = rt.block_on(reader.clone().async_try_read(0x20, 8)).unwrap();
+        assert_eq!(buf, &data[0x20..0x28]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        let basic = format!("Basic {}", auth);
+        assert_eq!(
+            http_header(&requests[3], "authorization"),
+            Some(basic.as_str())
+        );
+        // The authorization header obtained by the blocking request is cached for later requests.
+        let buf = rt.block_on(reader.async_try_read(0x30, 2)).unwrap();
+        assert_eq!(buf, &data[0x30..0x32]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 5);
+        assert_eq!(
+            http_header(&requests[4], "authorization"),
+            Some(basic.as_str())
+        );
+        registry.shutdown();
+        registry.metrics.release().unwrap();
+    }
+
+    #[test]
+    fn test_update_config() {
+        let mut config = RegistryConfig {
+            host: "127.0.0.1:1".to_string(),
+            repo: "test".to_string(),
+            auth: Some(base64::encode("user:pass")),
+            registry_token: Some("token".to_string()),
+            ..Default::default()
+        };
+        let registry = Registry::new(&config, Some("test_update_config")).unwrap();
+        assert_eq!(registry.state.cached_auth.get(), "Bearer token");
+        registry.state.refresh_token_time.store(Some(Arc::new(1)));
+        registry
+            .state
+            .cached_bearer_auth
+            .store(Some(Arc::new(BearerAuth {
+                realm: "http://127.0.0.1:1/token".to_string(),
+                service: "test".to_string(),
+                scope: "repository:test:pull".to_string(),
+                header: None,
+            })));
+
+        // Cached tokens are dropped when the credential is updated.
+        config.auth = Some(base64::encode("user2:pass2"));
+        config.registry_token = None;
+        config.mirrors = vec![MirrorConfig {
+            host: "http://127.0.0.1:2".to_string(),
+            auth_through: true,
+            ..Default::default()
+        }];
+        let backend_cfg = BackendConfigV2 {
+            backend_type: "registry".to_string(),
+            registry: Some(config.clone()),
+            ..Default::default()
+        };
+        registry.update_config(&backend_cfg).unwrap();
+        let credential = registry.state.credential.load();
+        assert_eq!(credential.username, "user2");
+        assert_eq!(credential.password, "pass2");
+        assert_eq!(registry.state.cached_auth.get(), "");
+        assert!(registry.state.refresh_token_time.load().is_none());
+        assert!(registry.state.cached_bearer_auth.load().is_none());
+        let mirrors = registry.connection.mirrors.load();
+        assert_eq!(mirrors.len(), 1);
+        assert_eq!(mirrors[0].config.host, "http://127.0.0.1:2");
+
+        config.registry_token = Some("token2".to_string());
+        let backend_cfg = BackendConfigV2 {
+            backend_type: "registry".to_string(),
+            registry: Some(config.clone()),
+            ..Default::default()
+        };
+        registry.update_config(&backend_cfg).unwrap();
+        assert_eq!(registry.state.cached_auth.get(), "Bearer token2");
+
+        // Invalid configuration is rejected without touching the credential.
+        config.auth = Some(base64::encode("user3:pass3"));
+        config.proxy.url = "http://127.0.0.1:3".to_string();
+        let backend_cfg = BackendConfigV2 {
+            backend_type: "registry".to_string(),
+            registry: Some(config),
+            ..Default::default()
+        };
+        assert!(registry.update_config(&backend_cfg).is_err());
+        assert_eq!(registry.state.credential.load().username, "user2");
+        assert_eq!(registry.state.cached_auth.get(), "Bearer token2");
+
+        registry.shutdown();
+        registry.metrics.release().unwrap();
+    }
+
     #[test]
     fn test_trim() {
         assert_eq!(trim(None), None);
@@ -0,0 +1,297 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Retry policy and circuit breaker for storage backends.
+//!
+//! The [RetryPolicy] decides whether a failed request should be retried and how long to wait
+//! before the next attempt, with exponential backoff and optional jitter. The [CircuitBreaker]
+//! tracks consecutive failures of a storage backend, and fails requests fast once the storage
+//! backend is considered unavailable, only letting a single probe request through periodically.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use nydus_api::{CircuitBreakerConfig, HttpStatusPattern, RetryConfig};
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::BackendError;
+
+const CIRCUIT_CLOSED: u8 = 0;
+const CIRCUIT_OPEN: u8 = 1;
+const CIRCUIT_HALF_OPEN: u8 = 2;
+
+/// Policy to retry failed requests to storage backends.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    retryable_status: Vec<HttpStatusPattern>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(&RetryConfig::default())
+    }
+}
+
+impl RetryPolicy {
+    /// Create a new instance of [RetryPolicy] from configuration information.
+    pub fn new(config: &RetryConfig) -> Self {
+        let retryable_status = config
+            .retryable_status
+            .iter()
+            .filter_map(|s| {
+                let pattern = HttpStatusPattern::parse(s);
+                if pattern.is_none() {
+                    warn!("retry: ignore invalid HTTP status '{}'", s);
+                }
+                pattern
+            })
+            .collect();
+
+        RetryPolicy {
+            base_delay: Duration::from_millis(config.base_delay),
+            max_delay: Duration::from_millis(config.max_delay.max(config.base_delay)),
+            jitter: config.jitter,
+            retryable_status,
+        }
+    }
+
+    /// Get the delay before the `attempt`-th retry, starting from 0.
+    ///
+    /// The delay doubles for each attempt until reaching the upper limit. With jitter enabled,
+    /// the delay is randomized within [delay / 2, delay].
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        if self.jitter {
+            let half = delay / 2;
+            let range = half.as_nanos() as u64 + 1;
+            half + Duration::from_nanos(random_u64() % range)
+        } else {
+            delay
+        }
+    }
+
+    /// Check whether a request failed with HTTP status `status` should be retried.
+    pub fn is_retryable_status(&self, status: u16) -> bool {
+        self.retryable_status.iter().any(|p| p.matches(status))
+    }
+
+    /// Check whether a request failed with error `err` should be retried.
+    ///
+    /// Errors without an HTTP status, such as network errors, are always retried.
+    pub fn is_retryable(&self, err: &BackendError) -> bool {
+        match err.status_code() {
+            None => true,
+            Some(status) => self.is_retryable_status(status),
+        }
+    }
+}
+
+/// Circuit breaker to fail fast when a storage backend is unavailable.
+///
+/// Failures are counted per request instead of per attempt, so retries of a single request don't
+/// open the circuit breaker by themselves.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    probe_interval: Duration,
+    state: AtomicU8,
+    failures: AtomicU32,
+    // Time when the circuit breaker has been opened, in milliseconds since UNIX epoch.
+    opened_at: AtomicU64,
+}
+
+impl CircuitBreaker {
+    /// Create a new instance of [CircuitBreaker], or `None` if it's disabled by configuration.
+    pub fn new(config: &CircuitBreakerConfig) -> Option<Self> {
+        if !config.enable {
+            return None;
+        }
+
+        Some(CircuitBreaker {
+            failure_threshold: config.failure_threshold.max(1),
+            probe_interval: Duration::from_secs(config.probe_interval),
+            state: AtomicU8::new(CIRCUIT_CLOSED),
+            failures: AtomicU32::new(0),
+            opened_at: AtomicU64::new(0),
+        })
+    }
+
+    /// Check whether the circuit breaker is open or half-open.
+    pub fn is_open(&self) -> bool {
+        self.state.load(Ordering::Acquire) != CIRCUIT_CLOSED
+    }
+
+    /// Check whether a request is allowed to be sent to the storage backend.
+    ///
+    /// When the circuit breaker is open and the probe interval has elapsed, the caller becomes
+    /// the probe and the circuit breaker transits into half-open state. All other requests are
+    /// rejected until the probe completes.
+    pub fn allow(&self, metrics: &BackendMetrics) -> bool {
+        match self.state.load(Ordering::Acquire) {
+            CIRCUIT_CLOSED => true,
+            CIRCUIT_OPEN => {
+                let opened_at = self.opened_at.load(Ordering::Acquire);
+                if now_millis().saturating_sub(opened_at) >= self.probe_interval.as_millis() as u64
+                    && self
+                        .state
+                        .compare_exchange(
+                            CIRCUIT_OPEN,
+                            CIRCUIT_HALF_OPEN,
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                        )
+                        .is_ok()
+                {
+                    metrics.set_circuit_breaker_state(CIRCUIT_HALF_OPEN as u64);
+                    true
+                } else {
+                    metrics.circuit_breaker_reject();
+                    false
+                }
+            }
+            _ => {
+                metrics.circuit_breaker_reject();
+                false
+            }
+        }
+    }
+
+    /// Record a request reaching the storage backend, which closes the circuit breaker.
+    pub fn on_success(&self, metrics: &BackendMetrics) {
+        if self.failures.load(Ordering::Relaxed) != 0 {
+            self.failures.store(0, Ordering::Relaxed);
+        }
+        if self.state.load(Ordering::Acquire) != CIRCUIT_CLOSED
+            && self.state.swap(CIRCUIT_CLOSED, Ordering::AcqRel) != CIRCUIT_CLOSED
+        {
+            info!("circuit breaker closed, storage backend recovered");
+            metrics.set_circuit_breaker_state(CIRCUIT_CLOSED as u64);
+        }
+    }
+
+    /// Record a request failed after all retries, which may open the circuit breaker.
+    pub fn on_failure(&self, metrics: &BackendMetrics) {
+        match self.state.load(Ordering::Acquire) {
+            CIRCUIT_CLOSED => {
+                let failures = self.failures.fetch_add(1, Ordering::AcqRel) + 1;
+                if failures >= self.failure_threshold {
+                    self.open(metrics);
+                }
+            }
+            CIRCUIT_HALF_OPEN => self.open(metrics),
+            _ => {}
+        }
+    }
+
+    fn open(&self, metrics: &BackendMetrics) {
+        self.opened_at.store(now_millis(), Ordering::Release);
+        self.failures.store(0, Ordering::Relaxed);
+        if self.state.swap(CIRCUIT_OPEN, Ordering::AcqRel) != CIRCUIT_OPEN {
+            warn!(
+                "circuit breaker opened, storage backend is unavailable, probe in {:?}",
+                self.probe_interval
+            );
+            metrics.set_circuit_breaker_state(CIRCUIT_OPEN as u64);
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// Each `RandomState` is seeded with different keys, which is good enough for jitter.
+pub(crate) fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        let config = RetryConfig {
+            base_delay: 100,
+            max_delay: 1000,
+            jitter: false,
+            retryable_status: vec!["5xx".to_string(), "429".to_string(), "6xx".to_string()],
+            ..Default::default()
+        };
+        let policy = RetryPolicy::new(&config);
+        assert_eq!(policy.retryable_status.len(), 2);
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(800));
+        assert_eq!(policy.delay(4), Duration::from_millis(1000));
+        assert_eq!(policy.delay(64), Duration::from_millis(1000));
+
+        assert!(policy.is_retryable_status(500));
+        assert!(policy.is_retryable_status(503));
+        assert!(policy.is_retryable_status(429));
+        assert!(!policy.is_retryable_status(404));
+        assert!(!policy.is_retryable_status(401));
+        assert!(policy.is_retryable(&BackendError::Unsupported("test".to_string())));
+
+        let policy = RetryPolicy::new(&RetryConfig {
+            jitter: true,
+            ..config
+        });
+        for attempt in 0..8 {
+            let delay = policy.delay(attempt);
+            let max = Duration::from_millis((100u64 << attempt).min(1000));
+            assert!(delay >= max / 2 && delay <= max);
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let metrics = BackendMetrics::new("test_circuit_breaker", "registry");
+        let config = CircuitBreakerConfig::default();
+        assert!(CircuitBreaker::new(&config).is_none());
+
+        let config = CircuitBreakerConfig {
+            enable: true,
+            failure_threshold: 3,
+            probe_interval: 1,
+        };
+        let cb = CircuitBreaker::new(&config).unwrap();
+        assert!(cb.allow(&metrics));
+        cb.on_failure(&metrics);
+        cb.on_failure(&metrics);
+        cb.on_success(&metrics);
+        cb.on_failure(&metrics);
+        cb.on_failure(&metrics);
+        assert!(!cb.is_open());
+        cb.on_failure(&metrics);
+        assert!(cb.is_open());
+        assert!(!cb.allow(&metrics));
+
+        // Failed probe re-opens the circuit breaker.
+        cb.opened_at.store(0, Ordering::Release);
+        assert!(cb.allow(&metrics));
+        assert!(!cb.allow(&metrics));
+        cb.on_failure(&metrics);
+        assert_eq!(cb.state.load(Ordering::Acquire), CIRCUIT_OPEN);
+        assert!(!cb.allow(&metrics));
+
+        // Successful probe closes the circuit breaker.
+        cb.opened_at.store(0, Ordering::Release);
+        assert!(cb.allow(&metrics));
+        cb.on_success(&metrics);
+        assert!(!cb.is_open());
+        assert!(cb.allow(&metrics));
+
+        metrics.release().unwrap();
+    }
+}
@@ -12,7 +12,7 @@ use std::sync::Arc;
 
 use hmac::{Hmac, Mac};
 use http::Uri;
-use nydus_api::S3Config;
+use nydus_api::{BackendConfigV2, S3Config};
 use nydus_utils::metrics::BackendMetrics;
 use reqwest::header::HeaderMap;
 use reqwest::Method;
@@ -28,7 +28,7 @@ const HEADER_AWZ_DATE: &str = "x-amz-date";
 const HEADER_AWZ_CONTENT_SHA256: &str = "x-amz-content-sha256";
 const S3_DEFAULT_ENDPOINT: &str = "s3.amazonaws.com";
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct S3State {
     region: String,
     access_key_id: String,
@@ -48,7 +48,8 @@ impl S3 {
     pub fn new(s3_config: &S3Config, id: Option<&str>) -> Result<S3> {
         let con_config: ConnectionConfig = s3_config.clone().into();
         let retry_limit = con_config.retry_limit;
-        let connection = Connection::new(&con_config)?;
+        let metrics = id.map(|i| BackendMetrics::new(i, "oss"));
+        let connection = Connection::new(&con_config, metrics.clone())?;
         let final_endpoint = if s3_config.endpoint.is_empty() {
             S3_DEFAULT_ENDPOINT.to_string()
         } else {
@@ -65,7 +66,6 @@ impl S3 {
             bucket_name: s3_config.bucket_name.clone(),
             retry_limit,
         });
-        let metrics = id.map(|i| BackendMetrics::new(i, "oss"));
 
         Ok(ObjectStorage::new_object_storage(
             connection,
@@ -224,6 +224,14 @@ impl ObjectStorageState for S3State {
     fn retry_limit(&self) -> u8 {
         self.retry_limit
     }
+
+    fn update_config(&self, config: &BackendConfigV2) -> Result<Self> {
+        let s3_config = config.get_s3_config()?;
+        let mut state = self.clone();
+        state.access_key_id = s3_config.access_key_id.clone();
+        state.access_key_secret = s3_config.access_key_secret.clone();
+        Ok(state)
+    }
 }
 
 // modified based on https://github.com/minio/minio-rs/blob/5fea81d68d381fd2a4c27e4d259f7012de08ab77/src/s3/utils.rs#L52-L56
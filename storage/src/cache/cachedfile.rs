@@ -9,13 +9,13 @@
 //! performance. It may be used by both the userspace `FileCacheMgr` or the `FsCacheMgr` based
 //! on the in-kernel fscache system.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{ErrorKind, Read, Result};
 use std::mem::ManuallyDrop;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
@@ -26,12 +26,16 @@ use nydus_utils::{compress, digest, DelayType, Delayer, FileRangeReader};
 use tokio::runtime::Runtime;
 
 use crate::backend::BlobReader;
+use crate::cache::chunkstore::ChunkStore;
+use crate::cache::memcache::ChunkMemCache;
+use crate::cache::qos::OndemandLimiter;
 use crate::cache::state::ChunkMap;
+use crate::cache::uring::UringFile;
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncPrefetchMessage, AsyncWorkerMgr};
-use crate::cache::{BlobCache, BlobIoMergeState};
+use crate::cache::{BlobCache, BlobIoMergeState, BlobScrubResult, ChunkDecompressState};
 use crate::device::{
-    BlobChunkInfo, BlobInfo, BlobIoDesc, BlobIoRange, BlobIoSegment, BlobIoTag, BlobIoVec,
-    BlobObject, BlobPrefetchRequest,
+    BlobChunkInfo, BlobFeatures, BlobInfo, BlobIoDesc, BlobIoRange, BlobIoSegment, BlobIoTag,
+    BlobIoVec, BlobObject, BlobPrefetchRequest,
 };
 use crate::meta::{BlobCompressionContextInfo, BlobMetaChunk};
 use crate::utils::{alloc_buf, copyv, readv, MemSliceCursor};
@@ -40,10 +44,13 @@ use crate::{StorageError, StorageResult, RAFS_BATCH_SIZE_TO_GAP_SHIFT, RAFS_DEFA
 const DOWNLOAD_META_RETRY_COUNT: u32 = 5;
 const DOWNLOAD_META_RETRY_DELAY: u64 = 400;
 
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub(crate) struct FileCacheMeta {
     has_error: Arc<AtomicBool>,
     meta: Arc<Mutex<Option<Arc<BlobCompressionContextInfo>>>>,
+    blob_file: String,
+    blob_info: Arc<BlobInfo>,
+    reader: Option<Arc<dyn BlobReader>>,
 }
 
 impl FileCacheMeta {
@@ -65,6 +72,9 @@ impl FileCacheMeta {
                 Ok(m) => Ok(FileCacheMeta {
                     has_error: Arc::new(AtomicBool::new(false)),
                     meta: Arc::new(Mutex::new(Some(Arc::new(m)))),
+                    blob_file,
+                    blob_info,
+                    reader,
                 }),
                 Err(e) => Err(e),
             }
@@ -72,6 +82,9 @@ impl FileCacheMeta {
             let meta = FileCacheMeta {
                 has_error: Arc::new(AtomicBool::new(false)),
                 meta: Arc::new(Mutex::new(None)),
+                blob_file: blob_file.clone(),
+                blob_info: blob_info.clone(),
+                reader: reader.clone(),
             };
             let meta1 = meta.clone();
 
@@ -124,15 +137,49 @@ impl FileCacheMeta {
             std::thread::sleep(Duration::from_millis(2));
         }
     }
+
+    /// Get the blob meta object with chunk digests loaded, which are needed to verify chunk data.
+    pub(crate) fn get_blob_meta_with_digest(&self) -> Result<Arc<BlobCompressionContextInfo>> {
+        let meta = self
+            .get_blob_meta()
+            .ok_or_else(|| einval!("failed to get blob meta object for cache file"))?;
+        if meta.get_chunk_count() == 0 || meta.get_chunk_digest(0).is_some() {
+            return Ok(meta);
+        }
+        if !self
+            .blob_info
+            .has_feature(BlobFeatures::INLINED_CHUNK_DIGEST)
+        {
+            return Err(enosys!("no chunk digest information available for blob"));
+        }
+
+        // Chunk digests are only loaded when data validation is enabled, load them on demand.
+        BlobCompressionContextInfo::new(
+            &self.blob_file,
+            &self.blob_info,
+            self.reader.as_ref(),
+            true,
+        )
+        .map(Arc::new)
+    }
 }
 
 pub(crate) struct FileCacheEntry {
     pub(crate) blob_id: String,
     pub(crate) blob_info: Arc<BlobInfo>,
     pub(crate) chunk_map: Arc<dyn ChunkMap>,
+    // Content-addressed store shared by blobs, used instead of `file` to cache chunk data if set.
+    pub(crate) chunk_store: Option<Arc<ChunkStore>>,
+    // In-memory cache for hot chunks shared by blobs and the key of this blob in the cache,
+    // consulted before reading the cache file.
+    pub(crate) mem_cache: Option<(Arc<ChunkMemCache>, u32)>,
     pub(crate) file: Arc<File>,
+    // The io_uring handle of `file`, used instead of synchronous I/O syscalls to access `file` if set.
+    pub(crate) uring_file: Option<Arc<UringFile>>,
     pub(crate) meta: Option<FileCacheMeta>,
     pub(crate) metrics: Arc<BlobcacheMetrics>,
+    // Rate limiter for on-demand fetches from the storage backend, shared by blobs of the mount.
+    pub(crate) ondemand_limiter: Option<Arc<OndemandLimiter>>,
     pub(crate) prefetch_state: Arc<AtomicU32>,
     pub(crate) reader: Arc<dyn BlobReader>,
     pub(crate) runtime: Arc<Runtime>,
@@ -179,28 +226,53 @@ impl FileCacheEntry {
         assert_eq!(self.is_raw_data, is_raw_data);
         let delayed_chunk_map = self.chunk_map.clone();
         let file = self.file.clone();
+        let uring_file = self.uring_file.clone();
+        let chunk_store = self.chunk_store.clone();
         let metrics = self.metrics.clone();
 
         metrics.buffered_backend_size.add(buffer.size() as u64);
         self.runtime.spawn_blocking(move || {
             metrics.buffered_backend_size.sub(buffer.size() as u64);
-            let offset = if is_raw_data {
-                chunk.compressed_offset()
+            let res = if let Some(store) = chunk_store.as_ref() {
+                store.write(chunk.chunk_id(), buffer.slice())
             } else {
-                chunk.uncompressed_offset()
+                let offset = if is_raw_data {
+                    chunk.compressed_offset()
+                } else {
+                    chunk.uncompressed_offset()
+                };
+                Self::persist_cached_data(&file, uring_file.as_deref(), offset, buffer.slice())
             };
-            let res = Self::persist_cached_data(&file, offset, buffer.slice());
             Self::_update_chunk_pending_status(&delayed_chunk_map, chunk.as_ref(), res.is_ok());
         });
     }
 
     fn persist_chunk_data(&self, chunk: &dyn BlobChunkInfo, buf: &[u8]) {
-        let offset = chunk.uncompressed_offset();
-        let res = Self::persist_cached_data(&self.file, offset, buf);
+        let res = if let Some(store) = self.chunk_store.as_ref() {
+            let size = chunk.uncompressed_size() as usize;
+            store.write(chunk.chunk_id(), &buf[..size])
+        } else {
+            let offset = chunk.uncompressed_offset();
+            Self::persist_cached_data(&self.file, self.uring_file.as_deref(), offset, buf)
+        };
         self.update_chunk_pending_status(chunk, res.is_ok());
     }
 
-    fn persist_cached_data(file: &Arc<File>, offset: u64, buffer: &[u8]) -> Result<()> {
+    fn persist_cached_data(
+        file: &Arc<File>,
+        uring_file: Option<&UringFile>,
+        offset: u64,
+        buffer: &[u8],
+    ) -> Result<()> {
+        if let Some(uring_file) = uring_file {
+            trace!(
+                "write {}(offset={}) bytes to cache file",
+                buffer.len(),
+                offset
+            );
+            return uring_file.write_all_at(buffer, offset);
+        }
+
         let fd = file.as_raw_fd();
 
         let n = loop {
@@ -371,6 +443,38 @@ impl FileCacheEntry {
             Ok((start, end, size as usize))
         }
     }
+
+    /// Scrub cached data of the blob with id `blob_id` in `blobs`, or all blobs if `blob_id` is
+    /// None.
+    ///
+    /// Failing to scrub a blob doesn't stop scrubbing other blobs, the error is recorded in the
+    /// result for the blob instead.
+    pub(crate) fn scrub_entries(
+        blobs: &RwLock<HashMap<String, Arc<FileCacheEntry>>>,
+        blob_id: Option<&str>,
+    ) -> Vec<BlobScrubResult> {
+        let entries: Vec<Arc<FileCacheEntry>> = {
+            let guard = blobs.read().unwrap();
+            match blob_id {
+                Some(id) => guard.get(id).cloned().into_iter().collect(),
+                None => guard.values().cloned().collect(),
+            }
+        };
+
+        entries
+            .iter()
+            .map(|entry| {
+                entry.scrub().unwrap_or_else(|e| {
+                    warn!("scrub: failed to scrub blob {}, {}", entry.blob_id, e);
+                    BlobScrubResult {
+                        blob_id: entry.blob_id.clone(),
+                        error: Some(e.to_string()),
+                        ..Default::default()
+                    }
+                })
+            })
+            .collect()
+    }
 }
 
 impl AsRawFd for FileCacheEntry {
@@ -435,6 +539,14 @@ impl BlobCache for FileCacheEntry {
         }
     }
 
+    fn get_backing_file(&self) -> Option<Arc<File>> {
+        if self.is_raw_data || self.chunk_store.is_some() || self.need_validation {
+            None
+        } else {
+            Some(self.file.clone())
+        }
+    }
+
     fn start_prefetch(&self) -> StorageResult<()> {
         self.prefetch_state.fetch_add(1, Ordering::Release);
         Ok(())
@@ -553,6 +665,7 @@ impl BlobCache for FileCacheEntry {
                     if self.is_raw_data {
                         let res = Self::persist_cached_data(
                             &self.file,
+                            self.uring_file.as_deref(),
                             blob_offset,
                             bufs.compressed_buf(),
                         );
@@ -589,6 +702,18 @@ impl BlobCache for FileCacheEntry {
         Ok(total_size)
     }
 
+    fn throttle_ondemand_fetch(&self, size: u64) {
+        if let Some(limiter) = self.ondemand_limiter.as_ref() {
+            let waited = limiter.acquire(size);
+            if !waited.is_zero() {
+                self.metrics.ondemand_throttled.inc();
+                self.metrics
+                    .ondemand_throttled_millis
+                    .add(waited.as_millis() as u64);
+            }
+        }
+    }
+
     fn read(&self, iovec: &mut BlobIoVec, buffers: &[FileVolatileSlice]) -> Result<usize> {
         self.metrics.total.inc();
         self.workers.consume_prefetch_budget(iovec.size());
@@ -616,6 +741,57 @@ impl BlobCache for FileCacheEntry {
             Ok(None)
         }
     }
+
+    fn scrub(&self) -> Result<BlobScrubResult> {
+        if self.is_legacy_stargz {
+            return Err(enosys!("scrub is not supported for the blob"));
+        }
+        let meta = self
+            .meta
+            .as_ref()
+            .ok_or_else(|| enosys!("scrub requires blob meta information"))?
+            .get_blob_meta_with_digest()?;
+
+        let mut result = BlobScrubResult {
+            blob_id: self.blob_id.clone(),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        for idx in 0..meta.get_chunk_count() {
+            let chunk = meta.get_chunk_info(idx);
+            if !matches!(self.chunk_map.is_ready(chunk.as_ref()), Ok(true)) {
+                result.skipped_chunks += 1;
+                continue;
+            }
+
+            result.checked_chunks += 1;
+            buf.resize(chunk.uncompressed_size() as usize, 0);
+            let valid = self.read_file_cache(chunk.as_ref(), &mut buf).is_ok()
+                && self.validate_chunk_data(chunk.as_ref(), &buf, true).is_ok();
+            if !valid {
+                warn!(
+                    "scrub: cached data for chunk {} of blob {} is corrupted",
+                    idx, self.blob_id
+                );
+                if let Some((cache, key)) = self.mem_cache.as_ref() {
+                    cache.remove(*key, chunk.id());
+                }
+                result.corrupted_chunks.push(idx as u32);
+                // Chunks in the shared store may be marked as ready by other blobs too, so
+                // repair them in place instead of removing them.
+                if self.chunk_store.is_some() {
+                    match self.repair_stored_chunk(chunk.as_ref(), &mut buf) {
+                        Ok(()) => continue,
+                        Err(e) => warn!("scrub: failed to repair chunk {}, {}", idx, e),
+                    }
+                }
+                self.chunk_map.clear_ready(chunk.as_ref())?;
+                self.punch_chunk_hole(chunk.as_ref());
+            }
+        }
+
+        Ok(result)
+    }
 }
 
 impl BlobObject for FileCacheEntry {
@@ -702,10 +878,22 @@ impl FileCacheEntry {
             .ok_or_else(|| einval!("invalid chunk_map for do_fetch_chunks()"))?;
         let chunk_index = chunks[0].id();
         let count = chunks.len() as u32;
-        let pending = match bitmap.check_range_ready_and_mark_pending(chunk_index, count)? {
+        let mut pending = match bitmap.check_range_ready_and_mark_pending(chunk_index, count)? {
             None => return Ok(()),
             Some(v) => v,
         };
+        // Chunks may have been cached by other blobs sharing the same chunk store.
+        if self.chunk_store.is_some() {
+            pending.retain(|chunk_id| {
+                let chunk = chunks[(*chunk_id - chunk_index) as usize].as_ref();
+                if self.is_chunk_in_store(chunk) {
+                    self.update_chunk_pending_status(chunk, true);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
 
         let mut status = vec![false; count as usize];
         let (start_idx, end_idx) = {
@@ -743,6 +931,7 @@ impl FileCacheEntry {
                     if self.is_raw_data {
                         let res = Self::persist_cached_data(
                             &self.file,
+                            self.uring_file.as_deref(),
                             blob_offset,
                             bufs.compressed_buf(),
                         );
@@ -802,15 +991,28 @@ impl FileCacheEntry {
                     Ok(false) => {
                         info!("retry for timeout chunk, {}", chunk.id());
                         let mut buf = alloc_buf(chunk.uncompressed_size() as usize);
-                        self.read_chunk_from_backend(chunk.as_ref(), &mut buf)
+                        let c_buf = self
+                            .read_chunk_from_backend(chunk.as_ref(), &mut buf)
                             .map_err(|e| {
                                 self.update_chunk_pending_status(chunk.as_ref(), false);
                                 eio!(format!("read_raw_chunk failed, {:?}", e))
                             })?;
-                        if self.dio_enabled {
-                            self.adjust_buffer_for_dio(&mut buf)
+                        if self.is_raw_data {
+                            // Uncompressed chunks have no separate raw data.
+                            let raw = c_buf.as_deref().unwrap_or(&buf);
+                            let res = Self::persist_cached_data(
+                                &self.file,
+                                self.uring_file.as_deref(),
+                                chunk.compressed_offset(),
+                                raw,
+                            );
+                            self.update_chunk_pending_status(chunk.as_ref(), res.is_ok());
+                        } else {
+                            if self.dio_enabled {
+                                self.adjust_buffer_for_dio(&mut buf)
+                            }
+                            self.persist_chunk_data(chunk.as_ref(), &buf);
                         }
-                        self.persist_chunk_data(chunk.as_ref(), &buf);
                     }
                 }
             }
@@ -819,6 +1021,61 @@ impl FileCacheEntry {
         Ok(())
     }
 
+    // Check whether the chunk has been cached into the shared chunk store, possibly by other blobs.
+    fn is_chunk_in_store(&self, chunk: &dyn BlobChunkInfo) -> bool {
+        match self.chunk_store.as_ref() {
+            Some(store) => store.contains(chunk.chunk_id()),
+            None => false,
+        }
+    }
+
+    // Replace corrupted chunk data in the shared chunk store with data from the storage backend.
+    fn repair_stored_chunk(&self, chunk: &dyn BlobChunkInfo, buf: &mut [u8]) -> Result<()> {
+        let store = self
+            .chunk_store
+            .as_ref()
+            .ok_or_else(|| einval!("chunk store is not enabled"))?;
+        self.read_chunk_from_backend(chunk, buf)?;
+        self.validate_chunk_data(chunk, buf, true)?;
+        store.replace(chunk.chunk_id(), buf)
+    }
+
+    // Punch a hole for the chunk in the cache file, so it won't be treated as ready again when
+    // chunk state gets restored from holes in the cache file.
+    fn punch_chunk_hole(&self, chunk: &dyn BlobChunkInfo) {
+        if let Some(store) = self.chunk_store.as_ref() {
+            if let Err(e) = store.remove(chunk.chunk_id()) {
+                debug!(
+                    "failed to remove chunk {} from chunk store, {}",
+                    chunk.id(),
+                    e
+                );
+            }
+            return;
+        } else if self.is_raw_data {
+            return;
+        }
+
+        #[cfg(target_os = "linux")]
+        let ret = unsafe {
+            libc::fallocate(
+                self.file.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                chunk.uncompressed_offset() as libc::off_t,
+                chunk.uncompressed_size() as libc::off_t,
+            )
+        };
+        #[cfg(not(target_os = "linux"))]
+        let ret = 0;
+        if ret < 0 {
+            debug!(
+                "failed to punch hole for chunk {}, {}",
+                chunk.id(),
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
     fn adjust_buffer_for_dio(&self, buf: &mut Vec<u8>) {
         assert_eq!(buf.capacity() % 0x1000, 0);
         if buf.len() != buf.capacity() {
@@ -889,7 +1146,12 @@ impl FileCacheEntry {
             // - the chunk is ready in the file cache
             // - data in the file cache is plaintext.
             // - data validation is disabled
-            if is_ready && !self.is_raw_data && !self.need_validation() {
+            // - the chunk is not in the in-memory chunk cache
+            if is_ready
+                && !self.is_raw_data
+                && !self.need_validation()
+                && !self.is_chunk_in_mem_cache(chunk.as_ref())
+            {
                 // Internal IO should not be committed to local cache region, just
                 // commit this region without pushing any chunk to avoid discontinuous
                 // chunks in a region.
@@ -904,11 +1166,13 @@ impl FileCacheEntry {
                 } else {
                     state.commit()
                 }
-            } else if !self.is_direct_chunkmap || is_ready {
+            } else if !self.is_direct_chunkmap || is_ready || self.is_chunk_in_store(chunk.as_ref())
+            {
                 // Case to try loading data from cache
                 // - chunk is ready but data validation is needed.
                 // - direct chunk map is not used, so there may be data in the file cache but
                 //   the readiness flag has been lost.
+                // - chunk has been cached into the shared chunk store by other blobs.
                 if req.tags[i].is_user_io() {
                     state.push(
                         RegionType::CacheSlow,
@@ -958,10 +1222,13 @@ impl FileCacheEntry {
     fn dispatch_cache_fast(&self, cursor: &mut MemSliceCursor, region: &Region) -> Result<usize> {
         let offset = region.blob_address + region.seg.offset as u64;
         let size = region.seg.len as usize;
-        let mut iovec = cursor.consume(size);
 
         self.metrics.partial_hits.inc();
-        readv(self.file.as_raw_fd(), &mut iovec, offset)
+        let mut iovec = cursor.consume(size);
+        match self.uring_file.as_ref() {
+            Some(uring_file) => uring_file.readv_exact_at(&mut iovec, offset),
+            None => readv(self.file.as_raw_fd(), &mut iovec, offset),
+        }
     }
 
     // Try to read data from blob cache and validate it, fallback to storage backend.
@@ -1061,8 +1328,12 @@ impl FileCacheEntry {
             })?;
 
         if self.is_raw_data {
-            let res =
-                Self::persist_cached_data(&self.file, region.blob_address, bufs.compressed_buf());
+            let res = Self::persist_cached_data(
+                &self.file,
+                self.uring_file.as_deref(),
+                region.blob_address,
+                bufs.compressed_buf(),
+            );
             for chunk in region.chunks.iter() {
                 self.update_chunk_pending_status(chunk.as_ref(), res.is_ok());
             }
@@ -1074,6 +1345,9 @@ impl FileCacheEntry {
         for (i, v) in bufs.enumerate() {
             let d = Arc::new(DataBuffer::Allocated(v?));
             if region.tags[i] {
+                if let Some((cache, key)) = self.mem_cache.as_ref() {
+                    cache.insert(*key, region.chunks[i].id(), d.slice());
+                }
                 buffer_holder.push(d.clone());
             }
             if !self.is_raw_data {
@@ -1120,15 +1394,26 @@ impl FileCacheEntry {
 
         let buffer_holder;
         let d_size = chunk.uncompressed_size() as usize;
-        let mut d = DataBuffer::Allocated(alloc_buf(d_size));
 
         // Try to read and validate data from cache if:
         // - it's an stargz image and the chunk is ready.
         // - chunk data validation is enabled.
         // - digested or dummy chunk map is used.
         let is_ready = self.chunk_map.is_ready(chunk.as_ref())?;
-        let try_cache = is_ready || !self.is_direct_chunkmap;
-        let buffer = if try_cache && self.read_file_cache(chunk.as_ref(), d.mut_slice()).is_ok() {
+        let try_cache = is_ready || !self.is_direct_chunkmap || self.chunk_store.is_some();
+        let mem_data = self.read_mem_cache(chunk.as_ref());
+        // No buffer is needed if the chunk is served from the in-memory cache.
+        let mut d = match mem_data {
+            Some(_) => DataBuffer::Allocated(Vec::new()),
+            None => DataBuffer::Allocated(alloc_buf(d_size)),
+        };
+        let buffer = if let Some(data) = mem_data.as_ref() {
+            // The chunk has been marked as pending if it's not ready yet.
+            if !is_ready {
+                self.chunk_map.clear_pending(chunk.as_ref());
+            }
+            data.as_slice()
+        } else if try_cache && self.read_file_cache(chunk.as_ref(), d.mut_slice()).is_ok() {
             self.metrics.whole_hits.inc();
             self.chunk_map.set_ready_and_clear_pending(chunk.as_ref())?;
             trace!(
@@ -1138,7 +1423,7 @@ impl FileCacheEntry {
                 user_offset,
                 size,
             );
-            &d
+            d.slice()
         } else {
             let c = self
                 .read_chunk_from_backend(chunk.as_ref(), d.mut_slice())
@@ -1151,24 +1436,29 @@ impl FileCacheEntry {
                     Some(v) => {
                         let buf = Arc::new(DataBuffer::Allocated(v));
                         self.delay_persist_chunk_data(chunk.clone(), buf, true);
-                        &d
+                        d.slice()
                     }
                     None => {
                         buffer_holder = Arc::new(d.convert_to_owned_buffer());
                         self.delay_persist_chunk_data(chunk.clone(), buffer_holder.clone(), true);
-                        buffer_holder.as_ref()
+                        buffer_holder.slice()
                     }
                 }
             } else {
                 buffer_holder = Arc::new(d.convert_to_owned_buffer());
                 self.delay_persist_chunk_data(chunk.clone(), buffer_holder.clone(), false);
-                buffer_holder.as_ref()
+                buffer_holder.slice()
             }
         };
+        if mem_data.is_none() {
+            if let Some((cache, key)) = self.mem_cache.as_ref() {
+                cache.insert(*key, chunk.id(), buffer);
+            }
+        }
 
         let dst_buffers = mem_cursor.inner_slice();
         let read_size = copyv(
-            &[buffer.slice()],
+            &[buffer],
             dst_buffers,
             user_offset as usize,
             size as usize,
@@ -1185,39 +1475,96 @@ impl FileCacheEntry {
         Ok(read_size)
     }
 
+    fn is_chunk_in_mem_cache(&self, chunk: &dyn BlobChunkInfo) -> bool {
+        match self.mem_cache.as_ref() {
+            Some((cache, key)) => cache.contains(*key, chunk.id()),
+            None => false,
+        }
+    }
+
+    fn read_mem_cache(&self, chunk: &dyn BlobChunkInfo) -> Option<Arc<Vec<u8>>> {
+        let (cache, key) = self.mem_cache.as_ref()?;
+        let data = cache.get(*key, chunk.id());
+        if data.is_some() {
+            self.metrics.mem_cache_hits.inc();
+        } else {
+            self.metrics.mem_cache_misses.inc();
+        }
+        data
+    }
+
     fn read_file_cache(&self, chunk: &dyn BlobChunkInfo, buffer: &mut [u8]) -> Result<()> {
-        if self.is_raw_data {
+        if self.is_raw_data && self.is_zran {
+            self.read_zran_file_cache(chunk, buffer)?;
+        } else if self.is_raw_data {
             let offset = chunk.compressed_offset();
             let size = if self.is_legacy_stargz() {
                 self.get_legacy_stargz_size(offset, chunk.uncompressed_size() as usize)? as u64
             } else {
                 chunk.compressed_size() as u64
             };
-            let mut reader = FileRangeReader::new(&self.file, offset, size);
             if !chunk.is_compressed() {
-                reader.read_exact(buffer)?;
-            } else if self.blob_compressor() == compress::Algorithm::Lz4Block {
+                self.read_cache_file(offset, buffer)?;
+            } else if self.blob_compressor() == compress::Algorithm::Lz4Block
+                || self.uring_file.is_some()
+            {
                 let mut buf = alloc_buf(size as usize);
-                reader.read_exact(&mut buf)?;
-                let size = compress::decompress(&buf, buffer, self.blob_compressor())?;
-                if size != buffer.len() {
-                    return Err(einval!(
-                        "data size decoded by lz4_block doesn't match expected"
-                    ));
+                self.read_cache_file(offset, &mut buf)?;
+                if self.blob_compressor() == compress::Algorithm::Lz4Block {
+                    let size = compress::decompress(&buf, buffer, self.blob_compressor())?;
+                    if size != buffer.len() {
+                        return Err(einval!(
+                            "data size decoded by lz4_block doesn't match expected"
+                        ));
+                    }
+                } else {
+                    let mut decoder = Decoder::new(buf.as_slice(), self.blob_compressor())?;
+                    decoder.read_exact(buffer)?;
                 }
             } else {
+                let reader = FileRangeReader::new(&self.file, offset, size);
                 let mut decoder = Decoder::new(reader, self.blob_compressor())?;
                 decoder.read_exact(buffer)?;
             }
+        } else if let Some(store) = self.chunk_store.as_ref() {
+            store.read(chunk.chunk_id(), buffer)?;
         } else {
-            let offset = chunk.uncompressed_offset();
-            let size = chunk.uncompressed_size() as u64;
-            FileRangeReader::new(&self.file, offset, size).read_exact(buffer)?;
+            self.read_cache_file(chunk.uncompressed_offset(), buffer)?;
         }
         self.validate_chunk_data(chunk, buffer, false)?;
         Ok(())
     }
 
+    // ZRan chunks can't be decompressed independently, so decompress the whole ZRan context
+    // containing the chunk from the cached raw data.
+    fn read_zran_file_cache(&self, chunk: &dyn BlobChunkInfo, buffer: &mut [u8]) -> Result<()> {
+        let meta = self
+            .get_blob_meta_info()?
+            .ok_or_else(|| einval!("failed to get blob meta object for ZRan"))?;
+        let (ctx, _) = meta
+            .get_zran_context(meta.get_zran_index(chunk.id()))
+            .ok_or_else(|| einval!("failed to get ZRan context for chunk"))?;
+        let mut c_buf = alloc_buf(ctx.in_len as usize);
+        self.read_cache_file(ctx.in_offset, &mut c_buf)?;
+
+        let mut state = ChunkDecompressState::new(ctx.in_offset, self, vec![chunk], c_buf);
+        let data = state
+            .next()
+            .ok_or_else(|| einval!("failed to decompress ZRan chunk from cache file"))??;
+        buffer.copy_from_slice(&data);
+        Ok(())
+    }
+
+    // Read exactly `buffer.len()` bytes from the cache file at `offset`.
+    fn read_cache_file(&self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        match self.uring_file.as_ref() {
+            Some(uring_file) => uring_file.read_exact_at(buffer, offset),
+            None => {
+                FileRangeReader::new(&self.file, offset, buffer.len() as u64).read_exact(buffer)
+            }
+        }
+    }
+
     fn merge_requests_for_user(
         &self,
         bios: &[BlobIoDesc],
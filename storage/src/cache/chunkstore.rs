@@ -0,0 +1,189 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Content-addressed store for cached chunk data.
+//!
+//! The `ChunkStore` saves uncompressed chunk data into files named by chunk digest, so chunks
+//! with the same content are stored only once even if they belong to different blobs. Chunk files
+//! are spread into 256 sub-directories by the first byte of the digest to avoid huge directories:
+//! `<work_dir>/chunks/<xx>/<digest>`.
+//!
+//! There's no eviction or size limit for the store, chunk files are kept until being removed
+//! manually or replaced when found corrupted, so the store may keep growing as more images are
+//! accessed. Chunks known to exist are remembered in memory to avoid a `stat()` for each access,
+//! which costs about 32 bytes per chunk.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use nydus_utils::digest::RafsDigest;
+
+const CHUNK_STORE_DIR: &str = "chunks";
+
+/// Content-addressed store for chunk data, shared by all blobs managed by a cache manager.
+pub(crate) struct ChunkStore {
+    root: PathBuf,
+    seq: AtomicU64,
+    // Chunks known to exist in the store.
+    present: RwLock<HashSet<RafsDigest>>,
+}
+
+impl ChunkStore {
+    /// Create a new instance of `ChunkStore` under the `work_dir`.
+    pub fn new(work_dir: &str) -> Result<Self> {
+        let root = Path::new(work_dir).join(CHUNK_STORE_DIR);
+        fs::create_dir_all(&root).map_err(|e| {
+            eio!(format!(
+                "failed to create chunk store directory {}, {}",
+                root.display(),
+                e
+            ))
+        })?;
+
+        Ok(ChunkStore {
+            root,
+            seq: AtomicU64::new(0),
+            present: RwLock::new(HashSet::new()),
+        })
+    }
+
+    /// Check whether data for the chunk with `digest` is available in the store.
+    ///
+    /// Chunks may be added by other blobs or processes sharing the store, so the file system is
+    /// checked if the chunk is unknown yet.
+    pub fn contains(&self, digest: &RafsDigest) -> bool {
+        if self.present.read().unwrap().contains(digest) {
+            return true;
+        }
+        let exists = self.chunk_path(digest).is_file();
+        if exists {
+            self.present.write().unwrap().insert(*digest);
+        }
+        exists
+    }
+
+    /// Read data for the chunk with `digest` into `buf`.
+    ///
+    /// The size of the stored chunk must match the size of `buf`.
+    pub fn read(&self, digest: &RafsDigest, buf: &mut [u8]) -> Result<()> {
+        let mut file = File::open(self.chunk_path(digest)).map_err(|e| {
+            if e.kind() == ErrorKind::NotFound {
+                self.present.write().unwrap().remove(digest);
+            }
+            e
+        })?;
+        let size = file.metadata()?.len();
+        if size != buf.len() as u64 {
+            return Err(eio!(format!(
+                "size of stored chunk {} doesn't match, got 0x{:x}, expect 0x{:x}",
+                digest,
+                size,
+                buf.len()
+            )));
+        }
+        file.read_exact(buf)
+    }
+
+    /// Save data for the chunk with `digest` into the store.
+    ///
+    /// Data is written into a temporary file first and then renamed, so readers never observe
+    /// partially written chunks. It's a no-op if the chunk already exists.
+    pub fn write(&self, digest: &RafsDigest, buf: &[u8]) -> Result<()> {
+        if self.contains(digest) {
+            return Ok(());
+        }
+        self.replace(digest, buf)
+    }
+
+    /// Save data for the chunk with `digest` into the store, overwriting the existing one.
+    ///
+    /// It's used to repair corrupted chunks, which may still be in use by other blobs.
+    pub fn replace(&self, digest: &RafsDigest, buf: &[u8]) -> Result<()> {
+        let path = self.chunk_path(digest);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = path.with_extension(format!("{}.{}.tmp", std::process::id(), seq));
+        let res = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&tmp_path)
+            .and_then(|mut f| f.write_all(buf))
+            .and_then(|_| fs::rename(&tmp_path, &path));
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        } else {
+            self.present.write().unwrap().insert(*digest);
+        }
+        res
+    }
+
+    /// Remove the chunk with `digest` from the store.
+    pub fn remove(&self, digest: &RafsDigest) -> Result<()> {
+        self.present.write().unwrap().remove(digest);
+        match fs::remove_file(self.chunk_path(digest)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn chunk_path(&self, digest: &RafsDigest) -> PathBuf {
+        let name = digest.to_string();
+        self.root.join(&name[..2]).join(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nydus_utils::digest::{self, RafsDigest};
+    use vmm_sys_util::tempdir::TempDir;
+
+    #[test]
+    fn test_chunk_store() {
+        let tmpdir = TempDir::new().unwrap();
+        let work_dir = tmpdir.as_path().to_str().unwrap();
+        let store = ChunkStore::new(work_dir).unwrap();
+
+        let data = vec![0x5au8; 0x1000];
+        let digest = RafsDigest::from_buf(&data, digest::Algorithm::Blake3);
+        assert!(!store.contains(&digest));
+        let mut buf = vec![0u8; 0x1000];
+        assert!(store.read(&digest, &mut buf).is_err());
+
+        store.write(&digest, &data).unwrap();
+        assert!(store.contains(&digest));
+        store.read(&digest, &mut buf).unwrap();
+        assert_eq!(buf, data);
+        // Writing an existing chunk is a no-op.
+        store.write(&digest, &data).unwrap();
+
+        // Another store instance on the same directory shares the chunks.
+        let store2 = ChunkStore::new(work_dir).unwrap();
+        assert!(store2.contains(&digest));
+        let mut small = vec![0u8; 0x800];
+        assert!(store2.read(&digest, &mut small).is_err());
+
+        // Overwrite a corrupted chunk.
+        store.replace(&digest, &[0u8; 0x1000]).unwrap();
+        store2.read(&digest, &mut buf).unwrap();
+        assert_eq!(buf, [0u8; 0x1000]);
+        store.replace(&digest, &data).unwrap();
+        store2.read(&digest, &mut buf).unwrap();
+        assert_eq!(buf, data);
+
+        store.remove(&digest).unwrap();
+        assert!(!store.contains(&digest));
+        // Chunks removed by other instances are detected on read.
+        assert!(store2.read(&digest, &mut buf).is_err());
+        assert!(!store2.contains(&digest));
+        store.remove(&digest).unwrap();
+    }
+}
@@ -27,6 +27,7 @@ use nydus_api::CacheConfigV2;
 use nydus_utils::{compress, digest};
 
 use crate::backend::{BlobBackend, BlobReader};
+use crate::cache::qos::OndemandLimiter;
 use crate::cache::state::{ChunkMap, NoopChunkMap};
 use crate::cache::{BlobCache, BlobCacheMgr};
 use crate::device::{
@@ -40,6 +41,7 @@ struct DummyCache {
     blob_info: Arc<BlobInfo>,
     chunk_map: Arc<dyn ChunkMap>,
     reader: Arc<dyn BlobReader>,
+    ondemand_limiter: Option<Arc<OndemandLimiter>>,
     compressor: compress::Algorithm,
     digester: digest::Algorithm,
     is_legacy_stargz: bool,
@@ -79,6 +81,12 @@ impl BlobCache for DummyCache {
         &*self.reader
     }
 
+    fn throttle_ondemand_fetch(&self, size: u64) {
+        if let Some(limiter) = self.ondemand_limiter.as_ref() {
+            limiter.acquire(size);
+        }
+    }
+
     fn get_chunk_map(&self) -> &Arc<dyn ChunkMap> {
         &self.chunk_map
     }
@@ -163,6 +171,7 @@ pub struct DummyCacheMgr {
     backend: Arc<dyn BlobBackend>,
     cached: bool,
     need_validation: bool,
+    ondemand_limiter: Option<Arc<OndemandLimiter>>,
     closed: AtomicBool,
 }
 
@@ -172,11 +181,13 @@ impl DummyCacheMgr {
         config: &CacheConfigV2,
         backend: Arc<dyn BlobBackend>,
         cached: bool,
+        ondemand_limiter: Option<Arc<OndemandLimiter>>,
     ) -> Result<DummyCacheMgr> {
         Ok(DummyCacheMgr {
             backend,
             cached,
             need_validation: config.cache_validate,
+            ondemand_limiter,
             closed: AtomicBool::new(false),
         })
     }
@@ -217,6 +228,7 @@ impl BlobCacheMgr for DummyCacheMgr {
             blob_info: blob_info.clone(),
             chunk_map: Arc::new(NoopChunkMap::new(self.cached)),
             reader,
+            ondemand_limiter: self.ondemand_limiter.clone(),
             compressor: blob_info.compressor(),
             digester: blob_info.digester(),
             is_legacy_stargz: blob_info.is_legacy_stargz(),
@@ -4,8 +4,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::collections::HashMap;
-use std::fs::OpenOptions;
+use std::fs::{self, OpenOptions};
 use std::io::Result;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, RwLock};
 
@@ -16,9 +17,13 @@ use nydus_utils::metrics::BlobcacheMetrics;
 
 use crate::backend::BlobBackend;
 use crate::cache::cachedfile::{FileCacheEntry, FileCacheMeta};
+use crate::cache::chunkstore::ChunkStore;
+use crate::cache::memcache::ChunkMemCache;
+use crate::cache::qos::OndemandLimiter;
 use crate::cache::state::{BlobStateMap, ChunkMap, DigestedChunkMap, IndexedChunkMap};
+use crate::cache::uring::UringEngine;
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncWorkerMgr};
-use crate::cache::{BlobCache, BlobCacheMgr};
+use crate::cache::{BlobCache, BlobCacheMgr, BlobScrubResult};
 use crate::device::{BlobFeatures, BlobInfo};
 use crate::RAFS_DEFAULT_CHUNK_SIZE;
 
@@ -28,12 +33,17 @@ use crate::RAFS_DEFAULT_CHUNK_SIZE;
 pub struct FileCacheMgr {
     blobs: Arc<RwLock<HashMap<String, Arc<FileCacheEntry>>>>,
     backend: Arc<dyn BlobBackend>,
+    chunk_store: Option<Arc<ChunkStore>>,
+    mem_cache: Option<Arc<ChunkMemCache>>,
+    uring_engine: Option<Arc<UringEngine>>,
     metrics: Arc<BlobcacheMetrics>,
+    ondemand_limiter: Option<Arc<OndemandLimiter>>,
     prefetch_config: Arc<AsyncPrefetchConfig>,
     runtime: Arc<Runtime>,
     worker_mgr: Arc<AsyncWorkerMgr>,
     work_dir: String,
     validate: bool,
+    direct_io: bool,
     disable_indexed_map: bool,
     cache_raw_data: bool,
     closed: Arc<AtomicBool>,
@@ -46,23 +56,54 @@ impl FileCacheMgr {
         backend: Arc<dyn BlobBackend>,
         runtime: Arc<Runtime>,
         id: &str,
+        ondemand_limiter: Option<Arc<OndemandLimiter>>,
     ) -> Result<FileCacheMgr> {
         let blob_cfg = config.get_filecache_config()?;
         let work_dir = blob_cfg.get_work_dir()?;
         let metrics = BlobcacheMetrics::new(id, work_dir);
         let prefetch_config: Arc<AsyncPrefetchConfig> = Arc::new((&config.prefetch).into());
         let worker_mgr = AsyncWorkerMgr::new(metrics.clone(), prefetch_config.clone())?;
+        let chunk_store = if blob_cfg.shared_chunk_store {
+            if config.cache_compressed {
+                return Err(einval!(
+                    "shared chunk store can't be used together with compressed cache"
+                ));
+            }
+            Some(Arc::new(ChunkStore::new(work_dir)?))
+        } else {
+            None
+        };
+        let mem_cache = if config.memory_cache_size > 0 {
+            Some(Arc::new(ChunkMemCache::new(config.memory_cache_size)))
+        } else {
+            None
+        };
+        let uring_engine = match blob_cfg.io_engine.as_str() {
+            "" | "sync" => None,
+            "io_uring" => Some(UringEngine::new(id)?),
+            v => return Err(einval!(format!("invalid filecache I/O engine {}", v))),
+        };
+        if blob_cfg.direct_io && uring_engine.is_none() {
+            return Err(einval!(
+                "direct I/O is only supported by the io_uring I/O engine"
+            ));
+        }
 
         Ok(FileCacheMgr {
             blobs: Arc::new(RwLock::new(HashMap::new())),
             backend,
+            chunk_store,
+            mem_cache,
+            uring_engine,
             metrics,
+            ondemand_limiter,
             prefetch_config,
             runtime,
             worker_mgr: Arc::new(worker_mgr),
             work_dir: work_dir.to_owned(),
             disable_indexed_map: blob_cfg.disable_indexed_map,
             validate: config.cache_validate,
+            direct_io: blob_cfg.direct_io,
             cache_raw_data: config.cache_compressed,
             closed: Arc::new(AtomicBool::new(false)),
         })
@@ -154,6 +195,10 @@ impl BlobCacheMgr for FileCacheMgr {
     }
 
     fn check_stat(&self) {}
+
+    fn scrub(&self, blob_id: Option<&str>) -> Result<Vec<BlobScrubResult>> {
+        Ok(FileCacheEntry::scrub_entries(&self.blobs, blob_id))
+    }
 }
 
 impl Drop for FileCacheMgr {
@@ -194,8 +239,16 @@ impl FileCacheEntry {
         };
 
         let blob_file_path = format!("{}/{}", mgr.work_dir, blob_meta_id);
+        // Raw and plaintext cache files are tracked by different chunk maps, so switching the
+        // cache mode won't treat data in the other cache file as ready.
+        let chunk_map_path = if mgr.cache_raw_data {
+            Self::migrate_raw_chunk_map(&blob_file_path)?;
+            format!("{}.raw", blob_file_path)
+        } else {
+            blob_file_path.clone()
+        };
         let (chunk_map, is_direct_chunkmap) =
-            Self::create_chunk_map(mgr, &blob_info, &blob_file_path)?;
+            Self::create_chunk_map(mgr, &blob_info, &chunk_map_path)?;
 
         let blob_compressed_size = Self::get_blob_size(&reader, &blob_info)?;
         let blob_uncompressed_size = blob_info.uncompressed_size();
@@ -203,18 +256,35 @@ impl FileCacheEntry {
         // Validation is supported by RAFS v5 (which has no meta_ci) or v6 with chunk digest array.
         let validation_supported = !blob_info.meta_ci_is_valid()
             || blob_info.has_feature(BlobFeatures::INLINED_CHUNK_DIGEST);
-        let need_validation =
-            ((mgr.validate && validation_supported) || !is_direct_chunkmap) && !is_legacy_stargz;
+        // Chunks are addressed by digest in the shared chunk store, so it's only usable when
+        // chunk digests are available.
+        let chunk_store = match mgr.chunk_store.as_ref() {
+            Some(store) if validation_supported && !is_legacy_stargz => Some(store.clone()),
+            Some(_) => {
+                info!(
+                    "no chunk digest for blob {}, fall back to per blob cache file",
+                    blob_id
+                );
+                None
+            }
+            None => None,
+        };
+        // Chunks in the shared chunk store may be written by other blobs, always validate them.
+        let need_validation = ((mgr.validate && validation_supported)
+            || !is_direct_chunkmap
+            || chunk_store.is_some())
+            && !is_legacy_stargz;
         trace!(
-            "filecache entry: is_raw_data {}, direct {}, legacy_stargz {}, separate_meta {}, zran {}",
+            "filecache entry: is_raw_data {}, direct {}, chunk_store {}, legacy_stargz {}, separate_meta {}, zran {}",
             mgr.cache_raw_data,
             is_direct_chunkmap,
+            chunk_store.is_some(),
             is_legacy_stargz,
             is_separate_meta,
             is_zran,
         );
 
-        // Set cache file to its expected size.
+        // Set cache file to its expected size, it stays as a sparse file if chunk store is used.
         let suffix = if mgr.cache_raw_data {
             ".blob.raw"
         } else {
@@ -241,6 +311,10 @@ impl FileCacheEntry {
             );
             return Err(einval!(msg));
         }
+        let uring_file = match mgr.uring_engine.as_ref() {
+            None => None,
+            Some(engine) => Some(Arc::new(engine.open(&blob_data_file_path, mgr.direct_io)?)),
+        };
         let meta = if blob_info.meta_ci_is_valid() {
             let meta = FileCacheMeta::new(
                 blob_file_path,
@@ -254,15 +328,24 @@ impl FileCacheEntry {
         } else {
             None
         };
-        let is_get_blob_object_supported = meta.is_some() && is_direct_chunkmap;
+        let is_get_blob_object_supported =
+            meta.is_some() && is_direct_chunkmap && chunk_store.is_none();
+        let mem_cache = mgr
+            .mem_cache
+            .as_ref()
+            .map(|cache| (cache.clone(), cache.blob_key(&blob_id)));
 
         Ok(FileCacheEntry {
             blob_id,
             blob_info,
             chunk_map,
+            chunk_store,
+            mem_cache,
             file: Arc::new(file),
+            uring_file,
             meta,
             metrics: mgr.metrics.clone(),
+            ondemand_limiter: mgr.ondemand_limiter.clone(),
             prefetch_state: Arc::new(AtomicU32::new(0)),
             reader,
             runtime,
@@ -282,6 +365,24 @@ impl FileCacheEntry {
         })
     }
 
+    // Chunk maps of raw cache files used to be named after the blob, just like chunk maps of
+    // plaintext cache files. Adopt such a legacy chunk map for the raw cache file if there's no
+    // plaintext cache file it may belong to, so cached raw data is not downloaded again.
+    fn migrate_raw_chunk_map(blob_file_path: &str) -> Result<()> {
+        let legacy_map = format!("{}.chunk_map", blob_file_path);
+        let raw_map = format!("{}.raw.chunk_map", blob_file_path);
+        if Path::new(&legacy_map).exists()
+            && !Path::new(&raw_map).exists()
+            && Path::new(&format!("{}.blob.raw", blob_file_path)).exists()
+            && !Path::new(&format!("{}.blob.data", blob_file_path)).exists()
+        {
+            info!("migrate chunk map {} to {}", legacy_map, raw_map);
+            fs::rename(&legacy_map, &raw_map)?;
+        }
+
+        Ok(())
+    }
+
     fn create_chunk_map(
         mgr: &FileCacheMgr,
         blob_info: &BlobInfo,
@@ -773,3 +874,394 @@ pub mod blob_cache_tests {
        }
     */
 }
+
+#[cfg(all(test, feature = "backend-localfs"))]
+mod tests {
+    use super::*;
+    use crate::device::{BlobDevice, BlobIoDesc, BlobIoVec, BlobObject};
+    use crate::factory::BLOB_FACTORY;
+    use crate::meta::toc::{TocEntryList, TOC_ENTRY_BLOB_DIGEST, TOC_ENTRY_BLOB_TOC};
+    use crate::meta::{
+        BlobCompressionContextHeader, BlobMetaChunkArray, BlobMetaChunkInfo, ZranContextGenerator,
+    };
+    use fuse_backend_rs::file_buf::FileVolatileSlice;
+    use nydus_api::ConfigV2;
+    use nydus_utils::digest::RafsDigest;
+    use nydus_utils::{compress, digest, round_up};
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::fs::FileExt;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use tar::{Archive, EntryType, Header};
+    use vmm_sys_util::tempdir::TempDir;
+
+    fn new_config(id: &str, blobs: &Path, cache: &Path, compressed: bool) -> Arc<ConfigV2> {
+        let config = format!(
+            r#"
+            version = 2
+            id = "{}"
+            [backend]
+            type = "localfs"
+            [backend.localfs]
+            dir = "{}"
+            [cache]
+            type = "filecache"
+            compressed = {}
+            [cache.filecache]
+            work_dir = "{}"
+            "#,
+            id,
+            blobs.display(),
+            compressed,
+            cache.display()
+        );
+        Arc::new(ConfigV2::from_str(&config).unwrap())
+    }
+
+    // Read all chunks of the blob one by one through the blob cache.
+    fn read_chunks(config: &Arc<ConfigV2>, blob_info: &Arc<BlobInfo>) -> Vec<Vec<u8>> {
+        let blob = BLOB_FACTORY.new_blob_cache(config, blob_info).unwrap();
+        (0..blob_info.chunk_count())
+            .map(|idx| {
+                let chunk = blob.get_chunk_info(idx).unwrap();
+                let size = chunk.uncompressed_size();
+                let mut buf = vec![0u8; size as usize];
+                let mut iovec = BlobIoVec::new(blob_info.clone());
+                iovec.push(BlobIoDesc::new(
+                    blob_info.clone(),
+                    chunk.into(),
+                    0,
+                    size,
+                    true,
+                ));
+                let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+                assert_eq!(blob.read(&mut iovec, &[slice]).unwrap(), size as usize);
+                buf
+            })
+            .collect()
+    }
+
+    // Generate a ZRan blob from the tar.gz file `source` and store it into `blobs`, in the same
+    // way as `nydus-image create --type targz-ref` does. The data blob is the tar.gz file itself,
+    // and the blob meta is stored as a separate blob.
+    fn new_zran_blob(source: &Path, blobs: &Path) -> BlobInfo {
+        let data = fs::read(source).unwrap();
+        let blob_id = RafsDigest::from_buf(&data, digest::Algorithm::Sha256).to_string();
+        fs::write(blobs.join(&blob_id), &data).unwrap();
+
+        let mut generator = ZranContextGenerator::new(File::open(source).unwrap()).unwrap();
+        let mut tar = Archive::new(generator.reader());
+        tar.set_ignore_zeros(true);
+        let mut chunks = BlobMetaChunkArray::new_v2();
+        let mut uncompressed_size = 0;
+        let mut buf = vec![0u8; 0x1000];
+        for entry in tar.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.header().entry_type() != EntryType::Regular {
+                continue;
+            }
+            let mut left = entry.size();
+            while left > 0 {
+                let size = std::cmp::min(left, buf.len() as u64) as usize;
+                generator.start_chunk(buf.len() as u64).unwrap();
+                entry.read_exact(&mut buf[..size]).unwrap();
+                let chunk = generator.finish_chunk().unwrap();
+                uncompressed_size = round_up(chunk.uncompressed_end(), 0x1000);
+                chunks.add_v2_info(chunk);
+                left -= size as u64;
+            }
+        }
+
+        let (zran_data, zran_count) = generator.to_vec().unwrap();
+        let ci_data = [chunks.as_byte_slice(), &zran_data].concat();
+        let mut header = BlobCompressionContextHeader::default();
+        header.set_ci_zran_count(zran_count);
+        header.set_ci_zran_offset(chunks.as_byte_slice().len() as u64);
+        header.set_ci_zran_size(zran_data.len() as u64);
+        header.set_ci_zran(true);
+        header.set_separate_blob(true);
+        header.set_ci_compressor(compress::Algorithm::None);
+        header.set_ci_entries(chunks.len() as u32);
+        header.set_ci_compressed_offset(0);
+        header.set_ci_compressed_size(ci_data.len() as u64);
+        header.set_ci_uncompressed_size(ci_data.len() as u64);
+        header.set_4k_aligned(true);
+        header.set_chunk_info_v2(true);
+        let meta_digest = RafsDigest::from_buf(&ci_data, digest::Algorithm::Sha256);
+        let meta = [ci_data.as_slice(), header.as_bytes()].concat();
+        fs::write(blobs.join(meta_digest.to_string()), meta).unwrap();
+
+        let mut blob_info = BlobInfo::new(
+            0,
+            blob_id,
+            uncompressed_size,
+            data.len() as u64,
+            buf.len() as u32,
+            chunks.len() as u32,
+            BlobFeatures::ALIGNED
+                | BlobFeatures::CHUNK_INFO_V2
+                | BlobFeatures::SEPARATE
+                | BlobFeatures::ZRAN,
+        );
+        blob_info.set_compressor(compress::Algorithm::GZip);
+        blob_info.set_digester(digest::Algorithm::Sha256);
+        blob_info.set_blob_meta_info(
+            0,
+            ci_data.len() as u64,
+            ci_data.len() as u64,
+            compress::Algorithm::None as u32,
+        );
+        blob_info.set_blob_meta_digest(meta_digest.data);
+        blob_info
+    }
+
+    // Generate a blob with inlined chunk digests from uncompressed chunks and store it into
+    // `blobs`, with the same layout as `nydus-image create --features blob-toc` generates.
+    fn new_toc_blob(blob_id: &str, chunks: &[[u8; 0x1000]], blobs: &Path) -> BlobInfo {
+        let mut data = Vec::new();
+        let mut chunk_infos = BlobMetaChunkArray::new_v2();
+        let mut digests = Vec::new();
+        for chunk in chunks {
+            let offset = data.len() as u64;
+            let size = chunk.len() as u32;
+            chunk_infos.add_v2(offset, size, offset, size, false, 0);
+            let digest = RafsDigest::from_buf(chunk, digest::Algorithm::Blake3);
+            digests.extend_from_slice(&digest.data);
+            data.extend_from_slice(chunk);
+        }
+        let uncompressed_size = data.len() as u64;
+        let digest_offset = data.len() as u64;
+        data.extend_from_slice(&digests);
+
+        let ci_data = chunk_infos.as_byte_slice();
+        let ci_offset = data.len() as u64;
+        let mut header = BlobCompressionContextHeader::default();
+        header.set_ci_compressor(compress::Algorithm::None);
+        header.set_ci_entries(chunks.len() as u32);
+        header.set_ci_compressed_offset(ci_offset);
+        header.set_ci_compressed_size(ci_data.len() as u64);
+        header.set_ci_uncompressed_size(ci_data.len() as u64);
+        header.set_4k_aligned(true);
+        header.set_chunk_info_v2(true);
+        header.set_inlined_chunk_digest(true);
+        data.extend_from_slice(ci_data);
+        data.extend_from_slice(header.as_bytes());
+
+        let mut toc = TocEntryList::new();
+        toc.add(
+            TOC_ENTRY_BLOB_DIGEST,
+            compress::Algorithm::None,
+            RafsDigest::from_buf(&digests, digest::Algorithm::Sha256),
+            digest_offset,
+            digests.len() as u64,
+            digests.len() as u64,
+        )
+        .unwrap();
+        data.extend_from_slice(toc.as_bytes());
+        let mut toc_header = Header::new_gnu();
+        toc_header.set_path(TOC_ENTRY_BLOB_TOC).unwrap();
+        toc_header.set_entry_type(EntryType::Regular);
+        toc_header.set_size(toc.as_bytes().len() as u64);
+        toc_header.set_cksum();
+        data.extend_from_slice(toc_header.as_bytes());
+        fs::write(blobs.join(blob_id), &data).unwrap();
+
+        let mut blob_info = BlobInfo::new(
+            0,
+            blob_id.to_string(),
+            uncompressed_size,
+            data.len() as u64,
+            0x1000,
+            chunks.len() as u32,
+            BlobFeatures::ALIGNED
+                | BlobFeatures::CHUNK_INFO_V2
+                | BlobFeatures::INLINED_CHUNK_DIGEST,
+        );
+        blob_info.set_blob_meta_info(
+            ci_offset,
+            ci_data.len() as u64,
+            ci_data.len() as u64,
+            compress::Algorithm::None as u32,
+        );
+        blob_info
+    }
+
+    #[test]
+    fn test_zran_compressed_blob_cache() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let source = PathBuf::from(root_dir).join("../tests/texture/zran/zran-two-streams.tar.gz");
+        let tmpdir = TempDir::new().unwrap();
+        let blobs = tmpdir.as_path().join("blobs");
+        let raw_cache = tmpdir.as_path().join("raw_cache");
+        let data_cache = tmpdir.as_path().join("data_cache");
+        for dir in [&blobs, &raw_cache, &data_cache] {
+            fs::create_dir(dir).unwrap();
+        }
+
+        let blob_info = Arc::new(new_zran_blob(&source, &blobs));
+        assert!(blob_info.chunk_count() > 1);
+        let blob_id = blob_info.blob_id();
+        let blob_meta_id = blob_info.get_blob_meta_id().unwrap();
+        assert_ne!(blob_id, blob_meta_id);
+        let blob_path = blobs.join(&blob_id);
+
+        let raw_config = new_config("test_zran_raw_cache", &blobs, &raw_cache, true);
+        let data_config = new_config("test_zran_data_cache", &blobs, &data_cache, false);
+        let expected = read_chunks(&data_config, &blob_info);
+        // Compressed data is fetched from the backend and saved into the raw cache file.
+        assert_eq!(read_chunks(&raw_config, &blob_info), expected);
+        assert!(raw_cache
+            .join(format!("{}.blob.raw", blob_meta_id))
+            .exists());
+        assert!(raw_cache
+            .join(format!("{}.raw.chunk_map", blob_meta_id))
+            .exists());
+
+        // Break the backend blob, so chunks must be decompressed from the raw cache file.
+        let size = fs::metadata(&blob_path).unwrap().len();
+        fs::write(&blob_path, vec![0u8; size as usize]).unwrap();
+        assert_eq!(read_chunks(&raw_config, &blob_info), expected);
+
+        BLOB_FACTORY.gc(Some((&raw_config, &blob_id)));
+        BLOB_FACTORY.gc(Some((&data_config, &blob_id)));
+    }
+
+    #[test]
+    fn test_scrub_blob_cache() {
+        let tmpdir = TempDir::new().unwrap();
+        let blobs = tmpdir.as_path().join("blobs");
+        let cache = tmpdir.as_path().join("cache");
+        for dir in [&blobs, &cache] {
+            fs::create_dir(dir).unwrap();
+        }
+
+        let blob_id = "test_scrub_blob";
+        let blob_info = Arc::new(new_toc_blob(
+            blob_id,
+            &[[0x5au8; 0x1000], [0xa5u8; 0x1000]],
+            &blobs,
+        ));
+        let config = new_config("test_scrub_blob_cache", &blobs, &cache, false);
+        let blob = BLOB_FACTORY.new_blob_cache(&config, &blob_info).unwrap();
+        let fetch_all = || {
+            blob.get_blob_object()
+                .unwrap()
+                .fetch_range_uncompressed(0, blob_info.uncompressed_size())
+                .unwrap()
+        };
+
+        fetch_all();
+        let results = BLOB_FACTORY.scrub(Some(blob_id)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].blob_id, blob_id);
+        assert_eq!(results[0].checked_chunks, 2);
+        assert_eq!(results[0].skipped_chunks, 0);
+        assert!(results[0].corrupted_chunks.is_empty());
+        assert!(results[0].error.is_none());
+
+        // Corrupt the first chunk in the cache file.
+        let file = OpenOptions::new()
+            .write(true)
+            .open(cache.join(format!("{}.blob.data", blob_id)))
+            .unwrap();
+        file.write_all_at(&[0u8; 16], 0).unwrap();
+        let results = BLOB_FACTORY.scrub(Some(blob_id)).unwrap();
+        assert_eq!(results[0].checked_chunks, 2);
+        assert_eq!(results[0].corrupted_chunks, vec![0]);
+
+        // The corrupted chunk is not ready anymore.
+        let results = BLOB_FACTORY.scrub(Some(blob_id)).unwrap();
+        assert_eq!(results[0].checked_chunks, 1);
+        assert_eq!(results[0].skipped_chunks, 1);
+        assert!(results[0].corrupted_chunks.is_empty());
+
+        // And it's fetched from the backend again.
+        fetch_all();
+        let results = BLOB_FACTORY.scrub(Some(blob_id)).unwrap();
+        assert_eq!(results[0].checked_chunks, 2);
+        assert!(results[0].corrupted_chunks.is_empty());
+
+        drop(blob);
+        BLOB_FACTORY.gc(Some((&config, blob_id)));
+    }
+
+    #[test]
+    fn test_blob_cache_backing_file() {
+        let tmpdir = TempDir::new().unwrap();
+        let blobs = tmpdir.as_path().join("blobs");
+        let raw_cache = tmpdir.as_path().join("raw_cache");
+        let data_cache = tmpdir.as_path().join("data_cache");
+        for dir in [&blobs, &raw_cache, &data_cache] {
+            fs::create_dir(dir).unwrap();
+        }
+
+        let blob_id = "test_backing_file_blob";
+        let blob_info = Arc::new(new_toc_blob(
+            blob_id,
+            &[[0x5au8; 0x1000], [0xa5u8; 0x1000]],
+            &blobs,
+        ));
+        let raw_config = new_config("test_backing_file_raw", &blobs, &raw_cache, true);
+        let raw_blob = BLOB_FACTORY
+            .new_blob_cache(&raw_config, &blob_info)
+            .unwrap();
+        // Raw cache files contain compressed data.
+        assert!(raw_blob.get_backing_file().is_none());
+        drop(raw_blob);
+        BLOB_FACTORY.gc(Some((&raw_config, blob_id)));
+
+        let config = new_config("test_backing_file_data", &blobs, &data_cache, false);
+        let blob = BLOB_FACTORY.new_blob_cache(&config, &blob_info).unwrap();
+        assert!(blob.get_backing_file().is_some());
+        let device = BlobDevice::new(&config, &[blob_info.clone()]).unwrap();
+        let io_vecs = |ranges: &[(u32, u32, u32)]| {
+            let mut iovec = BlobIoVec::new(blob_info.clone());
+            for (idx, offset, size) in ranges {
+                let chunk = device.create_io_chunk(0, *idx).unwrap();
+                iovec.push(BlobIoDesc::new(
+                    blob_info.clone(),
+                    chunk,
+                    *offset,
+                    *size,
+                    true,
+                ));
+            }
+            vec![iovec]
+        };
+
+        // Chunks are not ready yet.
+        assert!(device
+            .get_backing_file(&io_vecs(&[(0, 0, 0x1000), (1, 0, 0x1000)]))
+            .is_none());
+
+        blob.get_blob_object()
+            .unwrap()
+            .fetch_range_uncompressed(0, blob_info.uncompressed_size())
+            .unwrap();
+        let (file, offset) = device
+            .get_backing_file(&io_vecs(&[(0, 0, 0x1000), (1, 0, 0x1000)]))
+            .unwrap();
+        assert_eq!(offset, 0);
+        let mut buf = vec![0u8; 0x2000];
+        file.read_exact_at(&mut buf, offset).unwrap();
+        assert!(buf[..0x1000].iter().all(|v| *v == 0x5a));
+        assert!(buf[0x1000..].iter().all(|v| *v == 0xa5));
+
+        let (_, offset) = device
+            .get_backing_file(&io_vecs(&[(0, 0x800, 0x800), (1, 0, 0x10)]))
+            .unwrap();
+        assert_eq!(offset, 0x800);
+        // Data is not contiguous in the cache file.
+        assert!(device
+            .get_backing_file(&io_vecs(&[(1, 0, 0x1000), (0, 0, 0x1000)]))
+            .is_none());
+        assert!(device
+            .get_backing_file(&io_vecs(&[(0, 0, 0x800), (1, 0, 0x1000)]))
+            .is_none());
+
+        device.close().unwrap();
+        drop(blob);
+        BLOB_FACTORY.gc(Some((&config, blob_id)));
+    }
+}
@@ -15,9 +15,10 @@ use tokio::runtime::Runtime;
 
 use crate::backend::BlobBackend;
 use crate::cache::cachedfile::{FileCacheEntry, FileCacheMeta};
+use crate::cache::qos::OndemandLimiter;
 use crate::cache::state::{BlobStateMap, IndexedChunkMap, RangeMap};
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncWorkerMgr};
-use crate::cache::{BlobCache, BlobCacheMgr};
+use crate::cache::{BlobCache, BlobCacheMgr, BlobScrubResult};
 use crate::device::{BlobFeatures, BlobInfo, BlobObject};
 use crate::factory::BLOB_FACTORY;
 use crate::RAFS_DEFAULT_CHUNK_SIZE;
@@ -31,6 +32,7 @@ pub struct FsCacheMgr {
     blobs: Arc<RwLock<HashMap<String, Arc<FileCacheEntry>>>>,
     backend: Arc<dyn BlobBackend>,
     metrics: Arc<BlobcacheMetrics>,
+    ondemand_limiter: Option<Arc<OndemandLimiter>>,
     prefetch_config: Arc<AsyncPrefetchConfig>,
     runtime: Arc<Runtime>,
     worker_mgr: Arc<AsyncWorkerMgr>,
@@ -47,6 +49,7 @@ impl FsCacheMgr {
         backend: Arc<dyn BlobBackend>,
         runtime: Arc<Runtime>,
         id: &str,
+        ondemand_limiter: Option<Arc<OndemandLimiter>>,
     ) -> Result<FsCacheMgr> {
         if config.cache_compressed {
             return Err(enosys!("fscache doesn't support compressed cache mode"));
@@ -64,6 +67,7 @@ impl FsCacheMgr {
             blobs: Arc::new(RwLock::new(HashMap::new())),
             backend,
             metrics,
+            ondemand_limiter,
             prefetch_config,
             runtime,
             worker_mgr: Arc::new(worker_mgr),
@@ -182,6 +186,10 @@ impl BlobCacheMgr for FsCacheMgr {
             self.blobs_check_count.store(0, Ordering::Release);
         }
     }
+
+    fn scrub(&self, blob_id: Option<&str>) -> Result<Vec<BlobScrubResult>> {
+        Ok(FileCacheEntry::scrub_entries(&self.blobs, blob_id))
+    }
 }
 
 impl Drop for FsCacheMgr {
@@ -259,9 +267,13 @@ impl FileCacheEntry {
             blob_id,
             blob_info: blob_info.clone(),
             chunk_map,
+            chunk_store: None,
+            mem_cache: None,
+            uring_file: None,
             file,
             meta: Some(meta),
             metrics: mgr.metrics.clone(),
+            ondemand_limiter: mgr.ondemand_limiter.clone(),
             prefetch_state: Arc::new(AtomicU32::new(0)),
             reader,
             runtime,
@@ -0,0 +1,163 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-memory LRU cache for decompressed chunk data.
+//!
+//! The `ChunkMemCache` sits above the file cache to serve hot chunks directly from memory, so
+//! repeated reads don't need to read, decompress and validate chunk data from the cache file
+//! again. It's shared by all blobs managed by a cache manager and bounded by total data size.
+//! Blobs are identified by numeric keys allocated by [ChunkMemCache::blob_key()], so lookups
+//! don't need to allocate memory.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+type ChunkKey = (u32, u32);
+
+#[derive(Default)]
+struct ChunkMemCacheInner {
+    size: u64,
+    tick: u64,
+    // Map chunk key to chunk data and the last access tick.
+    entries: HashMap<ChunkKey, (Arc<Vec<u8>>, u64)>,
+    // Map last access tick to chunk key, the first one is the least recently used.
+    lru: BTreeMap<u64, ChunkKey>,
+}
+
+/// A bounded LRU cache for decompressed chunk data, indexed by blob key and chunk index.
+pub(crate) struct ChunkMemCache {
+    capacity: u64,
+    blob_keys: Mutex<HashMap<String, u32>>,
+    inner: Mutex<ChunkMemCacheInner>,
+}
+
+impl ChunkMemCache {
+    /// Create a new instance of `ChunkMemCache` holding at most `capacity` bytes of chunk data.
+    pub fn new(capacity: u64) -> Self {
+        ChunkMemCache {
+            capacity,
+            blob_keys: Mutex::new(HashMap::new()),
+            inner: Mutex::new(ChunkMemCacheInner::default()),
+        }
+    }
+
+    /// Get the key to index chunks of blob `blob_id`, the same blob always gets the same key.
+    pub fn blob_key(&self, blob_id: &str) -> u32 {
+        let mut guard = self.blob_keys.lock().unwrap();
+        let next = guard.len() as u32;
+        *guard.entry(blob_id.to_string()).or_insert(next)
+    }
+
+    /// Check whether data of the chunk at `index` of blob `blob` is cached, without marking it
+    /// as recently used.
+    pub fn contains(&self, blob: u32, index: u32) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .entries
+            .contains_key(&(blob, index))
+    }
+
+    /// Get data of the chunk at `index` of blob `blob`, and mark it as recently used.
+    pub fn get(&self, blob: u32, index: u32) -> Option<Arc<Vec<u8>>> {
+        let key = (blob, index);
+        let mut guard = self.inner.lock().unwrap();
+        guard.tick += 1;
+        let tick = guard.tick;
+        let (data, old_tick) = match guard.entries.get_mut(&key) {
+            None => return None,
+            Some((data, last)) => {
+                let old_tick = *last;
+                *last = tick;
+                (data.clone(), old_tick)
+            }
+        };
+        guard.lru.remove(&old_tick);
+        guard.lru.insert(tick, key);
+
+        Some(data)
+    }
+
+    /// Save data of the chunk at `index` of blob `blob`, evicting least recently used chunks if
+    /// needed.
+    pub fn insert(&self, blob: u32, index: u32, data: &[u8]) {
+        let len = data.len() as u64;
+        if len > self.capacity {
+            return;
+        }
+
+        let key = (blob, index);
+        let mut guard = self.inner.lock().unwrap();
+        if guard.entries.contains_key(&key) {
+            return;
+        }
+        while guard.size + len > self.capacity {
+            let (tick, victim) = match guard.lru.iter().next() {
+                None => break,
+                Some((tick, victim)) => (*tick, *victim),
+            };
+            guard.lru.remove(&tick);
+            if let Some((data, _)) = guard.entries.remove(&victim) {
+                guard.size -= data.len() as u64;
+            }
+        }
+
+        guard.tick += 1;
+        let tick = guard.tick;
+        guard.size += len;
+        guard.entries.insert(key, (Arc::new(data.to_vec()), tick));
+        guard.lru.insert(tick, key);
+    }
+
+    /// Remove data of the chunk at `index` of blob `blob`.
+    pub fn remove(&self, blob: u32, index: u32) {
+        let key = (blob, index);
+        let mut guard = self.inner.lock().unwrap();
+        if let Some((data, tick)) = guard.entries.remove(&key) {
+            guard.size -= data.len() as u64;
+            guard.lru.remove(&tick);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_mem_cache() {
+        let cache = ChunkMemCache::new(0x3000);
+        let blob1 = cache.blob_key("blob1");
+        let blob2 = cache.blob_key("blob2");
+        let blob3 = cache.blob_key("blob3");
+        assert_ne!(blob1, blob2);
+        assert_eq!(cache.blob_key("blob1"), blob1);
+        assert!(cache.get(blob1, 0).is_none());
+
+        cache.insert(blob1, 0, &[1u8; 0x1000]);
+        cache.insert(blob1, 1, &[2u8; 0x1000]);
+        cache.insert(blob2, 0, &[3u8; 0x1000]);
+        assert_eq!(cache.get(blob1, 0).unwrap()[0], 1);
+        assert_eq!(cache.get(blob2, 0).unwrap()[0], 3);
+
+        // "blob1/1" is the least recently used one and gets evicted, `contains()` doesn't
+        // affect the LRU order.
+        assert!(cache.contains(blob1, 1));
+        cache.insert(blob2, 1, &[4u8; 0x1000]);
+        assert!(!cache.contains(blob1, 1));
+        assert!(cache.get(blob1, 1).is_none());
+        assert_eq!(cache.get(blob1, 0).unwrap()[0], 1);
+        assert_eq!(cache.get(blob2, 1).unwrap()[0], 4);
+
+        // Chunks larger than the capacity are not cached.
+        cache.insert(blob3, 0, &[5u8; 0x4000]);
+        assert!(cache.get(blob3, 0).is_none());
+        assert_eq!(cache.inner.lock().unwrap().size, 0x3000);
+
+        cache.remove(blob1, 0);
+        assert!(cache.get(blob1, 0).is_none());
+        assert_eq!(cache.inner.lock().unwrap().size, 0x2000);
+        assert_eq!(cache.inner.lock().unwrap().lru.len(), 2);
+    }
+}
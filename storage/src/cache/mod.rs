@@ -17,6 +17,7 @@
 //!   configuration.
 
 use std::cmp;
+use std::fs::File;
 use std::io::Result;
 use std::sync::Arc;
 use std::time::Instant;
@@ -24,6 +25,7 @@ use std::time::Instant;
 use fuse_backend_rs::file_buf::FileVolatileSlice;
 use nydus_utils::compress::zlib_random::ZranDecoder;
 use nydus_utils::{compress, digest};
+use serde::Serialize;
 
 use crate::backend::{BlobBackend, BlobReader};
 use crate::cache::state::ChunkMap;
@@ -35,18 +37,59 @@ use crate::utils::{alloc_buf, check_digest};
 use crate::{StorageResult, RAFS_MAX_CHUNK_SIZE};
 
 mod cachedfile;
+mod chunkstore;
 mod dummycache;
 mod filecache;
 #[cfg(target_os = "linux")]
 mod fscache;
+mod memcache;
+mod qos;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
 mod worker;
 
+// Placeholder for the io_uring I/O engine if it's not enabled.
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+mod uring {
+    use std::io::{IoSliceMut, Result};
+    use std::sync::Arc;
+
+    pub(crate) struct UringEngine {}
+
+    impl UringEngine {
+        pub fn new(_name: &str) -> Result<Arc<Self>> {
+            Err(enosys!("io_uring I/O engine is not enabled"))
+        }
+
+        pub fn open(self: &Arc<Self>, _path: &str, _direct_io: bool) -> Result<UringFile> {
+            Err(enosys!("io_uring I/O engine is not enabled"))
+        }
+    }
+
+    pub(crate) struct UringFile {}
+
+    impl UringFile {
+        pub fn read_exact_at(&self, _buf: &mut [u8], _offset: u64) -> Result<()> {
+            Err(enosys!("io_uring I/O engine is not enabled"))
+        }
+
+        pub fn readv_exact_at(&self, _bufs: &mut [IoSliceMut], _offset: u64) -> Result<usize> {
+            Err(enosys!("io_uring I/O engine is not enabled"))
+        }
+
+        pub fn write_all_at(&self, _buf: &[u8], _offset: u64) -> Result<()> {
+            Err(enosys!("io_uring I/O engine is not enabled"))
+        }
+    }
+}
+
 pub mod state;
 
 pub use dummycache::DummyCacheMgr;
 pub use filecache::FileCacheMgr;
 #[cfg(target_os = "linux")]
 pub use fscache::FsCacheMgr;
+pub use qos::OndemandLimiter;
 
 /// Timeout in milli-seconds to retrieve blob data from backend storage.
 pub const SINGLE_INFLIGHT_WAIT_TIMEOUT: u64 = 2000;
@@ -132,6 +175,26 @@ impl<'a, F: FnMut(BlobIoRange)> BlobIoMergeState<'a, F> {
     }
 }
 
+/// Result of scrubbing cached data of a blob.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BlobScrubResult {
+    /// Id of the scrubbed blob.
+    pub blob_id: String,
+    /// Number of ready chunks which have been verified.
+    pub checked_chunks: u32,
+    /// Number of chunks skipped because they are not ready in the cache.
+    pub skipped_chunks: u32,
+    /// Indexes of chunks whose cached data doesn't match the chunk digest.
+    ///
+    /// The ready state of those chunks has been cleared, so they will be fetched from the storage
+    /// backend again on next access. Chunks in the shared chunk store are repaired in place
+    /// instead, because they may be in use by other blobs.
+    pub corrupted_chunks: Vec<u32>,
+    /// Error message if the blob can't be scrubbed, such as scrub is not supported by the blob.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 /// Trait representing a cache object for a blob on backend storage.
 ///
 /// The caller may use the `BlobCache` trait to access blob data on backend storage, with an
@@ -193,6 +256,14 @@ pub trait BlobCache: Send + Sync {
         None
     }
 
+    /// Get the cache file which stores uncompressed chunk data at chunk uncompressed offsets.
+    ///
+    /// Data of ready chunks may be read from the file directly without going through the blob
+    /// cache. `None` is returned if cached data needs decompression or validation before use.
+    fn get_backing_file(&self) -> Option<Arc<File>> {
+        None
+    }
+
     /// Enable prefetching blob data in background.
     ///
     /// It should be paired with stop_prefetch().
@@ -219,6 +290,9 @@ pub trait BlobCache: Send + Sync {
         Err(enosys!("doesn't support prefetch_range()"))
     }
 
+    /// Wait until an on-demand fetch of `size` bytes from the storage backend is allowed by QoS.
+    fn throttle_ondemand_fetch(&self, _size: u64) {}
+
     /// Read chunk data described by the blob Io descriptors from the blob cache into the buffer.
     fn read(&self, iovec: &mut BlobIoVec, buffers: &[FileVolatileSlice]) -> Result<usize>;
 
@@ -241,6 +315,10 @@ pub trait BlobCache: Send + Sync {
     where
         Self: Sized,
     {
+        if !prefetch {
+            self.throttle_ondemand_fetch(blob_size as u64);
+        }
+
         // Read requested data from the backend by altogether.
         let mut c_buf = alloc_buf(blob_size);
         let start = Instant::now();
@@ -283,7 +361,9 @@ pub trait BlobCache: Send + Sync {
 
         if self.is_zran() {
             return Err(enosys!("read_chunk_from_backend"));
-        } else if chunk.is_compressed() {
+        }
+        self.throttle_ondemand_fetch(chunk.compressed_size() as u64);
+        if chunk.is_compressed() {
             let c_size = if self.is_legacy_stargz() {
                 self.get_legacy_stargz_size(offset, buffer.len())?
             } else {
@@ -372,6 +452,14 @@ pub trait BlobCache: Send + Sync {
     fn get_blob_meta_info(&self) -> Result<Option<Arc<BlobCompressionContextInfo>>> {
         Ok(None)
     }
+
+    /// Verify all ready chunks in the cache against their digests.
+    ///
+    /// Chunks with corrupted cached data are marked as not ready, so they will be fetched from
+    /// the storage backend again.
+    fn scrub(&self) -> Result<BlobScrubResult> {
+        Err(enosys!("doesn't support scrub()"))
+    }
 }
 
 /// An iterator to enumerate decompressed data for chunks.
@@ -539,6 +627,11 @@ pub(crate) trait BlobCacheMgr: Send + Sync {
 
     /// Check the blob cache data status, if data all ready stop prefetch workers.
     fn check_stat(&self);
+
+    /// Scrub cached data of the blob with id `blob_id`, or all blobs if `blob_id` is None.
+    fn scrub(&self, _blob_id: Option<&str>) -> Result<Vec<BlobScrubResult>> {
+        Ok(Vec::new())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,374 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rate limiting for on-demand data fetches from storage backends.
+//!
+//! On-demand fetches are throttled at three scopes: the mount itself, all mounts of the daemon and
+//! all mounts fetching from the same storage backend host. Rate limiters of the daemon and host
+//! scopes are shared by mounts, and waiting requests are served in the order of start-time fair
+//! queueing, so a mount scanning the whole image can't starve other mounts.
+//!
+//! Shared rate limits are decided by the first mount creating them. Mounts with different rate
+//! limits for a shared scope are rejected instead of silently changing limits of other mounts,
+//! until all mounts sharing the scope are gone.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use nydus_api::{QosConfig, RateLimitConfig};
+
+// Upper limit of time to wait before checking the rate limiter again.
+const MAX_WAIT_TIME: Duration = Duration::from_millis(100);
+// Scale of virtual time, so small requests from mounts with big weight still advance it.
+const VIRTUAL_TIME_SCALE: u64 = 1024;
+
+lazy_static! {
+    static ref SHARED_LIMITERS: Mutex<HashMap<String, Weak<RateLimiter>>> =
+        Mutex::new(HashMap::new());
+}
+
+static NEXT_MOUNT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Option<Self> {
+        if rate == 0 {
+            return None;
+        }
+        Some(TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: Instant::now(),
+        })
+    }
+
+    // Refill tokens, allowing bursting up to one second worth of tokens.
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+
+    // Get time to wait until `cost` tokens are available. A request bigger than the burst size
+    // is admitted once the bucket is full and leaves the bucket in debt, otherwise it would be
+    // blocked forever.
+    fn wait_time(&self, cost: f64) -> Duration {
+        let needed = cost.min(self.rate);
+        if self.tokens >= needed {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((needed - self.tokens) / self.rate)
+        }
+    }
+
+    fn consume(&mut self, cost: f64) {
+        self.tokens -= cost;
+    }
+}
+
+#[derive(Debug, Default)]
+struct RateLimiterState {
+    bytes: Option<TokenBucket>,
+    requests: Option<TokenBucket>,
+    config: RateLimitConfig,
+    // Virtual time, which is the start tag of the last admitted request.
+    virtual_time: u64,
+    // Finish tag of the last request from each mount.
+    finish_tags: HashMap<u64, u64>,
+    // Waiting requests ordered by start tag and sequence number.
+    waiters: BTreeSet<(u64, u64)>,
+    next_seq: u64,
+}
+
+impl RateLimiterState {
+    fn set_config(&mut self, config: &RateLimitConfig) {
+        if &self.config != config {
+            self.bytes = TokenBucket::new(config.bandwidth_limit);
+            self.requests = TokenBucket::new(config.request_limit as u64);
+            self.config = config.clone();
+        }
+    }
+
+    fn wait_time(&mut self, size: u64) -> Duration {
+        let now = Instant::now();
+        let mut wait = Duration::ZERO;
+        if let Some(bucket) = self.bytes.as_mut() {
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_time(size as f64));
+        }
+        if let Some(bucket) = self.requests.as_mut() {
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_time(1.0));
+        }
+        wait
+    }
+
+    fn consume(&mut self, size: u64) {
+        if let Some(bucket) = self.bytes.as_mut() {
+            bucket.consume(size as f64);
+        }
+        if let Some(bucket) = self.requests.as_mut() {
+            bucket.consume(1.0);
+        }
+    }
+}
+
+/// Rate limiter shared by mounts, which serves waiting mounts in weighted fair order.
+#[derive(Debug, Default)]
+struct RateLimiter {
+    state: Mutex<RateLimiterState>,
+    cond: Condvar,
+}
+
+impl RateLimiter {
+    fn new(config: &RateLimitConfig) -> Self {
+        let limiter = RateLimiter::default();
+        limiter.state.lock().unwrap().set_config(config);
+        limiter
+    }
+
+    // Get the rate limiter shared by all users of `key`, or create a new one.
+    //
+    // Fails if the existing rate limiter has different limits.
+    fn get_shared(key: &str, config: &RateLimitConfig) -> Result<Arc<Self>> {
+        let mut limiters = SHARED_LIMITERS.lock().unwrap();
+        limiters.retain(|_, v| v.strong_count() > 0);
+        if let Some(limiter) = limiters.get(key).and_then(|v| v.upgrade()) {
+            let state = limiter.state.lock().unwrap();
+            if &state.config != config {
+                return Err(einval!(format!(
+                    "qos: rate limits {:?} conflict with {:?} in use by other mounts for {}",
+                    config, state.config, key
+                )));
+            }
+            drop(state);
+            return Ok(limiter);
+        }
+
+        let limiter = Arc::new(RateLimiter::new(config));
+        limiters.insert(key.to_string(), Arc::downgrade(&limiter));
+        Ok(limiter)
+    }
+
+    // Wait until a request of `size` bytes from mount `id` is admitted, returns time waited.
+    fn acquire(&self, id: u64, weight: u64, size: u64) -> Duration {
+        let begin = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if state.bytes.is_none() && state.requests.is_none() {
+            return Duration::ZERO;
+        }
+
+        let last_finish = state.finish_tags.get(&id).copied().unwrap_or_default();
+        let start = state.virtual_time.max(last_finish);
+        let cost = size.max(1).saturating_mul(VIRTUAL_TIME_SCALE) / weight.max(1);
+        state.finish_tags.insert(id, start.saturating_add(cost));
+        let key = (start, state.next_seq);
+        state.next_seq += 1;
+        state.waiters.insert(key);
+
+        let mut waited = false;
+        loop {
+            let timeout = if state.waiters.iter().next() == Some(&key) {
+                let wait = state.wait_time(size);
+                if wait.is_zero() {
+                    state.consume(size);
+                    state.waiters.remove(&key);
+                    state.virtual_time = state.virtual_time.max(start);
+                    self.cond.notify_all();
+                    return if waited {
+                        begin.elapsed()
+                    } else {
+                        Duration::ZERO
+                    };
+                }
+                wait.min(MAX_WAIT_TIME)
+            } else {
+                MAX_WAIT_TIME
+            };
+            state = self.cond.wait_timeout(state, timeout).unwrap().0;
+            waited = true;
+        }
+    }
+
+    fn remove_mount(&self, id: u64) {
+        self.state.lock().unwrap().finish_tags.remove(&id);
+    }
+}
+
+/// Rate limiter for on-demand data fetches of a mount.
+pub struct OndemandLimiter {
+    id: u64,
+    weight: u64,
+    limiters: Vec<Arc<RateLimiter>>,
+}
+
+impl OndemandLimiter {
+    /// Create a rate limiter for on-demand fetches from storage backend `host`.
+    ///
+    /// Returns `None` if QoS is disabled or there's no rate limit at all, and fails if the daemon
+    /// or host rate limits conflict with those in use by other mounts.
+    pub fn new(config: &QosConfig, host: Option<&str>) -> Result<Option<Arc<Self>>> {
+        if !config.enable {
+            return Ok(None);
+        }
+
+        let mut limiters = Vec::new();
+        if config.mount != RateLimitConfig::default() {
+            limiters.push(Arc::new(RateLimiter::new(&config.mount)));
+        }
+        if let Some(host) = host.filter(|h| !h.is_empty()) {
+            if config.host != RateLimitConfig::default() {
+                let key = format!("host:{}", host);
+                limiters.push(RateLimiter::get_shared(&key, &config.host)?);
+            }
+        }
+        if config.daemon != RateLimitConfig::default() {
+            limiters.push(RateLimiter::get_shared("daemon", &config.daemon)?);
+        }
+        if limiters.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Arc::new(OndemandLimiter {
+            id: NEXT_MOUNT_ID.fetch_add(1, Ordering::Relaxed),
+            weight: config.weight.max(1) as u64,
+            limiters,
+        })))
+    }
+
+    /// Wait until an on-demand fetch of `size` bytes is allowed, returns time waited.
+    pub fn acquire(&self, size: u64) -> Duration {
+        self.limiters
+            .iter()
+            .map(|l| l.acquire(self.id, self.weight, size))
+            .sum()
+    }
+}
+
+impl Drop for OndemandLimiter {
+    fn drop(&mut self) {
+        for limiter in self.limiters.iter() {
+            limiter.remove_mount(self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_token_bucket() {
+        assert!(TokenBucket::new(0).is_none());
+
+        let mut bucket = TokenBucket::new(1024).unwrap();
+        assert_eq!(bucket.wait_time(1024.0), Duration::ZERO);
+        bucket.consume(1536.0);
+        assert_eq!(bucket.wait_time(512.0), Duration::from_secs(1));
+        // Requests bigger than the burst size only wait for a full bucket.
+        assert_eq!(bucket.wait_time(4096.0), Duration::from_millis(1500));
+
+        let now = bucket.last + Duration::from_secs(10);
+        bucket.refill(now);
+        assert_eq!(bucket.tokens, 1024.0);
+    }
+
+    #[test]
+    fn test_ondemand_limiter() {
+        let mut config = QosConfig::default();
+        let host = Some("test_ondemand_limiter");
+        assert!(OndemandLimiter::new(&config, host).unwrap().is_none());
+        config.enable = true;
+        assert!(OndemandLimiter::new(&config, host).unwrap().is_none());
+
+        config.mount.request_limit = 10;
+        config.host.bandwidth_limit = 0x100000;
+        let limiter1 = OndemandLimiter::new(&config, host).unwrap().unwrap();
+        let limiter2 = OndemandLimiter::new(&config, host).unwrap().unwrap();
+        assert_eq!(limiter1.limiters.len(), 2);
+        assert!(!Arc::ptr_eq(&limiter1.limiters[0], &limiter2.limiters[0]));
+        assert!(Arc::ptr_eq(&limiter1.limiters[1], &limiter2.limiters[1]));
+        assert_eq!(
+            OndemandLimiter::new(&config, None)
+                .unwrap()
+                .unwrap()
+                .limiters
+                .len(),
+            1
+        );
+
+        // Shared rate limits can't be changed by other mounts.
+        let mut other = config.clone();
+        other.host.bandwidth_limit = 0x200000;
+        assert!(OndemandLimiter::new(&other, host).is_err());
+        // Mounts without limits for the shared scope don't join it.
+        other.host = RateLimitConfig::default();
+        let limiter3 = OndemandLimiter::new(&other, host).unwrap().unwrap();
+        assert_eq!(limiter3.limiters.len(), 1);
+
+        // Burst of the mount request limit.
+        for _ in 0..10 {
+            assert_eq!(limiter1.acquire(0x1000), Duration::ZERO);
+        }
+        assert!(limiter1.acquire(0x1000) > Duration::from_millis(50));
+
+        drop(limiter1);
+        drop(limiter2);
+        {
+            let limiters = SHARED_LIMITERS.lock().unwrap();
+            assert!(limiters
+                .get("host:test_ondemand_limiter")
+                .map(|v| v.strong_count() == 0)
+                .unwrap_or(true));
+        }
+        // New rate limits are accepted once all mounts sharing the old ones are gone.
+        other.host.bandwidth_limit = 0x200000;
+        assert!(OndemandLimiter::new(&other, host).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_weighted_fair_sharing() {
+        let limiter = Arc::new(RateLimiter::new(&RateLimitConfig {
+            bandwidth_limit: 0,
+            request_limit: 50,
+        }));
+        // Drain the burst.
+        for _ in 0..50 {
+            limiter.acquire(0, 1, 1);
+        }
+
+        let admitted = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for (id, weight) in [(1u64, 1u64), (2, 3)] {
+            let limiter = limiter.clone();
+            let admitted = admitted.clone();
+            handles.push(thread::spawn(move || {
+                for _ in 0..8 {
+                    limiter.acquire(id, weight, 1);
+                    admitted.lock().unwrap().push(id);
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // The mount with bigger weight gets more requests admitted while both are waiting.
+        let admitted = admitted.lock().unwrap();
+        let first_half = &admitted[..8];
+        let heavy = first_half.iter().filter(|id| **id == 2).count();
+        assert!(heavy >= 5, "{:?}", admitted);
+    }
+}
@@ -63,6 +63,10 @@ impl ChunkMap for IndexedChunkMap {
         self.map.set_chunk_ready(chunk.id())
     }
 
+    fn clear_ready(&self, chunk: &dyn BlobChunkInfo) -> Result<bool> {
+        self.map.clear_chunk_ready(chunk.id())
+    }
+
     fn is_persist(&self) -> bool {
         true
     }
@@ -217,6 +221,30 @@ mod tests {
         assert!(map.is_ready(chunk.as_base()).unwrap());
     }
 
+    #[test]
+    fn test_indexed_clear_ready() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-1");
+        let blob_path = blob_path.as_os_str().to_str().unwrap().to_string();
+
+        let chunk = MockChunkInfo::new();
+        let map = IndexedChunkMap::new(&blob_path, 1, true).unwrap();
+        assert!(!map.clear_ready(chunk.as_base()).unwrap());
+        map.set_ready_and_clear_pending(chunk.as_base()).unwrap();
+        assert!(map.is_range_all_ready());
+        assert!(map.is_ready(chunk.as_base()).unwrap());
+
+        assert!(map.clear_ready(chunk.as_base()).unwrap());
+        assert!(!map.is_range_all_ready());
+        assert!(!map.is_ready(chunk.as_base()).unwrap());
+        assert_eq!(map.map.not_ready_count.load(Ordering::Acquire), 1);
+        drop(map);
+
+        let map = IndexedChunkMap::new(&blob_path, 1, true).unwrap();
+        assert!(!map.is_range_all_ready());
+        assert!(!map.is_ready(chunk.as_base()).unwrap());
+    }
+
     #[test]
     fn test_indexed_new_header_not_ready() {
         let dir = TempDir::new().unwrap();
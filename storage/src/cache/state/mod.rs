@@ -91,6 +91,13 @@ pub trait ChunkMap: Any + Send + Sync {
         panic!("no support of clear_pending()");
     }
 
+    /// Clear the ready state of the chunk, so it will be fetched from the backend again.
+    ///
+    /// Return `Ok(true)` if the chunk was ready before.
+    fn clear_ready(&self, _chunk: &dyn BlobChunkInfo) -> Result<bool> {
+        Err(enosys!("no support of clear_ready()"))
+    }
+
     /// Check whether the implementation supports state persistence.
     fn is_persist(&self) -> bool {
         false
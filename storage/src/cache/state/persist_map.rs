@@ -246,6 +246,32 @@ impl PersistMap {
         Ok(())
     }
 
+    /// Clear the ready bit for the chunk at `index`, so it will be fetched again.
+    ///
+    /// Return true if the chunk was marked as ready before.
+    pub fn clear_chunk_ready(&self, index: u32) -> Result<bool> {
+        let index = self.validate_index(index)?;
+        let mask = Self::index_to_mask(index);
+        let start = HEADER_SIZE + (index as usize >> 3);
+        let atomic_value = self.filemap.get_ref::<AtomicU8>(start)?;
+
+        let prev = atomic_value.fetch_and(!mask, Ordering::AcqRel);
+        if prev & mask == 0 {
+            return Ok(false);
+        }
+
+        self.not_ready_count.fetch_add(1, Ordering::AcqRel);
+        // The `all_ready` flag lives at offset 12 of the header, reset it so the state will be
+        // recomputed from the bitmap next time the file gets opened.
+        let all_ready = self.filemap.get_ref::<AtomicU32>(12)?;
+        if all_ready.load(Ordering::Acquire) == MAGIC_ALL_READY {
+            all_ready.store(0, Ordering::Release);
+        }
+        self.filemap.sync_data()?;
+
+        Ok(true)
+    }
+
     fn mark_all_ready(&self) {
         if self.filemap.sync_data().is_ok() {
             /*
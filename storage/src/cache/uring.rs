@@ -0,0 +1,467 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! An io_uring based I/O engine for cache files.
+//!
+//! The `UringEngine` runs `tokio-uring` runtimes on a group of dedicated threads, each with its
+//! own io_uring instance. Requests from worker threads are distributed among those runtimes in
+//! round-robin order and each request is handled by a separate task, so requests issued
+//! concurrently get submitted to the kernel in batch.
+//!
+//! Data is transferred directly between cache files and buffers of the caller, which is blocked
+//! until the request has completed. O_DIRECT reads which are not aligned to the logical block
+//! size go through aligned bounce buffers, and data is copied into the caller's buffers from
+//! there.
+//!
+//! Worker threads of `service::block_nbd` run their own `tokio-uring` runtimes and read cache
+//! files on them directly, the engine is used to fetch and persist data into cache files for
+//! them, just like for FUSE worker threads.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{IoSliceMut, Result};
+use std::os::unix::fs::OpenOptionsExt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_uring::buf::{IoBuf, IoBufMut};
+
+use crate::utils::alloc_buf;
+
+const URING_MAX_THREADS: usize = 4;
+const DIRECT_IO_ALIGNMENT: u64 = 0x1000;
+
+// A memory buffer owned by the caller of `UringFile`, which is blocked until the request using
+// the buffer has completed.
+struct UserBuf {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl UserBuf {
+    fn new(buf: &mut [u8]) -> Self {
+        UserBuf {
+            ptr: buf.as_mut_ptr(),
+            len: buf.len(),
+        }
+    }
+
+    // The buffer must only be used as data source for write requests.
+    fn from_slice(buf: &[u8]) -> Self {
+        UserBuf {
+            ptr: buf.as_ptr() as *mut u8,
+            len: buf.len(),
+        }
+    }
+}
+
+// Safe because the caller keeps the buffer alive and doesn't access it until the request has
+// completed.
+unsafe impl Send for UserBuf {}
+
+unsafe impl IoBuf for UserBuf {
+    fn stable_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len
+    }
+
+    fn bytes_total(&self) -> usize {
+        self.len
+    }
+}
+
+unsafe impl IoBufMut for UserBuf {
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr
+    }
+
+    unsafe fn set_init(&mut self, _pos: usize) {}
+}
+
+enum UringRequest {
+    Register(u64, File, bool),
+    Unregister(u64),
+    Read(u64, u64, Vec<UserBuf>, mpsc::Sender<Result<()>>),
+    Write(u64, u64, UserBuf, mpsc::Sender<Result<()>>),
+}
+
+/// An io_uring based I/O engine shared by cache files of a cache manager.
+pub(crate) struct UringEngine {
+    next_id: AtomicU64,
+    next_ring: AtomicUsize,
+    senders: Vec<UnboundedSender<UringRequest>>,
+}
+
+impl UringEngine {
+    /// Create a new instance of `UringEngine` and start the io_uring worker threads.
+    pub fn new(name: &str) -> Result<Arc<Self>> {
+        let threads = thread::available_parallelism()
+            .map(|v| v.get())
+            .unwrap_or(1)
+            .min(URING_MAX_THREADS);
+        let mut senders = Vec::with_capacity(threads);
+        for idx in 0..threads {
+            let (sender, receiver) = unbounded_channel();
+            thread::Builder::new()
+                .name(format!("{}_uring{}", name, idx))
+                .spawn(move || Self::run(receiver))
+                .map_err(|e| eother!(format!("failed to create io_uring thread, {}", e)))?;
+            senders.push(sender);
+        }
+
+        Ok(Arc::new(UringEngine {
+            next_id: AtomicU64::new(1),
+            next_ring: AtomicUsize::new(0),
+            senders,
+        }))
+    }
+
+    /// Open the cache file at `path` for io_uring based I/O.
+    ///
+    /// If `direct_io` is true, read requests bypass the page cache with O_DIRECT. Write requests
+    /// always go through the page cache because chunk data may not be aligned.
+    pub fn open(self: &Arc<Self>, path: &str, direct_io: bool) -> Result<UringFile> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let direct_file = if direct_io {
+            let file = OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_DIRECT)
+                .open(path)?;
+            Some(file)
+        } else {
+            None
+        };
+
+        let id = self.register(file, false)?;
+        let direct_id = match direct_file {
+            None => None,
+            Some(file) => Some(self.register(file, true)?),
+        };
+
+        Ok(UringFile {
+            engine: self.clone(),
+            id,
+            direct_id,
+        })
+    }
+
+    // Register the file to all io_uring instances, so requests may be sent to any of them.
+    fn register(&self, file: File, direct: bool) -> Result<u64> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        for sender in self.senders.iter().skip(1) {
+            Self::send_to(
+                sender,
+                UringRequest::Register(id, file.try_clone()?, direct),
+            )?;
+        }
+        Self::send_to(&self.senders[0], UringRequest::Register(id, file, direct))?;
+        Ok(id)
+    }
+
+    fn unregister(&self, id: u64) {
+        for sender in self.senders.iter() {
+            let _ = Self::send_to(sender, UringRequest::Unregister(id));
+        }
+    }
+
+    fn send(&self, req: UringRequest) -> Result<()> {
+        let idx = self.next_ring.fetch_add(1, Ordering::Relaxed) % self.senders.len();
+        Self::send_to(&self.senders[idx], req)
+    }
+
+    fn send_to(sender: &UnboundedSender<UringRequest>, req: UringRequest) -> Result<()> {
+        sender
+            .send(req)
+            .map_err(|_| eio!("io_uring worker thread has exited"))
+    }
+
+    fn run(mut receiver: UnboundedReceiver<UringRequest>) {
+        tokio_uring::start(async move {
+            let mut files = HashMap::new();
+
+            // The loop only exits after all `UringFile` objects have been dropped, so no caller
+            // is waiting for in-flight requests when the runtime shuts down.
+            while let Some(req) = receiver.recv().await {
+                match req {
+                    UringRequest::Register(id, file, direct) => {
+                        let file = tokio_uring::fs::File::from_std(file);
+                        files.insert(id, (Rc::new(file), direct));
+                    }
+                    UringRequest::Unregister(id) => {
+                        files.remove(&id);
+                    }
+                    UringRequest::Read(id, offset, bufs, reply) => match files.get(&id) {
+                        None => {
+                            let _ = reply.send(Err(ebadf!("unknown io_uring file")));
+                        }
+                        Some((file, false)) => {
+                            let file = file.clone();
+                            tokio_uring::spawn(async move {
+                                let _ = reply.send(Self::readv_exact_at(&file, bufs, offset).await);
+                            });
+                        }
+                        Some((file, true)) => {
+                            let file = file.clone();
+                            tokio_uring::spawn(async move {
+                                let _ = reply.send(Self::read_direct(&file, bufs, offset).await);
+                            });
+                        }
+                    },
+                    UringRequest::Write(id, offset, buf, reply) => match files.get(&id) {
+                        None => {
+                            let _ = reply.send(Err(ebadf!("unknown io_uring file")));
+                        }
+                        Some((file, _)) => {
+                            let file = file.clone();
+                            tokio_uring::spawn(async move {
+                                let _ = reply.send(Self::write_all_at(&file, buf, offset).await);
+                            });
+                        }
+                    },
+                }
+            }
+        });
+    }
+
+    // Fill all buffers with data from the file at `offset`, retrying on short reads.
+    async fn readv_exact_at(
+        file: &tokio_uring::fs::File,
+        bufs: Vec<UserBuf>,
+        mut offset: u64,
+    ) -> Result<()> {
+        for mut buf in bufs {
+            let size = buf.len;
+            let mut pos = 0;
+            while pos < size {
+                let (res, slice) = file.read_at(buf.slice(pos..), offset + pos as u64).await;
+                buf = slice.into_inner();
+                match res? {
+                    0 => return Err(eio!("unexpected EOF when reading cache file")),
+                    count => pos += count,
+                }
+            }
+            offset += size as u64;
+        }
+
+        Ok(())
+    }
+
+    // O_DIRECT requires file offset and size aligned to the logical block size, so read the
+    // aligned range into an aligned buffer, then copy requested data into `bufs`.
+    async fn read_direct(
+        file: &tokio_uring::fs::File,
+        bufs: Vec<UserBuf>,
+        offset: u64,
+    ) -> Result<()> {
+        let size = bufs.iter().map(|v| v.len).sum::<usize>() as u64;
+        let start = offset & !(DIRECT_IO_ALIGNMENT - 1);
+        let end = (offset + size + DIRECT_IO_ALIGNMENT - 1) & !(DIRECT_IO_ALIGNMENT - 1);
+        let len = (end - start) as usize;
+        let skip = (offset - start) as usize;
+
+        // Short reads only happen at end of file, where the next read returns 0.
+        let mut data = alloc_buf(len);
+        let mut pos = 0;
+        while pos < len {
+            let (res, slice) = file.read_at(data.slice(pos..), start + pos as u64).await;
+            data = slice.into_inner();
+            match res? {
+                0 => break,
+                count => pos += count,
+            }
+        }
+        Self::copy_out(&data[..pos], skip, bufs)
+    }
+
+    fn copy_out(data: &[u8], skip: usize, bufs: Vec<UserBuf>) -> Result<()> {
+        let size = bufs.iter().map(|v| v.len).sum::<usize>();
+        if data.len() < skip + size {
+            return Err(eio!("unexpected EOF when reading cache file"));
+        }
+        let mut pos = skip;
+        for buf in bufs {
+            // Safe because the caller keeps the buffer alive until the request has completed.
+            let dst = unsafe { std::slice::from_raw_parts_mut(buf.ptr, buf.len) };
+            dst.copy_from_slice(&data[pos..pos + buf.len]);
+            pos += buf.len;
+        }
+
+        Ok(())
+    }
+
+    async fn write_all_at(file: &tokio_uring::fs::File, buf: UserBuf, offset: u64) -> Result<()> {
+        let size = buf.len;
+        let mut buf = buf;
+        let mut pos = 0;
+        while pos < size {
+            let (res, slice) = file.write_at(buf.slice(pos..), offset + pos as u64).await;
+            buf = slice.into_inner();
+            match res? {
+                0 => return Err(eio!("failed to write data to cache file")),
+                count => pos += count,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A cache file opened by [UringEngine].
+pub(crate) struct UringFile {
+    engine: Arc<UringEngine>,
+    id: u64,
+    direct_id: Option<u64>,
+}
+
+impl UringFile {
+    /// Read exactly `buf.len()` bytes from the file at `offset`.
+    pub fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        self.read(vec![UserBuf::new(buf)], offset)
+    }
+
+    /// Fill all buffers in `bufs` with data from the file at `offset`, and return total size.
+    pub fn readv_exact_at(&self, bufs: &mut [IoSliceMut], offset: u64) -> Result<usize> {
+        let size = bufs.iter().map(|v| v.len()).sum();
+        let bufs = bufs.iter_mut().map(|v| UserBuf::new(v)).collect();
+        self.read(bufs, offset)?;
+        Ok(size)
+    }
+
+    /// Write all data in `buf` into the file at `offset`.
+    pub fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        self.engine.send(UringRequest::Write(
+            self.id,
+            offset,
+            UserBuf::from_slice(buf),
+            sender,
+        ))?;
+        Self::wait(receiver)
+    }
+
+    fn read(&self, bufs: Vec<UserBuf>, offset: u64) -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        let id = self.direct_id.unwrap_or(self.id);
+        self.engine
+            .send(UringRequest::Read(id, offset, bufs, sender))?;
+        Self::wait(receiver)
+    }
+
+    // Callers may be threads of a tokio runtime, such as `tokio-uring` runtimes of NBD workers,
+    // so use std channels instead of tokio channels, which panic when blocking a runtime thread.
+    fn wait(receiver: mpsc::Receiver<Result<()>>) -> Result<()> {
+        receiver
+            .recv()
+            .map_err(|_| eio!("io_uring worker thread has exited"))?
+    }
+}
+
+impl Drop for UringFile {
+    fn drop(&mut self) {
+        self.engine.unregister(self.id);
+        if let Some(id) = self.direct_id {
+            self.engine.unregister(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vmm_sys_util::tempdir::TempDir;
+
+    fn test_data(size: usize) -> Vec<u8> {
+        (0..size).map(|v| (v % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_uring_file_read_write() {
+        let engine = UringEngine::new("test_uring_file_read_write").unwrap();
+        let tmpdir = TempDir::new().unwrap();
+        let path = tmpdir.as_path().join("cache");
+        std::fs::write(&path, b"").unwrap();
+        let file = engine.open(path.to_str().unwrap(), false).unwrap();
+
+        let data = test_data(0x3000);
+        file.write_all_at(&data, 0x100).unwrap();
+        let mut buf = vec![0u8; 0x3000];
+        file.read_exact_at(&mut buf, 0x100).unwrap();
+        assert_eq!(buf, data);
+
+        let (mut buf1, mut buf2) = (vec![0u8; 0x10], vec![0u8; 0x2000]);
+        let mut bufs = [IoSliceMut::new(&mut buf1), IoSliceMut::new(&mut buf2)];
+        assert_eq!(file.readv_exact_at(&mut bufs, 0x110).unwrap(), 0x2010);
+        assert_eq!(buf1, &data[0x10..0x20]);
+        assert_eq!(buf2, &data[0x20..0x2020]);
+
+        // Reading beyond end of file fails instead of returning partial data.
+        let mut buf = vec![0u8; 0x10];
+        assert!(file.read_exact_at(&mut buf, 0x30f8).is_err());
+        file.read_exact_at(&mut buf[..8], 0x30f8).unwrap();
+        assert_eq!(&buf[..8], &data[0x2ff8..]);
+    }
+
+    #[test]
+    fn test_uring_file_direct_io() {
+        let engine = UringEngine::new("test_uring_file_direct_io").unwrap();
+        let tmpdir = TempDir::new_in(std::path::Path::new(env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let path = tmpdir.as_path().join("cache");
+        let data = test_data(0x2_1003);
+        std::fs::write(&path, &data).unwrap();
+        // Some filesystems, such as tmpfs, don't support O_DIRECT.
+        let file = match engine.open(path.to_str().unwrap(), true) {
+            Ok(v) => v,
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => return,
+            Err(e) => panic!("failed to open cache file, {}", e),
+        };
+
+        // Unaligned ranges go through aligned bounce buffers.
+        let mut buf = vec![0u8; 0x1801];
+        file.read_exact_at(&mut buf, 0x123).unwrap();
+        assert_eq!(buf, &data[0x123..0x1924]);
+        let mut buf = vec![0u8; 0x1_0010];
+        file.read_exact_at(&mut buf, 0x10).unwrap();
+        assert_eq!(buf, &data[0x10..0x1_0020]);
+        // Read up to the unaligned end of file.
+        let mut buf = vec![0u8; 0x1003];
+        file.read_exact_at(&mut buf, 0x2_0000).unwrap();
+        assert_eq!(buf, &data[0x2_0000..]);
+        let mut buf = vec![0u8; 0x1004];
+        assert!(file.read_exact_at(&mut buf, 0x2_0000).is_err());
+    }
+
+    #[test]
+    fn test_uring_engine_concurrent_requests() {
+        let engine = UringEngine::new("test_uring_engine_concurrent").unwrap();
+        let tmpdir = TempDir::new().unwrap();
+        let path = tmpdir.as_path().join("cache");
+        std::fs::write(&path, b"").unwrap();
+        let file = Arc::new(engine.open(path.to_str().unwrap(), false).unwrap());
+
+        let threads: Vec<_> = (0..8u64)
+            .map(|idx| {
+                let file = file.clone();
+                thread::spawn(move || {
+                    let data = vec![idx as u8; 0x1000];
+                    for round in 0..16u64 {
+                        let offset = (round * 8 + idx) * 0x1000;
+                        file.write_all_at(&data, offset).unwrap();
+                        let mut buf = vec![0u8; 0x1000];
+                        file.read_exact_at(&mut buf, offset).unwrap();
+                        assert_eq!(buf, data);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
@@ -36,7 +36,7 @@ use fuse_backend_rs::api::filesystem::ZeroCopyWriter;
 use fuse_backend_rs::file_buf::FileVolatileSlice;
 use fuse_backend_rs::file_traits::FileReadWriteVolatile;
 
-use nydus_api::ConfigV2;
+use nydus_api::{BackendConfigV2, ConfigV2};
 use nydus_utils::compress;
 use nydus_utils::digest::{self, RafsDigest};
 
@@ -1000,6 +1000,7 @@ pub trait BlobObject: AsRawFd {
 pub struct BlobDevice {
     blobs: Arc<ArcSwap<Vec<Arc<dyn BlobCache>>>>,
     blob_count: usize,
+    config: Arc<ArcSwap<ConfigV2>>,
 }
 
 impl BlobDevice {
@@ -1014,6 +1015,7 @@ impl BlobDevice {
         Ok(BlobDevice {
             blobs: Arc::new(ArcSwap::new(Arc::new(blobs))),
             blob_count: blob_infos.len(),
+            config: Arc::new(ArcSwap::new(config.clone())),
         })
     }
 
@@ -1046,6 +1048,7 @@ impl BlobDevice {
             self.stop_prefetch();
         }
         self.blobs.store(Arc::new(blobs));
+        self.config.store(config.clone());
         if fs_prefetch {
             self.start_prefetch();
         }
@@ -1053,6 +1056,23 @@ impl BlobDevice {
         Ok(())
     }
 
+    /// Update credentials, proxy and mirrors of storage backends used by the blob device.
+    ///
+    /// Return the updated configuration of the blob device.
+    pub fn update_backend_config(
+        &self,
+        backend_cfg: &BackendConfigV2,
+    ) -> io::Result<Arc<ConfigV2>> {
+        let config = self.config.load_full();
+        if self.blob_count == 0 {
+            return Ok(config);
+        }
+        let config = BLOB_FACTORY.update_backend_config(&config, backend_cfg)?;
+        self.config.store(config.clone());
+
+        Ok(config)
+    }
+
     /// Close the blob device.
     pub fn close(&self) -> io::Result<()> {
         Ok(())
@@ -1179,6 +1199,28 @@ impl BlobDevice {
         true
     }
 
+    /// Get the cache file containing data of the blob io vectors, and offset of the data in it.
+    ///
+    /// The data must be ready and stored contiguously as uncompressed bytes in a single cache
+    /// file, otherwise `None` is returned and the data should be read by `read_to()`.
+    pub fn get_backing_file(&self, io_vecs: &[BlobIoVec]) -> Option<(Arc<File>, u64)> {
+        if io_vecs.len() != 1 || !self.all_chunks_ready(io_vecs) {
+            return None;
+        }
+        let file = self.get_blob_by_iovec(&io_vecs[0])?.get_backing_file()?;
+        let descs = &io_vecs[0].bi_vec;
+        let start = descs.first()?.chunkinfo.uncompressed_offset() + descs[0].offset as u64;
+        let mut end = start;
+        for desc in descs.iter() {
+            if desc.chunkinfo.uncompressed_offset() + desc.offset as u64 != end {
+                return None;
+            }
+            end += desc.size as u64;
+        }
+
+        Some((file, start))
+    }
+
     /// RAFS V6: create a `BlobIoChunk` for chunk with index `chunk_index`.
     pub fn create_io_chunk(&self, blob_index: u32, chunk_index: u32) -> Option<BlobIoChunk> {
         if (blob_index as usize) < self.blob_count {
@@ -34,7 +34,9 @@ use crate::backend::registry;
 #[cfg(feature = "backend-s3")]
 use crate::backend::s3;
 use crate::backend::BlobBackend;
-use crate::cache::{BlobCache, BlobCacheMgr, DummyCacheMgr, FileCacheMgr};
+use crate::cache::{
+    BlobCache, BlobCacheMgr, BlobScrubResult, DummyCacheMgr, FileCacheMgr, OndemandLimiter,
+};
 use crate::device::BlobInfo;
 
 lazy_static! {
@@ -126,9 +128,17 @@ impl BlobFactory {
             return mgr.get_blob_cache(blob_info);
         }
         let backend = Self::new_backend(backend_cfg, &blob_info.blob_id())?;
+        let host = Self::backend_host(backend_cfg);
+        let limiter = OndemandLimiter::new(&cache_cfg.qos, host.as_deref())?;
         let mgr = match cache_cfg.cache_type.as_str() {
             "blobcache" | "filecache" => {
-                let mgr = FileCacheMgr::new(cache_cfg, backend, ASYNC_RUNTIME.clone(), &config.id)?;
+                let mgr = FileCacheMgr::new(
+                    cache_cfg,
+                    backend,
+                    ASYNC_RUNTIME.clone(),
+                    &config.id,
+                    limiter,
+                )?;
                 mgr.init()?;
                 Arc::new(mgr) as Arc<dyn BlobCacheMgr>
             }
@@ -139,12 +149,13 @@ impl BlobFactory {
                     backend,
                     ASYNC_RUNTIME.clone(),
                     &config.id,
+                    limiter,
                 )?;
                 mgr.init()?;
                 Arc::new(mgr) as Arc<dyn BlobCacheMgr>
             }
             _ => {
-                let mgr = DummyCacheMgr::new(cache_cfg, backend, false)?;
+                let mgr = DummyCacheMgr::new(cache_cfg, backend, false, limiter)?;
                 mgr.init()?;
                 Arc::new(mgr) as Arc<dyn BlobCacheMgr>
             }
@@ -190,6 +201,63 @@ impl BlobFactory {
         }
     }
 
+    /// Scrub cached data of the blob with id `blob_id`, or all cached blobs if `blob_id` is None.
+    ///
+    /// Cached chunks whose data doesn't match the chunk digest will be marked as not ready, so
+    /// they will be fetched from the storage backend again on next access.
+    pub fn scrub(&self, blob_id: Option<&str>) -> IOResult<Vec<BlobScrubResult>> {
+        let mgrs: Vec<Arc<dyn BlobCacheMgr>> =
+            self.mgrs.lock().unwrap().values().cloned().collect();
+        let mut results = Vec::new();
+        for mgr in mgrs {
+            results.append(&mut mgr.scrub(blob_id)?);
+        }
+
+        Ok(results)
+    }
+
+    /// Update credentials, proxy and mirrors of the storage backend associated with `config`.
+    ///
+    /// The storage backend object is shared by all blob cache objects created with the same
+    /// configuration, so all of them will use the new configuration. On success, the updated
+    /// configuration is returned and it should be used to replace `config` by the caller.
+    pub fn update_backend_config(
+        &self,
+        config: &Arc<ConfigV2>,
+        backend_cfg: &BackendConfigV2,
+    ) -> IOResult<Arc<ConfigV2>> {
+        let old_cfg = config.get_backend_config()?;
+        if old_cfg.backend_type != backend_cfg.backend_type {
+            return Err(einval!(format!(
+                "can't change storage backend type from '{}' to '{}'",
+                old_cfg.backend_type, backend_cfg.backend_type
+            )));
+        }
+        let key = BlobCacheMgrKey {
+            config: config.clone(),
+        };
+        let mut guard = self.mgrs.lock().unwrap();
+        let mgr = guard
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| enoent!("no blob cache manager for the configuration"))?;
+        mgr.backend().update_config(backend_cfg)?;
+
+        let mut new_cfg = ConfigV2::clone(config);
+        new_cfg.backend = Some(backend_cfg.clone());
+        let new_cfg = Arc::new(new_cfg);
+        // Keep the entry for the old configuration, so users which haven't switched to the new
+        // configuration yet still share the same blob cache manager.
+        guard.insert(
+            BlobCacheMgrKey {
+                config: new_cfg.clone(),
+            },
+            mgr,
+        );
+
+        Ok(new_cfg)
+    }
+
     /// Create a storage backend for the blob with id `blob_id`.
     #[allow(unused_variables)]
     pub fn new_backend(
@@ -234,6 +302,23 @@ impl BlobFactory {
         }
     }
 
+    // Get the remote host of the storage backend, which is the scope of per-host QoS.
+    fn backend_host(config: &BackendConfigV2) -> Option<String> {
+        match config.backend_type.as_str() {
+            "oss" => config.oss.as_ref().map(|c| c.endpoint.clone()),
+            "s3" => config.s3.as_ref().map(|c| {
+                if c.endpoint.is_empty() {
+                    "s3.amazonaws.com".to_string()
+                } else {
+                    c.endpoint.clone()
+                }
+            }),
+            "registry" => config.registry.as_ref().map(|c| c.host.clone()),
+            "http-proxy" => config.http_proxy.as_ref().map(|c| c.addr.clone()),
+            _ => None,
+        }
+    }
+
     fn check_cache_stat(&self) {
         let mgrs = self.mgrs.lock().unwrap();
         for (_key, mgr) in mgrs.iter() {
@@ -112,17 +112,15 @@ impl<R: Read> ZranContextGenerator<R> {
     /// Create a new instance of [ZranContextGenerator].
     pub fn new(file: R) -> Result<Self> {
         let reader = ZranReader::new(file)?;
-        let mut generator = ZranGenerator::new(reader.clone());
-
-        generator.set_min_compressed_size(RAFS_DEFAULT_CHUNK_SIZE / 2);
-        generator.set_max_compressed_size(RAFS_DEFAULT_CHUNK_SIZE);
-        generator.set_max_uncompressed_size(RAFS_DEFAULT_CHUNK_SIZE * 2);
-
-        Ok(Self {
+        let generator = ZranGenerator::new(reader.clone());
+        let mut zran = Self {
             generator,
             reader,
             uncomp_pos: 0,
-        })
+        };
+        zran.set_checkpoint_span(RAFS_DEFAULT_CHUNK_SIZE);
+
+        Ok(zran)
     }
 
     /// Create a new instance of [ZranContextGenerator] from a `BufReader`.
@@ -133,16 +131,25 @@ impl<R: Read> ZranContextGenerator<R> {
         let reader = ZranReader::new(file)?;
         reader.set_initial_data(&buf);
 
-        let mut generator = ZranGenerator::new(reader.clone());
-        generator.set_min_compressed_size(RAFS_DEFAULT_CHUNK_SIZE / 2);
-        generator.set_max_compressed_size(RAFS_DEFAULT_CHUNK_SIZE);
-        generator.set_max_uncompressed_size(RAFS_DEFAULT_CHUNK_SIZE * 2);
-
-        Ok(Self {
+        let generator = ZranGenerator::new(reader.clone());
+        let mut zran = Self {
             generator,
             reader,
             uncomp_pos: 0,
-        })
+        };
+        zran.set_checkpoint_span(RAFS_DEFAULT_CHUNK_SIZE);
+
+        Ok(zran)
+    }
+
+    /// Set spacing of compressed data between random access checkpoints.
+    ///
+    /// Each checkpoint saves an inflate dictionary of up to 32KB, so bigger spacing generates
+    /// smaller zran tables but causes more read amplification when randomly accessing data.
+    pub fn set_checkpoint_span(&mut self, span: u64) {
+        self.generator.set_min_compressed_size(span / 2);
+        self.generator.set_max_compressed_size(span);
+        self.generator.set_max_uncompressed_size(span * 2);
     }
 
     /// Get reader to read decompressed data.
@@ -107,3 +107,131 @@ impl BlobV5ChunkInfo for MockChunkInfo {
     impl_getter!(file_offset, file_offset, u64);
     impl_getter!(flags, flags, BlobChunkFlags);
 }
+
+#[cfg(any(
+    feature = "backend-oss",
+    feature = "backend-registry",
+    feature = "backend-s3",
+    feature = "backend-http-proxy",
+))]
+/// Response of [MockHttpServer].
+pub(crate) struct MockHttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[cfg(any(
+    feature = "backend-oss",
+    feature = "backend-registry",
+    feature = "backend-s3",
+    feature = "backend-http-proxy",
+))]
+impl MockHttpResponse {
+    pub fn new(status: u16, body: &[u8]) -> Self {
+        MockHttpResponse {
+            status,
+            headers: Vec::new(),
+            body: body.to_vec(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+#[cfg(any(
+    feature = "backend-oss",
+    feature = "backend-registry",
+    feature = "backend-s3",
+    feature = "backend-http-proxy",
+))]
+/// HTTP server to test storage backends, which answers each request by calling `handler` with
+/// the request line and headers, and records all requests received.
+pub(crate) struct MockHttpServer {
+    pub url: String,
+    requests: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[cfg(any(
+    feature = "backend-oss",
+    feature = "backend-registry",
+    feature = "backend-s3",
+    feature = "backend-http-proxy",
+))]
+impl MockHttpServer {
+    pub fn new<F>(handler: F) -> Self
+    where
+        F: Fn(&str) -> MockHttpResponse + Send + Sync + 'static,
+    {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let handler = Arc::new(handler);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => break,
+                };
+                let recorded = recorded.clone();
+                let handler = handler.clone();
+                std::thread::spawn(move || {
+                    let mut req = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let req = String::from_utf8_lossy(&req).to_string();
+                    let resp = handler(&req);
+                    recorded.lock().unwrap().push(req);
+
+                    let mut head = format!(
+                        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        resp.status,
+                        resp.body.len()
+                    );
+                    for (name, value) in resp.headers.iter() {
+                        head.push_str(&format!("{}: {}\r\n", name, value));
+                    }
+                    head.push_str("\r\n");
+                    let _ = stream.write_all(head.as_bytes());
+                    let _ = stream.write_all(&resp.body);
+                });
+            }
+        });
+
+        MockHttpServer { url, requests }
+    }
+
+    /// Get request lines and headers of all requests received.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(any(
+    feature = "backend-oss",
+    feature = "backend-registry",
+    feature = "backend-s3",
+    feature = "backend-http-proxy",
+))]
+/// Get value of header `name` from a HTTP request head.
+pub(crate) fn http_header<'a>(req: &'a str, name: &str) -> Option<&'a str> {
+    req.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim().eq_ignore_ascii_case(name) {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
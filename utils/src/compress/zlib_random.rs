@@ -28,6 +28,8 @@ pub const ZRAN_READER_BUF_SIZE: usize = 64 * 1024;
 const ZRAN_MIN_COMP_SIZE: u64 = 768 * 1024;
 const ZRAN_MAX_COMP_SIZE: u64 = 2048 * 1024;
 const ZRAN_MAX_UNCOMP_SIZE: u64 = 2048 * 1024;
+const GZIP_HEADER_SIZE: usize = 10;
+const GZIP_TRAILER_SIZE: usize = 8;
 const ZLIB_ALIGN: usize = std::mem::align_of::<usize>();
 
 /// Information to retrieve a data chunk from an associated random access slice.
@@ -120,21 +122,90 @@ impl ZranDecoder {
         self.stream.set_next_in(input);
         self.stream.set_next_out(output);
         self.stream.set_avail_out(ctx.out_len as uInt);
-        let ret = self.stream.inflate(true);
-        match ret {
-            Z_OK => {
-                let count = self.stream.next_out() as usize - output.as_ptr() as usize;
-                if count != ctx.out_len as usize {
-                    Err(eio!("failed to decode data from stream, size mismatch"))
-                } else {
-                    Ok(count)
+        loop {
+            let ret = self.stream.inflate(true);
+            let count = self.stream.next_out() as usize - output.as_ptr() as usize;
+            match ret {
+                Z_OK | Z_STREAM_END if count == ctx.out_len as usize => return Ok(count),
+                Z_OK => return Err(eio!("failed to decode data from stream, size mismatch")),
+                Z_STREAM_END => {
+                    // The slice spans multiple gzip members, skip the trailer of current member
+                    // and the header of next member, then decode the next member from scratch.
+                    let pos = self.stream.next_in() as usize - input.as_ptr() as usize
+                        + GZIP_TRAILER_SIZE;
+                    if pos >= input.len() {
+                        return Err(eio!("failed to decode data from stream, size mismatch"));
+                    }
+                    let header = GzipHeader::parse(&input[pos..])?;
+                    self.stream.reset()?;
+                    self.stream.set_next_in(&input[pos + header.size..]);
                 }
+                _ => return Err(eio!("failed to decode data from compressed data stream")),
             }
-            _ => Err(eio!("failed to decode data from compressed data stream")),
         }
     }
 }
 
+/// Information about a gzip member header.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct GzipHeader {
+    /// Size of the header.
+    size: usize,
+    /// Whether it's a BGZF (blocked gzip) member, which has a `BC` extra subfield.
+    bgzf: bool,
+}
+
+impl GzipHeader {
+    /// Parse a gzip member header at the start of `buf`.
+    fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < GZIP_HEADER_SIZE || buf[0] != 0x1f || buf[1] != 0x8b || buf[2] != 0x08 {
+            return Err(einval!("invalid gzip member header"));
+        }
+        let flags = buf[3];
+        let mut pos = GZIP_HEADER_SIZE;
+        let mut bgzf = false;
+
+        // FEXTRA
+        if flags & 0x04 != 0 {
+            if buf.len() < pos + 2 {
+                return Err(einval!("invalid gzip member header"));
+            }
+            let xlen = u16::from_le_bytes([buf[pos], buf[pos + 1]]) as usize;
+            pos += 2;
+            if buf.len() < pos + xlen {
+                return Err(einval!("invalid gzip member header"));
+            }
+            let mut extra = &buf[pos..pos + xlen];
+            while extra.len() >= 4 {
+                let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+                if extra[0] == b'B' && extra[1] == b'C' && len == 2 {
+                    bgzf = true;
+                }
+                extra = &extra[std::cmp::min(4 + len, extra.len())..];
+            }
+            pos += xlen;
+        }
+        // FNAME and FCOMMENT, both are zero terminated.
+        for flag in [0x08u8, 0x10u8] {
+            if flags & flag != 0 {
+                match buf[pos..].iter().position(|v| *v == 0) {
+                    Some(idx) => pos += idx + 1,
+                    None => return Err(einval!("invalid gzip member header")),
+                }
+            }
+        }
+        // FHCRC
+        if flags & 0x02 != 0 {
+            pos += 2;
+        }
+        if pos > buf.len() {
+            return Err(einval!("invalid gzip member header"));
+        }
+
+        Ok(GzipHeader { size: pos, bgzf })
+    }
+}
+
 /// Struct to generate random access information for OCIv1 image tarballs.
 ///
 /// `ZranGenerator` generates decompression context information to support random access to the
@@ -184,6 +255,7 @@ impl<R: Read> ZranGenerator<R> {
     #[allow(clippy::if_same_then_else)]
     pub fn begin_read(&mut self, chunk_size: u64) -> Result<u32> {
         let info = self.reader.get_current_ctx_info();
+        self.reader.clear_stream_switched();
         let ci_idx = if let Some(idx) = self.curr_ci_idx {
             let ctx = &self.ci_array[idx];
             let comp_size = info.in_pos - ctx.in_offset;
@@ -192,8 +264,16 @@ impl<R: Read> ZranGenerator<R> {
             let enough = !first
                 && (comp_size >= self.max_comp_size / 2
                     || uncomp_size + chunk_size >= self.max_uncomp_size);
-            if info.stream_switched != 0 || enough {
-                // The slice becomes too big after merging current data chunk.
+            if info.stream_switched != 0 && !first && (enough || comp_size >= self.min_comp_size) {
+                // A new gzip member has been started, which is a checkpoint without dictionary.
+                self.new_ci_entry()?
+            } else if enough
+                && !(self.reader.is_bgzf()
+                    && comp_size < self.max_comp_size
+                    && uncomp_size + chunk_size < 2 * self.max_uncomp_size)
+            {
+                // The slice becomes too big after merging current data chunk. For BGZF streams,
+                // prefer to split slices at member boundaries to avoid saving dictionaries.
                 self.new_ci_entry()?
             } else if !first
                 && comp_size > 2 * ctx.in_len as u64
@@ -327,11 +407,21 @@ impl<R> ZranReader<R> {
         self.inner.lock().unwrap().reader_hash.clone()
     }
 
+    /// Check whether the compressed stream is in BGZF (blocked gzip) format.
+    pub fn is_bgzf(&self) -> bool {
+        self.inner.lock().unwrap().bgzf == Some(true)
+    }
+
     /// Get inflate context information for current inflate position.
     fn get_current_ctx_info(&self) -> ZranCompInfo {
         self.inner.lock().unwrap().get_compression_info()
     }
 
+    /// Clear the flag indicating that a new gzip member has been started.
+    fn clear_stream_switched(&self) {
+        self.inner.lock().unwrap().stream_switched = 0;
+    }
+
     /// Get inflate context information for current inflate block.
     fn get_block_ctx_info(&self) -> ZranCompInfo {
         self.inner.lock().unwrap().block_ctx_info
@@ -378,6 +468,12 @@ struct ZranReaderState<R> {
     block_ctx_dict: Vec<u8>,
     block_ctx_dict_size: usize,
     stream_switched: u8,
+    // Whether the stream is in BGZF format, detected from header of the first gzip member.
+    bgzf: Option<bool>,
+    // Whether next input data should be checked for a new gzip member after the previous one ends.
+    member_ended: bool,
+    // Whether the end of the compressed stream has been reached.
+    finished: bool,
 }
 
 impl<R> ZranReaderState<R> {
@@ -396,15 +492,16 @@ impl<R> ZranReaderState<R> {
             block_ctx_dict: vec![0u8; ZRAN_DICT_WIN_SIZE],
             block_ctx_dict_size: 0,
             stream_switched: 0,
+            bgzf: None,
+            member_ended: false,
+            finished: false,
         })
     }
 
     /// Get decompression information about the stream.
     fn get_compression_info(&mut self) -> ZranCompInfo {
-        let stream_switched = self.stream_switched;
-        self.stream_switched = 0;
         self.stream
-            .get_compression_info(&self.input, stream_switched)
+            .get_compression_info(&self.input, self.stream_switched)
     }
 
     fn get_compression_dict(&mut self) -> Result<()> {
@@ -413,8 +510,28 @@ impl<R> ZranReaderState<R> {
     }
 }
 
+impl<R: Read> ZranReaderState<R> {
+    // Consume all remaining data, which is needed to compute digest of the whole stream.
+    fn drain(&mut self) -> Result<()> {
+        loop {
+            let sz = self.reader.read(self.input.as_mut_slice())?;
+            if sz == 0 {
+                break;
+            }
+            self.reader_hash.update(&self.input[0..sz]);
+            self.reader_size += sz as u64;
+        }
+        self.stream.set_next_in(&self.input[..0]);
+        self.finished = true;
+        Ok(())
+    }
+}
+
 impl<R: Read> Read for ZranReaderState<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.finished {
+            return Ok(0);
+        }
         self.stream.set_next_out(buf);
         self.stream.set_avail_out(buf.len() as u32);
 
@@ -426,23 +543,41 @@ impl<R: Read> Read for ZranReaderState<R> {
                 }
                 let sz = self.reader.read(self.input.as_mut_slice())?;
                 if sz == 0 {
-                    return Ok(0);
+                    // Return data decoded from the last gzip member.
+                    return Ok(self.stream.next_out() as usize - buf.as_ptr() as usize);
                 }
                 self.reader_hash.update(&self.input[0..sz]);
                 self.reader_size += sz as u64;
                 self.stream.set_next_in(&self.input[..sz]);
             }
+            if self.bgzf.is_none() {
+                let avail_in = self.stream.avail_in() as usize;
+                let header = GzipHeader::parse(&self.input[..avail_in]).unwrap_or_default();
+                self.bgzf = Some(header.bgzf);
+            }
+            if self.member_ended {
+                // Some tools append zero paddings or other data after the last gzip member,
+                // ignore them as gzip does.
+                if unsafe { *self.stream.next_in() } != 0x1f {
+                    self.drain()?;
+                    return Ok(self.stream.next_out() as usize - buf.as_ptr() as usize);
+                }
+                self.member_ended = false;
+            }
 
             match self.stream.inflate(false) {
                 Z_STREAM_END => {
                     self.stream.reset()?;
                     self.stream_switched = 1;
+                    self.member_ended = true;
                     continue;
                 }
                 Z_OK => {
                     let count = self.stream.next_out() as usize - buf.as_ptr() as usize;
                     let info = self.get_compression_info();
-                    if info.flags & 0x80 != 0 {
+                    // Ignore the end of the last deflate block, it's followed by the gzip trailer
+                    // instead of a deflate block.
+                    if info.flags & 0x80 != 0 && info.flags & 0x40 == 0 {
                         self.get_compression_dict()?;
                         self.block_ctx_info = info;
                     }
@@ -614,6 +749,10 @@ impl ZranStream {
         self.set_avail_in(buf.len() as u32);
     }
 
+    fn next_in(&self) -> *mut u8 {
+        self.stream.next_in
+    }
+
     fn avail_in(&self) -> u32 {
         self.stream.avail_in
     }
@@ -704,11 +843,133 @@ extern "system" {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use flate2::{Compression, GzBuilder};
     use std::fs::OpenOptions;
-    use std::io::{Seek, SeekFrom};
+    use std::io::{Cursor, Seek, SeekFrom, Write};
     use std::path::PathBuf;
     use tar::{Archive, EntryType};
 
+    fn generate_data(size: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(size + 16);
+        let mut idx = 0;
+        while data.len() < size {
+            data.extend_from_slice(format!("{:08}\n", idx * 7919 % 100000).as_bytes());
+            idx += 1;
+        }
+        data.truncate(size);
+        data
+    }
+
+    fn compress_members(data: &[u8], member_size: usize, bgzf: bool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for part in data.chunks(member_size) {
+            let builder = if bgzf {
+                GzBuilder::new().extra(vec![b'B', b'C', 2, 0, 0, 0])
+            } else {
+                GzBuilder::new().filename("member")
+            };
+            let mut encoder = builder.write(Vec::new(), Compression::default());
+            encoder.write_all(part).unwrap();
+            buf.extend_from_slice(&encoder.finish().unwrap());
+        }
+        buf
+    }
+
+    // Read data in chunks with the generator, then decode all chunks with the generated context.
+    fn generate_and_decode(
+        data: &[u8],
+        gz: &[u8],
+        generator: &mut ZranGenerator<Cursor<Vec<u8>>>,
+    ) -> Vec<ZranChunkInfo> {
+        // Skip the first block as the tar header does.
+        let mut buf = vec![0u8; 512];
+        generator.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[..512]);
+
+        let mut chunks = Vec::new();
+        let mut pos = 512;
+        while pos < data.len() {
+            let size = std::cmp::min(4096, data.len() - pos);
+            generator.begin_read(size as u64).unwrap();
+            let mut buf = vec![0u8; size];
+            generator.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, data[pos..pos + size]);
+            chunks.push((generator.end_read().unwrap(), pos));
+            pos += size;
+        }
+        assert_eq!(generator.read(&mut buf).unwrap(), 0);
+
+        let ctx_array = generator.get_compression_ctx_array();
+        let mut decoder = ZranDecoder::new().unwrap();
+        for (info, pos) in chunks.iter() {
+            let ctx = &ctx_array[info.ci_index as usize];
+            let start = ctx.in_offset as usize;
+            let input = &gz[start..start + ctx.in_len as usize];
+            let mut output = vec![0u8; ctx.out_len as usize];
+            decoder.uncompress(ctx, None, input, &mut output).unwrap();
+            let offset = info.ci_offset as usize;
+            let len = info.ci_len as usize;
+            assert_eq!(output[offset..offset + len], data[*pos..*pos + len]);
+        }
+
+        chunks.into_iter().map(|(info, _)| info).collect()
+    }
+
+    #[test]
+    fn test_parse_gzip_header() {
+        let gz = compress_members(b"data", 4, false);
+        let header = GzipHeader::parse(&gz).unwrap();
+        assert_eq!(header.size, GZIP_HEADER_SIZE + "member".len() + 1);
+        assert!(!header.bgzf);
+
+        let gz = compress_members(b"data", 4, true);
+        let header = GzipHeader::parse(&gz).unwrap();
+        assert_eq!(header.size, GZIP_HEADER_SIZE + 8);
+        assert!(header.bgzf);
+
+        assert!(GzipHeader::parse(&gz[..GZIP_HEADER_SIZE + 4]).is_err());
+        assert!(GzipHeader::parse(&[0u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_zran_multiple_gzip_members() {
+        let data = generate_data(0x30000);
+        let mut gz = compress_members(&data, 0x9000, false);
+        let compressed_size = gz.len();
+        // Zero paddings after the last gzip member should be ignored.
+        gz.extend_from_slice(&[0u8; 1024]);
+
+        let reader = ZranReader::new(Cursor::new(gz.clone())).unwrap();
+        assert!(!reader.is_bgzf());
+        let mut generator = ZranGenerator::new(reader.clone());
+        let chunks = generate_and_decode(&data, &gz[..compressed_size], &mut generator);
+        assert_eq!(reader.get_data_size(), gz.len() as u64);
+
+        // Slices span gzip members with the default checkpoint spacing.
+        let ctx_array = generator.get_compression_ctx_array();
+        assert_eq!(ctx_array.len(), 1);
+        assert_eq!(chunks.last().unwrap().ci_index, 0);
+    }
+
+    #[test]
+    fn test_zran_bgzf_members() {
+        let data = generate_data(0x20000);
+        let gz = compress_members(&data, 0x4000, true);
+
+        let reader = ZranReader::new(Cursor::new(gz.clone())).unwrap();
+        let mut generator = ZranGenerator::new(reader.clone());
+        generator.set_min_compressed_size(1024);
+        generator.set_max_compressed_size(1 << 20);
+        generator.set_max_uncompressed_size(1 << 20);
+        generate_and_decode(&data, &gz, &mut generator);
+        assert!(reader.is_bgzf());
+
+        // Slices start at member boundaries, so no dictionary is needed.
+        let ctx_array = generator.get_compression_ctx_array();
+        assert!(ctx_array.len() > 1);
+        assert!(ctx_array.iter().all(|ctx| ctx.dict.is_empty()));
+    }
+
     #[test]
     fn test_parse_single_gzip_object() {
         let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
@@ -31,6 +31,7 @@ pub mod mpmc;
 pub mod reader;
 pub mod trace;
 pub mod types;
+pub mod verity;
 
 /// Round up and divide the value `n` by `d`.
 pub fn div_round_up(n: u64, d: u64) -> u64 {
@@ -626,6 +626,20 @@ pub struct BackendMetrics {
     read_count_block_size_dist: [BasicMetric; BLOCK_READ_SIZES_MAX],
     // Categorize metrics as per their latency and request size
     read_latency_sizes_dist: [[BasicMetric; READ_LATENCY_RANGE_MAX]; BLOCK_READ_SIZES_MAX],
+    // Cumulative count of retried read requests to backend
+    read_retries: BasicMetric,
+    // Circuit breaker state: 0 for closed, 1 for open and 2 for half-open.
+    circuit_breaker_state: BasicMetric,
+    // Cumulative count of circuit breaker transitions into open state
+    circuit_breaker_opens: BasicMetric,
+    // Cumulative count of read requests rejected by an open circuit breaker
+    circuit_breaker_rejects: BasicMetric,
+    // Cumulative count of hedged requests issued for slow read requests
+    read_hedged: BasicMetric,
+    // Cumulative count of hedged requests completing before the original ones
+    read_hedge_wins: BasicMetric,
+    // Per-mirror metrics, indexed by mirror host.
+    mirrors: RwLock<HashMap<String, Arc<MirrorMetrics>>>,
 }
 
 impl BackendMetrics {
@@ -680,11 +694,86 @@ impl BackendMetrics {
         }
     }
 
+    /// Mark a read request to be retried.
+    pub fn retry(&self) {
+        self.read_retries.inc();
+    }
+
+    /// Update state of the circuit breaker: 0 for closed, 1 for open and 2 for half-open.
+    pub fn set_circuit_breaker_state(&self, state: u64) {
+        if state == 1 && self.circuit_breaker_state.count() != 1 {
+            self.circuit_breaker_opens.inc();
+        }
+        self.circuit_breaker_state.set(state);
+    }
+
+    /// Mark a read request to be rejected by the circuit breaker.
+    pub fn circuit_breaker_reject(&self) {
+        self.circuit_breaker_rejects.inc();
+    }
+
+    /// Get metrics object for mirror `host`, create one if it doesn't exist yet.
+    pub fn mirror_metrics(&self, host: &str) -> Arc<MirrorMetrics> {
+        if let Some(m) = self.mirrors.read().unwrap().get(host) {
+            return m.clone();
+        }
+        self.mirrors
+            .write()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(MirrorMetrics::default()))
+            .clone()
+    }
+
+    /// Remove metrics object for mirror `host`.
+    pub fn remove_mirror_metrics(&self, host: &str) {
+        self.mirrors.write().unwrap().remove(host);
+    }
+
+    /// Mark a hedged request to be issued for a slow read request.
+    pub fn hedge(&self) {
+        self.read_hedged.inc();
+    }
+
+    /// Mark a hedged request to complete before the original one.
+    pub fn hedge_win(&self) {
+        self.read_hedge_wins.inc();
+    }
+
     fn export_metrics(&self) -> IoStatsResult<String> {
         serde_json::to_string(self).map_err(MetricsError::Serialize)
     }
 }
 
+/// Metrics for a mirror server of storage backends.
+#[derive(Default, Serialize, Debug)]
+pub struct MirrorMetrics {
+    // Cumulative count of requests sent to the mirror
+    requests: BasicMetric,
+    // Cumulative count of failed requests sent to the mirror
+    errors: BasicMetric,
+    // Exponentially weighted moving average of request latency, in microseconds
+    latency_ewma_micros: BasicMetric,
+    // Whether the mirror is healthy: 1 for healthy and 0 for unhealthy.
+    healthy: BasicMetric,
+}
+
+impl MirrorMetrics {
+    /// Mark ending of a request to the mirror, with the updated average latency.
+    pub fn end(&self, latency_ewma_micros: u64, error: bool) {
+        self.requests.inc();
+        if error {
+            self.errors.inc();
+        }
+        self.latency_ewma_micros.set(latency_ewma_micros);
+    }
+
+    /// Update health status of the mirror.
+    pub fn set_healthy(&self, healthy: bool) {
+        self.healthy.set(healthy as u64);
+    }
+}
+
 // This function assumes that the counted duration won't be too long.
 fn saturating_duration_millis(d: &Duration) -> u64 {
     let d_secs = d.as_secs();
@@ -750,6 +839,12 @@ pub struct BlobcacheMetrics {
     pub prefetch_end_time_millis: BasicMetric,
     pub buffered_backend_size: BasicMetric,
     pub data_all_ready: AtomicBool,
+    // Hits and misses of the in-memory hot chunk cache.
+    pub mem_cache_hits: BasicMetric,
+    pub mem_cache_misses: BasicMetric,
+    // Number of on-demand fetches delayed by QoS rate limits, and cumulative time delayed.
+    pub ondemand_throttled: BasicMetric,
+    pub ondemand_throttled_millis: BasicMetric,
 }
 
 impl BlobcacheMetrics {
@@ -0,0 +1,146 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generate Merkle hash tree compatible with Linux dm-verity.
+//!
+//! The hash tree uses SHA256 without salt and 4096-byte data and hash blocks, as by
+//! `veritysetup format --no-superblock --salt=-`. Hash blocks of upper levels are stored before
+//! lower levels, and the leaf level, which contains digests of data blocks, is stored last.
+
+use std::io::{Result, Write};
+
+use crate::digest::{Algorithm, RafsDigest};
+
+/// Size of data and hash blocks.
+pub const VERITY_BLOCK_SIZE: usize = 4096;
+
+const DIGEST_SIZE: usize = 32;
+const DIGESTS_PER_BLOCK: usize = VERITY_BLOCK_SIZE / DIGEST_SIZE;
+
+/// Generator for dm-verity compatible Merkle hash tree.
+pub struct VerityGenerator {
+    leaves: Vec<RafsDigest>,
+    levels: Vec<Vec<u8>>,
+}
+
+impl VerityGenerator {
+    /// Create a new instance of [VerityGenerator] for `data_blocks` data blocks.
+    pub fn new(data_blocks: u32) -> Result<Self> {
+        if data_blocks == 0 {
+            return Err(einval!("verity: no data block to generate hash tree"));
+        }
+
+        Ok(VerityGenerator {
+            leaves: vec![RafsDigest::default(); data_blocks as usize],
+            levels: Vec::new(),
+        })
+    }
+
+    /// Compute digest of a data block.
+    pub fn digest_block(data: &[u8]) -> RafsDigest {
+        RafsDigest::from_buf(data, Algorithm::Sha256)
+    }
+
+    /// Set digest of data block with index `block`.
+    pub fn set_digest(&mut self, block: u32, digest: RafsDigest) -> Result<()> {
+        match self.leaves.get_mut(block as usize) {
+            Some(v) => {
+                *v = digest;
+                Ok(())
+            }
+            None => Err(einval!(format!(
+                "verity: block index {} is out of range",
+                block
+            ))),
+        }
+    }
+
+    /// Generate all levels of the hash tree, and return the root digest.
+    pub fn generate_all_digests(&mut self) -> RafsDigest {
+        self.levels.clear();
+
+        let mut digests = self.leaves.clone();
+        loop {
+            let blocks = (digests.len() + DIGESTS_PER_BLOCK - 1) / DIGESTS_PER_BLOCK;
+            let mut level = vec![0u8; blocks * VERITY_BLOCK_SIZE];
+            for (idx, digest) in digests.iter().enumerate() {
+                let pos = idx * DIGEST_SIZE;
+                level[pos..pos + DIGEST_SIZE].copy_from_slice(&digest.data);
+            }
+            digests = level
+                .chunks(VERITY_BLOCK_SIZE)
+                .map(Self::digest_block)
+                .collect();
+            self.levels.push(level);
+            if blocks == 1 {
+                return digests[0];
+            }
+        }
+    }
+
+    /// Get size of the hash tree, valid after calling `generate_all_digests()`.
+    pub fn tree_size(&self) -> u64 {
+        self.levels.iter().map(|v| v.len() as u64).sum()
+    }
+
+    /// Write the hash tree to `writer`, valid after calling `generate_all_digests()`.
+    pub fn write_tree<W: Write>(&self, writer: &mut W) -> Result<()> {
+        for level in self.levels.iter().rev() {
+            writer.write_all(level)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verity_single_level() {
+        assert!(VerityGenerator::new(0).is_err());
+
+        let data = vec![0x5au8; VERITY_BLOCK_SIZE];
+        let digest = VerityGenerator::digest_block(&data);
+        let mut generator = VerityGenerator::new(1).unwrap();
+        generator.set_digest(0, digest).unwrap();
+        assert!(generator.set_digest(1, digest).is_err());
+
+        let root = generator.generate_all_digests();
+        let mut block = vec![0u8; VERITY_BLOCK_SIZE];
+        block[..DIGEST_SIZE].copy_from_slice(&digest.data);
+        assert_eq!(root, VerityGenerator::digest_block(&block));
+        assert_eq!(generator.tree_size(), VERITY_BLOCK_SIZE as u64);
+
+        let mut tree = Vec::new();
+        generator.write_tree(&mut tree).unwrap();
+        assert_eq!(tree, block);
+    }
+
+    #[test]
+    fn test_verity_multiple_levels() {
+        let blocks = DIGESTS_PER_BLOCK as u32 + 1;
+        let mut generator = VerityGenerator::new(blocks).unwrap();
+        for idx in 0..blocks {
+            let data = vec![idx as u8; VERITY_BLOCK_SIZE];
+            generator
+                .set_digest(idx, VerityGenerator::digest_block(&data))
+                .unwrap();
+        }
+        let root = generator.generate_all_digests();
+        assert_eq!(generator.tree_size(), 3 * VERITY_BLOCK_SIZE as u64);
+
+        let mut tree = Vec::new();
+        generator.write_tree(&mut tree).unwrap();
+        assert_eq!(tree.len(), 3 * VERITY_BLOCK_SIZE);
+        // The upper level is stored first and its digests cover the leaf level.
+        let top = &tree[..VERITY_BLOCK_SIZE];
+        assert_eq!(root, VerityGenerator::digest_block(top));
+        let leaf0 = VerityGenerator::digest_block(&tree[VERITY_BLOCK_SIZE..2 * VERITY_BLOCK_SIZE]);
+        let leaf1 = VerityGenerator::digest_block(&tree[2 * VERITY_BLOCK_SIZE..]);
+        assert_eq!(&top[..DIGEST_SIZE], &leaf0.data);
+        assert_eq!(&top[DIGEST_SIZE..2 * DIGEST_SIZE], &leaf1.data);
+        assert!(top[2 * DIGEST_SIZE..].iter().all(|v| *v == 0));
+    }
+}